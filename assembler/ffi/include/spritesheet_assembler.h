@@ -0,0 +1,124 @@
+/* Generated by cbindgen from ffi/src/lib.rs; don't edit by hand. */
+
+#ifndef SPRITESHEET_ASSEMBLER_H
+#define SPRITESHEET_ASSEMBLER_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// The run succeeded.
+#define SA_SUCCESS 0
+
+// Anything not covered below, including a null job or argument.
+#define SA_FAILURE 1
+
+// An unknown option, or a value it can't take.
+#define SA_INVALID_OPTIONS 2
+
+#define SA_NO_IMAGES 3
+
+#define SA_INCONSISTENT_SIZE 4
+
+// A frame couldn't be decoded or the sheet couldn't be encoded.
+#define SA_IMAGE 5
+
+// Reading or writing files failed.
+#define SA_IO 6
+
+#define SA_SIZE_LIMIT 7
+
+// An output exists and neither the `force` nor the `backup` option was set.
+#define SA_OUTPUT_EXISTS 8
+
+// The run was cancelled, and what it had written removed.
+#define SA_CANCELLED 9
+
+// Another run is assembling in the same root, and the `wait` option wasn't
+// set.
+#define SA_LOCKED 10
+
+// An assembly being configured or one that has run.
+typedef struct SaJob SaJob;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Creates a job assembling the frames in `<root>/temp` into `<root>/out.png`,
+// or returns null if `root` is null or not UTF-8. Free it with `sa_job_free`.
+//
+// # Safety
+//
+// `root` must be null or a nul-terminated string.
+struct SaJob *sa_job_new(const char *root);
+
+// Adds a frame file, relative to the root or absolute. Once any are added,
+// only these frames are used, in the order they were added.
+//
+// # Safety
+//
+// `job` must come from `sa_job_new` and `path` must be a nul-terminated
+// string.
+int32_t sa_job_add_frame(struct SaJob *job, const char *path);
+
+// Sets one of the command line options by its long name without the
+// dashes, such as `out`, `metadata` or `max-size`. Flags take `1` or `0`,
+// repeatable options like `exclude` add a value each time, and
+// `max-memory` is in bytes.
+//
+// # Safety
+//
+// `job` must come from `sa_job_new`, and `name` and `value` must be
+// nul-terminated strings.
+int32_t sa_job_set_option(struct SaJob *job, const char *name, const char *value);
+
+// Runs the assembly. It can be run again, for instance after the frames
+// have been re-rendered.
+//
+// # Safety
+//
+// `job` must come from `sa_job_new`.
+int32_t sa_job_run(struct SaJob *job);
+
+// Describes the last failure, or returns null if the last call succeeded.
+// The string is valid until the next call on `job`.
+//
+// # Safety
+//
+// `job` must come from `sa_job_new`.
+const char *sa_job_error(const struct SaJob *job);
+
+// The last run's report as JSON, the same as `--report json` prints, or
+// null before the first run. The buffer isn't nul-terminated; its length is
+// stored in `len`, and it is valid until the next call on `job`.
+//
+// # Safety
+//
+// `job` must come from `sa_job_new` and `len` must be null or writable.
+const uint8_t *sa_job_report(struct SaJob *job, size_t *len);
+
+// The metadata the last run wrote next to its first sheet, in the first
+// format set with the `metadata` option, or null if there is none. The
+// buffer is valid until the next call on `job`, and its length is stored
+// in `len`.
+//
+// # Safety
+//
+// `job` must come from `sa_job_new` and `len` must be null or writable.
+const uint8_t *sa_job_metadata(struct SaJob *job, size_t *len);
+
+// Frees a job and every buffer it handed out. Null is ignored.
+//
+// # Safety
+//
+// `job` must be null or come from `sa_job_new`, and mustn't be used again.
+void sa_job_free(struct SaJob *job);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* SPRITESHEET_ASSEMBLER_H */
@@ -0,0 +1,258 @@
+//! A C API over the assembler, so asset pipelines and editors written in C
+//! or C++ can link it instead of running the command line tool.
+//!
+//! A job is created with `sa_job_new`, configured with `sa_job_add_frame` and
+//! `sa_job_set_option`, run with `sa_job_run` and freed with `sa_job_free`.
+//! Functions that can fail return the same codes the command line tool exits
+//! with, and `sa_job_error` describes the last failure. The header is
+//! generated into `include/` with `cbindgen --config cbindgen.toml --output
+//! include/spritesheet_assembler.h`.
+
+use spritesheet_assembler::{exit, Assembler, AssemblerError, Report};
+use std::{
+    error::Error,
+    ffi::{CStr, CString},
+    fs,
+    os::raw::c_char,
+    panic::{self, AssertUnwindSafe},
+    ptr,
+};
+
+/// Null or non-UTF-8 arguments fail alongside the library's own errors.
+type BoxResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// The run succeeded.
+pub const SA_SUCCESS: i32 = 0;
+/// Anything not covered below, including a null job or argument.
+pub const SA_FAILURE: i32 = 1;
+/// An unknown option, or a value it can't take.
+pub const SA_INVALID_OPTIONS: i32 = 2;
+pub const SA_NO_IMAGES: i32 = 3;
+pub const SA_INCONSISTENT_SIZE: i32 = 4;
+/// A frame couldn't be decoded or the sheet couldn't be encoded.
+pub const SA_IMAGE: i32 = 5;
+/// Reading or writing files failed.
+pub const SA_IO: i32 = 6;
+pub const SA_SIZE_LIMIT: i32 = 7;
+/// An output exists and neither the `force` nor the `backup` option was set.
+pub const SA_OUTPUT_EXISTS: i32 = 8;
+/// The run was cancelled, and what it had written removed.
+pub const SA_CANCELLED: i32 = 9;
+/// Another run is assembling in the same root, and the `wait` option wasn't
+/// set.
+pub const SA_LOCKED: i32 = 10;
+
+/// An assembly being configured or one that has run.
+pub struct SaJob {
+    assembler: Assembler,
+    report: Option<Report>,
+    /// Buffers handed out to the caller, kept until the next call that
+    /// replaces them.
+    report_json: Vec<u8>,
+    metadata_bytes: Vec<u8>,
+    error: Option<CString>,
+}
+
+impl SaJob {
+    /// Runs `f`, remembering any error and turning it into an exit code.
+    fn attempt(&mut self, f: impl FnOnce(&mut Self) -> BoxResult<()>) -> i32 {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| f(self)))
+            .unwrap_or_else(|_| Err("the assembler panicked".into()));
+        match result {
+            Ok(()) => {
+                self.error = None;
+                exit::SUCCESS
+            }
+            Err(err) => {
+                let code = err
+                    .downcast_ref::<AssemblerError>()
+                    .map_or(exit::FAILURE, exit::code);
+                self.error = CString::new(err.to_string().replace('\0', "")).ok();
+                code
+            }
+        }
+    }
+
+    fn run(&mut self) -> BoxResult<()> {
+        let mut report = Report::default();
+        let result = self.assembler.run_with(&mut report);
+        self.report = Some(report);
+        Ok(result?)
+    }
+}
+
+/// Reads a string argument, failing on null or invalid UTF-8.
+unsafe fn string<'a>(s: *const c_char) -> BoxResult<&'a str> {
+    if s.is_null() {
+        return Err("a string argument was null".into());
+    }
+    Ok(CStr::from_ptr(s).to_str()?)
+}
+
+/// Hands `bytes` to the caller, storing its length in `len` if given.
+unsafe fn buffer(bytes: &[u8], len: *mut usize) -> *const u8 {
+    if !len.is_null() {
+        *len = bytes.len();
+    }
+    bytes.as_ptr()
+}
+
+/// Creates a job assembling the frames in `<root>/temp` into `<root>/out.png`,
+/// or returns null if `root` is null or not UTF-8. Free it with `sa_job_free`.
+///
+/// # Safety
+///
+/// `root` must be null or a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn sa_job_new(root: *const c_char) -> *mut SaJob {
+    let root = match string(root) {
+        Ok(root) => root,
+        Err(_) => return ptr::null_mut(),
+    };
+    Box::into_raw(Box::new(SaJob {
+        assembler: Assembler::new(root),
+        report: None,
+        report_json: Vec::new(),
+        metadata_bytes: Vec::new(),
+        error: None,
+    }))
+}
+
+/// Adds a frame file, relative to the root or absolute. Once any are added,
+/// only these frames are used, in the order they were added.
+///
+/// # Safety
+///
+/// `job` must come from `sa_job_new` and `path` must be a nul-terminated
+/// string.
+#[no_mangle]
+pub unsafe extern "C" fn sa_job_add_frame(job: *mut SaJob, path: *const c_char) -> i32 {
+    let job = match job.as_mut() {
+        Some(job) => job,
+        None => return SA_FAILURE,
+    };
+    job.attempt(|job| {
+        let path = string(path)?;
+        job.assembler = job.assembler.clone().frame(path);
+        Ok(())
+    })
+}
+
+/// Sets one of the command line options by its long name without the
+/// dashes, such as `out`, `metadata` or `max-size`. Flags take `1` or `0`,
+/// repeatable options like `exclude` add a value each time, and
+/// `max-memory` is in bytes.
+///
+/// # Safety
+///
+/// `job` must come from `sa_job_new`, and `name` and `value` must be
+/// nul-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn sa_job_set_option(
+    job: *mut SaJob,
+    name: *const c_char,
+    value: *const c_char,
+) -> i32 {
+    let job = match job.as_mut() {
+        Some(job) => job,
+        None => return SA_FAILURE,
+    };
+    job.attempt(|job| {
+        job.assembler = job
+            .assembler
+            .clone()
+            .option(string(name)?, string(value)?)?;
+        Ok(())
+    })
+}
+
+/// Runs the assembly. It can be run again, for instance after the frames
+/// have been re-rendered.
+///
+/// # Safety
+///
+/// `job` must come from `sa_job_new`.
+#[no_mangle]
+pub unsafe extern "C" fn sa_job_run(job: *mut SaJob) -> i32 {
+    match job.as_mut() {
+        Some(job) => job.attempt(SaJob::run),
+        None => SA_FAILURE,
+    }
+}
+
+/// Describes the last failure, or returns null if the last call succeeded.
+/// The string is valid until the next call on `job`.
+///
+/// # Safety
+///
+/// `job` must come from `sa_job_new`.
+#[no_mangle]
+pub unsafe extern "C" fn sa_job_error(job: *const SaJob) -> *const c_char {
+    match job.as_ref().and_then(|job| job.error.as_ref()) {
+        Some(error) => error.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// The last run's report as JSON, the same as `--report json` prints, or
+/// null before the first run. The buffer isn't nul-terminated; its length is
+/// stored in `len`, and it is valid until the next call on `job`.
+///
+/// # Safety
+///
+/// `job` must come from `sa_job_new` and `len` must be null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn sa_job_report(job: *mut SaJob, len: *mut usize) -> *const u8 {
+    let job = match job.as_mut() {
+        Some(job) => job,
+        None => return ptr::null(),
+    };
+    let json = match job.report.as_ref().map(serde_json::to_vec) {
+        Some(Ok(json)) => json,
+        _ => return ptr::null(),
+    };
+    job.report_json = json;
+    buffer(&job.report_json, len)
+}
+
+/// The metadata the last run wrote next to its first sheet, in the first
+/// format set with the `metadata` option, or null if there is none. The
+/// buffer is valid until the next call on `job`, and its length is stored
+/// in `len`.
+///
+/// # Safety
+///
+/// `job` must come from `sa_job_new` and `len` must be null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn sa_job_metadata(job: *mut SaJob, len: *mut usize) -> *const u8 {
+    let job = match job.as_mut() {
+        Some(job) => job,
+        None => return ptr::null(),
+    };
+    let path = match (&job.report, job.assembler.metadata_formats().first()) {
+        (Some(report), Some(format)) if report.success => match report.outputs.first() {
+            Some(sheet) => sheet.path.with_extension(format.extension()),
+            None => return ptr::null(),
+        },
+        _ => return ptr::null(),
+    };
+    match fs::read(path) {
+        Ok(bytes) => {
+            job.metadata_bytes = bytes;
+            buffer(&job.metadata_bytes, len)
+        }
+        Err(_) => ptr::null(),
+    }
+}
+
+/// Frees a job and every buffer it handed out. Null is ignored.
+///
+/// # Safety
+///
+/// `job` must be null or come from `sa_job_new`, and mustn't be used again.
+#[no_mangle]
+pub unsafe extern "C" fn sa_job_free(job: *mut SaJob) {
+    if !job.is_null() {
+        drop(Box::from_raw(job));
+    }
+}
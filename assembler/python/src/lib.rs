@@ -0,0 +1,252 @@
+//! A Python extension module over the assembler, so the Blender add-on can
+//! assemble sheets in-process instead of running a platform-specific binary.
+//!
+//! ```python
+//! import spritesheet_assembler
+//!
+//! report = spritesheet_assembler.assemble(
+//!     "renders/hero", output="hero.png", metadata=["json"], overwrite="force"
+//! )
+//! ```
+//!
+//! Keyword arguments are named after the `Assembler` builder's methods, and
+//! the returned report is the dict `--report json` would print. Failures
+//! raise `AssemblyError`, whose `code` is the command line tool's exit code.
+//! Pass `on_event` a callable to be handed each progress event as a dict
+//! such as `{"event": "frames_decoded", "done": 12, "total": 48}`, from the
+//! assembler's threads.
+
+use assembler::{exit, Assembler, Compression, MetadataFormat, Overwrite, Report};
+use pyo3::{
+    create_exception,
+    exceptions::{PyException, PyTypeError, PyValueError},
+    prelude::*,
+    types::{PyDict, PyList},
+};
+use std::collections::BTreeMap;
+
+create_exception!(spritesheet_assembler, AssemblyError, PyException);
+
+/// Assembles the frames under `root` into a spritesheet and returns the
+/// report. The GIL is released while the assembly runs.
+#[pyfunction]
+#[pyo3(signature = (root, **options))]
+fn assemble<'py>(
+    py: Python<'py>,
+    root: &str,
+    options: Option<&Bound<'py, PyDict>>,
+) -> PyResult<Bound<'py, PyAny>> {
+    let mut assembler = Assembler::new(root);
+    if let Some(options) = options {
+        for (key, value) in options.iter() {
+            assembler = set_option(assembler, &key.extract::<String>()?, &value)?;
+        }
+    }
+    let mut report = Report::default();
+    let result = py.detach(|| assembler.run_with(&mut report));
+    if let Err(err) = result {
+        let error = AssemblyError::new_err(err.to_string());
+        error.value(py).setattr("code", exit::code(&err))?;
+        return Err(error);
+    }
+    let json =
+        serde_json::to_string(&report).map_err(|err| AssemblyError::new_err(err.to_string()))?;
+    py.import("json")?.call_method1("loads", (json,))
+}
+
+/// Applies one keyword argument of `assemble`.
+fn set_option(assembler: Assembler, key: &str, value: &Bound<PyAny>) -> PyResult<Assembler> {
+    Ok(match key {
+        "frames" => assembler.frames(value.extract::<String>()?),
+        "frame_files" => value
+            .cast::<PyList>()?
+            .iter()
+            .try_fold(assembler, |assembler, path| {
+                Ok::<_, PyErr>(assembler.frame(path.extract::<String>()?))
+            })?,
+        "output" => assembler.output(value.extract::<String>()?),
+        "name" => assembler.name(value.extract::<String>()?),
+        "exclude" => value
+            .extract::<Vec<String>>()?
+            .into_iter()
+            .fold(assembler, Assembler::exclude),
+        "max_depth" => assembler.max_depth(value.extract()?),
+        "follow_symlinks" => assembler.follow_symlinks(value.extract()?),
+        "name_pattern" => assembler
+            .name_pattern(&value.extract::<String>()?)
+            .map_err(|err| PyValueError::new_err(err.to_string()))?,
+        "start" => assembler.start(value.extract()?),
+        "end" => assembler.end(value.extract()?),
+        "overwrite" => assembler.overwrite(match value.extract::<String>()?.as_str() {
+            "refuse" => Overwrite::Refuse,
+            "force" => Overwrite::Force,
+            "backup" => Overwrite::Backup,
+            other => {
+                return Err(PyValueError::new_err(format!(
+                    "overwrite should be 'refuse', 'force' or 'backup', not '{}'",
+                    other
+                )))
+            }
+        }),
+        "cleanup" => assembler.cleanup(value.extract()?),
+        "wait" => assembler.wait(value.extract()?),
+        "metadata" => {
+            value
+                .extract::<Vec<String>>()?
+                .iter()
+                .try_fold(assembler, |assembler, name| {
+                    let format = MetadataFormat::parse(name)
+                        .map_err(|err| PyValueError::new_err(err.to_string()))?;
+                    Ok::<_, PyErr>(assembler.metadata(format))
+                })?
+        }
+        "embed_sheet" => assembler.embed_sheet(value.extract()?),
+        "frame_name" => assembler.frame_name(value.extract::<String>()?),
+        "debug_overlay" => assembler.debug_overlay(value.extract()?),
+        "number_frames" => assembler.number_frames(value.extract()?),
+        "number_size" => assembler.number_size(value.extract()?),
+        "number_corner" => assembler
+            .option("number-corner", &value.extract::<String>()?)
+            .map_err(|err| PyValueError::new_err(err.to_string()))?,
+        "preview" => assembler.preview(value.extract()?),
+        "preview_gif" => assembler.preview_gif(value.extract::<String>()?),
+        "preview_html" => assembler.preview_html(value.extract::<String>()?),
+        "preview_video" => assembler.preview_video(value.extract::<String>()?),
+        "preview_video_fps" => assembler.preview_video_fps(value.extract()?),
+        "preview_video_scale" => assembler.preview_video_scale(value.extract()?),
+        "palette" => assembler.palette(value.extract::<String>()?),
+        "palette_colors" => assembler.palette_colors(value.extract()?),
+        "provenance" => assembler.provenance(value.extract()?),
+        "checksums" => assembler
+            .option("checksums", &value.extract::<String>()?)
+            .map_err(|err| PyValueError::new_err(err.to_string()))?,
+        "scene" => assembler.scene(value.extract::<String>()?),
+        "low_memory" => assembler.low_memory(value.extract()?),
+        "gpu" => assembler.gpu(value.extract()?),
+        "max_memory" => assembler.max_memory(value.extract()?),
+        "max_size" => assembler.max_size(value.extract()?),
+        "max_frames_per_page" => assembler.max_frames_per_page(value.extract()?),
+        "fail_if_larger_than" => assembler.fail_if_larger_than(value.extract()?),
+        "target" => assembler
+            .option("target", &value.extract::<String>()?)
+            .map_err(|err| PyValueError::new_err(err.to_string()))?,
+        "compression" => assembler.compression(compression(value)?),
+        "deterministic" => assembler.deterministic(value.extract()?),
+        "cache" => assembler.cache(value.extract()?),
+        "resume" => assembler.resume(value.extract()?),
+        "metadata_only" => assembler.metadata_only(value.extract()?),
+        "empty_frames" => assembler
+            .option("empty-frames", &value.extract::<String>()?)
+            .map_err(|err| PyValueError::new_err(err.to_string()))?,
+        "strict_format" => assembler.strict_format(value.extract()?),
+        "strict_sequence" => assembler
+            .option("strict-sequence", &value.extract::<String>()?)
+            .map_err(|err| PyValueError::new_err(err.to_string()))?,
+        "find_duplicates" => assembler.find_duplicates(value.extract()?),
+        "similarity" => assembler.similarity(value.extract()?),
+        "pad_to_max" => assembler.pad_to_max(value.extract()?),
+        "pad_anchor" => assembler
+            .option("pad-anchor", &value.extract::<String>()?)
+            .map_err(|err| PyValueError::new_err(err.to_string()))?,
+        "trim" => assembler
+            .option("trim", &value.extract::<String>()?)
+            .map_err(|err| PyValueError::new_err(err.to_string()))?,
+        "pivot" => assembler
+            .option("pivot", &value.extract::<String>()?)
+            .map_err(|err| PyValueError::new_err(err.to_string()))?,
+        "pivot_marker" => assembler
+            .option("pivot-marker", &value.extract::<String>()?)
+            .map_err(|err| PyValueError::new_err(err.to_string()))?,
+        "fps" => assembler.fps(value.extract()?),
+        "markers" => assembler
+            .markers(value.extract::<String>()?)
+            .map_err(|err| PyValueError::new_err(err.to_string()))?,
+        "collision" => assembler
+            .option("collision", &value.extract::<String>()?)
+            .map_err(|err| PyValueError::new_err(err.to_string()))?,
+        "collision_tolerance" => assembler.collision_tolerance(value.extract()?),
+        "collision_mask" => assembler
+            .option("collision-mask", &value.extract::<String>()?)
+            .map_err(|err| PyValueError::new_err(err.to_string()))?,
+        "godot_scene" => assembler.godot_scene(value.extract()?),
+        "mesh" => assembler.mesh(value.extract()?),
+        "opaque_bounds" => assembler.opaque_bounds(value.extract()?),
+        "facings" => assembler.facings(value.extract()?),
+        "boxes" => assembler.boxes(value.extract::<String>()?),
+        "box_colours" => value
+            .extract::<BTreeMap<String, String>>()?
+            .into_iter()
+            .try_fold(assembler, |assembler, (label, colour)| {
+                assembler
+                    .option("box-colour", &format!("{}={}", label, colour))
+                    .map_err(|err| PyValueError::new_err(err.to_string()))
+            })?,
+        "passes" => {
+            value
+                .extract::<Vec<String>>()?
+                .iter()
+                .try_fold(assembler, |assembler, suffix| {
+                    assembler
+                        .option("pass", suffix)
+                        .map_err(|err| PyValueError::new_err(err.to_string()))
+                })?
+        }
+        "nine_slice" => assembler
+            .option("nine-slice", &value.extract::<String>()?)
+            .map_err(|err| PyValueError::new_err(err.to_string()))?,
+        "nine_slices" => assembler
+            .nine_slices(value.extract::<String>()?)
+            .map_err(|err| PyValueError::new_err(err.to_string()))?,
+        "on_event" => {
+            if !value.is_callable() {
+                return Err(PyTypeError::new_err("on_event should be callable"));
+            }
+            let callback = value.clone().unbind();
+            assembler.on_event(move |event| {
+                Python::attach(|py| {
+                    let result = serde_json::to_string(event)
+                        .map_err(|err| PyValueError::new_err(err.to_string()))
+                        .and_then(|json| py.import("json")?.call_method1("loads", (json,)))
+                        .and_then(|event| callback.call1(py, (event,)));
+                    // The assembly can't be stopped from here, so the error
+                    // is reported the way Python reports errors in `__del__`.
+                    if let Err(err) = result {
+                        err.write_unraisable(py, None);
+                    }
+                })
+            })
+        }
+        _ => {
+            return Err(PyTypeError::new_err(format!(
+                "assemble() got an unexpected keyword argument '{}'",
+                key
+            )))
+        }
+    })
+}
+
+/// `"default"`, `"fast"`, `"best"` or a level from 0 to 9.
+fn compression(value: &Bound<PyAny>) -> PyResult<Compression> {
+    if let Ok(level) = value.extract::<u8>() {
+        if level <= Compression::MAX_LEVEL {
+            return Ok(Compression::Level(level));
+        }
+    }
+    match value.extract::<String>().as_deref() {
+        Ok("default") => Ok(Compression::Default),
+        Ok("fast") => Ok(Compression::Fast),
+        Ok("best") => Ok(Compression::Best),
+        _ => Err(PyValueError::new_err(format!(
+            "compression should be 'default', 'fast', 'best' or a level from 0 to {}",
+            Compression::MAX_LEVEL
+        ))),
+    }
+}
+
+#[pymodule]
+fn spritesheet_assembler(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(assemble, m)?)?;
+    m.add("AssemblyError", m.py().get_type::<AssemblyError>())?;
+    m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    Ok(())
+}
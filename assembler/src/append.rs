@@ -0,0 +1,131 @@
+//! `assembler append SHEET.json FRAME.png...`: adds frames to a sheet from
+//! an earlier run without moving the ones already on it, so engines and
+//! scripts that refer to frames by index or position keep working. New
+//! frames fill empty cells of the grid first, then new rows at the bottom.
+
+use image::{GenericImage, RgbaImage};
+use log::info;
+use serde_json::{json, Value};
+use spritesheet_assembler::{errors::InconsistentSizeError, AssemblerError};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    sheet::{Rect, Sheet},
+    BoxResult,
+};
+
+/// Appends the frames at `frames`, named after their files, to the sheet
+/// described by the JSON at `metadata`, rewriting both in place.
+pub fn run(metadata: &Path, frames: &[PathBuf]) -> BoxResult<()> {
+    let mut sheet = Sheet::open(metadata)?;
+    let mut names = sheet
+        .frames
+        .iter()
+        .map(|frame| frame.name.clone())
+        .collect::<HashSet<_>>();
+    let mut added = Vec::with_capacity(frames.len());
+    for path in frames {
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        if !names.insert(name.clone()) {
+            return Err(sheet
+                .invalid(format!(
+                    "already has a frame named {}; use update to replace it",
+                    name
+                ))
+                .into());
+        }
+        let image = image::open(path)
+            .map_err(|source| AssemblerError::Image {
+                path: Some(path.clone()),
+                source,
+            })?
+            .to_rgba();
+        added.push((name, path, image));
+    }
+    let (first_path, (width, height)) = match (sheet.frames.first(), added.first()) {
+        (Some(frame), _) => (sheet.path.clone(), (frame.rect.w, frame.rect.h)),
+        (None, Some((_, path, image))) => ((*path).clone(), image.dimensions()),
+        (None, None) => return Ok(()),
+    };
+    for (_, path, image) in &added {
+        if image.dimensions() != (width, height) {
+            return Err(AssemblerError::from(InconsistentSizeError {
+                first: first_path,
+                first_size: (width, height),
+                path: (*path).clone(),
+                size: image.dimensions(),
+            })
+            .into());
+        }
+    }
+
+    // Keep the grid the sheet already has; an empty sheet starts as a row.
+    let columns = if sheet.frames.is_empty() {
+        added.len() as u32
+    } else {
+        (sheet.image.width() / width).max(1)
+    };
+    let taken = sheet
+        .frames
+        .iter()
+        .map(|frame| (frame.rect.x / width, frame.rect.y / height))
+        .collect::<HashSet<_>>();
+    let cells = (0..)
+        .flat_map(|row| (0..columns).map(move |column| (column, row)))
+        .filter(|cell| !taken.contains(cell))
+        .take(added.len())
+        .collect::<Vec<_>>();
+    let rows = cells
+        .iter()
+        .map(|(_, row)| row + 1)
+        .chain(Some(sheet.image.height() / height))
+        .max()
+        .unwrap_or(0);
+    let size = (
+        sheet.image.width().max(columns * width),
+        sheet.image.height().max(rows * height),
+    );
+    if size != sheet.image.dimensions() {
+        info!("Growing the sheet to {}x{}px", size.0, size.1);
+        let mut grown = RgbaImage::new(size.0, size.1);
+        grown.copy_from(&sheet.image, 0, 0);
+        sheet.image = grown;
+    }
+
+    // New frames are held for a single tick.
+    let millis = (1000.0 / sheet.fps()).round() as u32;
+    for ((name, _, image), (column, row)) in added.iter().zip(cells) {
+        let rect = Rect {
+            x: column * width,
+            y: row * height,
+            w: width,
+            h: height,
+        };
+        sheet.image.copy_from(image, rect.x, rect.y);
+        if let Some(frames) = sheet.metadata["frames"].as_array_mut() {
+            frames.push(entry(name, rect, millis));
+        }
+        info!("Added {} at {},{}", name, rect.x, rect.y);
+    }
+    sheet.metadata["meta"]["size"] = json!({"w": size.0, "h": size.1});
+    sheet.save()
+}
+
+/// A frame's entry in the JSON, as the assembler writes it.
+fn entry(name: &str, rect: Rect, millis: u32) -> Value {
+    json!({
+        "filename": name,
+        "frame": {"x": rect.x, "y": rect.y, "w": rect.w, "h": rect.h},
+        "rotated": false,
+        "trimmed": false,
+        "spriteSourceSize": {"x": 0, "y": 0, "w": rect.w, "h": rect.h},
+        "sourceSize": {"w": rect.w, "h": rect.h},
+        "duration": millis,
+    })
+}
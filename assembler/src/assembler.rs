@@ -0,0 +1,2602 @@
+//! The [`Assembler`] builder and the assembly pipeline it runs.
+
+use image::RgbaImage;
+use log::{debug, info, warn};
+use rayon::prelude::*;
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    boxes, cache,
+    cancel::{CancelHandle, CancellableWriter},
+    checkpoint::Checkpoint,
+    checksum::{self, Checksums},
+    collect::{self, Frame},
+    collision::{self, Collision},
+    debug, duplicates,
+    empty::EmptyFrames,
+    encode::{self, Compression},
+    errors::{
+        AssemblerError, ConfinementError, DuplicateFrameNameError, InconsistentSizeError,
+        IoContext, MissingSheetError, NoImagesError, OptionError, Phase, UnsafeCleanupError,
+    },
+    events::{Event, Events},
+    facing,
+    filter::{self, FrameFilter},
+    format::{self, Format},
+    gaps::{self, StrictSequence},
+    godot, layout,
+    limit::{self, Target},
+    lock,
+    mask::Mask,
+    memory::{self, EncodedFrame, EncodedSheet},
+    mesh,
+    metadata::{self, Atlas, MetadataFormat},
+    nine_slice::{self, Insets},
+    number, numbering,
+    output::{self, Overwrite},
+    pad::Anchor,
+    palette::{self, Palette},
+    passes, paths, preview,
+    progress::{self, Progress, ProgressWriter},
+    provenance,
+    report::{self, HashingWriter, Report},
+    stats, stream, template,
+    template::OutputTokens,
+    timing::{self, Direction},
+    trim::{self, Trim, Trimmed},
+    video, Dims, Options, Result,
+};
+
+/// Assembles the frames under a root folder into a spritesheet.
+///
+/// Every setting has the same default as the matching command line option,
+/// so `Assembler::new(root).run()` behaves like `assembler --root <root>`.
+#[derive(Debug, Clone)]
+pub struct Assembler {
+    options: Options,
+}
+
+impl Assembler {
+    /// An assembly of the frames in `<root>/temp`, writing `<root>/out.png`.
+    pub fn new(root: impl Into<String>) -> Self {
+        Assembler {
+            options: Options {
+                root: paths::simplify(root.into()),
+                frames: "temp".to_owned(),
+                inputs: Vec::new(),
+                output: "out.png".to_owned(),
+                name: None,
+                exclude: Vec::new(),
+                max_depth: None,
+                follow_symlinks: false,
+                name_pattern: None,
+                start: None,
+                end: None,
+                overwrite: Overwrite::Refuse,
+                cleanup: false,
+                wait: false,
+                metadata: Vec::new(),
+                embed_sheet: false,
+                frame_name: "{anim}/{stem}".to_owned(),
+                directions: Vec::new(),
+                sequence: None,
+                markers: Vec::new(),
+                collision: None,
+                collision_tolerance: collision::DEFAULT_TOLERANCE,
+                collision_mask: None,
+                godot_scene: false,
+                facings: false,
+                opaque_bounds: false,
+                mesh: false,
+                boxes: None,
+                box_colours: Vec::new(),
+                passes: Vec::new(),
+                nine_slice: None,
+                nine_slices: Default::default(),
+                fps: timing::DEFAULT_FPS,
+                animation_fps: Vec::new(),
+                debug_overlay: false,
+                number_frames: false,
+                number_size: number::DEFAULT_SIZE,
+                number_corner: Anchor::TopLeft,
+                preview: false,
+                preview_gif: None,
+                preview_html: None,
+                preview_video: None,
+                preview_video_fps: None,
+                preview_video_scale: 1.0,
+                palette: None,
+                palette_colors: None,
+                provenance: false,
+                checksums: None,
+                scene: None,
+                low_memory: false,
+                gpu: false,
+                compression: Compression::Default,
+                deterministic: false,
+                max_size: Some(layout::DEFAULT_MAX_SIZE),
+                max_frames_per_page: None,
+                max_memory: None,
+                fail_if_larger_than: None,
+                cache: false,
+                resume: false,
+                metadata_only: false,
+                split_by_animation: false,
+                animation: None,
+                pad_to_max: false,
+                pad_anchor: Anchor::Center,
+                trim: None,
+                pivot: None,
+                pivot_marker: None,
+                empty_frames: None,
+                strict_format: false,
+                strict_sequence: None,
+                find_duplicates: false,
+                similarity: duplicates::DEFAULT_SIMILARITY,
+                filters: Vec::new(),
+                events: Events::default(),
+                cancel: CancelHandle::default(),
+                confine: None,
+                checkpoint: None,
+            },
+        }
+    }
+
+    /// The folder holding the frames, relative to the root or absolute.
+    pub fn frames(mut self, dir: impl Into<String>) -> Self {
+        self.options.frames = paths::simplify(dir.into());
+        self
+    }
+
+    /// Adds a frame file, relative to the root or absolute. Once any are
+    /// added, only these frames are used, in the order they were added, and
+    /// the frames folder isn't searched.
+    pub fn frame(mut self, path: impl Into<PathBuf>) -> Self {
+        let path = match path.into().into_os_string().into_string() {
+            Ok(path) => PathBuf::from(paths::simplify(path)),
+            Err(path) => PathBuf::from(path),
+        };
+        self.options.inputs.push(self.root().join(path));
+        self
+    }
+
+    /// The sheet's filename relative to the root, which may contain
+    /// `{name}`, `{frames}`, `{w}`, `{h}`, `{page}`, `{anim}` and `{hash}`
+    /// tokens.
+    pub fn output(mut self, template: impl Into<String>) -> Self {
+        self.options.output = paths::simplify(template.into());
+        self
+    }
+
+    /// What `{name}` stands for; defaults to the root folder's name.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.options.name = Some(name.into());
+        self
+    }
+
+    /// Skips frames whose name or relative path matches `glob`.
+    pub fn exclude(mut self, glob: impl Into<String>) -> Self {
+        self.options.exclude.push(glob.into());
+        self
+    }
+
+    /// Only searches this many levels of subfolders below the frames folder.
+    pub fn max_depth(mut self, depth: Option<usize>) -> Self {
+        self.options.max_depth = depth;
+        self
+    }
+
+    pub fn follow_symlinks(mut self, follow: bool) -> Self {
+        self.options.follow_symlinks = follow;
+        self
+    }
+
+    /// Finds each frame's number in its filename with `pattern`, such as
+    /// `render_####.png`, where a run of `#` is the number and `*` any text,
+    /// or a regular expression whose `frame` group or first group captures
+    /// it, and orders the frames by number instead of by name. Frames that
+    /// don't match are skipped.
+    pub fn name_pattern(mut self, pattern: &str) -> Result<Self> {
+        self.options.name_pattern = Some(numbering::NamePattern::parse(pattern)?);
+        Ok(self)
+    }
+
+    /// Skips frames numbered before `start`, by the
+    /// [`name_pattern`](Self::name_pattern) or else the number at the end of
+    /// the filename.
+    pub fn start(mut self, start: Option<i64>) -> Self {
+        self.options.start = start;
+        self
+    }
+
+    /// Skips frames numbered after `end`, as for [`start`](Self::start).
+    pub fn end(mut self, end: Option<i64>) -> Self {
+        self.options.end = end;
+        self
+    }
+
+    /// What to do about outputs that already exist.
+    pub fn overwrite(mut self, overwrite: Overwrite) -> Self {
+        self.options.overwrite = overwrite;
+        self
+    }
+
+    /// Deletes the frames folder once everything has been written.
+    pub fn cleanup(mut self, cleanup: bool) -> Self {
+        self.options.cleanup = cleanup;
+        self
+    }
+
+    /// Waits for another run in the same root to finish, rather than failing
+    /// with [`LockedError`](crate::errors::LockedError). Every run holds a
+    /// lock on its root, so two can't write the same outputs at once.
+    pub fn wait(mut self, wait: bool) -> Self {
+        self.options.wait = wait;
+        self
+    }
+
+    /// Also writes frame metadata in `format` next to each sheet.
+    pub fn metadata(mut self, format: MetadataFormat) -> Self {
+        if !self.options.metadata.contains(&format) {
+            self.options.metadata.push(format);
+        }
+        self
+    }
+
+    /// Makes the module [`MetadataFormat::Rust`] writes include the sheet's
+    /// PNG with `include_bytes!`, so a game can be built with it and needs
+    /// no files alongside.
+    pub fn embed_sheet(mut self, embed: bool) -> Self {
+        self.options.embed_sheet = embed;
+        self
+    }
+
+    /// How frames are named in the metadata, using `{anim}`, `{index}`,
+    /// `{number}`, `{base}`, `{stem}` and `{file}` tokens; `{anim}/{stem}`
+    /// unless set.
+    pub fn frame_name(mut self, template: impl Into<String>) -> Self {
+        self.options.frame_name = template.into();
+        self
+    }
+
+    /// Plays `animation` in `direction`, whatever its folder's suffix says.
+    pub fn direction(mut self, animation: impl Into<String>, direction: Direction) -> Self {
+        let animation = animation.into();
+        self.options
+            .directions
+            .retain(|(name, _)| *name != animation);
+        self.options.directions.push((animation, direction));
+        self
+    }
+
+    /// Lists the frames in the order they play, such as `0-9, 4x3, 9-0`, with
+    /// indices counting from 0 in sheet order. Frames play in sheet order
+    /// without a sequence.
+    pub fn sequence(mut self, spec: &str) -> Result<Self> {
+        self.options.sequence = Some(timing::parse_sequence(spec)?);
+        Ok(self)
+    }
+
+    /// How many frames per second the animations play at, 24 unless set,
+    /// which the metadata gives as each frame's duration and the sheet's
+    /// frame rate.
+    pub fn fps(mut self, fps: u32) -> Self {
+        self.options.fps = fps.max(1);
+        self
+    }
+
+    /// Plays `animation` at `fps` rather than the sheet's rate.
+    pub fn animation_fps(mut self, animation: impl Into<String>, fps: u32) -> Self {
+        let animation = animation.into();
+        self.options
+            .animation_fps
+            .retain(|(name, _)| *name != animation);
+        self.options.animation_fps.push((animation, fps.max(1)));
+        self
+    }
+
+    /// Attaches the Blender timeline markers listed in the JSON file at
+    /// `path`, relative to the root, to the frames they mark, as events in
+    /// the metadata.
+    pub fn markers(mut self, path: impl AsRef<Path>) -> Result<Self> {
+        let markers = timing::load_markers(&self.root().join(path))?;
+        self.options.markers.extend(markers);
+        Ok(self)
+    }
+
+    /// Traces the outline of each frame's opaque pixels on the sheet and
+    /// exports it as collision polygons in the metadata, one per separate
+    /// part or one convex hull around them all.
+    pub fn collision(mut self, shape: Option<Collision>) -> Self {
+        self.options.collision = shape;
+        self
+    }
+
+    /// How far, in pixels, the [`collision`](Self::collision) outlines may
+    /// cut across the pixels to need fewer points, 1 unless set. 0 keeps
+    /// every corner.
+    pub fn collision_tolerance(mut self, tolerance: f64) -> Self {
+        self.options.collision_tolerance = tolerance;
+        self
+    }
+
+    /// Writes a mask of each frame's solid pixels, one bit each, next to
+    /// each sheet, as packed rows in a `.mask` file or runs in a
+    /// `.mask.json`.
+    pub fn collision_mask(mut self, mask: Option<Mask>) -> Self {
+        self.options.collision_mask = mask;
+        self
+    }
+
+    /// Writes a Godot 4 scene next to the first sheet, `.tscn`, with an
+    /// `AnimatedSprite2D` playing each animation and a `CollisionPolygon2D`
+    /// per part of the frame whose points switch along with it. Traces
+    /// outlines for it unless [`collision`](Self::collision) is set.
+    pub fn godot_scene(mut self, scene: bool) -> Self {
+        self.options.godot_scene = scene;
+        self
+    }
+
+    /// Exports the boxes painted on the frames of a separate pass in the
+    /// folder `dir`, relative to the root, whose files are named like the
+    /// frames they go with. Each part painted in a colour given to
+    /// [`box_colour`](Self::box_colour), or else red for `hitbox` and blue
+    /// for `hurtbox`, becomes a box with that label.
+    pub fn boxes(mut self, dir: impl Into<String>) -> Self {
+        self.options.boxes = Some(dir.into());
+        self
+    }
+
+    /// Labels the [`boxes`](Self::boxes) painted in `colour` as `label`.
+    pub fn box_colour(mut self, label: impl Into<String>, colour: [u8; 3]) -> Self {
+        let label = label.into();
+        self.options.box_colours.retain(|(name, _)| *name != label);
+        self.options.box_colours.push((label, colour));
+        self
+    }
+
+    /// Assembles frames saved with a `_{suffix}` per render pass, like
+    /// `frame_0001_color.png` and `frame_0001_emission.png`. The first pass
+    /// given is assembled as usual, and frames without a suffix count as
+    /// that pass. Each other pass is written to a sheet of its own, such as
+    /// `out_emission.png`, with every frame in the same place, and the
+    /// metadata lists them.
+    pub fn pass(mut self, suffix: impl Into<String>) -> Self {
+        let suffix = suffix.into();
+        if !self.options.passes.contains(&suffix) {
+            self.options.passes.push(suffix);
+        }
+        self
+    }
+
+    /// Reads the frames from `<facing>/<animation>/` folders, such as
+    /// `south/walk/0001.png` for characters rendered from several angles,
+    /// and gives each facing's animation a row of the sheet and a tag
+    /// naming both, with the facing's angle if it's a compass direction
+    /// like `north` or `sw`, or an angle like `45`.
+    pub fn facings(mut self, facings: bool) -> Self {
+        self.options.facings = facings;
+        self
+    }
+
+    /// Exports the smallest box holding each frame's visible pixels in the
+    /// metadata, relative to its cell, whether or not it's trimmed.
+    pub fn opaque_bounds(mut self, bounds: bool) -> Self {
+        self.options.opaque_bounds = bounds;
+        self
+    }
+
+    /// Exports a triangle mesh covering each frame's visible pixels in the
+    /// metadata, for engines that draw sprites as TexturePacker's polygon
+    /// sprites to skip their transparent parts.
+    pub fn mesh(mut self, mesh: bool) -> Self {
+        self.options.mesh = mesh;
+        self
+    }
+
+    /// Exports 9-slice borders for every frame, `insets` from its sides,
+    /// unless [`nine_slices`](Self::nine_slices) gives it or its animation
+    /// others.
+    pub fn nine_slice(mut self, insets: Insets) -> Self {
+        self.options.nine_slice = Some(insets);
+        self
+    }
+
+    /// Exports 9-slice borders for the frames and animations named in the
+    /// JSON file at `path`, relative to the root, which maps their names to
+    /// an inset for every side or `[left, top, right, bottom]`.
+    pub fn nine_slices(mut self, path: impl AsRef<Path>) -> Result<Self> {
+        let slices = nine_slice::load(&self.root().join(path))?;
+        self.options.nine_slices.extend(slices);
+        Ok(self)
+    }
+
+    /// Writes a `_debug.png` copy of each sheet with the cell boundaries,
+    /// padding, the box around each frame's opaque pixels and the pivots
+    /// drawn on top.
+    pub fn debug_overlay(mut self, debug_overlay: bool) -> Self {
+        self.options.debug_overlay = debug_overlay;
+        self
+    }
+
+    /// Writes a `_numbered.png` copy of each sheet with every frame's
+    /// index drawn in a corner of its cell, for QA to say which frame shows
+    /// a problem.
+    pub fn number_frames(mut self, number_frames: bool) -> Self {
+        self.options.number_frames = number_frames;
+        self
+    }
+
+    /// How large [`number_frames`](Self::number_frames) draws the indices,
+    /// in multiples of its 3x5 pixel font, 2 unless set.
+    pub fn number_size(mut self, size: u32) -> Self {
+        self.options.number_size = size.max(1);
+        self
+    }
+
+    /// Where in each cell [`number_frames`](Self::number_frames) draws the
+    /// index, the top left unless set.
+    pub fn number_corner(mut self, corner: Anchor) -> Self {
+        self.options.number_corner = corner;
+        self
+    }
+
+    /// Writes a `.preview.png` contact sheet next to each sheet, showing
+    /// every frame on a checkerboard, labelled with its index and name.
+    pub fn preview(mut self, preview: bool) -> Self {
+        self.options.preview = preview;
+        self
+    }
+
+    /// Also writes a small looping GIF of the frames in playback order to
+    /// `path`, relative to the root, which takes the same `{name}` and
+    /// `{anim}` tokens as [`output`](Self::output). Frames show for as long
+    /// as the metadata says, at [`fps`](Self::fps) unless timed otherwise.
+    pub fn preview_gif(mut self, path: impl Into<String>) -> Self {
+        self.options.preview_gif = Some(path.into());
+        self
+    }
+
+    /// Also writes a page to `path`, relative to the root, with the sheet
+    /// embedded and a player to step through each animation, change its
+    /// speed, zoom in and onion skin. It takes the same tokens as
+    /// [`preview_gif`](Self::preview_gif).
+    pub fn preview_html(mut self, path: impl Into<String>) -> Self {
+        self.options.preview_html = Some(path.into());
+        self
+    }
+
+    /// Also writes an MP4 or WebM clip of the frames in playback order to
+    /// `path`, relative to the root, by piping them to ffmpeg, which is
+    /// looked for on the `PATH` unless `SPRITESHEET_FFMPEG` names it. If
+    /// it can't be found the clip is skipped with a warning. It takes the
+    /// same tokens as [`preview_gif`](Self::preview_gif).
+    pub fn preview_video(mut self, path: impl Into<String>) -> Self {
+        self.options.preview_video = Some(path.into());
+        self
+    }
+
+    /// The clip's frame rate, [`fps`](Self::fps) unless set. Held frames
+    /// are repeated, so each still shows for as long as it should.
+    pub fn preview_video_fps(mut self, fps: u32) -> Self {
+        self.options.preview_video_fps = Some(fps.max(1));
+        self
+    }
+
+    /// How much larger than the frames to make the clip, 1 unless set.
+    pub fn preview_video_scale(mut self, scale: f64) -> Self {
+        self.options.preview_video_scale = scale;
+        self
+    }
+
+    /// Also writes the colours the sheet uses to `path`, relative to the
+    /// root, as a GIMP `.gpl`, JASC `.pal` or `.hex` list, going by its
+    /// extension. The most used come first. It takes the same tokens as
+    /// [`preview_gif`](Self::preview_gif).
+    pub fn palette(mut self, path: impl Into<String>) -> Self {
+        self.options.palette = Some(path.into());
+        self
+    }
+
+    /// Caps the [`palette`](Self::palette) at `colors`, picked by median
+    /// cut when the sheet uses more. Every colour is listed unless set.
+    pub fn palette_colors(mut self, colors: usize) -> Self {
+        self.options.palette_colors = Some(colors.max(1));
+        self
+    }
+
+    /// Writes a `.provenance.json` record of how the sheet was made.
+    pub fn provenance(mut self, provenance: bool) -> Self {
+        self.options.provenance = provenance;
+        self
+    }
+
+    /// Writes the SHA-256 of the sheet and its sidecars next to them, either
+    /// in a `<file>.sha256` each or all in one `<sheet>.sha256`, in the
+    /// format `sha256sum -c` checks.
+    pub fn checksums(mut self, checksums: Option<Checksums>) -> Self {
+        self.options.checksums = checksums;
+        self
+    }
+
+    /// The Blender scene the frames came from, for the provenance record.
+    pub fn scene(mut self, scene: impl Into<String>) -> Self {
+        self.options.scene = Some(scene.into());
+        self
+    }
+
+    /// Streams frames from disk a row at a time instead of decoding them all
+    /// up front. Frames must then be non-interlaced 8-bit RGBA PNGs.
+    pub fn low_memory(mut self, low_memory: bool) -> Self {
+        self.options.low_memory = low_memory;
+        self
+    }
+
+    /// Composites the sheet on the graphics card, falling back to the CPU
+    /// when there's none to use. Needs the `gpu` feature, and does nothing
+    /// with [`low_memory`](Self::low_memory), which composites as it reads.
+    pub fn gpu(mut self, gpu: bool) -> Self {
+        self.options.gpu = gpu;
+        self
+    }
+
+    /// Switches to [`low_memory`](Self::low_memory) when decoding up front
+    /// would need more than `bytes`.
+    pub fn max_memory(mut self, bytes: Option<u64>) -> Self {
+        self.options.max_memory = bytes;
+        self
+    }
+
+    /// The largest width or height of a sheet before frames wrap into a grid
+    /// and then onto more pages, or `None` for a single row regardless.
+    pub fn max_size(mut self, pixels: Option<u32>) -> Self {
+        self.options.max_size = pixels;
+        self
+    }
+
+    /// Puts at most `frames` frames on each sheet, however small, and the
+    /// rest onto more pages, for engines that import flipbooks of a fixed
+    /// length. `None` or 0 for as many as fit.
+    pub fn max_frames_per_page(mut self, frames: Option<usize>) -> Self {
+        self.options.max_frames_per_page = frames.filter(|frames| *frames > 0);
+        self
+    }
+
+    /// Fails with [`SheetLimitError`](crate::errors::SheetLimitError), which
+    /// lists the changes that would help, rather than write a sheet wider or
+    /// taller than `size`, such as a [`Target`]'s.
+    pub fn fail_if_larger_than(mut self, size: Option<(u32, u32)>) -> Self {
+        self.options.fail_if_larger_than = size;
+        self
+    }
+
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.options.compression = compression;
+        self
+    }
+
+    /// Writes byte-identical outputs for identical inputs on every platform,
+    /// for content-addressed stores and reproducible builds: compression is
+    /// pinned to an explicit deflate level and filter, and the provenance
+    /// record leaves out when it was made and where the root is. Frames are
+    /// always found in name order and written without ancillary PNG chunks.
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.options.deterministic = deterministic;
+        self
+    }
+
+    /// Whether to keep a cache in the root so unchanged runs are skipped and
+    /// unchanged cells of the last sheet are reused. Off by default, so a
+    /// plain run leaves nothing in the root but its outputs.
+    pub fn cache(mut self, cache: bool) -> Self {
+        self.options.cache = cache;
+        self
+    }
+
+    /// Only writes the metadata, and the other files describing the sheet
+    /// such as collision masks and Godot scenes, for the sheet an earlier
+    /// run wrote, without decoding the frames or encoding the sheet again.
+    /// The frames must be as they were, since the layout is worked out from
+    /// them anew; the sheet is only checked to be the size it should be.
+    pub fn metadata_only(mut self, metadata_only: bool) -> Self {
+        self.options.metadata_only = metadata_only;
+        self
+    }
+
+    /// Saves progress in the root as the sheet is assembled, so a run that
+    /// is interrupted can be started again with the same settings and pick
+    /// up where it stopped. The frames are streamed as with
+    /// [`low_memory`](Self::low_memory), unless filters or frame checks
+    /// need them decoded, in which case nothing is saved.
+    pub fn resume(mut self, resume: bool) -> Self {
+        self.options.resume = resume;
+        self
+    }
+
+    /// Writes a sheet, and its metadata, for each animation subfolder of the
+    /// frames folder, adding `_{anim}` to the output template unless it
+    /// already has an `{anim}` token. Frames outside any subfolder still go
+    /// on a sheet of their own.
+    pub fn split_by_animation(mut self, split: bool) -> Self {
+        self.options.split_by_animation = split;
+        self
+    }
+
+    /// Places frames smaller than the largest on a transparent canvas of its
+    /// size, at the [`pad_anchor`](Self::pad_anchor), rather than failing
+    /// with [`InconsistentSizeError`]. The report lists the frames padded.
+    /// Can't be used with [`low_memory`](Self::low_memory).
+    pub fn pad_to_max(mut self, pad: bool) -> Self {
+        self.options.pad_to_max = pad;
+        self
+    }
+
+    /// Where [`pad_to_max`](Self::pad_to_max) places smaller frames; centred
+    /// unless set.
+    pub fn pad_anchor(mut self, anchor: Anchor) -> Self {
+        self.options.pad_anchor = anchor;
+        self
+    }
+
+    /// Cuts every frame down to the box around all their visible pixels,
+    /// shrinking the cells while keeping them the same size, so the
+    /// character doesn't move from frame to frame. The metadata gives the
+    /// box within the original canvas, and pivots stay where they were on
+    /// it. Can't be used with [`low_memory`](Self::low_memory) or
+    /// [`boxes`](Self::boxes).
+    pub fn trim(mut self, trim: Option<Trim>) -> Self {
+        self.options.trim = trim;
+        self
+    }
+
+    /// Exports a pivot for every frame in the metadata, at `anchor` unless
+    /// the frame has its own from a `@pivot=X,Y` filename suffix or a
+    /// [`pivot_marker`](Self::pivot_marker).
+    pub fn pivot(mut self, anchor: Anchor) -> Self {
+        self.options.pivot = Some(anchor);
+        self
+    }
+
+    /// Takes each frame's pivot from its one pixel of `colour`, which is
+    /// made transparent on the sheet. Frames without one fall back to the
+    /// filename or [`pivot`](Self::pivot). Can't be used with
+    /// [`low_memory`](Self::low_memory).
+    pub fn pivot_marker(mut self, colour: [u8; 3]) -> Self {
+        self.options.pivot_marker = Some(colour);
+        self
+    }
+
+    /// Checks every frame for being fully transparent, the usual sign of a
+    /// broken render layer, and warns, fails or leaves it out. The report
+    /// lists the indices of the empty frames. Can't be used with
+    /// [`low_memory`](Self::low_memory).
+    pub fn empty_frames(mut self, policy: Option<EmptyFrames>) -> Self {
+        self.options.empty_frames = policy;
+        self
+    }
+
+    /// Fails with [`MixedFormatError`](crate::errors::MixedFormatError)
+    /// instead of warning when frames differ in bit depth or colour space,
+    /// which would be silently made uniform.
+    pub fn strict_format(mut self, strict: bool) -> Self {
+        self.options.strict_format = strict;
+        self
+    }
+
+    /// Checks the numbered frames of each animation run from the first
+    /// number to the last without skipping or repeating one, the usual sign
+    /// of a render that crashed partway, and warns or fails. The report
+    /// lists the gaps found.
+    pub fn strict_sequence(mut self, policy: Option<StrictSequence>) -> Self {
+        self.options.strict_sequence = policy;
+        self
+    }
+
+    /// Reports frames with exactly the same pixels as an earlier one, and
+    /// consecutive frames of an animation at least
+    /// [`similarity`](Self::similarity) alike, in the log and the report.
+    /// Can't be used with [`low_memory`](Self::low_memory).
+    pub fn find_duplicates(mut self, find: bool) -> Self {
+        self.options.find_duplicates = find;
+        self
+    }
+
+    /// How alike, from 0 to 1, consecutive frames must be for
+    /// [`find_duplicates`](Self::find_duplicates) to report them; 0.99
+    /// unless set. One minus the mean difference of their channels.
+    pub fn similarity(mut self, similarity: f64) -> Self {
+        self.options.similarity = similarity.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Sets an option by its command line name, such as `out`, `metadata`
+    /// or `max-size`, from a string, for hosts that can't call the builder
+    /// methods directly. Flags take `1` or `0`, repeatable options add a value
+    /// each time, `max-memory` is in bytes, `compression` takes `default`,
+    /// `fast`, `best` or a level, `direction` takes `ANIM=DIRECTION` and
+    /// `fps` a rate for every animation or `ANIM=FPS` for one.
+    pub fn option(self, name: &str, value: &str) -> Result<Self> {
+        let invalid = |expected: &str| OptionError {
+            name: name.to_owned(),
+            value: value.to_owned(),
+            expected: expected.to_owned(),
+        };
+        let flag = || match value.to_ascii_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => Ok(true),
+            "0" | "false" | "no" | "off" => Ok(false),
+            _ => Err(invalid("1 or 0")),
+        };
+        let number = || value.parse::<u64>().map_err(|_| invalid("a whole number"));
+        Ok(match name {
+            "out" => self.output(value),
+            "frames-dir" => self.frames(value),
+            "name" => self.name(value),
+            "exclude" => self.exclude(value),
+            "max-depth" => self.max_depth(Some(number()? as usize)),
+            "follow-symlinks" => self.follow_symlinks(flag()?),
+            "name-pattern" => self.name_pattern(value)?,
+            "start" | "end" => {
+                let number = value
+                    .parse::<i64>()
+                    .map_err(|_| invalid("a whole number, which may be negative"))?;
+                match name {
+                    "start" => self.start(Some(number)),
+                    _ => self.end(Some(number)),
+                }
+            }
+            "force" => self.overwrite(if flag()? {
+                Overwrite::Force
+            } else {
+                Overwrite::Refuse
+            }),
+            "backup" => self.overwrite(if flag()? {
+                Overwrite::Backup
+            } else {
+                Overwrite::Refuse
+            }),
+            "cleanup" => self.cleanup(flag()?),
+            "wait" => self.wait(flag()?),
+            "metadata" => self.metadata(MetadataFormat::parse(value)?),
+            "embed-sheet" => self.embed_sheet(flag()?),
+            "frame-name" => self.frame_name(value),
+            "direction" => {
+                let (animation, direction) = value
+                    .split_once('=')
+                    .and_then(|(animation, direction)| {
+                        Some((animation, Direction::parse(direction)?))
+                    })
+                    .ok_or_else(|| invalid("ANIM=forward, ANIM=reverse or ANIM=pingpong"))?;
+                self.direction(animation, direction)
+            }
+            "debug-overlay" => self.debug_overlay(flag()?),
+            "number-frames" => self.number_frames(flag()?),
+            "number-size" => match value.parse::<u32>() {
+                Ok(size) if size > 0 => self.number_size(size),
+                _ => return Err(invalid("a whole number above 0").into()),
+            },
+            "number-corner" => self.number_corner(
+                Anchor::parse(value)
+                    .ok_or_else(|| invalid(&format!("one of {}", Anchor::NAMES.join(", "))))?,
+            ),
+            "preview" => self.preview(flag()?),
+            "preview-gif" => self.preview_gif(value),
+            "preview-html" => self.preview_html(value),
+            "preview-video" => self.preview_video(value),
+            "preview-video-fps" => match value.parse::<u32>() {
+                Ok(fps) if fps > 0 => self.preview_video_fps(fps),
+                _ => return Err(invalid("a frame rate").into()),
+            },
+            "preview-video-scale" => match value.parse::<f64>() {
+                Ok(scale) if scale > 0.0 && scale.is_finite() => self.preview_video_scale(scale),
+                _ => return Err(invalid("a number above 0").into()),
+            },
+            "palette" => self.palette(value),
+            "palette-colors" => match value.parse::<usize>() {
+                Ok(colors) if colors > 0 => self.palette_colors(colors),
+                _ => return Err(invalid("a number of colours above 0").into()),
+            },
+            "provenance" => self.provenance(flag()?),
+            "checksums" => self.checksums(Some(
+                Checksums::parse(value).ok_or_else(|| invalid("each or manifest"))?,
+            )),
+            "scene" => self.scene(value),
+            "low-memory" => self.low_memory(flag()?),
+            "gpu" => self.gpu(flag()?),
+            "max-memory" => self.max_memory(Some(number()?)),
+            "max-size" => self.max_size(match number()? {
+                0 => None,
+                size => Some(size.min(u64::from(u32::MAX)) as u32),
+            }),
+            "max-frames-per-page" => self.max_frames_per_page(Some(number()? as usize)),
+            "fail-if-larger-than" => self.fail_if_larger_than(Some(
+                limit::parse_size(value).ok_or_else(|| invalid("a size like 4096x4096"))?,
+            )),
+            "target" => self.fail_if_larger_than(Some(
+                Target::parse(value)
+                    .ok_or_else(|| invalid(&format!("one of {}", Target::NAMES.join(", "))))?
+                    .size(),
+            )),
+            "compression" => self.compression(match value {
+                "default" => Compression::Default,
+                "fast" => Compression::Fast,
+                "best" => Compression::Best,
+                level => match level.parse::<u8>() {
+                    Ok(level) if level <= Compression::MAX_LEVEL => Compression::Level(level),
+                    _ => {
+                        return Err(invalid(&format!(
+                            "default, fast, best or a level from 0 to {}",
+                            Compression::MAX_LEVEL
+                        ))
+                        .into())
+                    }
+                },
+            }),
+            "deterministic" => self.deterministic(flag()?),
+            "cache" => self.cache(flag()?),
+            "resume" => self.resume(flag()?),
+            "metadata-only" => self.metadata_only(flag()?),
+            "split-by-animation" => self.split_by_animation(flag()?),
+            "pad-to-max" => self.pad_to_max(flag()?),
+            "pad-anchor" => self.pad_anchor(
+                Anchor::parse(value)
+                    .ok_or_else(|| invalid(&format!("one of {}", Anchor::NAMES.join(", "))))?,
+            ),
+            "trim" => self
+                .trim(Some(Trim::parse(value).ok_or_else(|| {
+                    invalid(&format!("one of {}", Trim::NAMES.join(", ")))
+                })?)),
+            "pivot" => self.pivot(
+                Anchor::parse(value)
+                    .ok_or_else(|| invalid(&format!("one of {}", Anchor::NAMES.join(", "))))?,
+            ),
+            "pivot-marker" => self
+                .pivot_marker(parse_colour(value).ok_or_else(|| invalid("a colour like ff00ff"))?),
+            "empty-frames" => self.empty_frames(Some(
+                EmptyFrames::parse(value).ok_or_else(|| invalid("warn, error or skip"))?,
+            )),
+            "strict-format" => self.strict_format(flag()?),
+            "strict-sequence" => self.strict_sequence(Some(
+                StrictSequence::parse(value).ok_or_else(|| invalid("warn or error"))?,
+            )),
+            "find-duplicates" => self.find_duplicates(flag()?),
+            "similarity" => match value.parse::<f64>() {
+                Ok(similarity) if (0.0..=1.0).contains(&similarity) => self.similarity(similarity),
+                _ => return Err(invalid("a number from 0 to 1").into()),
+            },
+            "filter-chain" => self.filter_chain(value)?,
+            "sequence" => self.sequence(value)?,
+            "markers" => self.markers(value)?,
+            "collision" => self.collision(Some(
+                Collision::parse(value).ok_or_else(|| invalid("polygon or hull"))?,
+            )),
+            "collision-tolerance" => match value.parse::<f64>() {
+                Ok(tolerance) if tolerance >= 0.0 && tolerance.is_finite() => {
+                    self.collision_tolerance(tolerance)
+                }
+                _ => return Err(invalid("a number of pixels").into()),
+            },
+            "collision-mask" => self.collision_mask(Some(
+                Mask::parse(value).ok_or_else(|| invalid("raw or rle"))?,
+            )),
+            "godot-scene" => self.godot_scene(flag()?),
+            "boxes" => self.boxes(value),
+            "box-colour" => match value.split_once('=') {
+                Some((label, colour)) if !label.is_empty() => match parse_colour(colour) {
+                    Some(colour) => self.box_colour(label, colour),
+                    None => return Err(invalid("LABEL=COLOUR, like hitbox=ff0000").into()),
+                },
+                _ => return Err(invalid("LABEL=COLOUR, like hitbox=ff0000").into()),
+            },
+            "pass" => match value {
+                "" => return Err(invalid("a filename suffix, like emission").into()),
+                suffix => self.pass(suffix),
+            },
+            "mesh" => self.mesh(flag()?),
+            "opaque-bounds" => self.opaque_bounds(flag()?),
+            "facings" => self.facings(flag()?),
+            "nine-slice" => self.nine_slice(
+                Insets::parse(value).ok_or_else(|| invalid("an inset or left,top,right,bottom"))?,
+            ),
+            "nine-slices" => self.nine_slices(value)?,
+            "fps" => match value.split_once('=') {
+                Some((animation, fps)) => match fps.parse::<u32>() {
+                    Ok(fps) if fps > 0 => self.animation_fps(animation, fps),
+                    _ => return Err(invalid("a frame rate, or ANIM=FPS").into()),
+                },
+                None => match value.parse::<u32>() {
+                    Ok(fps) if fps > 0 => self.fps(fps),
+                    _ => return Err(invalid("a frame rate, or ANIM=FPS").into()),
+                },
+            },
+            _ => return Err(invalid("the name of a command line option").into()),
+        })
+    }
+
+    /// The metadata formats that will be written, in order.
+    pub fn metadata_formats(&self) -> &[MetadataFormat] {
+        &self.options.metadata
+    }
+
+    /// Runs every frame through `filter` after the filters added before it.
+    /// Filters can't be used with [`low_memory`](Self::low_memory).
+    pub fn filter(mut self, filter: impl FrameFilter + 'static) -> Self {
+        self.options.filters.push(Arc::new(filter));
+        self
+    }
+
+    /// Adds the built-in filters in a chain such as
+    /// `tint:#ff8080,outline:#000000:2,flip:x`.
+    pub fn filter_chain(mut self, chain: &str) -> Result<Self> {
+        self.options.filters.extend(filter::parse_chain(chain)?);
+        Ok(self)
+    }
+
+    /// Calls `handler` with every [`Event`] as the assembly progresses, from
+    /// whichever thread is doing the work. To handle them on another thread,
+    /// such as a UI thread, send clones down a channel.
+    pub fn on_event(mut self, handler: impl Fn(&Event) + Send + Sync + 'static) -> Self {
+        self.options.events = Events::new(handler);
+        self
+    }
+
+    pub fn root(&self) -> &Path {
+        Path::new(&self.options.root)
+    }
+
+    /// Where the frames are read from.
+    pub fn frames_dir(&self) -> PathBuf {
+        self.options.frames_dir()
+    }
+
+    /// Runs the assembly, returning what was read and written.
+    pub fn run(&self) -> Result<Report> {
+        let mut report = Report::default();
+        self.run_with(&mut report)?;
+        Ok(report)
+    }
+
+    /// Assembles `frames` without touching the filesystem, returning each
+    /// page of the sheet encoded along with its metadata. Only the options
+    /// that affect the sheet's contents apply.
+    pub fn run_in_memory(&self, frames: &[EncodedFrame]) -> Result<Vec<EncodedSheet>> {
+        memory::run(&self.resolved(), frames)
+    }
+
+    /// Runs the assembly, filling in `report` as it goes so that callers can
+    /// still see how far a failed run got.
+    pub fn run_with(&self, report: &mut Report) -> Result<()> {
+        let started = Instant::now();
+        let options = self.resolved();
+        let mut result = lock::acquire(&options).and_then(|_lock| run(&options, report));
+        if self.options.cancel.is_cancelled() && result.is_err() {
+            // Whatever failed, it was most likely the cancellation.
+            result = Err(AssemblerError::Cancelled);
+            discard_outputs(report);
+        }
+        report.timings.total = report::millis(started.elapsed());
+        report.cancelled = matches!(result, Err(AssemblerError::Cancelled));
+        report.success = result.is_ok();
+        if let Err(err) = &result {
+            report.error = Some(err.to_string());
+        }
+        result
+    }
+
+    /// Stops a run in progress on another thread, and any later runs. See
+    /// [`CancelHandle`].
+    pub fn cancel(&self) {
+        self.options.cancel.cancel();
+    }
+
+    /// Refuses to write anything outside `dir`, checking each output's path
+    /// once its tokens are filled in, for servers running jobs for others.
+    pub fn confine(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.options.confine = Some(paths::resolve(&dir.into()));
+        self
+    }
+
+    /// A handle for cancelling this assembler's runs once it has been moved
+    /// into the thread running it.
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.options.cancel.clone()
+    }
+
+    /// The options with defaults that depend on the root filled in.
+    fn resolved(&self) -> Options {
+        Options {
+            name: Some(match &self.options.name {
+                Some(name) => name.clone(),
+                None => default_name(&self.options.root),
+            }),
+            compression: if self.options.deterministic {
+                self.options.compression.fixed()
+            } else {
+                self.options.compression
+            },
+            ..self.options.clone()
+        }
+    }
+}
+
+/// The root folder's own name, used when `--name` isn't given.
+fn default_name(root: &str) -> String {
+    let root = Path::new(root);
+    root.canonicalize()
+        .ok()
+        .as_deref()
+        .unwrap_or(root)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "spritesheet".to_owned())
+}
+
+fn run(options: &Options, report: &mut Report) -> Result<()> {
+    let described;
+    let options = if options.metadata_only {
+        // Nothing but the metadata and the files describing the sheet.
+        described = Options {
+            debug_overlay: false,
+            number_frames: false,
+            preview: false,
+            preview_gif: None,
+            preview_html: None,
+            preview_video: None,
+            palette: None,
+            provenance: false,
+            checksums: None,
+            ..options.clone()
+        };
+        &described
+    } else {
+        options
+    };
+    let tokens = OutputTokens {
+        name: options.name.as_deref().unwrap_or_default(),
+        anim: options.animation.as_deref().unwrap_or_default(),
+        frames: 0,
+        width: 0,
+        height: 0,
+        page: 0,
+        hash: None,
+    };
+    let planned = validate(options, tokens)?;
+
+    let cache = if options.cache {
+        cache::load(options)
+    } else {
+        None
+    };
+    let paths = collect::find_frames(options, report)?;
+    options.cancel.check()?;
+    if options.split_by_animation {
+        return run_split(options, report, paths);
+    }
+    // Every pass's files, so re-rendering any of them rebuilds the sheets.
+    let fingerprint = cache::fingerprint(options, &paths);
+    let paths = passes::split(options, report, paths)?;
+    if let Some(policy) = options.strict_sequence {
+        let frames = options.frames_dir();
+        let relative = paths
+            .iter()
+            .map(|path| {
+                passes::unsuffixed(&options.passes, path.strip_prefix(&frames).unwrap_or(path))
+            })
+            .collect::<Vec<_>>();
+        report.gaps = gaps::check(policy, &relative)?;
+    }
+    if let Some(cache) = &cache {
+        if cache.up_to_date(&fingerprint) {
+            info!("{} is up to date", cache.sheet.display());
+            report.up_to_date = true;
+            report.inputs = paths;
+            report.sheet = Some(cache.layout.clone());
+            report.outputs = cache.outputs.clone();
+            return clean_up(options, report);
+        }
+    }
+    let streamed;
+    let options = if !options.low_memory
+        && !options.metadata_only
+        && (over_budget(options, &paths) || streams_to_resume(options))
+    {
+        streamed = Options {
+            low_memory: true,
+            ..options.clone()
+        };
+        &streamed
+    } else {
+        options
+    };
+    let resumable;
+    let options = match &fingerprint {
+        Some(fingerprint) if options.resume && options.low_memory => {
+            resumable = Options {
+                checkpoint: Some(Arc::new(Checkpoint::open(options, fingerprint)?)),
+                ..options.clone()
+            };
+            &resumable
+        }
+        Some(_) | None => options,
+    };
+    check_destinations(options, tokens, &planned)?;
+
+    let (mut images, trimmed) = collect(options, report, paths, cache.as_ref())?;
+    let (packed, mut stats) = lay_out(options, report, &images, trimmed)?;
+    let sheet_paths = write_sheets(
+        options,
+        report,
+        &mut images,
+        &packed,
+        cache.as_ref(),
+        tokens,
+    )?;
+    let pass_sheets = write_passes(options, report, &images, &packed, &sheet_paths)?;
+    options.cancel.check()?;
+    let mut previews = Previews::new(options, tokens);
+    write_sidecars(
+        options,
+        report,
+        &images,
+        &packed,
+        &sheet_paths,
+        &pass_sheets,
+        &mut previews,
+    )?;
+    write_previews(
+        options,
+        report,
+        images.len(),
+        tokens,
+        &sheet_paths,
+        previews,
+    )?;
+
+    if options.provenance {
+        let path = provenance::path(&sheet_paths[0]);
+        let written = output::write_file(&path, options.overwrite, |out| {
+            provenance::write(options, &images, &report.outputs, out)
+        })?;
+        emit_written(options, &written);
+        report.outputs.push(written);
+    }
+
+    if let Some(checksums) = options.checksums {
+        let outputs = report.outputs.clone();
+        for (path, covered) in checksums.files(&sheet_paths[0], &outputs) {
+            let dir = path.parent().unwrap_or_else(|| Path::new(""));
+            let written = output::write_file(&path, options.overwrite, |out| {
+                checksum::write(dir, covered, out)
+            })?;
+            emit_written(options, &written);
+            report.outputs.push(written);
+        }
+    }
+
+    stats::formats(&mut stats, &report.outputs);
+    report.stats = Some(stats);
+
+    // The cache would vouch for sheets this run never looked inside.
+    if options.cache && !options.metadata_only {
+        let sheet = &packed.sheet;
+        let cache = cache::Cache::new(options, fingerprint, sheet, &images, &report.outputs);
+        if let Err(err) = cache.save(options) {
+            warn!("Couldn't save the rebuild cache: {}", err);
+        }
+    }
+    if let Some(checkpoint) = &options.checkpoint {
+        checkpoint.remove();
+    }
+
+    clean_up(options, report)
+}
+
+/// How the frames are laid out on the sheets.
+struct Packed {
+    dims: Dims,
+    layout: layout::Layout,
+    pages: Vec<layout::Page>,
+    sheet: report::Sheet,
+    /// The box every frame was trimmed to, with `--trim`.
+    trimmed: Option<Trimmed>,
+}
+
+/// What the metadata pass gathers from each page for the previews of the
+/// whole sheet.
+struct Previews {
+    cells: Vec<preview::Cell>,
+    player: Option<preview::Player>,
+    palette: Option<Palette>,
+    scene: Option<godot::Scene>,
+}
+
+impl Previews {
+    fn new(options: &Options, tokens: OutputTokens) -> Self {
+        Previews {
+            cells: Vec::new(),
+            player: options
+                .preview_html
+                .as_ref()
+                .map(|_| preview::Player::new(tokens.title())),
+            palette: options.palette.as_ref().map(|_| Palette::default()),
+            scene: if options.godot_scene {
+                Some(godot::Scene::new(tokens.title()))
+            } else {
+                None
+            },
+        }
+    }
+}
+
+/// Checks the options before doing any work, returning where the sheet is
+/// planned to go.
+fn validate(options: &Options, tokens: OutputTokens) -> Result<PathBuf> {
+    let root = Path::new(&options.root);
+    // Catch template typos before doing any work.
+    let planned = confined(options, root.join(tokens.render(&options.output)?))?;
+    if options.cleanup && root.join(&options.output).starts_with(options.frames_dir()) {
+        return Err(UnsafeCleanupError {
+            frames: options.frames_dir(),
+        }
+        .into());
+    }
+
+    template::FrameTokens::check(&options.frame_name)?;
+    let undecoded = if options.low_memory {
+        Some(("low-memory", false))
+    } else if options.metadata_only {
+        Some(("metadata-only", true))
+    } else {
+        None
+    };
+    if let Some((flag, metadata_only)) = undecoded {
+        if let Some((name, value)) = decoding(options, metadata_only) {
+            return Err(OptionError {
+                name: name.to_owned(),
+                value,
+                expected: format!("to be unset, since --{} doesn't decode the frames", flag),
+            }
+            .into());
+        }
+    }
+    if let (Some(trim), Some(_)) = (options.trim, &options.boxes) {
+        return Err(OptionError {
+            name: "trim".to_owned(),
+            value: trim.name().to_owned(),
+            expected: "to be unset, since --boxes may reach past the frames' visible pixels"
+                .to_owned(),
+        }
+        .into());
+    }
+    Ok(planned)
+}
+
+/// Fails if the sheet planned at `planned` or a preview would be
+/// overwritten without `--overwrite`, or a preview can't be written at all.
+fn check_destinations(options: &Options, tokens: OutputTokens, planned: &Path) -> Result<()> {
+    // Sheets an interrupted run already wrote are kept as they are.
+    if !options.output.contains('{') && !resumed(options, planned) {
+        check_outputs(options, planned)?;
+    }
+    if let Some(video) = &options.preview_video {
+        video::check(video)?;
+    }
+    if let Some(palette) = &options.palette {
+        palette::check(palette)?;
+    }
+    let root = Path::new(&options.root);
+    let previews = [
+        &options.preview_gif,
+        &options.preview_html,
+        &options.preview_video,
+        &options.palette,
+    ];
+    for preview in previews.iter().copied().flatten() {
+        output::check(
+            &confined(options, root.join(tokens.render(preview)?))?,
+            options.overwrite,
+        )?;
+    }
+    Ok(())
+}
+
+/// Reads the frames at `paths`, with their timing, and trims them with
+/// `--trim`.
+fn collect(
+    options: &Options,
+    report: &mut Report,
+    paths: Vec<PathBuf>,
+    cache: Option<&cache::Cache>,
+) -> Result<(Vec<Frame>, Option<Trimmed>)> {
+    let formats = paths
+        .par_iter()
+        .map(|path| {
+            let format = File::open(path)
+                .ok()
+                .and_then(|file| Format::read(BufReader::new(file)));
+            (path.as_path(), format)
+        })
+        .collect::<Vec<_>>();
+    format::check(&formats, options.strict_format)?;
+    let durations = timing::load(&options.frames_dir())?;
+    let mut images = collect::decode_frames(options, report, paths, cache)?;
+    timing::apply(&mut images, durations.as_ref());
+    if options.find_duplicates {
+        duplicates::find(&images, options.similarity, report);
+    }
+    let trimmed = options
+        .trim
+        .and_then(|_| trim::union(&mut images, options.pivot));
+    Ok((images, trimmed))
+}
+
+/// Lays the frames out on pages, returning the layout and the stats
+/// measured before compositing, which may let go of the frames' pixels.
+fn lay_out(
+    options: &Options,
+    report: &mut Report,
+    images: &[Frame],
+    trimmed: Option<Trimmed>,
+) -> Result<(Packed, report::Stats)> {
+    let started = Instant::now();
+    let dims = dims(images)?;
+    if let Some(sequence) = &options.sequence {
+        sequence.check(images.len())?;
+    }
+    let layout = plan(options, images, dims)?;
+    let pages = layout.pages()?;
+    limit::check(options, &layout, &pages, images)?;
+    let sheet = layout.report(&pages);
+    let stats = stats::measure(&pages, dims, images);
+    report.timings.pack = report::millis(started.elapsed());
+    info!(
+        "Layout: {}x{} tiles of {}x{}px on {} page(s), sheet is {}x{}px",
+        sheet.columns, sheet.rows, dims.x, dims.y, sheet.pages, sheet.width, sheet.height
+    );
+    if let Some(fallback) = &layout.fallback {
+        warn!("{}", fallback);
+    }
+    report.sheet = Some(sheet.clone());
+    let packed = Packed {
+        dims,
+        layout,
+        pages,
+        sheet,
+        trimmed,
+    };
+    Ok((packed, stats))
+}
+
+/// Composites and encodes each page of the first pass, or with
+/// `--metadata-only` finds the sheets an earlier run wrote, returning their
+/// paths.
+fn write_sheets(
+    options: &Options,
+    report: &mut Report,
+    images: &mut [Frame],
+    packed: &Packed,
+    cache: Option<&cache::Cache>,
+    tokens: OutputTokens,
+) -> Result<Vec<PathBuf>> {
+    let (pages, dims) = (&packed.pages, packed.dims);
+    let root = Path::new(&options.root);
+    let output = if pages.len() > 1 && !template::uses(&options.output, "page") {
+        template::paged(&options.output)
+    } else {
+        options.output.clone()
+    };
+    let frames = images.len();
+    let page_tokens = |page: &layout::Page| OutputTokens {
+        frames,
+        width: page.size.0,
+        height: page.size.1,
+        page: page.index,
+        ..tokens
+    };
+    let planned = pages
+        .iter()
+        .map(|page| confined(options, root.join(page_tokens(page).render(&output)?)))
+        .collect::<Result<Vec<_>>>()?;
+    if !template::uses(&output, "hash") {
+        for planned in planned.iter().filter(|planned| !resumed(options, planned)) {
+            check_outputs(options, planned)?;
+        }
+    }
+    let page_name = |page: &layout::Page, sha256: &str| {
+        // The last chance to stop before the page is moved into place.
+        options.cancel.check()?;
+        let tokens = OutputTokens {
+            hash: Some(&sha256[..8]),
+            ..page_tokens(page)
+        };
+        confined(options, root.join(tokens.render(&output)?))
+    };
+
+    let started = Instant::now();
+    let encoded = Progress::bytes("Encoding")
+        .emitting(&options.events, |bytes| Event::BytesWritten { bytes });
+    let written = if options.metadata_only {
+        pages
+            .iter()
+            .map(|page| existing_sheet(options, cache, page, page_tokens(page), &output))
+            .collect::<Result<Vec<_>>>()?
+    } else if options.low_memory {
+        // Compositing and encoding happen together, so it's all counted as
+        // encoding. Pages are streamed one at a time to keep memory bounded.
+        let encoded = encoded.hidden();
+        pages
+            .iter()
+            .zip(&planned)
+            .map(|(page, planned)| {
+                let written = stream::save_png(
+                    &images[page.first..][..page.count],
+                    page,
+                    dims,
+                    options,
+                    planned,
+                    &encoded,
+                    |sha256| page_name(page, sha256),
+                )?;
+                options.events.emit(Event::PageComposited {
+                    page: page.index,
+                    pages: pages.len(),
+                });
+                Ok(written)
+            })
+            .collect::<Result<Vec<_>>>()?
+    } else {
+        // Empty cells between rows would keep whatever the last sheet had
+        // there.
+        let base = cache
+            // And the box frames are trimmed to may have moved.
+            .filter(|_| {
+                pages.len() == 1 && packed.layout.rows.is_none() && packed.trimmed.is_none()
+            })
+            .and_then(|cache| Some((cache, cache.sheet(&packed.sheet)?)));
+        let mut reused = 0;
+        for (index, frame) in images.iter_mut().enumerate() {
+            let unchanged = base
+                .as_ref()
+                .is_some_and(|(cache, _)| cache.unchanged(index, &frame.sha256));
+            if unchanged {
+                // Already drawn in the right place.
+                frame.image = None;
+                reused += 1;
+            } else if frame.image.is_none() {
+                collect::decode(frame, options)?;
+            }
+        }
+        if reused > 0 {
+            info!(
+                "Reusing {} of {} cells from the last sheet",
+                reused,
+                images.len()
+            );
+        }
+        let rows = pages.iter().map(|page| u64::from(page.size.1)).sum();
+        let progress = Progress::new("Compositing", rows);
+        let mut base = base.map(|(_, sheet)| sheet);
+        let bases = pages.iter().map(|_| base.take()).collect::<Vec<_>>();
+        #[cfg(feature = "gpu")]
+        let gpu = if options.gpu {
+            crate::gpu::Gpu::open()
+        } else {
+            None
+        };
+        #[cfg(not(feature = "gpu"))]
+        if options.gpu {
+            warn!("Compositing on the CPU, since this build doesn't include the gpu feature");
+        }
+        // Each page is encoded as soon as it's composited, so encoding one
+        // page overlaps compositing the next, and only the pages being
+        // worked on are held in memory rather than every page at once.
+        let composited = Mutex::new(Duration::ZERO);
+        let written = pages
+            .par_iter()
+            .zip(bases)
+            .zip(&planned)
+            .map(|((page, base), planned)| {
+                let frames = &images[page.first..][..page.count];
+                let (size, tiles) = (page.size, page.tiles);
+                #[cfg(feature = "gpu")]
+                let drawn = gpu.as_ref().and_then(|gpu| {
+                    gpu.composite(base.as_ref(), frames, &page.cells, size, tiles, dims)
+                });
+                #[cfg(not(feature = "gpu"))]
+                let drawn = None;
+                let sheet = match drawn {
+                    Some(sheet) => {
+                        progress.inc(u64::from(size.1));
+                        sheet
+                    }
+                    None => composite(base, frames, &page.cells, size, tiles, dims, &progress),
+                };
+                options.events.emit(Event::PageComposited {
+                    page: page.index,
+                    pages: pages.len(),
+                });
+                let mut composited = composited.lock().unwrap_or_else(|err| err.into_inner());
+                *composited = (*composited).max(started.elapsed());
+                drop(composited);
+                options.cancel.check()?;
+                save_png(
+                    &sheet,
+                    options.compression,
+                    planned,
+                    options.overwrite,
+                    &encoded,
+                    &options.cancel,
+                    |sha256| page_name(page, sha256),
+                )
+            })
+            .collect::<Result<Vec<_>>>();
+        progress.finish();
+        let written = written?;
+        // Encoding is counted from when the last page was composited, since
+        // the rest of it happened alongside compositing.
+        let composited = composited
+            .into_inner()
+            .unwrap_or_else(|err| err.into_inner());
+        report.timings.composite = report::millis(composited);
+        report.bytes.composite = (images.len() - reused) as u64 * dims.x as u64 * dims.y as u64 * 4;
+        debug!("Composited sheet in {:.2?}", composited);
+        encoded.finish();
+        report.timings.encode = report::millis(started.elapsed().saturating_sub(composited));
+        written
+    };
+    if options.low_memory {
+        report.timings.encode = report::millis(started.elapsed());
+    }
+    let sheet_paths = written
+        .iter()
+        .map(|written| written.path.clone())
+        .collect::<Vec<_>>();
+    // The sheets were already there, so aren't this run's to report or
+    // to remove if it's cancelled.
+    if !options.metadata_only {
+        for written in &written {
+            info!("Wrote {}", written.path.display());
+            emit_written(options, written);
+        }
+        report.bytes.encode = written.iter().map(|written| written.bytes).sum();
+        report.outputs.extend(written);
+    }
+    Ok(sheet_paths)
+}
+
+/// Writes the other render passes' sheets next to `sheet_paths`, before
+/// the metadata, which names them. Returns each page's passes and their
+/// file names.
+fn write_passes(
+    options: &Options,
+    report: &mut Report,
+    images: &[Frame],
+    packed: &Packed,
+    sheet_paths: &[PathBuf],
+) -> Result<Vec<Vec<(String, String)>>> {
+    let pages = &packed.pages;
+    let mut pass_sheets = vec![Vec::new(); pages.len()];
+    for pass in options.passes.iter().skip(1) {
+        if options.metadata_only {
+            for (sheets, sheet) in pass_sheets.iter_mut().zip(sheet_paths) {
+                let path = passes::path(sheet, pass);
+                if !path.is_file() {
+                    return Err(MissingSheetError {
+                        path,
+                        reason: "doesn't exist".to_owned(),
+                    }
+                    .into());
+                }
+                let file = path.file_name().unwrap_or_default();
+                sheets.push((pass.clone(), file.to_string_lossy().into_owned()));
+            }
+            continue;
+        }
+        let (dims, trimmed) = (packed.dims, packed.trimmed);
+        let written = save_pass(options, pass, images, pages, sheet_paths, dims, trimmed)?;
+        for (sheets, written) in pass_sheets.iter_mut().zip(written) {
+            info!("Wrote {}", written.path.display());
+            emit_written(options, &written);
+            let file = written.path.file_name().unwrap_or_default();
+            sheets.push((pass.clone(), file.to_string_lossy().into_owned()));
+            report.outputs.push(written);
+        }
+    }
+    Ok(pass_sheets)
+}
+
+/// Writes each page's metadata and the files drawn over its sheet, such as
+/// collision masks and debug overlays, gathering what `previews` needs.
+fn write_sidecars(
+    options: &Options,
+    report: &mut Report,
+    images: &[Frame],
+    packed: &Packed,
+    sheet_paths: &[PathBuf],
+    pass_sheets: &[Vec<(String, String)>],
+    previews: &mut Previews,
+) -> Result<()> {
+    let (pages, dims) = (&packed.pages, packed.dims);
+    // The scene switches between traced shapes, so it needs some.
+    let collision_shape = options
+        .collision
+        .or_else(|| previews.scene.as_ref().map(|_| Collision::Polygon));
+    let drawn = options.preview
+        || previews.palette.is_some()
+        || options.debug_overlay
+        || options.number_frames
+        || options.preview_gif.is_some()
+        || options.preview_video.is_some();
+    let read_back = drawn
+        || collision_shape.is_some()
+        || options.collision_mask.is_some()
+        || options.mesh
+        || options.opaque_bounds;
+    if options.metadata.is_empty() && !read_back && previews.player.is_none() {
+        return Ok(());
+    }
+    let names = frame_names(options, images)?;
+    for (page, sheet_path) in pages.iter().zip(sheet_paths) {
+        let mut atlas = atlas(options, images, &names, page, sheet_path, dims)?;
+        atlas.passes = pass_sheets[page.index].clone();
+        atlas.trim = packed.trimmed;
+        atlas.pages = pages.len();
+        // Read back rather than kept, so streamed sheets get previews
+        // and collision shapes too.
+        let sheet = if read_back {
+            Some(
+                image::open(sheet_path)
+                    .map_err(|source| AssemblerError::Image {
+                        source,
+                        path: Some(sheet_path.clone()),
+                    })?
+                    .to_rgba(),
+            )
+        } else {
+            None
+        };
+        if let (Some(sheet), Some(shape)) = (&sheet, collision_shape) {
+            collision::trace(sheet, &mut atlas, shape, options.collision_tolerance);
+        }
+        if let Some(sheet) = sheet.as_ref().filter(|_| options.mesh) {
+            mesh::trace(sheet, &mut atlas);
+        }
+        if let Some(sheet) = sheet.as_ref().filter(|_| options.opaque_bounds) {
+            atlas.frames.par_iter_mut().for_each(|frame| {
+                let (x, y) = (frame.x, frame.y);
+                let bounds = debug::opaque_bounds(sheet, x, y, frame.width, frame.height);
+                frame.opaque_bounds = Some(match bounds {
+                    Some((left, top, right, bottom)) => {
+                        (left - x, top - y, right + 1 - left, bottom + 1 - top)
+                    }
+                    None => (0, 0, 0, 0),
+                });
+            });
+        }
+        for format in &options.metadata {
+            let path = format.path(sheet_path);
+            let written =
+                output::write_file(&path, options.overwrite, |out| format.write(&atlas, out))?;
+            emit_written(options, &written);
+            report.outputs.push(written);
+        }
+        if let Some(player) = &mut previews.player {
+            let png = fs::read(sheet_path).context(Phase::Read, sheet_path)?;
+            player.add_page(&png, &atlas);
+        }
+        if let Some(scene) = &mut previews.scene {
+            scene.add_page(&atlas);
+        }
+        if let (Some(sheet), Some(mask)) = (&sheet, options.collision_mask) {
+            let written = output::write_file(&mask.path(sheet_path), options.overwrite, |out| {
+                mask.write(sheet, &atlas, out)
+            })?;
+            emit_written(options, &written);
+            report.outputs.push(written);
+        }
+        let sheet = match sheet {
+            Some(sheet) if drawn => sheet,
+            _ => continue,
+        };
+        if options.debug_overlay {
+            let frames = &images[page.first..][..page.count];
+            let content = debug::content(frames, &report.padded, options.pad_anchor, dims);
+            let written = output::write_file(&debug::path(sheet_path), options.overwrite, |out| {
+                debug::write(&sheet, &atlas, &content, out)
+            })?;
+            emit_written(options, &written);
+            report.outputs.push(written);
+        }
+        if options.number_frames {
+            let (size, corner) = (options.number_size, options.number_corner);
+            let written =
+                output::write_file(&number::path(sheet_path), options.overwrite, |out| {
+                    number::write(&sheet, &atlas, page.first, size, corner, out)
+                })?;
+            emit_written(options, &written);
+            report.outputs.push(written);
+        }
+        if options.preview {
+            let written =
+                output::write_file(&preview::path(sheet_path), options.overwrite, |out| {
+                    preview::write(&sheet, &atlas, page.first, out)
+                })?;
+            emit_written(options, &written);
+            report.outputs.push(written);
+        }
+        if options.preview_gif.is_some() || options.preview_video.is_some() {
+            previews.cells.extend(preview::cells(&sheet, &atlas));
+        }
+        if let Some(palette) = &mut previews.palette {
+            palette.add(&sheet);
+        }
+    }
+    Ok(())
+}
+
+/// Writes the previews of the whole sheet: the GIF, video, Godot scene,
+/// HTML player and palette, playing `frames` frames in `--sequence` order.
+fn write_previews(
+    options: &Options,
+    report: &mut Report,
+    frames: usize,
+    tokens: OutputTokens,
+    sheet_paths: &[PathBuf],
+    previews: Previews,
+) -> Result<()> {
+    let root = Path::new(&options.root);
+    let Previews {
+        cells,
+        player,
+        palette,
+        scene,
+    } = previews;
+    let order = match &options.sequence {
+        Some(sequence) => sequence.frames.clone(),
+        None => (0..frames).collect(),
+    };
+    if let Some(gif) = &options.preview_gif {
+        let path = confined(options, root.join(tokens.render(gif)?))?;
+        let written = output::write_file(&path, options.overwrite, |out| {
+            preview::write_gif(order.iter().map(|index| &cells[*index]), out)
+        })?;
+        emit_written(options, &written);
+        report.outputs.push(written);
+    }
+    if let Some(video) = &options.preview_video {
+        let path = confined(options, root.join(tokens.render(video)?))?;
+        let fps = options.preview_video_fps.unwrap_or(options.fps);
+        let frames = order.iter().map(|index| &cells[*index]);
+        let scale = options.preview_video_scale;
+        if let Some(written) = video::write(frames, fps, scale, &path, options.overwrite)? {
+            emit_written(options, &written);
+            report.outputs.push(written);
+        }
+    }
+    if let Some(scene) = scene {
+        let path = godot::path(&sheet_paths[0]);
+        let written = output::write_file(&path, options.overwrite, |out| {
+            scene.write(&order, options.fps, out)
+        })?;
+        emit_written(options, &written);
+        report.outputs.push(written);
+    }
+    if let (Some(html), Some(player)) = (&options.preview_html, player) {
+        let path = confined(options, root.join(tokens.render(html)?))?;
+        let written = output::write_file(&path, options.overwrite, |out| {
+            player.write(order, options.fps, out)
+        })?;
+        emit_written(options, &written);
+        report.outputs.push(written);
+    }
+    if let (Some(template), Some(palette)) = (&options.palette, palette) {
+        let path = confined(options, root.join(tokens.render(template)?))?;
+        let written = output::write_file(&path, options.overwrite, |out| {
+            palette.write(options.palette_colors, tokens.title(), &path, out)
+        })?;
+        emit_written(options, &written);
+        report.outputs.push(written);
+    }
+    Ok(())
+}
+
+/// Assembles each animation in `paths` on its own, as if its frames had
+/// been passed in one by one.
+fn run_split(options: &Options, report: &mut Report, paths: Vec<PathBuf>) -> Result<()> {
+    let frames_dir = options.frames_dir();
+    let mut animations = Vec::<(String, Vec<PathBuf>)>::new();
+    for path in paths {
+        let relative = path.strip_prefix(&frames_dir).unwrap_or(&path);
+        let mut components = relative.components();
+        let folder = components.next().filter(|_| components.next().is_some());
+        let animation = folder
+            .map(|folder| {
+                timing::split_direction(&folder.as_os_str().to_string_lossy())
+                    .0
+                    .to_owned()
+            })
+            .unwrap_or_default();
+        match animations.iter_mut().find(|(name, _)| *name == animation) {
+            Some((_, paths)) => paths.push(path),
+            None => animations.push((animation, vec![path])),
+        }
+    }
+    if animations.is_empty() {
+        return Err(NoImagesError.into());
+    }
+    let split = if template::uses(&options.output, "anim") {
+        options.output.clone()
+    } else {
+        template::suffixed(&options.output, "anim")
+    };
+    let per_animation = |template: &str| {
+        if template::uses(template, "anim") {
+            template.to_owned()
+        } else {
+            template::suffixed(template, "anim")
+        }
+    };
+    let mut up_to_date = true;
+    for (animation, paths) in animations {
+        options.cancel.check()?;
+        info!(
+            "Assembling {}",
+            if animation.is_empty() {
+                "the frames outside any animation"
+            } else {
+                &animation
+            }
+        );
+        // Frames outside any animation keep the plain output name, or stand
+        // in for an `{anim}` with `{name}`.
+        let (output, animation) = if !animation.is_empty() {
+            (split.clone(), animation)
+        } else if split != options.output {
+            (options.output.clone(), animation)
+        } else {
+            (split.clone(), options.name.clone().unwrap_or_default())
+        };
+        let options = Options {
+            inputs: paths,
+            output,
+            preview_gif: options.preview_gif.as_deref().map(per_animation),
+            preview_html: options.preview_html.as_deref().map(per_animation),
+            preview_video: options.preview_video.as_deref().map(per_animation),
+            palette: options.palette.as_deref().map(per_animation),
+            split_by_animation: false,
+            animation: Some(animation),
+            cleanup: false,
+            ..options.clone()
+        };
+        // Each sheet's cache must only list its own outputs.
+        let mut part = Report::default();
+        let result = run(&options, &mut part);
+        up_to_date &= part.up_to_date;
+        report.inputs.extend(part.inputs);
+        report.skipped.extend(part.skipped);
+        report.empty.extend(part.empty);
+        report.duplicates.extend(part.duplicates);
+        report.similar.extend(part.similar);
+        report.padded.extend(part.padded);
+        report.gaps.extend(part.gaps);
+        report.outputs.extend(part.outputs);
+        report.sheet = part.sheet.or(report.sheet.take());
+        if let Some(part) = part.stats {
+            stats::merge(&mut report.stats, part);
+        }
+        report.timings.walk += part.timings.walk;
+        report.timings.decode += part.timings.decode;
+        report.timings.pack += part.timings.pack;
+        report.timings.composite += part.timings.composite;
+        report.timings.encode += part.timings.encode;
+        report.bytes.decode += part.bytes.decode;
+        report.bytes.composite += part.bytes.composite;
+        report.bytes.encode += part.bytes.encode;
+        result?;
+    }
+    report.up_to_date = up_to_date;
+    clean_up(options, report)
+}
+
+fn emit_written(options: &Options, written: &report::Output) {
+    options.events.emit(Event::OutputWritten {
+        path: written.path.clone(),
+        bytes: written.bytes,
+    });
+}
+
+/// Deletes the outputs a cancelled run had already moved into place, so it
+/// doesn't leave a sheet behind without its metadata. Files still being
+/// written are removed as they're dropped.
+fn discard_outputs(report: &mut Report) {
+    for written in report.outputs.drain(..) {
+        match fs::remove_file(&written.path) {
+            Ok(()) => info!("Removed {}", written.path.display()),
+            Err(err) => warn!("Couldn't remove {}: {}", written.path.display(), err),
+        }
+    }
+}
+
+/// Deletes the frames folder for `--cleanup`.
+fn clean_up(options: &Options, report: &mut Report) -> Result<()> {
+    if options.cleanup {
+        let frames = options.frames_dir();
+        fs::remove_dir_all(&frames).context(Phase::Cleanup, &frames)?;
+        info!("Removed {}", frames.display());
+        report.removed.push(frames);
+    }
+    Ok(())
+}
+
+/// `path`, unless it's outside the folder [`Assembler::confine`] allows.
+/// Files written next to it are in the same folder.
+fn confined(options: &Options, path: PathBuf) -> Result<PathBuf> {
+    match &options.confine {
+        Some(dir) if !paths::resolve(&path).starts_with(dir) => Err(ConfinementError {
+            path,
+            dir: dir.clone(),
+        }
+        .into()),
+        _ => Ok(path),
+    }
+}
+
+/// Fails early if the sheet or a sidecar can't be written.
+fn check_outputs(options: &Options, sheet: &Path) -> Result<()> {
+    if !options.metadata_only {
+        output::check(sheet, options.overwrite)?;
+    }
+    for path in sidecar_paths(options, sheet) {
+        output::check(&path, options.overwrite)?;
+    }
+    Ok(())
+}
+
+/// Files written next to the sheet at `sheet`.
+fn sidecar_paths(options: &Options, sheet: &Path) -> Vec<PathBuf> {
+    let mut paths = options
+        .metadata
+        .iter()
+        .map(|format| format.path(sheet))
+        .collect::<Vec<_>>();
+    if let Some(mask) = options.collision_mask {
+        paths.push(mask.path(sheet));
+    }
+    if options.debug_overlay {
+        paths.push(debug::path(sheet));
+    }
+    if options.number_frames {
+        paths.push(number::path(sheet));
+    }
+    if options.preview {
+        paths.push(preview::path(sheet));
+    }
+    if options.godot_scene {
+        paths.push(godot::path(sheet));
+    }
+    for pass in options
+        .passes
+        .iter()
+        .skip(1)
+        .filter(|_| !options.metadata_only)
+    {
+        paths.push(passes::path(sheet, pass));
+    }
+    if options.provenance {
+        paths.push(provenance::path(sheet));
+    }
+    if let Some(checksums) = options.checksums {
+        let covered = std::iter::once(sheet.to_path_buf())
+            .chain(paths.iter().cloned())
+            .collect::<Vec<_>>();
+        paths.extend(checksums.paths(sheet, &covered));
+    }
+    paths
+}
+
+/// Names each of `frames` with `--frame-name`. Fails if metadata is to be
+/// written and two frames get the same name, since it couldn't tell them
+/// apart.
+pub fn frame_names(options: &Options, frames: &[Frame]) -> Result<Vec<String>> {
+    let names = frames
+        .iter()
+        .enumerate()
+        .map(|(index, frame)| template::FrameTokens::new(frame, index).render(&options.frame_name))
+        .collect::<Result<Vec<_>, _>>()?;
+    if !options.metadata.is_empty() {
+        let mut named = HashMap::with_capacity(names.len());
+        for (index, name) in names.iter().enumerate() {
+            if let Some(first) = named.insert(name, index) {
+                return Err(DuplicateFrameNameError {
+                    template: options.frame_name.clone(),
+                    name: name.clone(),
+                    first: frames[first].relative.clone(),
+                    second: frames[index].relative.clone(),
+                }
+                .into());
+            }
+        }
+    }
+    Ok(names)
+}
+
+/// Describes where each frame on `page` ended up, for the metadata
+/// exporters, given every frame's `names`.
+pub fn atlas(
+    options: &Options,
+    frames: &[Frame],
+    names: &[String],
+    page: &layout::Page,
+    sheet: &Path,
+    dims: Dims,
+) -> Result<Atlas> {
+    let sequence = match &options.sequence {
+        Some(sequence) => sequence
+            .frames
+            .iter()
+            .map(|index| names[*index].clone())
+            .collect(),
+        None => Vec::new(),
+    };
+    // Once any frame has a pivot, they all need one.
+    let pivot = options.pivot.or_else(|| {
+        frames
+            .iter()
+            .any(|frame| frame.pivot.is_some())
+            .then_some(Anchor::Center)
+    });
+    let frames = &frames[page.first..][..page.count];
+    let mut metadata = frames
+        .iter()
+        .enumerate()
+        .map(|(cell, frame)| {
+            let (x, y) = layout::cell_position(page.cells[cell], page.tiles, dims);
+            let name = names[page.first + cell].clone();
+            let animation = animation(options, frame);
+            let nine_slice =
+                nine_slice::find(&options.nine_slices, options.nine_slice, &name, animation)
+                    .map(|insets| insets.check(&name, dims.x as u32, dims.y as u32))
+                    .transpose()?;
+            Ok(metadata::Frame {
+                name,
+                x,
+                y,
+                width: dims.x as u32,
+                height: dims.y as u32,
+                duration: frame.duration,
+                fps: timing::fps(options.fps, &options.animation_fps, animation),
+                events: timing::events(&options.markers, frame),
+                polygons: Vec::new(),
+                mesh: None,
+                boxes: Vec::new(),
+                opaque_bounds: None,
+                nine_slice,
+                pivot: match frame.pivot {
+                    Some((x, y)) => Some((x / dims.x as f64, y / dims.y as f64)),
+                    None => pivot.map(Anchor::fraction),
+                },
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if let Some(dir) = options.boxes_dir() {
+        let colours = if options.box_colours.is_empty() {
+            boxes::DEFAULT_COLOURS
+                .iter()
+                .map(|(label, colour)| (label.to_string(), *colour))
+                .collect()
+        } else {
+            options.box_colours.clone()
+        };
+        let pad = Some(options.pad_anchor).filter(|_| options.pad_to_max);
+        let tolerance = options.collision_tolerance;
+        boxes::find(&dir, &colours, frames, &mut metadata, pad, tolerance)?;
+    }
+    Ok(Atlas {
+        image: sheet
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        page: page.index,
+        pages: 1,
+        width: page.size.0,
+        height: page.size.1,
+        fps: options.fps,
+        frames: metadata,
+        tags: tags(options, frames),
+        sequence,
+        passes: Vec::new(),
+        trim: None,
+        embed_image: options.embed_sheet,
+    })
+}
+
+/// Parses a colour like `ff00ff` or `#FF00FF`.
+fn parse_colour(value: &str) -> Option<[u8; 3]> {
+    let hex = value.strip_prefix('#').unwrap_or(value);
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |at: usize| u8::from_str_radix(&hex[at..at + 2], 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// The animation `frame` belongs to, by the folder after its facing with
+/// [`facings`](Assembler::facings).
+fn animation<'a>(options: &Options, frame: &'a Frame) -> &'a str {
+    match facing::split(&frame.relative).filter(|_| options.facings) {
+        Some((_, folder)) => timing::split_direction(folder).0,
+        None => timing::animation(frame).0,
+    }
+}
+
+/// Groups consecutive frames from the same animation subfolder, or the same
+/// facing's animation, into tags.
+fn tags(options: &Options, frames: &[Frame]) -> Vec<metadata::Tag> {
+    let mut tags = Vec::<metadata::Tag>::new();
+    for (index, frame) in frames.iter().enumerate() {
+        let facing = facing::split(&frame.relative).filter(|_| options.facings);
+        let folder = match facing.map(|(_, animation)| animation) {
+            Some(folder) => folder,
+            None => match frame.animation() {
+                Some(folder) => folder,
+                None => continue,
+            },
+        };
+        let (animation, suffix) = timing::split_direction(folder);
+        let name = match facing {
+            Some((facing, _)) => format!("{}_{}", animation, facing),
+            None => animation.to_owned(),
+        };
+        match tags.last_mut() {
+            Some(tag) if tag.name == name && tag.to + 1 == index => tag.to = index,
+            _ => tags.push(metadata::Tag {
+                name,
+                view: facing.map(|(facing, _)| facing::View {
+                    animation: animation.to_owned(),
+                    facing: facing.to_owned(),
+                    angle: facing::angle(facing),
+                }),
+                from: index,
+                to: index,
+                direction: options
+                    .directions
+                    .iter()
+                    .find(|(name, _)| name == animation)
+                    .map(|(_, direction)| *direction)
+                    .or(suffix)
+                    .unwrap_or(Direction::Forward),
+                fps: timing::fps(options.fps, &options.animation_fps, animation),
+            }),
+        }
+    }
+    tags
+}
+
+/// Plans where `images` go: filling each row, or with
+/// [`facings`](Assembler::facings) a row for each facing's animation.
+pub fn plan(options: &Options, images: &[Frame], dims: Dims) -> Result<layout::Layout> {
+    if !options.facings {
+        return layout::plan(
+            images.len(),
+            dims,
+            options.max_size,
+            options.max_frames_per_page,
+        );
+    }
+    let mut rows = Vec::<(_, usize)>::new();
+    for frame in images {
+        let group = facing::split(&frame.relative).or_else(|| Some(("", frame.animation()?)));
+        match rows.last_mut() {
+            Some((last, length)) if *last == group => *length += 1,
+            _ => rows.push((group, 1)),
+        }
+    }
+    let rows = rows
+        .into_iter()
+        .map(|(_, length)| length)
+        .collect::<Vec<_>>();
+    let longest = rows.iter().copied().max().unwrap_or(0);
+    if let Some(frames) = options
+        .max_frames_per_page
+        .filter(|frames| *frames < longest)
+    {
+        return Err(OptionError {
+            name: "max-frames-per-page".to_owned(),
+            value: frames.to_string(),
+            expected: format!(
+                "at least {}, since --facings keeps each animation's row on one page",
+                longest
+            ),
+        }
+        .into());
+    }
+    layout::plan_rows(rows, dims, options.max_size, options.max_frames_per_page)
+}
+
+/// Copies every decoded frame into its cell of `base`, or of an empty sheet,
+/// the one `cells` gives it, filling rows of the sheet in parallel.
+pub fn composite(
+    base: Option<RgbaImage>,
+    frames: &[Frame],
+    cells: &[usize],
+    (width, height): (u32, u32),
+    tiles: Dims,
+    dims: Dims,
+    progress: &Progress,
+) -> RgbaImage {
+    let mut out: RgbaImage = base.unwrap_or_else(|| image::ImageBuffer::new(width, height));
+    let row_bytes = width as usize * 4;
+    let frame_row_bytes = dims.x * 4;
+    let mut grid = vec![None; tiles.x * tiles.y];
+    for (frame, cell) in frames.iter().zip(cells) {
+        grid[*cell] = Some(frame);
+    }
+    out.par_chunks_mut(row_bytes)
+        .enumerate()
+        .for_each(|(y, row)| {
+            let tile_row = y / dims.y;
+            let frame_y = y % dims.y;
+            for column in 0..tiles.x {
+                let frame = match grid.get(tile_row * tiles.x + column) {
+                    Some(Some(frame)) => frame,
+                    Some(None) => continue,
+                    None => break,
+                };
+                let pixels: &[u8] = match &frame.image {
+                    Some(image) => image,
+                    None => continue,
+                };
+                let source = &pixels[frame_y * frame_row_bytes..][..frame_row_bytes];
+                row[column * frame_row_bytes..][..frame_row_bytes].copy_from_slice(source);
+            }
+            progress.inc(1);
+        });
+    out
+}
+
+pub fn dims(images: &[Frame]) -> Result<Dims> {
+    let first = images.first().ok_or(NoImagesError)?;
+    let size = (first.width, first.height);
+    match images.iter().find(|next| (next.width, next.height) != size) {
+        None => Ok(Dims {
+            x: size.0 as usize,
+            y: size.1 as usize,
+        }),
+        Some(next) => Err(InconsistentSizeError {
+            first: first.path.clone(),
+            first_size: size,
+            path: next.path.clone(),
+            size: (next.width, next.height),
+        }
+        .into()),
+    }
+}
+
+/// The sheet an earlier run wrote for `page`, for `--metadata-only`,
+/// named by `output` with `tokens`.
+fn existing_sheet(
+    options: &Options,
+    cache: Option<&cache::Cache>,
+    page: &layout::Page,
+    tokens: OutputTokens,
+    output: &str,
+) -> Result<report::Output> {
+    let root = Path::new(&options.root);
+    let planned = root.join(tokens.render(output)?);
+    let path = if template::uses(output, "hash") {
+        // Only the cache knows which hash the sheet was named by.
+        cache
+            .into_iter()
+            .flat_map(|cache| &cache.outputs)
+            .find(|written| {
+                let tokens = OutputTokens {
+                    hash: written.sha256.get(..8),
+                    ..tokens
+                };
+                tokens
+                    .render(output)
+                    .is_ok_and(|name| root.join(name) == written.path)
+            })
+            .map_or_else(|| planned.clone(), |written| written.path.clone())
+    } else {
+        planned
+    };
+    if !path.is_file() {
+        return Err(MissingSheetError {
+            path,
+            reason: "doesn't exist".to_owned(),
+        }
+        .into());
+    }
+    let size = image::image_dimensions(&path).map_err(|source| AssemblerError::Image {
+        source,
+        path: Some(path.clone()),
+    })?;
+    if size != page.size {
+        return Err(MissingSheetError {
+            reason: format!(
+                "is {}x{}px, but the frames need {}x{}px",
+                size.0, size.1, page.size.0, page.size.1
+            ),
+            path,
+        }
+        .into());
+    }
+    let bytes = fs::read(&path).context(Phase::Read, &path)?;
+    Ok(report::Output {
+        bytes: bytes.len() as u64,
+        sha256: report::sha256(&bytes),
+        path,
+    })
+}
+
+/// An option that needs the frames' pixels, with its value when it's set.
+struct Decoding {
+    name: &'static str,
+    value: fn(&Options) -> Option<String>,
+    /// Whether `--metadata-only` refuses it too, rather than only
+    /// `--low-memory`: the sizes it describes don't depend on the pixels.
+    metadata_only: bool,
+}
+
+/// The options `--low-memory` and `--metadata-only` can't honour, since
+/// they never decode the frames.
+const DECODING: &[Decoding] = &[
+    Decoding {
+        name: "filter-chain",
+        value: |options| {
+            Some(filter::chain(&options.filters)).filter(|_| !options.filters.is_empty())
+        },
+        metadata_only: false,
+    },
+    Decoding {
+        name: "pad-to-max",
+        value: |options| Some("1".to_owned()).filter(|_| options.pad_to_max),
+        metadata_only: false,
+    },
+    Decoding {
+        name: "trim",
+        value: |options| options.trim.map(|trim| trim.name().to_owned()),
+        metadata_only: true,
+    },
+    Decoding {
+        name: "pivot-marker",
+        value: |options| {
+            let colour = options.pivot_marker?;
+            Some(format!(
+                "{:02x}{:02x}{:02x}",
+                colour[0], colour[1], colour[2]
+            ))
+        },
+        metadata_only: true,
+    },
+    Decoding {
+        name: "empty-frames",
+        value: |options| options.empty_frames.map(|policy| policy.name().to_owned()),
+        metadata_only: true,
+    },
+    Decoding {
+        name: "find-duplicates",
+        value: |options| Some("1".to_owned()).filter(|_| options.find_duplicates),
+        metadata_only: true,
+    },
+];
+
+/// The first option set that needs the frames decoded, and its value,
+/// counting only those `--metadata-only` refuses if `metadata_only`.
+fn decoding(options: &Options, metadata_only: bool) -> Option<(&'static str, String)> {
+    DECODING
+        .iter()
+        .filter(|decoding| decoding.metadata_only || !metadata_only)
+        .find_map(|decoding| Some((decoding.name, (decoding.value)(options)?)))
+}
+
+/// Whether the frames can be streamed from disk, which filters and frame
+/// checks can't be.
+fn streamable(options: &Options) -> bool {
+    decoding(options, false).is_none()
+}
+
+/// Whether to stream the frames for `--resume`, which only saves the
+/// progress of streamed runs.
+fn streams_to_resume(options: &Options) -> bool {
+    if !options.resume {
+        return false;
+    }
+    if !streamable(options) {
+        warn!("Not saving progress for --resume, since filters and frame checks need the frames decoded");
+        return false;
+    }
+    debug!("Streaming the frames so the run can be resumed");
+    true
+}
+
+/// Whether an interrupted run already wrote the sheet planned at `path`.
+fn resumed(options: &Options, path: &Path) -> bool {
+    options
+        .checkpoint
+        .as_ref()
+        .is_some_and(|checkpoint| checkpoint.sheet(path).is_some())
+}
+
+/// Whether decoding `paths` up front would go over `--max-memory`, judging
+/// by the first frame's size.
+fn over_budget(options: &Options, paths: &[PathBuf]) -> bool {
+    let (budget, first) = match (options.max_memory, paths.first()) {
+        (Some(budget), Some(first)) if streamable(options) => (budget, first),
+        (Some(_), Some(_)) => {
+            debug!(
+                "Not checking --max-memory, since filters and frame checks need the frames decoded"
+            );
+            return false;
+        }
+        _ => return false,
+    };
+    let (width, height) = match image::image_dimensions(first) {
+        Ok(size) => size,
+        Err(err) => {
+            debug!(
+                "Can't estimate memory use from {}: {}",
+                first.display(),
+                err
+            );
+            return false;
+        }
+    };
+    let dims = Dims {
+        x: width as usize,
+        y: height as usize,
+    };
+    let columns = layout::plan(
+        paths.len(),
+        dims,
+        options.max_size,
+        options.max_frames_per_page,
+    )
+    .map(|layout| layout.tiles.x)
+    .unwrap_or(paths.len());
+    let frame_bytes = u64::from(width) * u64::from(height) * 4;
+    // The decoded frames, plus sheets holding a copy of each.
+    let estimate = 2 * paths.len() as u64 * frame_bytes;
+    if estimate <= budget {
+        return false;
+    }
+    warn!(
+        "Assembling in memory needs about {}, over the {} budget; switching to --low-memory",
+        progress::format_bytes(estimate),
+        progress::format_bytes(budget)
+    );
+    // A row of the sheet, plus a decoder holding two frame rows and a
+    // deflate window for every column.
+    let streaming = columns as u64 * (u64::from(width) * 4 * 3 + (40 << 10));
+    if streaming > budget {
+        warn!(
+            "Even --low-memory needs about {}, so the budget will likely be exceeded",
+            progress::format_bytes(streaming)
+        );
+    }
+    true
+}
+
+/// Writes each page of the render pass `pass` next to the first pass's
+/// sheet of that page in `sheets`, copying the pass's file of each of
+/// `images` into the frame's cell.
+fn save_pass(
+    options: &Options,
+    pass: &str,
+    images: &[Frame],
+    pages: &[layout::Page],
+    sheets: &[PathBuf],
+    dims: Dims,
+    trimmed: Option<Trimmed>,
+) -> Result<Vec<report::Output>> {
+    let encoded = Progress::bytes("Encoding")
+        .emitting(&options.events, |bytes| Event::BytesWritten { bytes });
+    // A page at a time, so only one page of the pass is ever decoded.
+    let written = pages
+        .iter()
+        .zip(sheets)
+        .map(|(page, sheet)| {
+            let path = passes::path(sheet, pass);
+            let mut frames = images[page.first..][..page.count]
+                .iter()
+                .map(|frame| Frame {
+                    path: passes::counterpart(&options.passes, &frame.path, pass),
+                    sha256: String::new(),
+                    relative: frame.relative.clone(),
+                    // Trimmed once decoded, like the first pass's frames.
+                    width: trimmed.map_or(frame.width, |trimmed| trimmed.source.0),
+                    height: trimmed.map_or(frame.height, |trimmed| trimmed.source.1),
+                    image: None,
+                    duration: frame.duration,
+                    pivot: None,
+                })
+                .collect::<Vec<_>>();
+            if options.low_memory {
+                return stream::save_png(&frames, page, dims, options, &path, &encoded, |_| {
+                    Ok(path.clone())
+                });
+            }
+            frames
+                .par_iter_mut()
+                .zip(&images[page.first..])
+                .map(|(frame, first)| {
+                    // Smaller ones are padded like the first pass's frames.
+                    let cell = (frame.width, frame.height);
+                    let fits = |(width, height): (u32, u32)| {
+                        if options.pad_to_max {
+                            width <= cell.0 && height <= cell.1
+                        } else {
+                            (width, height) == cell
+                        }
+                    };
+                    match image::image_dimensions(&frame.path) {
+                        Ok(size) if !fits(size) => Err(InconsistentSizeError {
+                            first: first.path.clone(),
+                            first_size: cell,
+                            path: frame.path.clone(),
+                            size,
+                        }
+                        .into()),
+                        _ => collect::decode(frame, options),
+                    }?;
+                    if let Some(trimmed) = trimmed {
+                        frame.image = frame.image.as_ref().map(|image| trimmed.crop(image));
+                        frame.width = trimmed.width;
+                        frame.height = trimmed.height;
+                    }
+                    Ok(())
+                })
+                .collect::<Result<Vec<()>>>()?;
+            options.cancel.check()?;
+            let progress = Progress::new("Compositing", u64::from(page.size.1));
+            let (size, tiles) = (page.size, page.tiles);
+            let image = composite(None, &frames, &page.cells, size, tiles, dims, &progress);
+            progress.finish();
+            save_png(
+                &image,
+                options.compression,
+                &path,
+                options.overwrite,
+                &encoded,
+                &options.cancel,
+                |_| Ok(path.clone()),
+            )
+        })
+        .collect::<Result<Vec<_>>>()?;
+    encoded.finish();
+    Ok(written)
+}
+
+/// Encodes `img` next to `near`, then moves it to the path `name` picks
+/// given the SHA-256 of the encoded file.
+fn save_png(
+    img: &RgbaImage,
+    compression: Compression,
+    near: &Path,
+    overwrite: Overwrite,
+    progress: &Progress,
+    cancel: &CancelHandle,
+    name: impl FnOnce(&str) -> Result<PathBuf>,
+) -> Result<report::Output> {
+    let mut pending = output::PendingFile::create(near)?;
+    let writer = CancellableWriter::new(pending.writer(), cancel);
+    let mut writer = HashingWriter::new(ProgressWriter::new(writer, progress));
+    encode::encoder(&mut writer, img.dimensions(), compression)
+        .write_header()?
+        .write_image_data(img)?;
+    let (_, bytes, sha256) = writer.finish();
+    let path = name(&sha256)?;
+    pending.persist(&path, overwrite)?;
+    Ok(report::Output {
+        path,
+        bytes,
+        sha256,
+    })
+}
@@ -0,0 +1,126 @@
+//! `assembler batch JOBS`: many assemblies in one process. The jobs run side
+//! by side on the one thread pool, so a project with dozens of characters
+//! doesn't start a process, and a pool, per sheet.
+//!
+//! The jobs file is a JSON array, or a TOML file of `[[jobs]]` tables, of
+//! the same `{"root": DIR, "options": {...}}` specs the JSON-RPC `assemble`
+//! method takes. Relative roots are relative to the jobs file.
+
+use log::{error, info};
+use rayon::prelude::*;
+use serde_json::Value;
+use spritesheet_assembler::{disable_progress, exit, Assembler, Report};
+use std::{
+    error, fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{serve, BoxResult, Reporting};
+
+/// Some of the jobs failed. `code` is the exit code of the first of them.
+#[derive(Debug, Clone)]
+pub struct BatchError {
+    pub failed: usize,
+    pub total: usize,
+    pub code: i32,
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} of {} jobs failed", self.failed, self.total)
+    }
+}
+
+impl error::Error for BatchError {}
+
+/// The jobs file couldn't be read as a list of jobs, or one of them is
+/// invalid.
+#[derive(Debug, Clone)]
+pub struct JobsFileError {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl fmt::Display for JobsFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.message)
+    }
+}
+
+impl error::Error for JobsFileError {}
+
+/// Runs every job in the file at `path`. Every spec is checked before any
+/// job starts, and a failed job doesn't stop the others.
+pub fn run(path: &Path, reporting: Reporting) -> BoxResult<()> {
+    let invalid = |message: String| JobsFileError {
+        path: path.to_path_buf(),
+        message,
+    };
+    let text = fs::read_to_string(path).map_err(|err| invalid(err.to_string()))?;
+    let specs = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => toml::from_str::<Value>(&text).map_err(|err| err.to_string()),
+        _ => serde_json::from_str::<Value>(&text).map_err(|err| err.to_string()),
+    }
+    .map_err(invalid)?;
+    let specs = match specs {
+        Value::Array(specs) => specs,
+        Value::Object(mut table) => match table.remove("jobs") {
+            Some(Value::Array(specs)) => specs,
+            _ => return Err(invalid("there's no [[jobs]] list".to_owned()).into()),
+        },
+        _ => return Err(invalid("it should hold a list of jobs".to_owned()).into()),
+    };
+    let base = path.parent().unwrap_or_else(|| Path::new(""));
+    let jobs = specs
+        .into_iter()
+        .enumerate()
+        .map(|(index, mut spec)| {
+            if let Some(root) = spec.get("root").and_then(Value::as_str) {
+                spec["root"] = Value::from(base.join(root).to_string_lossy());
+            }
+            serve::assembler(&spec, &serve::Access::default())
+                .map_err(|err| invalid(format!("job {}: {}", index + 1, err)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    crate::cancel_on_signal(jobs.iter().map(Assembler::cancel_handle).collect())?;
+    // Bars for jobs running at the same time would draw over each other.
+    disable_progress();
+    info!("Running {} jobs", jobs.len());
+    let results = jobs
+        .par_iter()
+        .map(|assembler| {
+            let mut report = Report::default();
+            let result = assembler.run_with(&mut report);
+            match &result {
+                Ok(()) => info!("Assembled {}", assembler.root().display()),
+                Err(err) => error!("{}: {}", assembler.root().display(), err),
+            }
+            (report, result)
+        })
+        .collect::<Vec<_>>();
+
+    if reporting.timings {
+        for (report, _) in &results {
+            report.print_timings();
+        }
+    }
+    if reporting.json {
+        let reports = results.iter().map(|(report, _)| report).collect::<Vec<_>>();
+        serde_json::to_writer_pretty(io::stdout(), &reports)?;
+        println!();
+    }
+    let failures = results
+        .iter()
+        .filter_map(|(_, result)| result.as_ref().err())
+        .collect::<Vec<_>>();
+    match failures.first() {
+        None => Ok(()),
+        Some(first) => Err(BatchError {
+            failed: failures.len(),
+            total: results.len(),
+            code: exit::code(first),
+        }
+        .into()),
+    }
+}
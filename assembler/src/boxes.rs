@@ -0,0 +1,134 @@
+//! `--boxes`: hitboxes, hurtboxes and the like, painted in flat colours on
+//! a separate render pass, such as an emission-only view layer in Blender.
+//! The pass's frames mirror the frames folder, and each part painted in a
+//! labelled colour becomes a box with that label, its bounding rectangle
+//! and its outline.
+
+use image::RgbaImage;
+use log::debug;
+use rayon::prelude::*;
+use std::path::Path;
+
+use crate::{
+    collect::Frame,
+    collision::{self, Point, ALPHA_THRESHOLD},
+    errors::InconsistentSizeError,
+    metadata,
+    pad::Anchor,
+    AssemblerError, Result,
+};
+
+/// The labels and colours used unless others are given: red hitboxes and
+/// blue hurtboxes.
+pub const DEFAULT_COLOURS: &[(&str, [u8; 3])] =
+    &[("hitbox", [255, 0, 0]), ("hurtbox", [0, 0, 255])];
+
+/// How far each channel may be from a label's colour, so slightly
+/// antialiased or colour-managed passes still match.
+const TOLERANCE: u8 = 48;
+
+/// One labelled part of a frame, in pixels from its top left.
+pub struct HitBox {
+    pub label: String,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub polygon: Vec<Point>,
+}
+
+/// Finds the boxes of each of `frames` on its counterpart in the pass at
+/// `dir`, moved by the frame's padding if `pad` says where it goes, and
+/// outlined like the collision shapes to within `tolerance`. Frames the
+/// pass doesn't have get no boxes.
+pub fn find(
+    dir: &Path,
+    colours: &[(String, [u8; 3])],
+    frames: &[Frame],
+    metadata: &mut [metadata::Frame],
+    pad: Option<Anchor>,
+    tolerance: f64,
+) -> Result<()> {
+    frames
+        .par_iter()
+        .zip(metadata)
+        .map(|(frame, metadata)| {
+            let path = dir.join(&frame.relative);
+            if !path.is_file() {
+                debug!("No boxes for {}", frame.relative.display());
+                return Ok(());
+            }
+            let pass = image::open(&path)
+                .map_err(|source| AssemblerError::Image {
+                    source,
+                    path: Some(path.clone()),
+                })?
+                .to_rgba();
+            let cell = (metadata.width, metadata.height);
+            let size = pass.dimensions();
+            let offset = match pad {
+                _ if size == cell => (0, 0),
+                Some(anchor) if size.0 <= cell.0 && size.1 <= cell.1 => anchor.offset(size, cell),
+                _ => {
+                    return Err(InconsistentSizeError {
+                        first: frame.path.clone(),
+                        first_size: cell,
+                        path,
+                        size,
+                    }
+                    .into())
+                }
+            };
+            metadata.boxes = colours
+                .iter()
+                .flat_map(|(label, colour)| {
+                    outlines(&pass, *colour)
+                        .into_iter()
+                        .map(move |outline| (label, outline))
+                })
+                .map(|(label, outline)| {
+                    let moved = |(x, y): Point| (x + offset.0, y + offset.1);
+                    let (left, top) = outline.iter().fold((u32::MAX, u32::MAX), |min, point| {
+                        (min.0.min(point.0), min.1.min(point.1))
+                    });
+                    let (right, bottom) = outline.iter().fold((0, 0), |max, point| {
+                        (max.0.max(point.0), max.1.max(point.1))
+                    });
+                    let (x, y) = moved((left, top));
+                    // Small boxes keep their corners rather than collapse.
+                    let mut polygon = collision::simplify(&outline, tolerance);
+                    if polygon.len() < 3 {
+                        polygon = outline.clone();
+                    }
+                    HitBox {
+                        label: label.clone(),
+                        x,
+                        y,
+                        width: right - left,
+                        height: bottom - top,
+                        polygon: polygon.into_iter().map(moved).collect(),
+                    }
+                })
+                .collect();
+            Ok(())
+        })
+        .collect::<Result<Vec<()>>>()?;
+    Ok(())
+}
+
+/// The outlines of the parts of `pass` painted `colour`.
+fn outlines(pass: &RgbaImage, colour: [u8; 3]) -> Vec<Vec<Point>> {
+    let (width, height) = pass.dimensions();
+    collision::outlines(width, height, |x, y| {
+        if x < 0 || y < 0 || x >= i64::from(width) || y >= i64::from(height) {
+            return false;
+        }
+        let pixel = pass.get_pixel(x as u32, y as u32);
+        pixel[3] >= ALPHA_THRESHOLD
+            && pixel
+                .0
+                .iter()
+                .zip(&colour)
+                .all(|(channel, wanted)| channel.abs_diff(*wanted) <= TOLERANCE)
+    })
+}
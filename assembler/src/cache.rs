@@ -0,0 +1,307 @@
+//! Remembers what the last sheet was built from, so a re-run can skip the
+//! work entirely when nothing changed, or else only decode and redraw the
+//! cells whose frames did.
+
+use image::RgbaImage;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use crate::{
+    collect::Frame,
+    filter,
+    output::{self, Overwrite},
+    pad::Anchor,
+    report, timing, Options, Result,
+};
+
+const FILE_NAME: &str = ".spritesheet-cache.json";
+
+/// Options that don't change what gets written.
+const IGNORED_OPTIONS: &[&str] = &[
+    "overwrite",
+    "cleanup",
+    "cache",
+    "resume",
+    "metadata_only",
+    "wait",
+    "gpu",
+];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Cache {
+    /// Assembler version that wrote the cache; any other version ignores it.
+    version: String,
+    /// The output template, so runs writing different sheets from one root
+    /// don't reuse each other's work.
+    output: String,
+    /// Hash of the options and of every input's size and modification time.
+    fingerprint: Option<String>,
+    /// The filter chain the cells were drawn with.
+    filters: String,
+    /// Where smaller frames were placed in their cells, if they were padded.
+    #[serde(default)]
+    pad: Option<Anchor>,
+    pub sheet: PathBuf,
+    sheet_sha256: String,
+    pub layout: report::Sheet,
+    /// SHA-256 of the frame drawn in each cell.
+    frames: Vec<String>,
+    /// Every file the run wrote, starting with the sheet.
+    pub outputs: Vec<report::Output>,
+}
+
+/// Where the cache for `options` is kept. Each animation split off with
+/// `--split-by-animation` has its own.
+pub fn path(options: &Options) -> PathBuf {
+    let root = Path::new(&options.root);
+    match options.animation.as_deref() {
+        Some(animation) if !animation.is_empty() => {
+            root.join(format!(".spritesheet-cache-{}.json", animation))
+        }
+        _ => root.join(FILE_NAME),
+    }
+}
+
+/// The cache from the previous run, if there is a usable one.
+pub fn load(options: &Options) -> Option<Cache> {
+    let path = path(options);
+    let cache = fs::read(&path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice::<Cache>(&bytes).ok())?;
+    if cache.version != env!("CARGO_PKG_VERSION")
+        || cache.output != options.output
+        || cache.filters != filter::chain(&options.filters)
+        || cache.pad != pad(options)
+    {
+        debug!("Ignoring stale cache {}", path.display());
+        return None;
+    }
+    Some(cache)
+}
+
+/// Identifies a run's settings and inputs without reading any frames, or
+/// `None` if an input can't be examined.
+pub fn fingerprint(options: &Options, paths: &[PathBuf]) -> Option<String> {
+    let mut settings = serde_json::to_value(options).ok()?;
+    if let Some(settings) = settings.as_object_mut() {
+        for name in IGNORED_OPTIONS {
+            settings.remove(*name);
+        }
+    }
+    let inputs = paths
+        .iter()
+        .map(|path| {
+            let metadata = fs::metadata(path).ok()?;
+            let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+            Some((
+                path,
+                metadata.len(),
+                modified.as_secs(),
+                modified.subsec_nanos(),
+            ))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    // Editing only the durations sidecar changes the metadata too.
+    let durations = fs::metadata(options.frames_dir().join(timing::SIDECAR))
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|modified| (modified.as_secs(), modified.subsec_nanos()));
+    // As does repainting the boxes.
+    let boxes = options.boxes_dir().map(|dir| {
+        let frames = options.frames_dir();
+        paths
+            .iter()
+            .map(|path| {
+                let relative = path.strip_prefix(&frames).unwrap_or(path);
+                let metadata = fs::metadata(dir.join(relative)).ok()?;
+                let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+                Some((metadata.len(), modified.as_secs(), modified.subsec_nanos()))
+            })
+            .collect::<Vec<_>>()
+    });
+    let bytes = serde_json::to_vec(&(settings, inputs, durations, boxes)).ok()?;
+    Some(report::sha256(&bytes))
+}
+
+/// Where `options` pads smaller frames, if it does.
+fn pad(options: &Options) -> Option<Anchor> {
+    Some(options.pad_anchor).filter(|_| options.pad_to_max)
+}
+
+impl Cache {
+    /// Records a run; `outputs` must start with the sheet.
+    pub fn new(
+        options: &Options,
+        fingerprint: Option<String>,
+        layout: &report::Sheet,
+        frames: &[Frame],
+        outputs: &[report::Output],
+    ) -> Self {
+        Cache {
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            output: options.output.clone(),
+            fingerprint,
+            filters: filter::chain(&options.filters),
+            pad: pad(options),
+            sheet: outputs[0].path.clone(),
+            sheet_sha256: outputs[0].sha256.clone(),
+            layout: layout.clone(),
+            frames: frames.iter().map(|frame| frame.sha256.clone()).collect(),
+            outputs: outputs.to_vec(),
+        }
+    }
+
+    /// Whether a run with this fingerprint would write exactly what is
+    /// already on disk. Every output is hashed again, so one edited or
+    /// corrupted in place, even at the same size, gets written afresh.
+    pub fn up_to_date(&self, fingerprint: &Option<String>) -> bool {
+        fingerprint.is_some()
+            && *fingerprint == self.fingerprint
+            && self.outputs.iter().all(|output| {
+                let unchanged = fs::metadata(&output.path)
+                    .is_ok_and(|metadata| metadata.len() == output.bytes)
+                    && fs::read(&output.path)
+                        .is_ok_and(|bytes| report::sha256(&bytes) == output.sha256);
+                if !unchanged {
+                    debug!("{} changed since it was cached", output.path.display());
+                }
+                unchanged
+            })
+    }
+
+    /// The size of a frame with this hash, if it was part of the last sheet.
+    pub fn frame_size(&self, sha256: &str) -> Option<(u32, u32)> {
+        if self.frames.iter().any(|cached| cached == sha256) {
+            Some((self.layout.tile_width, self.layout.tile_height))
+        } else {
+            None
+        }
+    }
+
+    /// Whether the cell at `index` of the last sheet already shows this frame.
+    pub fn unchanged(&self, index: usize, sha256: &str) -> bool {
+        self.frames.get(index).map(String::as_str) == Some(sha256)
+    }
+
+    /// The last sheet, if it had the same layout and hasn't been touched since.
+    pub fn sheet(&self, layout: &report::Sheet) -> Option<RgbaImage> {
+        if self.layout != *layout {
+            return None;
+        }
+        let bytes = fs::read(&self.sheet).ok()?;
+        if report::sha256(&bytes) != self.sheet_sha256 {
+            debug!("{} changed since it was cached", self.sheet.display());
+            return None;
+        }
+        match image::load_from_memory(&bytes).ok()? {
+            image::ImageRgba8(sheet) => Some(sheet),
+            _ => None,
+        }
+    }
+
+    pub fn save(&self, options: &Options) -> Result<()> {
+        output::write_atomic(&path(options), Overwrite::Force, |out| {
+            Ok(serde_json::to_writer(out, self)?)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A cache recording `outputs` with `fingerprint`.
+    fn cache(fingerprint: &str, outputs: Vec<report::Output>) -> Cache {
+        Cache {
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            output: "out.png".to_owned(),
+            fingerprint: Some(fingerprint.to_owned()),
+            filters: String::new(),
+            pad: None,
+            sheet: outputs[0].path.clone(),
+            sheet_sha256: outputs[0].sha256.clone(),
+            layout: report::Sheet {
+                width: 2,
+                height: 1,
+                tile_width: 1,
+                tile_height: 1,
+                columns: 2,
+                rows: 1,
+                frames: 2,
+                pages: 1,
+                fallback: None,
+            },
+            frames: Vec::new(),
+            outputs,
+        }
+    }
+
+    /// A folder of a test's own, removed once the test is done with it.
+    struct Scratch(PathBuf);
+
+    impl Scratch {
+        fn new(test: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "spritesheet-cache-{}-{}",
+                test,
+                std::process::id()
+            ));
+            fs::create_dir_all(&dir).unwrap();
+            Scratch(dir)
+        }
+
+        /// Writes `bytes` to `name` and records it.
+        fn written(&self, name: &str, bytes: &[u8]) -> report::Output {
+            let path = self.0.join(name);
+            fs::write(&path, bytes).unwrap();
+            report::Output {
+                path,
+                bytes: bytes.len() as u64,
+                sha256: report::sha256(bytes),
+            }
+        }
+    }
+
+    impl Drop for Scratch {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn up_to_date_when_fingerprint_and_outputs_match() {
+        let scratch = Scratch::new("same");
+        let cache = cache("abc", vec![scratch.written("same.png", b"sheet")]);
+        assert!(cache.up_to_date(&Some("abc".to_owned())));
+    }
+
+    #[test]
+    fn not_up_to_date_with_other_or_no_fingerprint() {
+        let scratch = Scratch::new("fingerprint");
+        let cache = cache("abc", vec![scratch.written("fingerprint.png", b"sheet")]);
+        assert!(!cache.up_to_date(&Some("abd".to_owned())));
+        assert!(!cache.up_to_date(&None));
+    }
+
+    #[test]
+    fn not_up_to_date_once_an_output_is_missing() {
+        let scratch = Scratch::new("missing");
+        let output = scratch.written("missing.png", b"sheet");
+        fs::remove_file(&output.path).unwrap();
+        assert!(!cache("abc", vec![output]).up_to_date(&Some("abc".to_owned())));
+    }
+
+    #[test]
+    fn not_up_to_date_once_an_output_is_edited_at_the_same_size() {
+        let scratch = Scratch::new("edited");
+        let output = scratch.written("edited.png", b"sheet");
+        fs::write(&output.path, b"shoot").unwrap();
+        assert!(!cache("abc", vec![output]).up_to_date(&Some("abc".to_owned())));
+    }
+}
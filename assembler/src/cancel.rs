@@ -0,0 +1,66 @@
+//! Stopping a run from another thread, for Ctrl+C and the daemon's `cancel`
+//! method.
+
+use std::{
+    io::{self, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use crate::{errors::AssemblerError, Result};
+
+/// Cancels the runs of an [`Assembler`](crate::Assembler) from another
+/// thread. Clones share the same flag, and once set it stays set, so every
+/// later run of that assembler is cancelled straight away too.
+#[derive(Debug, Clone, Default)]
+pub struct CancelHandle(Arc<AtomicBool>);
+
+impl CancelHandle {
+    /// Asks the run to stop. It finishes the step it's on, deletes whatever
+    /// it had written and fails with [`AssemblerError::Cancelled`].
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Fails if the run has been cancelled, to be called between steps.
+    pub(crate) fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            Err(AssemblerError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Passes writes through to `inner` until the run is cancelled, so encoding
+/// a large sheet stops part way.
+pub struct CancellableWriter<'a, W> {
+    inner: W,
+    cancel: &'a CancelHandle,
+}
+
+impl<'a, W: Write> CancellableWriter<'a, W> {
+    pub fn new(inner: W, cancel: &'a CancelHandle) -> Self {
+        CancellableWriter { inner, cancel }
+    }
+}
+
+impl<'a, W: Write> Write for CancellableWriter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.cancel.is_cancelled() {
+            // Not `Interrupted`, which `write_all` would retry forever.
+            return Err(io::Error::other("the run was cancelled"));
+        }
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,260 @@
+//! `--resume`: a long streamed assembly saves its progress as it goes, so a
+//! run cut short, say by a render farm node going down, picks up where it
+//! stopped instead of starting over from the first frame. Each frame's size
+//! and hash are kept once read, each row of tiles once composited, and each
+//! sheet once written, in a folder in the root that a finished run deletes.
+
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::{
+    encode::{self, Compression},
+    errors::{AssemblerError, IoContext, Phase},
+    output::{self, Overwrite},
+    report, Options, Result,
+};
+
+const DIR_NAME: &str = ".spritesheet-checkpoint";
+const STATE: &str = "state.json";
+
+/// How many frames to read between saves.
+const SAVE_EVERY: usize = 256;
+
+/// A frame's size, file length and SHA-256.
+type Probe = ((u32, u32), u64, String);
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct State {
+    /// Assembler version that wrote the checkpoint; any other version
+    /// starts over.
+    version: String,
+    /// The run's settings and inputs, as the cache fingerprints them.
+    fingerprint: String,
+    /// What was read of each frame, by path.
+    frames: BTreeMap<PathBuf, Probe>,
+    /// The rows of tiles already composited, by the sheet they're part of.
+    bands: BTreeMap<PathBuf, Vec<usize>>,
+    /// Sheets written in full, by the path they were planned at.
+    sheets: BTreeMap<PathBuf, report::Output>,
+}
+
+/// The progress of a run, shared by the threads reading its frames.
+#[derive(Debug)]
+pub struct Checkpoint {
+    dir: PathBuf,
+    /// The state, and how many frames have been read since it was saved.
+    state: Mutex<(State, usize)>,
+}
+
+/// Where the checkpoint for `options` is kept. Each animation split off
+/// with `--split-by-animation` has its own.
+pub fn path(options: &Options) -> PathBuf {
+    let root = Path::new(&options.root);
+    match options.animation.as_deref() {
+        Some(animation) if !animation.is_empty() => {
+            root.join(format!("{}-{}", DIR_NAME, animation))
+        }
+        _ => root.join(DIR_NAME),
+    }
+}
+
+impl Checkpoint {
+    /// Picks up the checkpoint an interrupted run with the same
+    /// `fingerprint` left, or starts a new one.
+    pub fn open(options: &Options, fingerprint: &str) -> Result<Self> {
+        let dir = path(options);
+        let state = fs::read(dir.join(STATE))
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<State>(&bytes).ok())
+            .filter(|state| {
+                state.version == env!("CARGO_PKG_VERSION") && state.fingerprint == fingerprint
+            });
+        let state = match state {
+            Some(state) => {
+                info!(
+                    "Resuming from {}: {} frames read, {} rows of tiles and {} sheets done",
+                    dir.display(),
+                    state.frames.len(),
+                    state.bands.values().map(Vec::len).sum::<usize>(),
+                    state.sheets.len()
+                );
+                state
+            }
+            None => {
+                if dir.exists() {
+                    debug!("Discarding stale checkpoint {}", dir.display());
+                    fs::remove_dir_all(&dir).context(Phase::Write, &dir)?;
+                }
+                fs::create_dir_all(&dir).context(Phase::Write, &dir)?;
+                State {
+                    version: env!("CARGO_PKG_VERSION").to_owned(),
+                    fingerprint: fingerprint.to_owned(),
+                    ..State::default()
+                }
+            }
+        };
+        let checkpoint = Checkpoint {
+            dir,
+            state: Mutex::new((state, 0)),
+        };
+        checkpoint.save()?;
+        Ok(checkpoint)
+    }
+
+    /// What an earlier run read of the frame at `path`.
+    pub fn probe(&self, path: &Path) -> Option<Probe> {
+        self.lock().0.frames.get(path).cloned()
+    }
+
+    /// Keeps what was read of the frame at `path`, saving every so often.
+    /// Failing to save only means more is read again after an interruption,
+    /// so it's just logged.
+    pub fn record(&self, path: &Path, probe: &Probe) {
+        let mut state = self.lock();
+        state.0.frames.insert(path.to_path_buf(), probe.clone());
+        state.1 += 1;
+        if state.1 >= SAVE_EVERY {
+            if let Err(err) = self.write(&mut state) {
+                warn!("Couldn't save the checkpoint: {}", err);
+            }
+        }
+    }
+
+    /// The sheet planned at `near`, if an earlier run wrote it and it's
+    /// still there.
+    pub fn sheet(&self, near: &Path) -> Option<report::Output> {
+        let written = self.lock().0.sheets.get(near).cloned()?;
+        let bytes = fs::metadata(&written.path).ok()?.len();
+        Some(written).filter(|written| written.bytes == bytes)
+    }
+
+    /// Records that the sheet planned at `near` was written, and forgets
+    /// its rows of tiles.
+    pub fn finish_sheet(&self, near: &Path, written: &report::Output) -> Result<()> {
+        let mut state = self.lock();
+        state.0.sheets.insert(near.to_path_buf(), written.clone());
+        for row in state.0.bands.remove(near).unwrap_or_default() {
+            let _ = fs::remove_file(self.band_path(near, row));
+        }
+        self.write(&mut state)
+    }
+
+    /// The pixels of the row of tiles `row` of the sheet planned at `near`,
+    /// if an earlier run composited it. One that can't be read is just
+    /// composited again.
+    pub fn band(&self, near: &Path, row: usize) -> Option<Vec<u8>> {
+        if !self.lock().0.bands.get(near)?.contains(&row) {
+            return None;
+        }
+        let path = self.band_path(near, row);
+        let read = || -> Result<Vec<u8>> {
+            let file = File::open(&path).context(Phase::Read, &path)?;
+            let mut reader = png::Decoder::new(BufReader::new(file))
+                .read_info()
+                .map_err(|source| AssemblerError::Png {
+                    path: Some(path.clone()),
+                    source,
+                })?;
+            let mut pixels = vec![0; reader.output_buffer_size().unwrap_or_default()];
+            reader
+                .next_frame(&mut pixels)
+                .map_err(|source| AssemblerError::Png {
+                    path: Some(path.clone()),
+                    source,
+                })?;
+            Ok(pixels)
+        };
+        match read() {
+            Ok(pixels) => Some(pixels),
+            Err(err) => {
+                warn!("Compositing row {} of tiles again: {}", row, err);
+                None
+            }
+        }
+    }
+
+    /// Starts saving the row of tiles `row` of the sheet planned at `near`,
+    /// `size` pixels in all.
+    pub fn begin_band(&self, near: &Path, row: usize, size: (u32, u32)) -> Result<Band> {
+        let path = self.band_path(near, row);
+        let mut pending = path.clone().into_os_string();
+        pending.push(".tmp");
+        let pending = PathBuf::from(pending);
+        let file = File::create(&pending).context(Phase::Write, &pending)?;
+        let writer = encode::encoder(BufWriter::new(file), size, Compression::Fast)
+            .write_header()?
+            .into_stream_writer()?;
+        Ok(Band {
+            near: near.to_path_buf(),
+            row,
+            path,
+            pending,
+            writer,
+        })
+    }
+
+    /// Saves the state now.
+    pub fn save(&self) -> Result<()> {
+        self.write(&mut self.lock())
+    }
+
+    /// Deletes the checkpoint once the run it was for has finished.
+    pub fn remove(&self) {
+        match fs::remove_dir_all(&self.dir) {
+            Ok(()) => debug!("Removed checkpoint {}", self.dir.display()),
+            Err(err) => warn!("Couldn't remove {}: {}", self.dir.display(), err),
+        }
+    }
+
+    fn band_path(&self, near: &Path, row: usize) -> PathBuf {
+        let sheet = report::sha256(near.to_string_lossy().as_bytes());
+        self.dir.join(format!("{}-{}.png", &sheet[..16], row))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, (State, usize)> {
+        // A thread that panicked while holding it left nothing half done.
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn write(&self, state: &mut (State, usize)) -> Result<()> {
+        output::write_atomic(&self.dir.join(STATE), Overwrite::Force, |out| {
+            serde_json::to_writer(out, &state.0)?;
+            Ok(())
+        })?;
+        state.1 = 0;
+        Ok(())
+    }
+}
+
+/// A row of tiles being saved as it's composited.
+pub struct Band {
+    near: PathBuf,
+    row: usize,
+    path: PathBuf,
+    pending: PathBuf,
+    writer: png::StreamWriter<'static, BufWriter<File>>,
+}
+
+impl Band {
+    pub fn write(&mut self, pixels: &[u8]) -> Result<()> {
+        self.writer
+            .write_all(pixels)
+            .context(Phase::Write, &self.pending)
+    }
+
+    /// Moves the row into place and records it as done.
+    pub fn finish(self, checkpoint: &Checkpoint) -> Result<()> {
+        self.writer.finish()?;
+        fs::rename(&self.pending, &self.path).context(Phase::Write, &self.path)?;
+        let mut state = checkpoint.lock();
+        state.0.bands.entry(self.near).or_default().push(self.row);
+        checkpoint.write(&mut state)
+    }
+}
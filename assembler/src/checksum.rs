@@ -0,0 +1,81 @@
+//! SHA-256 sidecars so pipelines can check outputs after moving them,
+//! without hashing big sheets themselves.
+
+use serde::Serialize;
+use std::{
+    ffi::OsString,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{report, Result};
+
+/// Which checksum files to write next to the outputs.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Checksums {
+    /// A `<file>.sha256` for every output.
+    Each,
+    /// One `<sheet>.sha256` listing every output.
+    Manifest,
+}
+
+impl Checksums {
+    pub const NAMES: &'static [&'static str] = &["each", "manifest"];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "each" => Some(Checksums::Each),
+            "manifest" => Some(Checksums::Manifest),
+            _ => None,
+        }
+    }
+
+    /// Where the checksums of `outputs`, written for the sheet at `sheet`,
+    /// go, each with the outputs it covers.
+    pub fn files<'a>(
+        self,
+        sheet: &Path,
+        outputs: &'a [report::Output],
+    ) -> Vec<(PathBuf, &'a [report::Output])> {
+        match self {
+            Checksums::Each => outputs
+                .iter()
+                .map(|output| (each_path(&output.path), std::slice::from_ref(output)))
+                .collect(),
+            Checksums::Manifest => vec![(sheet.with_extension("sha256"), outputs)],
+        }
+    }
+
+    /// The checksum files for outputs at `paths`, to check before writing.
+    pub fn paths(self, sheet: &Path, paths: &[PathBuf]) -> Vec<PathBuf> {
+        match self {
+            Checksums::Each => paths.iter().map(|path| each_path(path)).collect(),
+            Checksums::Manifest => vec![sheet.with_extension("sha256")],
+        }
+    }
+}
+
+/// `hero.png` gets `hero.png.sha256`, as `sha256sum` users expect.
+fn each_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(".sha256");
+    PathBuf::from(name)
+}
+
+/// Lists `outputs` in the format `sha256sum -c` reads, relative to `dir`,
+/// the folder the list is written to.
+pub fn write(dir: &Path, outputs: &[report::Output], out: &mut dyn Write) -> Result<()> {
+    for output in outputs {
+        let name = match output.path.strip_prefix(dir) {
+            Ok(relative) => relative
+                .iter()
+                .map(|part| part.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/"),
+            Err(_) => output.path.display().to_string(),
+        };
+        writeln!(out, "{}  {}", output.sha256, name)?;
+    }
+    Ok(())
+}
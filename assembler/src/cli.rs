@@ -0,0 +1,1091 @@
+use clap::{App, AppSettings, Arg, ArgGroup, SubCommand};
+
+use spritesheet_assembler::{
+    Anchor, Checksums, Collision, Compression, EmptyFrames, Mask, MetadataFormat, StrictSequence,
+    Target, Trim,
+};
+
+use crate::terminal;
+
+/// The top-level options that can't be used together, as declared with
+/// `conflicts_with` below. Clap never sees environment variables, so
+/// [`Config::check_conflicts`](crate::config::Config::check_conflicts) checks
+/// these again once they're merged in.
+pub const CONFLICTS: &[(&str, &str)] = &[
+    ("quiet", "verbose"),
+    ("no-recurse", "max-depth"),
+    ("backup", "force"),
+    ("cleanup", "keep-temp"),
+    ("cleanup", "watch"),
+    ("target", "fail-if-larger-than"),
+    ("compression", "fast"),
+    ("compression", "best"),
+    ("fast", "best"),
+    ("pad-to-max", "low-memory"),
+    ("pivot-marker", "low-memory"),
+    ("empty-frames", "low-memory"),
+    ("find-duplicates", "low-memory"),
+];
+
+/// The command line definition, also used to generate shell completions.
+pub fn app() -> App<'static, 'static> {
+    App::new("assembler")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Combined PNGs into a spritesheet")
+        .after_help(
+            "Every option can also be set with an environment variable named after it, \
+             e.g. SPRITESHEET_ROOT for --root or SPRITESHEET_MAX_DEPTH for --max-depth. \
+             Flags are enabled with a value of 1, and repeated options take a \
+             ';'-separated list. Arguments given on the command line take precedence.",
+        )
+        .arg(
+            Arg::with_name("root")
+                .short("r")
+                .long("root")
+                .value_name("DIR")
+                .help("Where to search for spritesheet tiles")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("frames-dir")
+                .long("frames-dir")
+                .value_name("NAME|PATH")
+                .help("Folder holding the frames, relative to the root or absolute [default: temp]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("out")
+                .short("o")
+                .long("out")
+                .value_name("PNG_FILENAME")
+                .help(
+                    "Spritesheet output filename, which may contain {name}, {frames}, {w}, {h}, \
+                     {page}, {anim} and {hash} tokens",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("name")
+                .long("name")
+                .value_name("NAME")
+                .help("Name used for the {name} token [default: the root folder's name]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .long("verbose")
+                .multiple(true)
+                .help("Log more detail (-v for progress, -vv for every file)"),
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .short("q")
+                .long("quiet")
+                .conflicts_with("verbose")
+                .help("Only log errors"),
+        )
+        .arg(
+            Arg::with_name("report")
+                .long("report")
+                .value_name("FORMAT")
+                .possible_values(&["json"])
+                .help("Print a summary of the run on stdout")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("timings")
+                .long("timings")
+                .help("Print the time taken and bytes processed by each phase on stderr"),
+        )
+        .arg(
+            Arg::with_name("watch")
+                .short("w")
+                .long("watch")
+                .help("Keep running and rebuild the sheet whenever the frames change"),
+        )
+        .arg(
+            Arg::with_name("exclude")
+                .short("x")
+                .long("exclude")
+                .value_name("GLOB")
+                .help("Skip frames whose name or path matches GLOB, e.g. '*_depth.png'")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("max-depth")
+                .long("max-depth")
+                .value_name("N")
+                .help("Only search N levels of subfolders below the frames folder")
+                .takes_value(true)
+                .validator(|v| {
+                    v.parse::<usize>()
+                        .map(|_| ())
+                        .map_err(|_| "must be a whole number".to_owned())
+                }),
+        )
+        .arg(
+            Arg::with_name("no-recurse")
+                .long("no-recurse")
+                .conflicts_with("max-depth")
+                .help("Only use frames directly inside the frames folder"),
+        )
+        .arg(
+            Arg::with_name("follow-symlinks")
+                .long("follow-symlinks")
+                .help("Follow symbolic links while searching for frames"),
+        )
+        .arg(
+            Arg::with_name("name-pattern")
+                .long("name-pattern")
+                .value_name("PATTERN")
+                .help(
+                    "Where the frame number is in each filename, ordering frames by it: '#'s \
+                     for the number and '*' for any text, e.g. 'render_####.png', or a regular \
+                     expression capturing it",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("start")
+                .long("start")
+                .value_name("FRAME")
+                .help("Skip frames numbered before FRAME")
+                .takes_value(true)
+                .allow_hyphen_values(true),
+        )
+        .arg(
+            Arg::with_name("end")
+                .long("end")
+                .value_name("FRAME")
+                .help("Skip frames numbered after FRAME")
+                .takes_value(true)
+                .allow_hyphen_values(true),
+        )
+        .arg(
+            Arg::with_name("force")
+                .short("f")
+                .long("force")
+                .help("Overwrite existing output files"),
+        )
+        .arg(
+            Arg::with_name("backup")
+                .long("backup")
+                .conflicts_with("force")
+                .help("Rename existing output files to <name>.bak before writing"),
+        )
+        .arg(
+            Arg::with_name("cleanup")
+                .long("cleanup")
+                .conflicts_with_all(&["keep-temp", "watch"])
+                .help("Delete the frames folder once the sheet has been written"),
+        )
+        .arg(
+            Arg::with_name("keep-temp")
+                .long("keep-temp")
+                .help("Leave the frames folder in place (the default)"),
+        )
+        .arg(
+            Arg::with_name("wait")
+                .long("wait")
+                .help("Wait for another assembly in the same root to finish instead of failing"),
+        )
+        .arg(
+            Arg::with_name("metadata")
+                .short("m")
+                .long("metadata")
+                .value_name("FORMAT")
+                .help("Also write frame metadata next to the sheet")
+                .possible_values(MetadataFormat::NAMES)
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("embed-sheet").long("embed-sheet").help(
+                "Include the sheet in the module --metadata rust writes, with include_bytes!",
+            ),
+        )
+        .arg(
+            Arg::with_name("frame-name")
+                .long("frame-name")
+                .value_name("TEMPLATE")
+                .help(
+                    "How frames are named in the metadata, using {anim}, {index}, {number}, \
+                     {base}, {stem} and {file} tokens, e.g. \"{anim}/{index:03}\" \
+                     [default: {anim}/{stem}]",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("sequence")
+                .long("sequence")
+                .value_name("SPEC")
+                .help(
+                    "List the frames in the order they play in the metadata, by index and \
+                     with repeats, e.g. '0-9, 4x3, 9-0'",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("fps")
+                .long("fps")
+                .value_name("FPS|ANIM=FPS")
+                .help(
+                    "Frames per second the animations play at, or one animation plays at, \
+                     for the metadata's durations and frame rates [default: 24]",
+                )
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("markers")
+                .long("markers")
+                .value_name("JSON")
+                .help(
+                    "Write the Blender timeline markers listed in JSON, relative to the root, \
+                     as events on the frames they mark",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("collision")
+                .long("collision")
+                .value_name("SHAPE")
+                .possible_values(Collision::NAMES)
+                .help(
+                    "Trace collision polygons around each frame's opaque pixels into the \
+                     metadata, an outline per part or one convex hull",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("collision-tolerance")
+                .long("collision-tolerance")
+                .value_name("PIXELS")
+                .requires("collision")
+                .help("How far the --collision outlines may cut across pixels [default: 1]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("collision-mask")
+                .long("collision-mask")
+                .value_name("FORMAT")
+                .possible_values(Mask::NAMES)
+                .help(
+                    "Write a 1-bit mask of each frame's solid pixels next to the sheet, as \
+                     packed rows in a .mask file or runs in a .mask.json",
+                )
+                .takes_value(true),
+        )
+        .arg(Arg::with_name("facings").long("facings").help(
+            "Read the frames from <facing>/<animation>/ folders, such as south/walk, and lay \
+             out a row per facing per animation",
+        ))
+        .arg(Arg::with_name("opaque-bounds").long("opaque-bounds").help(
+            "Export the box around each frame's visible pixels into the metadata, even \
+             though frames aren't trimmed",
+        ))
+        .arg(Arg::with_name("mesh").long("mesh").help(
+            "Export a triangle mesh covering each frame's visible pixels into the metadata, \
+             so engines can skip drawing its transparent parts",
+        ))
+        .arg(Arg::with_name("godot-scene").long("godot-scene").help(
+            "Write a Godot 4 .tscn scene next to the sheet, with an AnimatedSprite2D and \
+             collision polygons that switch with its frames",
+        ))
+        .arg(
+            Arg::with_name("boxes")
+                .long("boxes")
+                .value_name("DIR")
+                .help(
+                    "Export the hitboxes and other boxes painted in flat colours on a separate \
+                     pass, in a folder mirroring the frames folder, relative to the root",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("box-colour")
+                .long("box-colour")
+                .value_name("LABEL=COLOUR")
+                .requires("boxes")
+                .help(
+                    "Label the --boxes painted in a colour like ff0000 [default: hitbox=ff0000 \
+                     hurtbox=0000ff]",
+                )
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("pass")
+                .long("pass")
+                .value_name("SUFFIX")
+                .help(
+                    "Assemble frames saved per render pass, like frame_0001_color.png, with a \
+                     sheet per pass in the same layout; the first --pass is the main one",
+                )
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("nine-slice")
+                .long("nine-slice")
+                .value_name("INSETS")
+                .help(
+                    "Export 9-slice borders for every frame, this many pixels in from each side \
+                     or from the left,top,right,bottom",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("nine-slices")
+                .long("nine-slices")
+                .value_name("JSON")
+                .help(
+                    "Export the 9-slice borders the JSON file, relative to the root, gives \
+                     frames or animations by name",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("direction")
+                .long("direction")
+                .value_name("ANIM=DIR")
+                .help(
+                    "Play an animation forward, reverse or pingpong in the metadata, \
+                     e.g. 'walk=pingpong', overriding a '@pingpong' folder suffix",
+                )
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(Arg::with_name("debug-overlay").long("debug-overlay").help(
+            "Write a _debug.png copy of the sheet with cell boundaries, padding, opaque \
+                     bounds and pivots drawn on top",
+        ))
+        .arg(Arg::with_name("number-frames").long("number-frames").help(
+            "Write a _numbered.png copy of the sheet with each frame's index drawn in a corner \
+                     of its cell, for QA",
+        ))
+        .arg(
+            Arg::with_name("number-size")
+                .long("number-size")
+                .value_name("SCALE")
+                .requires("number-frames")
+                .help(
+                    "How many times larger than its 3x5 pixel font to draw each index [default: 2]",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("number-corner")
+                .long("number-corner")
+                .value_name("ANCHOR")
+                .possible_values(Anchor::NAMES)
+                .requires("number-frames")
+                .help("Where in each cell to draw its index [default: top-left]")
+                .takes_value(true),
+        )
+        .arg(Arg::with_name("preview").long("preview").help(
+            "Write a .preview.png contact sheet showing every frame on a checkerboard, \
+                     labelled with its index and name",
+        ))
+        .arg(
+            Arg::with_name("preview-gif")
+                .long("preview-gif")
+                .value_name("GIF_FILENAME")
+                .help(
+                    "Also write a small looping GIF of the animation, played at --fps, which \
+                     may contain {name} and {anim} tokens",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("preview-html")
+                .long("preview-html")
+                .value_name("HTML_FILENAME")
+                .help(
+                    "Also write a page embedding the sheet with a player to step through each \
+                     animation, change its speed, zoom and onion skin",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("preview-video")
+                .long("preview-video")
+                .value_name("VIDEO_FILENAME")
+                .help(
+                    "Also write an .mp4 or .webm clip of the animation with ffmpeg, if it's \
+                     installed, which may contain {name} and {anim} tokens",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("preview-video-fps")
+                .long("preview-video-fps")
+                .value_name("FPS")
+                .requires("preview-video")
+                .help("Frame rate of the --preview-video clip [default: --fps]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("preview-video-scale")
+                .long("preview-video-scale")
+                .value_name("SCALE")
+                .requires("preview-video")
+                .help(
+                    "How much larger than the frames to make the --preview-video clip [default: 1]",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("palette")
+                .long("palette")
+                .value_name("PALETTE_FILENAME")
+                .help(
+                    "Also write the colours the sheet uses, most used first, as a GIMP .gpl, \
+                     JASC .pal or .hex list, which may contain {name} and {anim} tokens",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("palette-colors")
+                .long("palette-colors")
+                .value_name("COUNT")
+                .requires("palette")
+                .help("Reduce the --palette to at most this many colours [default: all of them]")
+                .takes_value(true),
+        )
+        .arg(Arg::with_name("provenance").long("provenance").help(
+            "Write a .provenance.json file recording the assembler version, options \
+                     and input hashes",
+        ))
+        .arg(
+            Arg::with_name("checksums")
+                .long("checksums")
+                .value_name("MODE")
+                .possible_values(Checksums::NAMES)
+                .help(
+                    "Write the SHA-256 of every output, in a <file>.sha256 each or together in \
+                     a <sheet>.sha256 manifest, for checking with sha256sum -c",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("scene")
+                .long("scene")
+                .value_name("NAME")
+                .help("Blender scene the frames came from, for the provenance record")
+                .takes_value(true),
+        )
+        .arg(Arg::with_name("low-memory").long("low-memory").help(
+            "Stream frames from disk a row at a time instead of decoding them all \
+                     first. Slower, but uses little memory; frames must be 8-bit RGBA PNGs",
+        ))
+        .arg(Arg::with_name("gpu").long("gpu").help(
+            "Composite the sheet on the graphics card, falling back to the CPU when there's \
+                     none; needs a build with the gpu feature",
+        ))
+        .arg(
+            Arg::with_name("max-memory")
+                .long("max-memory")
+                .value_name("SIZE")
+                .help(
+                    "Switch to --low-memory when decoding everything up front would need more \
+                     than SIZE, e.g. 2G",
+                )
+                .takes_value(true)
+                .validator(|v| {
+                    crate::config::parse_size(&v)
+                        .map(|_| ())
+                        .ok_or_else(|| "must be a size like 512M or 2G".to_owned())
+                }),
+        )
+        .arg(
+            Arg::with_name("max-size")
+                .long("max-size")
+                .value_name("PX")
+                .help(
+                    "Wrap frames onto more rows, then more pages, rather than make a sheet wider \
+                     or taller than PX; 0 for no limit [default: 16384]",
+                )
+                .takes_value(true)
+                .validator(|v| {
+                    v.parse::<u32>()
+                        .map(|_| ())
+                        .map_err(|_| "must be a whole number of pixels".to_owned())
+                }),
+        )
+        .arg(
+            Arg::with_name("max-frames-per-page")
+                .long("max-frames-per-page")
+                .value_name("N")
+                .help("Put at most N frames on each sheet, and the rest on more pages")
+                .takes_value(true)
+                .validator(|v| {
+                    v.parse::<usize>()
+                        .map(|_| ())
+                        .map_err(|_| "must be a whole number".to_owned())
+                }),
+        )
+        .arg(
+            Arg::with_name("fail-if-larger-than")
+                .long("fail-if-larger-than")
+                .value_name("WxH")
+                .help(
+                    "Fail, suggesting what would help, rather than write a sheet wider or taller \
+                     than this, e.g. 4096x4096",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("target")
+                .long("target")
+                .value_name("PLATFORM")
+                .possible_values(Target::NAMES)
+                .conflicts_with("fail-if-larger-than")
+                .help(
+                    "Fail like --fail-if-larger-than on sheets the platform can't load: 4096px \
+                     for webgl, 2048px for mobile, 16384px for desktop",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("compression")
+                .long("compression")
+                .value_name("LEVEL")
+                .help("PNG compression level, from 0 (none) to 9 (smallest)")
+                .takes_value(true)
+                .conflicts_with_all(&["fast", "best"])
+                .validator(|v| match v.parse::<u8>() {
+                    Ok(level) if level <= Compression::MAX_LEVEL => Ok(()),
+                    _ => Err(format!(
+                        "must be a level from 0 to {}",
+                        Compression::MAX_LEVEL
+                    )),
+                }),
+        )
+        .arg(
+            Arg::with_name("fast")
+                .long("fast")
+                .conflicts_with("best")
+                .help("Compress quickly, for iteration builds"),
+        )
+        .arg(
+            Arg::with_name("filter-chain")
+                .long("filter-chain")
+                .value_name("FILTERS")
+                .help(
+                    "Process every frame with a comma-separated chain of filters: tint:COLOR, \
+                     outline:COLOR[:WIDTH] and flip:x|y",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("pad-to-max")
+                .long("pad-to-max")
+                .conflicts_with("low-memory")
+                .help(
+                    "Pad frames smaller than the largest onto a canvas of its size instead of \
+                     failing, listing them in the report",
+                ),
+        )
+        .arg(
+            Arg::with_name("pad-anchor")
+                .long("pad-anchor")
+                .value_name("ANCHOR")
+                .possible_values(Anchor::NAMES)
+                .help("Where padded frames sit on the larger canvas [default: center]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("trim")
+                .long("trim")
+                .value_name("MODE")
+                .possible_values(Trim::NAMES)
+                .help(
+                    "Cut every frame down to the box around all their visible pixels, keeping \
+                     the cells the same size",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("pivot")
+                .long("pivot")
+                .value_name("ANCHOR")
+                .possible_values(Anchor::NAMES)
+                .help(
+                    "Export a pivot for every frame in the metadata, here unless its filename \
+                     or a --pivot-marker gives its own",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("pivot-marker")
+                .long("pivot-marker")
+                .value_name("COLOUR")
+                .conflicts_with("low-memory")
+                .help(
+                    "Take each frame's pivot from its one pixel of this colour, such as ff00ff, \
+                     and make that pixel transparent",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("empty-frames")
+                .long("empty-frames")
+                .value_name("ACTION")
+                .possible_values(EmptyFrames::NAMES)
+                .conflicts_with("low-memory")
+                .help(
+                    "Warn about, fail on or skip fully transparent frames, usually from a \
+                     broken render layer",
+                )
+                .takes_value(true),
+        )
+        .arg(Arg::with_name("strict-format").long("strict-format").help(
+            "Fail, rather than warn, if frames differ in bit depth or colour space, which \
+                 would otherwise be made uniform",
+        ))
+        .arg(
+            Arg::with_name("strict-sequence")
+                .long("strict-sequence")
+                .value_name("ACTION")
+                .possible_values(StrictSequence::NAMES)
+                .min_values(0)
+                .max_values(1)
+                .help(
+                    "Fail, or warn, if an animation's frame numbers skip or repeat one, as when \
+                     a render crashed partway [default: error]",
+                ),
+        )
+        .arg(
+            Arg::with_name("find-duplicates")
+                .long("find-duplicates")
+                .conflicts_with("low-memory")
+                .help(
+                    "Report frames identical to an earlier one, and consecutive frames that are \
+                     nearly the same, with how alike they are",
+                ),
+        )
+        .arg(
+            Arg::with_name("similarity")
+                .long("similarity")
+                .value_name("SCORE")
+                .requires("find-duplicates")
+                .help(
+                    "How alike consecutive frames must be, from 0 to 1, for --find-duplicates \
+                     to report them [default: 0.99]",
+                )
+                .takes_value(true)
+                .validator(|v| match v.parse::<f64>() {
+                    Ok(similarity) if (0.0..=1.0).contains(&similarity) => Ok(()),
+                    _ => Err("must be a number from 0 to 1".to_owned()),
+                }),
+        )
+        .arg(
+            Arg::with_name("best")
+                .long("best")
+                .help("Compress as much as possible, for release assets"),
+        )
+        .arg(Arg::with_name("deterministic").long("deterministic").help(
+            "Write byte-identical files for identical inputs on any platform, with fixed \
+                     compression settings and no timestamps",
+        ))
+        .arg(Arg::with_name("cache").long("cache").help(
+            "Keep a cache in the root, to skip runs whose inputs haven't changed and reuse \
+                 unchanged parts of the last sheet",
+        ))
+        .arg(Arg::with_name("resume").long("resume").help(
+            "Save progress while streaming the sheet, and pick up where an interrupted \
+                     run with the same settings stopped",
+        ))
+        .arg(Arg::with_name("metadata-only").long("metadata-only").help(
+            "Only rewrite the metadata and other files describing the sheet a previous run \
+                     wrote, without decoding the frames or encoding the sheet again",
+        ))
+        .arg(
+            Arg::with_name("split-by-animation")
+                .long("split-by-animation")
+                .help(
+                    "Write a sheet and metadata per animation subfolder, named by adding \
+                     _{anim} to the output filename unless it already has an {anim} token",
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("append")
+                .about("Add frames to a sheet from an earlier run, keeping the others in place")
+                .arg(
+                    Arg::with_name("sheet")
+                        .value_name("SHEET_JSON")
+                        .help("The sheet's JSON metadata, found next to it")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("frames")
+                        .value_name("FRAME_PNG")
+                        .help("Frames to add, named after their files")
+                        .multiple(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("batch")
+                .about("Run every assembly listed in a JSON or TOML jobs file")
+                .arg(
+                    Arg::with_name("jobs")
+                        .value_name("JOBS")
+                        .help("A JSON array or TOML [[jobs]] list of {root, options} specs")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("merge")
+                .about("Repack sheets from earlier runs into one atlas")
+                .arg(
+                    Arg::with_name("sheets")
+                        .value_name("SHEET_JSON")
+                        .help("The JSON metadata of each sheet, found next to it")
+                        .multiple(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("out")
+                        .short("o")
+                        .long("out")
+                        .value_name("PNG_FILENAME")
+                        .help("The merged sheet, written with its JSON metadata")
+                        .default_value("merged.png"),
+                )
+                .arg(
+                    Arg::with_name("force")
+                        .short("f")
+                        .long("force")
+                        .help("Overwrite existing output files"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("slice")
+                .about("Cut a sheet back into its frames, by a grid or by its metadata")
+                .arg(
+                    Arg::with_name("sheet")
+                        .value_name("PNG_FILENAME")
+                        .help("The sheet to cut up [default: the image the metadata names]")
+                        .required_unless("metadata"),
+                )
+                .arg(
+                    Arg::with_name("frame-size")
+                        .long("frame-size")
+                        .value_name("WxH")
+                        .help("The size of every frame of a grid, such as 64x64")
+                        .required_unless("metadata")
+                        .conflicts_with("metadata")
+                        .validator(|v| {
+                            crate::slice::parse_size(&v)
+                                .map(|_| ())
+                                .ok_or_else(|| "must be a size like 64x64".to_owned())
+                        }),
+                )
+                .arg(
+                    Arg::with_name("metadata")
+                        .short("m")
+                        .long("metadata")
+                        .value_name("FILE")
+                        .help(
+                            "Cut out the frames listed in TexturePacker or Aseprite JSON, or a \
+                             libGDX or Spine .atlas, restoring trimmed frames' canvases",
+                        )
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("out")
+                        .short("o")
+                        .long("out")
+                        .value_name("DIR")
+                        .help("The folder to write the frames to")
+                        .default_value("frames"),
+                )
+                .arg(
+                    Arg::with_name("force")
+                        .short("f")
+                        .long("force")
+                        .help("Overwrite existing frames"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("serve")
+                .about("Stay running and take assembly jobs as JSON-RPC requests")
+                .arg(
+                    Arg::with_name("stdio")
+                        .long("stdio")
+                        .help("Read requests from stdin and answer on stdout, one per line"),
+                )
+                .arg(
+                    Arg::with_name("listen")
+                        .long("listen")
+                        .value_name("ADDR")
+                        .help(
+                            "Take connections on a TCP address such as 127.0.0.1:7878; \
+                             a bare :7878 only listens on this machine",
+                        )
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("http")
+                        .long("http")
+                        .value_name("ADDR")
+                        .help(
+                            "Serve a REST API on an address such as 127.0.0.1:8080; \
+                             a bare :8080 only listens on this machine",
+                        )
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("socket")
+                        .long("socket")
+                        .value_name("PATH")
+                        .help("Take connections on a Unix socket")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("token")
+                        .long("token")
+                        .value_name("TOKEN")
+                        .help(
+                            "Only take requests from clients that send this token, \
+                             needed to listen beyond this machine",
+                        )
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("base")
+                        .long("base")
+                        .value_name("DIR")
+                        .help(
+                            "Only run jobs on files within this folder; relative roots are \
+                             found in it [default: the current folder, unless serving on stdio]",
+                        )
+                        .takes_value(true),
+                )
+                .group(
+                    ArgGroup::with_name("transport")
+                        .args(&["stdio", "listen", "socket", "http"])
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("update")
+                .about("Redraw frames of a sheet from an earlier run in place")
+                .arg(
+                    Arg::with_name("sheet")
+                        .value_name("SHEET_JSON")
+                        .help("The sheet's JSON metadata, found next to it")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("frames")
+                        .value_name("[NAME=]FRAME_PNG")
+                        .help("New pixels for a frame, named after the file unless NAME= is given")
+                        .multiple(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("verify")
+                .about("Check that a sheet's frames still match the frames they came from")
+                .arg(
+                    Arg::with_name("root")
+                        .short("r")
+                        .long("root")
+                        .value_name("DIR")
+                        .help("The root the sheet was assembled from")
+                        .default_value("."),
+                )
+                .arg(
+                    Arg::with_name("frames-dir")
+                        .long("frames-dir")
+                        .value_name("NAME|PATH")
+                        .help("Folder holding the frames, relative to the root or absolute")
+                        .default_value("temp"),
+                )
+                .arg(
+                    Arg::with_name("metadata")
+                        .short("m")
+                        .long("metadata")
+                        .value_name("SHEET_JSON")
+                        .help("The sheet's JSON metadata")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("sheet")
+                        .long("sheet")
+                        .value_name("PNG_FILENAME")
+                        .help("The sheet [default: the image the metadata names]")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("tolerance")
+                        .long("tolerance")
+                        .value_name("N")
+                        .help("How far each channel of a pixel may be from the source's")
+                        .default_value("0")
+                        .validator(|v| {
+                            v.parse::<u8>()
+                                .map(|_| ())
+                                .map_err(|_| "must be a whole number from 0 to 255".to_owned())
+                        }),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("diff")
+                .about("Compare two versions of a sheet frame by frame")
+                .arg(
+                    Arg::with_name("old")
+                        .value_name("OLD")
+                        .help("The earlier sheet, or its JSON metadata")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("new")
+                        .value_name("NEW")
+                        .help("The later sheet, or its JSON metadata")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("threshold")
+                        .long("threshold")
+                        .value_name("DIFFERENCE")
+                        .help(
+                            "Fail if a frame's mean difference, from 0 for identical to 1 for \
+                             black against white, is above this",
+                        )
+                        .default_value("0")
+                        .validator(|v| match v.parse::<f64>() {
+                            Ok(threshold) if (0.0..=1.0).contains(&threshold) => Ok(()),
+                            _ => Err("must be a number from 0 to 1".to_owned()),
+                        }),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .value_name("PNG_FILENAME")
+                        .help("Write the new sheet in grey, with changed pixels in red")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("preview")
+                .about("Show a sheet, or play its animation, in the terminal")
+                .arg(
+                    Arg::with_name("sheet")
+                        .value_name("SHEET")
+                        .help("The sheet, or its JSON metadata")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("terminal")
+                        .long("terminal")
+                        .help(
+                            "Draw it in this terminal with the sixel, kitty or iTerm2 image \
+                             protocol, such as over SSH",
+                        )
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("protocol")
+                        .long("protocol")
+                        .value_name("PROTOCOL")
+                        .help("The image protocol, or auto to tell from the terminal")
+                        .possible_values(terminal::Protocol::NAMES)
+                        .default_value("auto"),
+                )
+                .arg(
+                    Arg::with_name("play")
+                        .long("play")
+                        .help("Play the frames at their durations, from the JSON metadata"),
+                )
+                .arg(
+                    Arg::with_name("tag")
+                        .long("tag")
+                        .value_name("ANIMATION")
+                        .help("Only play this animation from the metadata's frame tags")
+                        .requires("play")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("loops")
+                        .long("loops")
+                        .value_name("COUNT")
+                        .help("How many times to play the frames, or 0 until interrupted")
+                        .default_value("1")
+                        .validator(|v| {
+                            v.parse::<u32>()
+                                .map(|_| ())
+                                .map_err(|_| "must be a whole number".to_owned())
+                        }),
+                )
+                .arg(
+                    Arg::with_name("size")
+                        .long("size")
+                        .value_name("PIXELS")
+                        .help(
+                            "Shrink or enlarge the image to about this many pixels on its \
+                             longest side",
+                        )
+                        .default_value("512")
+                        .validator(|v| match v.parse::<u32>() {
+                            Ok(size) if size > 0 => Ok(()),
+                            _ => Err("must be a whole number above 0".to_owned()),
+                        }),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("completions")
+                .about("Print a shell completion script")
+                .setting(AppSettings::Hidden)
+                .arg(
+                    Arg::with_name("shell")
+                        .possible_values(&clap::Shell::variants())
+                        .required(true),
+                ),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A value clap accepts for each option in [`CONFLICTS`] that takes one.
+    fn sample(name: &str) -> Option<&'static str> {
+        match name {
+            "max-depth" => Some("1"),
+            "target" => Some("webgl"),
+            "fail-if-larger-than" => Some("64x64"),
+            "compression" => Some("5"),
+            "pivot-marker" => Some("#ff00ff"),
+            "empty-frames" => Some("skip"),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn conflicts_match_the_arguments() {
+        for (first, second) in CONFLICTS {
+            let mut args = vec!["assembler".to_owned()];
+            for name in &[first, second] {
+                args.push(format!("--{}", name));
+                args.extend(sample(name).map(str::to_owned));
+            }
+            let err = app()
+                .get_matches_from_safe(&args)
+                .expect_err(&format!("{:?} should conflict", args));
+            assert_eq!(err.kind, clap::ErrorKind::ArgumentConflict, "{:?}", args);
+        }
+    }
+}
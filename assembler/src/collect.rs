@@ -0,0 +1,425 @@
+#[cfg(feature = "fs")]
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use image::RgbaImage;
+#[cfg(feature = "fs")]
+use log::debug;
+use log::{info, warn};
+use rayon::prelude::*;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use crate::{
+    cache::Cache,
+    empty,
+    errors::{AssemblerError, ImageFormatError, InconsistentSizeError, IoContext, Phase},
+    events::Event,
+    filter, numbering,
+    pad::{self, Anchor},
+    passes, pivot,
+    progress::Progress,
+    report::{self, Padded, Report, Skipped},
+    stream, Options, Result,
+};
+
+/// A decoded frame and where it came from.
+pub struct Frame {
+    pub path: PathBuf,
+    /// SHA-256 of the file as it was on disk.
+    pub sha256: String,
+    /// `path` relative to the frames folder, without the first render
+    /// pass's suffix.
+    pub relative: PathBuf,
+    pub width: u32,
+    pub height: u32,
+    /// The pixels, or `None` when `--low-memory` streams them from `path`
+    /// later or the cached sheet may already have them.
+    pub image: Option<RgbaImage>,
+    /// How many ticks the frame is shown for.
+    pub duration: u32,
+    /// Its own pivot, in pixels from the top left of its cell, if its
+    /// filename or a marker pixel gave one.
+    pub pivot: Option<(f64, f64)>,
+}
+
+impl Frame {
+    /// The animation the frame belongs to: the subfolder of the frames
+    /// folder it was found in, if any.
+    pub fn animation(&self) -> Option<&str> {
+        let mut components = self.relative.components();
+        let first = components.next()?;
+        components.next()?;
+        first.as_os_str().to_str()
+    }
+}
+
+type ReadFrame = (Option<RgbaImage>, (u32, u32), u64, String);
+
+/// Finds the frame files, in the order they'll appear on the sheet.
+pub fn find_frames(options: &Options, report: &mut Report) -> Result<Vec<PathBuf>> {
+    let started = Instant::now();
+    let paths = if options.inputs.is_empty() {
+        collect_paths(options, report)?
+    } else {
+        options.inputs.clone()
+    };
+    let paths = numbering::select(options, report, paths);
+    report.timings.walk = report::millis(started.elapsed());
+    Ok(paths)
+}
+
+/// Decodes the frames, leaving out the pixels of those the cache says were
+/// part of the last sheet.
+pub fn decode_frames(
+    options: &Options,
+    report: &mut Report,
+    paths: Vec<PathBuf>,
+    cache: Option<&Cache>,
+) -> Result<Vec<Frame>> {
+    let frames_dir = options.frames_dir();
+    let started = Instant::now();
+    // Frames the cache knows aren't decoded, so can't be checked for
+    // transparency or duplicates.
+    // Nor can their pivot markers be found, or their pixels trimmed.
+    let cache = cache.filter(|_| {
+        options.empty_frames.is_none()
+            && !options.find_duplicates
+            && options.pivot_marker.is_none()
+            && options.trim.is_none()
+    });
+    let sizes = header_sizes(&paths);
+    let canvas = if options.pad_to_max {
+        largest(&sizes).map(|canvas| (canvas, options.pad_anchor))
+    } else {
+        check_sizes(&paths, &sizes)?;
+        None
+    };
+    let total = paths.len() as u64;
+    let progress = Progress::new("Decoding", total).emitting(&options.events, move |done| {
+        Event::FramesDecoded { done, total }
+    });
+    // Decode in parallel, then walk the results in order so the sheet's
+    // layout doesn't depend on which thread finished first.
+    let decoded = paths
+        .par_iter()
+        .zip(&sizes)
+        .map(|(path, size)| {
+            if options.cancel.is_cancelled() {
+                return Err(String::new());
+            }
+            let decoded = if options.low_memory {
+                probe(path, options).map(|(size, bytes, sha256)| (None, size, bytes, sha256))
+            } else if options.metadata_only {
+                measure_frame(path, *size, cache, options)
+            } else {
+                read_frame(path, cache, options, canvas)
+            };
+            let decoded = decoded.map_err(|err| err.reason());
+            progress.inc(1);
+            decoded
+        })
+        .collect::<Vec<_>>();
+    options.cancel.check()?;
+    let mut images = Vec::with_capacity(paths.len());
+    let checked = paths.into_iter().zip(decoded).zip(sizes).enumerate();
+    for (index, ((path, decoded), size)) in checked {
+        match decoded {
+            Ok((mut image, (mut width, mut height), bytes, sha256)) => {
+                // Before checking for transparency, so a frame that's just
+                // its marker counts as empty.
+                let marked = match (&mut image, options.pivot_marker) {
+                    (Some(image), Some(colour)) => pivot::take_marker(image, colour, &path)?,
+                    _ => None,
+                };
+                let empty = options
+                    .empty_frames
+                    .filter(|_| image.as_ref().is_some_and(empty::is_empty));
+                if let Some(policy) = empty {
+                    report.empty.push(index);
+                    if !empty::keep(policy, &path)? {
+                        let reason = "fully transparent".to_owned();
+                        warn!("Skipping {}: {}", path.display(), reason);
+                        options.events.emit(Event::FrameSkipped {
+                            path: path.clone(),
+                            reason: reason.clone(),
+                        });
+                        report.skipped.push(Skipped { path, reason });
+                        continue;
+                    }
+                }
+                if let Some((canvas, _)) = canvas {
+                    if let Some(size) = size.filter(|size| *size != canvas) {
+                        info!(
+                            "Padding {} from {}x{}px to {}x{}px",
+                            path.display(),
+                            size.0,
+                            size.1,
+                            canvas.0,
+                            canvas.1
+                        );
+                        report.padded.push(Padded {
+                            path: path.clone(),
+                            width: size.0,
+                            height: size.1,
+                        });
+                    }
+                    // Frames the cache knows have its old tile size.
+                    width = canvas.0;
+                    height = canvas.1;
+                }
+                let offset = match (canvas, size) {
+                    (Some((canvas, anchor)), Some(size)) => anchor.offset(size, canvas),
+                    _ => (0, 0),
+                };
+                let relative = path.strip_prefix(&frames_dir).unwrap_or(&path);
+                let relative = passes::unsuffixed(&options.passes, relative);
+                report.inputs.push(path.clone());
+                report.bytes.decode += bytes;
+                images.push(Frame {
+                    pivot: pivot::resolve(marked, &relative, offset),
+                    relative,
+                    path,
+                    sha256,
+                    width,
+                    height,
+                    image,
+                    duration: 1,
+                });
+            }
+            Err(err) => {
+                warn!("Skipping {}: {}", path.display(), err);
+                options.events.emit(Event::FrameSkipped {
+                    path: path.clone(),
+                    reason: err.clone(),
+                });
+                report.skipped.push(Skipped { path, reason: err });
+            }
+        }
+    }
+    progress.finish();
+    if let Some(checkpoint) = &options.checkpoint {
+        checkpoint.save()?;
+    }
+    report.timings.decode = report::millis(started.elapsed());
+    info!(
+        "Read {} frames in {:.2?}, decoding {}",
+        images.len(),
+        started.elapsed(),
+        images.iter().filter(|frame| frame.image.is_some()).count()
+    );
+    Ok(images)
+}
+
+#[cfg(feature = "fs")]
+fn collect_paths(options: &Options, report: &mut Report) -> Result<Vec<PathBuf>> {
+    let exclude = exclude_set(&options.exclude)?;
+    let temporary = options.frames_dir();
+    info!("Searching for tiles in {}", temporary.display());
+    let mut walk = walkdir::WalkDir::new(&temporary)
+        .follow_links(options.follow_symlinks)
+        .sort_by(|a, b| a.file_name().cmp(b.file_name()));
+    if let Some(depth) = options.max_depth {
+        // Depth 0 is the frames folder itself, 1 is the files directly inside it.
+        walk = walk.max_depth(depth.saturating_add(1));
+    }
+    let paths = walk
+        .into_iter()
+        .filter_map(|e| match e {
+            Ok(entry) if entry.file_type().is_dir() => None,
+            Ok(entry) => {
+                let relative = entry
+                    .path()
+                    .strip_prefix(&temporary)
+                    .unwrap_or(entry.path());
+                let matched = exclude
+                    .matches(relative)
+                    .into_iter()
+                    .chain(exclude.matches(entry.file_name()))
+                    .next();
+                if relative == Path::new(crate::timing::SIDECAR) {
+                    return None;
+                }
+                if let Some(index) = matched {
+                    let reason = format!("excluded by '{}'", options.exclude[index]);
+                    debug!("Skipping {}: {}", entry.path().display(), reason);
+                    skip(options, report, entry.into_path(), reason);
+                    return None;
+                }
+                // Its name would be garbled in the metadata and output names.
+                if relative.to_str().is_none() {
+                    let reason = "its path isn't valid Unicode".to_owned();
+                    warn!("Skipping {}: {}", entry.path().display(), reason);
+                    // Reports are JSON, which can only hold it approximately.
+                    let path = entry.path().to_string_lossy().into_owned();
+                    skip(options, report, PathBuf::from(path), reason);
+                    return None;
+                }
+                debug!("Found {}", entry.path().display());
+                Some(entry.into_path())
+            }
+            Err(err) => {
+                warn!("Skipping unreadable entry: {}", err);
+                let path = err.path().map(Path::to_path_buf).unwrap_or_default();
+                skip(options, report, path, err.to_string());
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+    Ok(paths)
+}
+
+/// Records a file left out of the sheet in the report and the events.
+#[cfg(feature = "fs")]
+fn skip(options: &Options, report: &mut Report, path: PathBuf, reason: String) {
+    options.events.emit(Event::FrameSkipped {
+        path: path.clone(),
+        reason: reason.clone(),
+    });
+    report.skipped.push(Skipped { path, reason });
+}
+
+#[cfg(feature = "fs")]
+fn exclude_set(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern)?);
+    }
+    Ok(builder.build()?)
+}
+
+/// Without the `fs` feature there is nothing to search, so only frames
+/// passed in explicitly are used.
+#[cfg(not(feature = "fs"))]
+fn collect_paths(_: &Options, _: &mut Report) -> Result<Vec<PathBuf>> {
+    Ok(Vec::new())
+}
+
+/// Each frame's size from its header, or `None` for files whose header can't
+/// be read, which are left for decoding to skip.
+fn header_sizes(paths: &[PathBuf]) -> Vec<Option<(u32, u32)>> {
+    paths
+        .par_iter()
+        .map(|path| image::image_dimensions(path).ok())
+        .collect()
+}
+
+/// The smallest canvas every frame fits on, for `--pad-to-max`.
+fn largest(sizes: &[Option<(u32, u32)>]) -> Option<(u32, u32)> {
+    sizes
+        .iter()
+        .flatten()
+        .copied()
+        .reduce(|a, b| (a.0.max(b.0), a.1.max(b.1)))
+}
+
+/// Fails before anything is decoded if the frames' headers disagree on
+/// their size.
+fn check_sizes(paths: &[PathBuf], sizes: &[Option<(u32, u32)>]) -> Result<()> {
+    let mut known = paths
+        .iter()
+        .zip(sizes)
+        .filter_map(|(path, size)| Some((path, (*size)?)));
+    if let Some((first, first_size)) = known.next() {
+        if let Some((path, size)) = known.find(|(_, size)| *size != first_size) {
+            return Err(InconsistentSizeError {
+                first: first.clone(),
+                first_size,
+                path: path.clone(),
+                size,
+            }
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// Reads a frame's size and hashes it for streaming, unless an interrupted
+/// run already did.
+fn probe(path: &Path, options: &Options) -> Result<((u32, u32), u64, String)> {
+    let checkpoint = options.checkpoint.as_deref();
+    if let Some(probe) = checkpoint.and_then(|checkpoint| checkpoint.probe(path)) {
+        return Ok(probe);
+    }
+    let probe = stream::probe(path)?;
+    if let Some(checkpoint) = checkpoint {
+        checkpoint.record(path, &probe);
+    }
+    Ok(probe)
+}
+
+/// Hashes a frame and decodes it unless the cache already knows it,
+/// returning the pixels, their size, the file's length and its SHA-256.
+/// The pixels are padded onto `canvas` if given.
+fn read_frame(
+    path: &Path,
+    cache: Option<&Cache>,
+    options: &Options,
+    canvas: Option<((u32, u32), Anchor)>,
+) -> Result<ReadFrame> {
+    let bytes = fs::read(path).context(Phase::Read, path)?;
+    let length = bytes.len() as u64;
+    let sha256 = report::sha256(&bytes);
+    if let Some(size) = cache.and_then(|cache| cache.frame_size(&sha256)) {
+        return Ok((None, size, length, sha256));
+    }
+    let mut image = image_filter(path, &bytes)?;
+    if let Some((canvas, anchor)) = canvas {
+        image = pad::pad(image, canvas, anchor);
+    }
+    let image = filter::apply(&options.filters, image)?;
+    let size = image.dimensions();
+    Ok((Some(image), size, length, sha256))
+}
+
+/// Hashes a frame for `--metadata-only`, taking its size from the cache or
+/// its header. Only a frame whose filters the cache hasn't seen is decoded,
+/// since they may change its size.
+fn measure_frame(
+    path: &Path,
+    size: Option<(u32, u32)>,
+    cache: Option<&Cache>,
+    options: &Options,
+) -> Result<ReadFrame> {
+    let bytes = fs::read(path).context(Phase::Read, path)?;
+    let length = bytes.len() as u64;
+    let sha256 = report::sha256(&bytes);
+    if let Some(size) = cache.and_then(|cache| cache.frame_size(&sha256)) {
+        return Ok((None, size, length, sha256));
+    }
+    match size {
+        Some(size) if options.filters.is_empty() => Ok((None, size, length, sha256)),
+        _ => {
+            let image = filter::apply(&options.filters, image_filter(path, &bytes)?)?;
+            Ok((None, image.dimensions(), length, sha256))
+        }
+    }
+}
+
+/// Decodes a frame that `collect_frames` left for the cache after all,
+/// padding it to the frame's size if it's smaller.
+pub fn decode(frame: &mut Frame, options: &Options) -> Result<()> {
+    let bytes = fs::read(&frame.path).context(Phase::Read, &frame.path)?;
+    let mut image = image_filter(&frame.path, &bytes)?;
+    if options.pad_to_max {
+        image = pad::pad(image, (frame.width, frame.height), options.pad_anchor);
+    }
+    frame.image = Some(filter::apply(&options.filters, image)?);
+    Ok(())
+}
+
+/// Decodes an 8-bit RGBA frame, naming it `path` in errors.
+pub fn image_filter(path: &Path, bytes: &[u8]) -> Result<RgbaImage> {
+    let image = image::load_from_memory(bytes).map_err(|source| AssemblerError::Image {
+        path: Some(path.to_path_buf()),
+        source,
+    })?;
+    match image {
+        image::ImageRgba8(img) => Ok(img),
+        _ => Err(ImageFormatError {
+            path: path.to_path_buf(),
+        }
+        .into()),
+    }
+}
@@ -0,0 +1,266 @@
+//! `--collision`: polygons around each frame's opaque pixels, for physics
+//! engines, traced from the sheet so they match what was drawn. The
+//! outlines follow the pixels' edges, then lose the points that stray less
+//! than `--collision-tolerance` from a straight line, or are wrapped in one
+//! convex hull. Holes are left out, as most engines can't use them.
+
+use image::RgbaImage;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::metadata::Atlas;
+
+/// How far points may be from the simplified outline, in pixels, unless set.
+pub const DEFAULT_TOLERANCE: f64 = 1.0;
+
+/// Pixels at least this opaque are solid.
+pub const ALPHA_THRESHOLD: u8 = 128;
+
+/// A point on the pixel grid, from the top left of the frame.
+pub type Point = (u32, u32);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Collision {
+    /// An outline around each separate part of the frame.
+    Polygon,
+    /// One convex polygon around all of it.
+    Hull,
+}
+
+impl Collision {
+    pub const NAMES: &'static [&'static str] = &["polygon", "hull"];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "polygon" => Some(Collision::Polygon),
+            "hull" => Some(Collision::Hull),
+            _ => None,
+        }
+    }
+}
+
+/// Traces the shapes of the frames of `atlas` on `sheet`, its page.
+pub fn trace(sheet: &RgbaImage, atlas: &mut Atlas, shape: Collision, tolerance: f64) {
+    atlas.frames.par_iter_mut().for_each(|frame| {
+        let solid = |x: i64, y: i64| {
+            x >= 0
+                && y >= 0
+                && x < i64::from(frame.width)
+                && y < i64::from(frame.height)
+                && sheet.get_pixel(frame.x + x as u32, frame.y + y as u32)[3] >= ALPHA_THRESHOLD
+        };
+        let outlines = outlines(frame.width, frame.height, solid);
+        frame.polygons = match shape {
+            Collision::Polygon => outlines
+                .iter()
+                .map(|outline| simplify(outline, tolerance))
+                .filter(|polygon| polygon.len() >= 3)
+                .collect(),
+            Collision::Hull => {
+                let hull = hull(outlines.into_iter().flatten().collect());
+                if hull.len() >= 3 {
+                    vec![hull]
+                } else {
+                    Vec::new()
+                }
+            }
+        };
+    });
+}
+
+/// The outer edges of the solid parts of a `width` by `height` grid, each
+/// running clockwise with the solid pixels on its right, without the points
+/// partway along a straight edge.
+pub fn outlines(width: u32, height: u32, solid: impl Fn(i64, i64) -> bool) -> Vec<Vec<Point>> {
+    // Every pixel edge between a solid pixel and an empty one, from and to,
+    // and the pixel it belongs to.
+    let mut edges = Vec::new();
+    for y in 0..i64::from(height) {
+        for x in 0..i64::from(width) {
+            if !solid(x, y) {
+                continue;
+            }
+            let pixel = (x, y);
+            if !solid(x, y - 1) {
+                edges.push(((x, y), (x + 1, y), pixel));
+            }
+            if !solid(x + 1, y) {
+                edges.push(((x + 1, y), (x + 1, y + 1), pixel));
+            }
+            if !solid(x, y + 1) {
+                edges.push(((x + 1, y + 1), (x, y + 1), pixel));
+            }
+            if !solid(x - 1, y) {
+                edges.push(((x, y + 1), (x, y), pixel));
+            }
+        }
+    }
+    let mut starting = HashMap::<_, Vec<usize>>::new();
+    for (index, (from, _, _)) in edges.iter().enumerate() {
+        starting.entry(*from).or_default().push(index);
+    }
+    let mut used = vec![false; edges.len()];
+    let mut outlines = Vec::new();
+    for first in 0..edges.len() {
+        if used[first] {
+            continue;
+        }
+        let mut outline = Vec::new();
+        let mut edge = first;
+        while !used[edge] {
+            used[edge] = true;
+            let (from, to, pixel) = edges[edge];
+            outline.push(from);
+            // Where two parts touch only at a corner, keep them apart by
+            // going on around the same pixel.
+            let next = starting.get(&to).and_then(|next| {
+                next.iter()
+                    .copied()
+                    .filter(|next| !used[*next])
+                    .min_by_key(|next| edges[*next].2 != pixel)
+            });
+            match next {
+                Some(next) => edge = next,
+                None => break,
+            }
+        }
+        if area(&outline) > 0 {
+            outlines.push(
+                corners(&outline)
+                    .into_iter()
+                    .map(|(x, y)| (x as u32, y as u32))
+                    .collect(),
+            );
+        }
+    }
+    outlines
+}
+
+/// Twice the area inside `outline`, positive when it runs clockwise on
+/// screen, as outer edges do, and negative around holes.
+fn area(outline: &[(i64, i64)]) -> i64 {
+    outline
+        .iter()
+        .zip(outline.iter().cycle().skip(1))
+        .map(|(a, b)| a.0 * b.1 - b.0 * a.1)
+        .sum()
+}
+
+/// The points of `outline` where it changes direction.
+fn corners(outline: &[(i64, i64)]) -> Vec<(i64, i64)> {
+    let count = outline.len();
+    (0..count)
+        .filter(|index| {
+            let previous = outline[(index + count - 1) % count];
+            let point = outline[*index];
+            let next = outline[(index + 1) % count];
+            (point.0 - previous.0) * (next.1 - point.1)
+                != (point.1 - previous.1) * (next.0 - point.0)
+        })
+        .map(|index| outline[index])
+        .collect()
+}
+
+/// Drops the points of a closed `outline` less than `tolerance` from the
+/// line through their neighbours, by Douglas-Peucker from its first point
+/// and the point furthest from it.
+pub fn simplify(outline: &[Point], tolerance: f64) -> Vec<Point> {
+    if outline.len() <= 3 || tolerance <= 0.0 {
+        return outline.to_vec();
+    }
+    let distance = |a: Point, b: Point| {
+        let (dx, dy) = (
+            f64::from(a.0) - f64::from(b.0),
+            f64::from(a.1) - f64::from(b.1),
+        );
+        dx * dx + dy * dy
+    };
+    let far = (1..outline.len())
+        .max_by(|a, b| {
+            distance(outline[0], outline[*a]).total_cmp(&distance(outline[0], outline[*b]))
+        })
+        .unwrap_or(0);
+    let mut kept = vec![false; outline.len()];
+    kept[0] = true;
+    kept[far] = true;
+    let closed = outline
+        .iter()
+        .chain(std::iter::once(&outline[0]))
+        .copied()
+        .collect::<Vec<_>>();
+    douglas_peucker(&closed, 0, far, tolerance, &mut kept);
+    douglas_peucker(&closed, far, outline.len(), tolerance, &mut kept);
+    outline
+        .iter()
+        .zip(kept)
+        .filter(|(_, kept)| *kept)
+        .map(|(point, _)| *point)
+        .collect()
+}
+
+/// Marks the point between `from` and `to` furthest from the line joining
+/// them as kept, if it's further than `tolerance`, and does the same on
+/// either side of it.
+fn douglas_peucker(points: &[Point], from: usize, to: usize, tolerance: f64, kept: &mut [bool]) {
+    if to <= from + 1 {
+        return;
+    }
+    let (a, b) = (points[from], points[to]);
+    let (ax, ay) = (f64::from(a.0), f64::from(a.1));
+    let (dx, dy) = (f64::from(b.0) - ax, f64::from(b.1) - ay);
+    let length = (dx * dx + dy * dy).sqrt();
+    let (index, distance) = (from + 1..to)
+        .map(|index| {
+            let (px, py) = (
+                f64::from(points[index].0) - ax,
+                f64::from(points[index].1) - ay,
+            );
+            let distance = if length == 0.0 {
+                (px * px + py * py).sqrt()
+            } else {
+                (px * dy - py * dx).abs() / length
+            };
+            (index, distance)
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap_or((from, 0.0));
+    if distance > tolerance {
+        kept[index % kept.len()] = true;
+        douglas_peucker(points, from, index, tolerance, kept);
+        douglas_peucker(points, index, to, tolerance, kept);
+    }
+}
+
+/// The convex hull of `points`, clockwise on screen like the outlines.
+fn hull(mut points: Vec<Point>) -> Vec<Point> {
+    points.sort_unstable();
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+    let cross = |o: Point, a: Point, b: Point| {
+        (i64::from(a.0) - i64::from(o.0)) * (i64::from(b.1) - i64::from(o.1))
+            - (i64::from(a.1) - i64::from(o.1)) * (i64::from(b.0) - i64::from(o.0))
+    };
+    // Andrew's monotone chain, the lower half then the upper.
+    let mut hull = Vec::<Point>::with_capacity(points.len() * 2);
+    for pass in 0..2 {
+        let start = hull.len();
+        let ordered: Box<dyn Iterator<Item = &Point>> = match pass {
+            0 => Box::new(points.iter()),
+            _ => Box::new(points.iter().rev()),
+        };
+        for point in ordered {
+            while hull.len() >= start + 2
+                && cross(hull[hull.len() - 2], hull[hull.len() - 1], *point) <= 0
+            {
+                hull.pop();
+            }
+            hull.push(*point);
+        }
+        hull.pop();
+    }
+    hull
+}
@@ -0,0 +1,275 @@
+use clap::ArgMatches;
+use std::{env, error, fmt};
+
+use crate::BoxResult;
+
+/// Prefix for the environment variables that can stand in for any flag,
+/// e.g. `SPRITESHEET_ROOT` for `--root` or `SPRITESHEET_MAX_DEPTH` for `--max-depth`.
+pub const ENV_PREFIX: &str = "SPRITESHEET_";
+
+/// Looks up arguments on the command line first and falls back to the
+/// matching `SPRITESHEET_*` environment variable, so render farm jobs can be
+/// configured without rewriting command lines.
+pub struct Config<'a> {
+    matches: &'a ArgMatches<'a>,
+}
+
+impl<'a> Config<'a> {
+    pub fn new(matches: &'a ArgMatches<'a>) -> Self {
+        Config { matches }
+    }
+
+    pub fn value(&self, name: &str) -> Option<String> {
+        match self.matches.value_of(name) {
+            Some(value) => Some(value.to_owned()),
+            None => env::var(env_name(name)).ok().filter(|v| !v.is_empty()),
+        }
+    }
+
+    /// A value that has to be one of `allowed`.
+    pub fn choice(&self, name: &str, allowed: &[&str]) -> BoxResult<Option<String>> {
+        match self.value(name) {
+            Some(value) if !allowed.contains(&value.as_str()) => Err(EnvVarError {
+                name: env_name(name),
+                value,
+                expected: format!("one of {}", allowed.join(", ")),
+            }
+            .into()),
+            value => Ok(value),
+        }
+    }
+
+    /// A number, validated here since clap never sees environment values.
+    pub fn number(&self, name: &str) -> BoxResult<Option<usize>> {
+        match self.value(name) {
+            Some(value) => match value.parse() {
+                Ok(n) => Ok(Some(n)),
+                Err(_) => Err(EnvVarError {
+                    name: env_name(name),
+                    value,
+                    expected: "a whole number".to_owned(),
+                }
+                .into()),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// A byte count such as `2G` or `512MiB`, see `parse_size`.
+    pub fn size(&self, name: &str) -> BoxResult<Option<u64>> {
+        match self.value(name) {
+            Some(value) => match parse_size(&value) {
+                Some(size) => Ok(Some(size)),
+                None => Err(EnvVarError {
+                    name: env_name(name),
+                    value,
+                    expected: "a size like 512M or 2G".to_owned(),
+                }
+                .into()),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Repeatable arguments. The environment variable holds a `;`-separated list.
+    pub fn values(&self, name: &str) -> Vec<String> {
+        match self.matches.values_of(name) {
+            Some(values) => values.map(str::to_owned).collect(),
+            None => env::var(env_name(name))
+                .map(|list| {
+                    list.split(';')
+                        .filter(|v| !v.is_empty())
+                        .map(str::to_owned)
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Flags. The environment variable is on for `1`, `true`, `yes` or `on`.
+    pub fn flag(&self, name: &str) -> bool {
+        self.matches.is_present(name) || self.occurrences(name) > 0
+    }
+
+    /// Fails if both options of any of `pairs` are set, on the command line
+    /// or in the environment.
+    pub fn check_conflicts(&self, pairs: &[(&str, &str)]) -> Result<(), ConflictError> {
+        let set = |name: &str| {
+            self.matches.is_present(name)
+                || env::var(env_name(name)).is_ok_and(|value| {
+                    !matches!(
+                        value.to_ascii_lowercase().as_str(),
+                        "" | "0" | "false" | "no" | "off"
+                    )
+                })
+        };
+        match pairs
+            .iter()
+            .find(|(first, second)| set(first) && set(second))
+        {
+            Some((first, second)) => Err(ConflictError {
+                first: self.source(first),
+                second: self.source(second),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// How an option was given, for messages: `--force` or `SPRITESHEET_FORCE`.
+    fn source(&self, name: &str) -> String {
+        if self.matches.is_present(name) {
+            format!("--{}", name)
+        } else {
+            env_name(name)
+        }
+    }
+
+    /// Counted flags like `-vv`. The environment variable may hold the count.
+    pub fn occurrences(&self, name: &str) -> u64 {
+        match self.matches.occurrences_of(name) {
+            0 => match env::var(env_name(name)) {
+                Ok(value) => match value.to_ascii_lowercase().as_str() {
+                    "1" | "true" | "yes" | "on" => 1,
+                    other => other.parse().unwrap_or(0),
+                },
+                Err(_) => 0,
+            },
+            n => n,
+        }
+    }
+}
+
+/// `max-depth` becomes `SPRITESHEET_MAX_DEPTH`.
+pub fn env_name(arg: &str) -> String {
+    format!(
+        "{}{}",
+        ENV_PREFIX,
+        arg.to_ascii_uppercase().replace('-', "_")
+    )
+}
+
+/// Parses a byte count with an optional binary unit: `1048576`, `512K`,
+/// `1.5G`, `2GiB` and `100mb` are all accepted.
+pub fn parse_size(value: &str) -> Option<u64> {
+    let value = value.trim().to_ascii_lowercase();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let scale: u64 = match unit.trim().trim_end_matches("ib").trim_end_matches('b') {
+        "" => 1,
+        "k" => 1 << 10,
+        "m" => 1 << 20,
+        "g" => 1 << 30,
+        "t" => 1 << 40,
+        _ => return None,
+    };
+    let number = number.parse::<f64>().ok().filter(|n| n.is_finite())?;
+    Some((number * scale as f64) as u64)
+}
+
+#[derive(Debug, Clone)]
+pub struct EnvVarError {
+    pub name: String,
+    pub value: String,
+    pub expected: String,
+}
+
+impl fmt::Display for EnvVarError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}={:?} is invalid, expected {}",
+            self.name, self.value, self.expected
+        )
+    }
+}
+
+impl error::Error for EnvVarError {}
+
+#[derive(Debug, Clone)]
+pub struct ConflictError {
+    pub first: String,
+    pub second: String,
+}
+
+impl fmt::Display for ConflictError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} can't be used with {}", self.first, self.second)
+    }
+}
+
+impl error::Error for ConflictError {}
+
+#[derive(Debug, Clone)]
+pub struct MissingArgumentError {
+    pub name: String,
+}
+
+impl fmt::Display for MissingArgumentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "--{} is required (or set {})",
+            self.name,
+            env_name(&self.name)
+        )
+    }
+}
+
+impl error::Error for MissingArgumentError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{App, Arg};
+
+    #[test]
+    fn parses_plain_byte_counts() {
+        assert_eq!(parse_size("1048576"), Some(1048576));
+        assert_eq!(parse_size(" 42 "), Some(42));
+    }
+
+    #[test]
+    fn parses_binary_units_in_any_case_and_spelling() {
+        assert_eq!(parse_size("512K"), Some(512 << 10));
+        assert_eq!(parse_size("100mb"), Some(100 << 20));
+        assert_eq!(parse_size("2GiB"), Some(2 << 30));
+        assert_eq!(parse_size("1 t"), Some(1 << 40));
+    }
+
+    #[test]
+    fn parses_fractions() {
+        assert_eq!(parse_size("1.5G"), Some(3 << 29));
+        assert_eq!(parse_size("0.5k"), Some(512));
+    }
+
+    #[test]
+    fn rejects_other_units_and_missing_numbers() {
+        assert_eq!(parse_size("5x"), None);
+        assert_eq!(parse_size("10 bytes"), None);
+        assert_eq!(parse_size("G"), None);
+        assert_eq!(parse_size(""), None);
+    }
+
+    #[test]
+    fn finds_conflicts_set_in_the_environment() {
+        let app = App::new("test")
+            .arg(Arg::with_name("config-test-a").long("config-test-a"))
+            .arg(Arg::with_name("config-test-b").long("config-test-b"));
+        let matches = app.get_matches_from(["test", "--config-test-a"]);
+        let config = Config::new(&matches);
+        let pairs = &[("config-test-a", "config-test-b")];
+        assert!(config.check_conflicts(pairs).is_ok());
+
+        env::set_var("SPRITESHEET_CONFIG_TEST_B", "off");
+        assert!(config.check_conflicts(pairs).is_ok());
+        env::set_var("SPRITESHEET_CONFIG_TEST_B", "1");
+        let err = config.check_conflicts(pairs).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "--config-test-a can't be used with SPRITESHEET_CONFIG_TEST_B"
+        );
+        env::remove_var("SPRITESHEET_CONFIG_TEST_B");
+    }
+}
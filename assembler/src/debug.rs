@@ -0,0 +1,158 @@
+//! `--debug-overlay`: a copy of the sheet with the layout drawn on top, for
+//! working out why a sprite jitters or is cut off in the engine. Cell
+//! boundaries are magenta, padding added by `--pad-to-max` is tinted blue,
+//! the box around each frame's opaque pixels is green and the pivot, the
+//! cell's centre unless pivots are exported, is a red cross.
+
+use image::{Rgba, RgbaImage};
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    collect::Frame,
+    encode::{self, Compression},
+    metadata::Atlas,
+    pad::Anchor,
+    preview,
+    report::Padded,
+    simd, Dims, Result,
+};
+
+const CELL: Rgba<u8> = Rgba([255, 0, 255, 255]);
+const PADDING: Rgba<u8> = Rgba([0, 96, 255, 96]);
+const TRIM: Rgba<u8> = Rgba([0, 224, 0, 255]);
+const PIVOT: Rgba<u8> = Rgba([255, 0, 0, 255]);
+/// How far the pivot's cross reaches from its centre.
+const PIVOT_SIZE: i64 = 3;
+
+/// `hero.png` gets `hero_debug.png`.
+pub fn path(sheet: &Path) -> PathBuf {
+    let stem = sheet.file_stem().unwrap_or_default().to_string_lossy();
+    sheet.with_file_name(format!("{}_debug.png", stem))
+}
+
+/// Where a frame's own pixels are within its cell, as `(x, y, width,
+/// height)`, which is all of it unless it was padded.
+pub type Content = (u32, u32, u32, u32);
+
+/// Where each of `frames` has its own pixels, given the frames that were
+/// padded and where to.
+pub fn content(frames: &[Frame], padded: &[Padded], anchor: Anchor, dims: Dims) -> Vec<Content> {
+    let canvas = (dims.x as u32, dims.y as u32);
+    frames
+        .iter()
+        .map(
+            |frame| match padded.iter().find(|padded| padded.path == frame.path) {
+                Some(padded) => {
+                    let size = (padded.width, padded.height);
+                    let (x, y) = anchor.offset(size, canvas);
+                    (x, y, size.0, size.1)
+                }
+                None => (0, 0, canvas.0, canvas.1),
+            },
+        )
+        .collect()
+}
+
+/// Draws the overlay on the page described by `atlas`, whose frames' own
+/// pixels are at `content` within their cells, and writes it to `out` as a
+/// PNG.
+pub fn write(
+    sheet: &RgbaImage,
+    atlas: &Atlas,
+    content: &[Content],
+    out: &mut dyn Write,
+) -> Result<()> {
+    let mut overlay = sheet.clone();
+    for (frame, content) in atlas.frames.iter().zip(content) {
+        let (x, y, width, height) = (frame.x, frame.y, frame.width, frame.height);
+        let (left, top, inner_width, inner_height) = *content;
+        for cy in y..y + height {
+            for cx in x..x + width {
+                let inside = (left..left + inner_width).contains(&(cx - x))
+                    && (top..top + inner_height).contains(&(cy - y));
+                if !inside {
+                    blend(&mut overlay, cx, cy, PADDING);
+                }
+            }
+        }
+        rectangle(&mut overlay, (x, y, x + width - 1, y + height - 1), CELL);
+        if let Some(bounds) = opaque_bounds(sheet, x, y, width, height) {
+            rectangle(&mut overlay, bounds, TRIM);
+        }
+        let (px, py) = frame.pivot.unwrap_or((0.5, 0.5));
+        let pivot = (
+            i64::from(x) + (px * f64::from(width)) as i64,
+            i64::from(y) + (py * f64::from(height)) as i64,
+        );
+        for offset in -PIVOT_SIZE..=PIVOT_SIZE {
+            plot(&mut overlay, pivot.0 + offset, pivot.1, PIVOT);
+            plot(&mut overlay, pivot.0, pivot.1 + offset, PIVOT);
+        }
+    }
+    encode::encoder(out, overlay.dimensions(), Compression::Fast)
+        .write_header()?
+        .write_image_data(&overlay)?;
+    Ok(())
+}
+
+/// The smallest box, inclusive, holding the cell's opaque pixels, or
+/// `None` if it's empty.
+pub fn opaque_bounds(
+    sheet: &RgbaImage,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+) -> Option<(u32, u32, u32, u32)> {
+    let (pixels, row_bytes): (&[u8], _) = (sheet, sheet.width() as usize * 4);
+    let mut bounds: Option<(u32, u32, u32, u32)> = None;
+    for cy in y..y + height {
+        let row = &pixels[cy as usize * row_bytes + x as usize * 4..][..width as usize * 4];
+        let (first, last) = match simd::opaque_span(row) {
+            Some((first, last)) => (x + first as u32, x + last as u32),
+            None => continue,
+        };
+        bounds = Some(match bounds {
+            Some((left, top, right, bottom)) => (
+                left.min(first),
+                top.min(cy),
+                right.max(last),
+                bottom.max(cy),
+            ),
+            None => (first, cy, last, cy),
+        });
+    }
+    bounds
+}
+
+/// Outlines a box whose corners are inclusive.
+fn rectangle(
+    image: &mut RgbaImage,
+    (left, top, right, bottom): (u32, u32, u32, u32),
+    colour: Rgba<u8>,
+) {
+    for x in left..=right {
+        image.put_pixel(x, top, colour);
+        image.put_pixel(x, bottom, colour);
+    }
+    for y in top..=bottom {
+        image.put_pixel(left, y, colour);
+        image.put_pixel(right, y, colour);
+    }
+}
+
+/// Sets a pixel, unless it's off the image.
+fn plot(image: &mut RgbaImage, x: i64, y: i64, colour: Rgba<u8>) {
+    if (0..i64::from(image.width())).contains(&x) && (0..i64::from(image.height())).contains(&y) {
+        image.put_pixel(x as u32, y as u32, colour);
+    }
+}
+
+/// Draws a translucent `colour` over a pixel.
+fn blend(image: &mut RgbaImage, x: u32, y: u32, colour: Rgba<u8>) {
+    let under = *image.get_pixel(x, y);
+    image.put_pixel(x, y, preview::blend(colour, under));
+}
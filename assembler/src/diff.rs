@@ -0,0 +1,278 @@
+//! `assembler diff OLD NEW`: compares two versions of a sheet frame by
+//! frame, so CI can catch sprites that changed when nobody meant them to.
+
+use image::{Rgba, RgbaImage};
+use log::{info, warn};
+use serde::Serialize;
+use std::{error, fmt, path::Path};
+
+use crate::{
+    sheet::{self, Rect, Sheet},
+    BoxResult,
+};
+
+/// The largest YIQ distance between two colours, between black and white.
+const MAX_DELTA: f64 = 35215.0;
+
+/// Some frames changed by more than the threshold, or were added, removed
+/// or resized.
+#[derive(Debug, Clone)]
+pub struct DiffError {
+    pub changed: usize,
+    pub compared: usize,
+    pub threshold: f64,
+}
+
+impl fmt::Display for DiffError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} frames changed by more than {}",
+            self.changed, self.compared, self.threshold
+        )
+    }
+}
+
+impl error::Error for DiffError {}
+
+/// How a frame of the new sheet compares with the old.
+#[derive(Debug, Serialize)]
+pub struct FrameDiff {
+    pub name: String,
+    pub status: Status,
+    /// The mean perceptual difference of the frame's pixels, from 0 for
+    /// identical to 1 for black against white.
+    pub difference: f64,
+    /// How many pixels changed at all.
+    pub pixels: u64,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Same,
+    Changed,
+    Added,
+    Removed,
+    Resized,
+}
+
+/// A sheet image and its frames, or the whole image as one frame named
+/// `sheet` when it has no JSON metadata next to it.
+struct Frames {
+    image: RgbaImage,
+    frames: Vec<sheet::Frame>,
+}
+
+/// Compares the frames of `old` and `new`, matched by name, printing how
+/// each changed, and fails if any changed by more than `threshold`. Writes
+/// a picture of the changes to `output` if given.
+pub fn run(
+    old: &Path,
+    new: &Path,
+    threshold: f64,
+    output: Option<&Path>,
+    json: bool,
+) -> BoxResult<()> {
+    let old = open(old)?;
+    let new = open(new)?;
+    let mut picture = output.map(|_| faded(&new.image));
+    let mut diffs = Vec::new();
+    for frame in &new.frames {
+        let before = old.frames.iter().find(|before| before.name == frame.name);
+        let diff = match before {
+            None => FrameDiff {
+                name: frame.name.clone(),
+                status: Status::Added,
+                difference: 1.0,
+                pixels: u64::from(frame.rect.w) * u64::from(frame.rect.h),
+            },
+            Some(before) if (before.rect.w, before.rect.h) != (frame.rect.w, frame.rect.h) => {
+                FrameDiff {
+                    name: frame.name.clone(),
+                    status: Status::Resized,
+                    difference: 1.0,
+                    pixels: u64::from(frame.rect.w) * u64::from(frame.rect.h),
+                }
+            }
+            Some(before) => {
+                let (difference, pixels) = compare(
+                    &old.image,
+                    before.rect,
+                    &new.image,
+                    frame.rect,
+                    picture.as_mut(),
+                );
+                FrameDiff {
+                    name: frame.name.clone(),
+                    status: if pixels == 0 {
+                        Status::Same
+                    } else {
+                        Status::Changed
+                    },
+                    difference,
+                    pixels,
+                }
+            }
+        };
+        if let (Some(picture), Status::Added | Status::Resized) = (picture.as_mut(), diff.status) {
+            mark(picture, frame.rect);
+        }
+        diffs.push(diff);
+    }
+    for frame in &old.frames {
+        if !new.frames.iter().any(|after| after.name == frame.name) {
+            diffs.push(FrameDiff {
+                name: frame.name.clone(),
+                status: Status::Removed,
+                difference: 1.0,
+                pixels: u64::from(frame.rect.w) * u64::from(frame.rect.h),
+            });
+        }
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&diffs)?);
+    }
+    let over = |diff: &FrameDiff| diff.status != Status::Same && diff.difference > threshold;
+    for diff in &diffs {
+        let line = match diff.status {
+            Status::Same => continue,
+            Status::Changed => format!(
+                "{} changed by {:.4} ({} pixels)",
+                diff.name, diff.difference, diff.pixels
+            ),
+            Status::Added => format!("{} was added", diff.name),
+            Status::Removed => format!("{} was removed", diff.name),
+            Status::Resized => format!("{} changed size", diff.name),
+        };
+        if over(diff) {
+            warn!("{}", line);
+        } else {
+            info!("{}", line);
+        }
+    }
+    if let (Some(picture), Some(output)) = (picture, output) {
+        picture.save(output)?;
+        info!("Wrote the changes to {}", output.display());
+    }
+
+    let changed = diffs.iter().filter(|diff| over(diff)).count();
+    if changed > 0 {
+        return Err(DiffError {
+            changed,
+            compared: diffs.len(),
+            threshold,
+        }
+        .into());
+    }
+    info!("No frame changed by more than {}", threshold);
+    Ok(())
+}
+
+/// Reads a sheet from its JSON metadata or its image, using the metadata
+/// next to the image if there is one.
+fn open(path: &Path) -> BoxResult<Frames> {
+    if let Some((metadata, image)) = sheet::metadata_for(path) {
+        let sheet = Sheet::open_with(&metadata, image)?;
+        return Ok(Frames {
+            image: sheet.image,
+            frames: sheet.frames,
+        });
+    }
+    let image = image::open(path)
+        .map_err(|err| sheet::SheetFileError {
+            path: path.to_path_buf(),
+            message: err.to_string(),
+        })?
+        .to_rgba();
+    let rect = Rect {
+        x: 0,
+        y: 0,
+        w: image.width(),
+        h: image.height(),
+    };
+    Ok(Frames {
+        image,
+        frames: vec![sheet::Frame {
+            name: "sheet".to_owned(),
+            rect,
+            duration: None,
+        }],
+    })
+}
+
+/// The mean perceptual difference between the same-sized `before` cell of
+/// `old` and `after` cell of `new`, and how many pixels differ, marking
+/// those on `picture`.
+fn compare(
+    old: &RgbaImage,
+    before: Rect,
+    new: &RgbaImage,
+    after: Rect,
+    mut picture: Option<&mut RgbaImage>,
+) -> (f64, u64) {
+    let mut total = 0.0;
+    let mut pixels = 0;
+    for row in 0..after.h {
+        for column in 0..after.w {
+            let a = old.get_pixel(before.x + column, before.y + row);
+            let b = new.get_pixel(after.x + column, after.y + row);
+            if a == b {
+                continue;
+            }
+            let delta = delta(a, b);
+            total += delta;
+            pixels += 1;
+            if let Some(picture) = picture.as_deref_mut() {
+                let red = (128.0 + delta * 127.0) as u8;
+                picture.put_pixel(after.x + column, after.y + row, Rgba([red, 0, 0, 255]));
+            }
+        }
+    }
+    let area = (f64::from(after.w) * f64::from(after.h)).max(1.0);
+    (total / area, pixels)
+}
+
+/// How different two pixels look over white, from 0 to 1, as the distance
+/// between their YIQ colours, which weighs brightness over hue the way eyes
+/// do.
+fn delta(a: &Rgba<u8>, b: &Rgba<u8>) -> f64 {
+    let (y1, i1, q1) = yiq(a);
+    let (y2, i2, q2) = yiq(b);
+    let (y, i, q) = (y1 - y2, i1 - i2, q1 - q2);
+    ((0.5053 * y * y + 0.299 * i * i + 0.1957 * q * q) / MAX_DELTA).sqrt()
+}
+
+fn yiq(pixel: &Rgba<u8>) -> (f64, f64, f64) {
+    let alpha = f64::from(pixel[3]) / 255.0;
+    let over_white = |channel: u8| 255.0 + (f64::from(channel) - 255.0) * alpha;
+    let (r, g, b) = (
+        over_white(pixel[0]),
+        over_white(pixel[1]),
+        over_white(pixel[2]),
+    );
+    (
+        0.29889531 * r + 0.58662247 * g + 0.11448223 * b,
+        0.59597799 * r - 0.27417610 * g - 0.32180189 * b,
+        0.21147017 * r - 0.52261711 * g + 0.31114694 * b,
+    )
+}
+
+/// The new sheet in faint grey, for changes to be drawn over.
+fn faded(image: &RgbaImage) -> RgbaImage {
+    RgbaImage::from_fn(image.width(), image.height(), |x, y| {
+        let (luma, _, _) = yiq(image.get_pixel(x, y));
+        let grey = (255.0 - (255.0 - luma) * 0.1) as u8;
+        Rgba([grey, grey, grey, 255])
+    })
+}
+
+/// Marks a whole frame as changed.
+fn mark(picture: &mut RgbaImage, rect: Rect) {
+    for y in rect.y..rect.y + rect.h {
+        for x in rect.x..rect.x + rect.w {
+            picture.put_pixel(x, y, Rgba([255, 0, 0, 255]));
+        }
+    }
+}
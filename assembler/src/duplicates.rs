@@ -0,0 +1,95 @@
+//! `--find-duplicates`: points out frames that were rendered twice, or
+//! barely change from the one before, so the artist can tell an accidental
+//! double render from a hold that could be a duration instead.
+
+use log::{info, warn};
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+use crate::{
+    collect::Frame,
+    report::{self, Duplicate, Report, Similar},
+    timing,
+};
+
+/// How alike consecutive frames must be to be reported, unless set.
+pub const DEFAULT_SIMILARITY: f64 = 0.99;
+
+/// Reports frames with exactly the same pixels as an earlier frame, and
+/// consecutive frames of one animation at least `threshold` similar.
+pub fn find(frames: &[Frame], threshold: f64, report: &mut Report) {
+    let hashes = frames
+        .par_iter()
+        .map(|frame| frame.image.as_ref().map(|image| report::sha256(image)))
+        .collect::<Vec<_>>();
+    let mut first = HashMap::<&str, usize>::new();
+    for (index, hash) in hashes.iter().enumerate() {
+        let hash = match hash {
+            Some(hash) => hash,
+            None => continue,
+        };
+        match first.get(hash.as_str()) {
+            Some(&original) => {
+                let (path, of) = (&frames[index].path, &frames[original].path);
+                warn!("{} is a duplicate of {}", path.display(), of.display());
+                report.duplicates.push(Duplicate {
+                    path: path.clone(),
+                    of: of.clone(),
+                });
+            }
+            None => {
+                first.insert(hash.as_str(), index);
+            }
+        }
+    }
+
+    let similar = (1..frames.len())
+        .into_par_iter()
+        .filter_map(|index| {
+            let (previous, frame) = (&frames[index - 1], &frames[index]);
+            if hashes[index] == hashes[index - 1]
+                || timing::animation(previous).0 != timing::animation(frame).0
+            {
+                return None;
+            }
+            let score = similarity(previous.image.as_ref()?, frame.image.as_ref()?);
+            Some((index, score)).filter(|_| score >= threshold)
+        })
+        .collect::<Vec<_>>();
+    for (index, similarity) in similar {
+        let (previous, path) = (&frames[index - 1].path, &frames[index].path);
+        info!(
+            "{} is {:.2}% like {}",
+            path.display(),
+            similarity * 100.0,
+            previous.display()
+        );
+        report.similar.push(Similar {
+            path: path.clone(),
+            previous: previous.clone(),
+            similarity,
+        });
+    }
+}
+
+/// How alike two frames of the same size are, from 0 to 1, as one minus
+/// the mean difference of their channels. Colour is weighted by alpha, so
+/// changes hidden under transparent pixels don't count.
+fn similarity(a: &[u8], b: &[u8]) -> f64 {
+    let difference = a
+        .chunks_exact(4)
+        .zip(b.chunks_exact(4))
+        .map(|(a, b)| {
+            let colour = (0..3)
+                .map(|channel| {
+                    let a = u32::from(a[channel]) * u32::from(a[3]);
+                    let b = u32::from(b[channel]) * u32::from(b[3]);
+                    a.abs_diff(b) as f64 / 255.0
+                })
+                .sum::<f64>();
+            colour + f64::from(a[3].abs_diff(b[3]))
+        })
+        .sum::<f64>();
+    let channels = a.len().max(1) as f64;
+    1.0 - difference / (channels * 255.0)
+}
@@ -0,0 +1,62 @@
+//! Fully transparent frames, the usual sign of a broken render layer, which
+//! would otherwise leave a silent gap in an animation.
+
+use image::RgbaImage;
+use log::warn;
+use serde::Serialize;
+use std::path::Path;
+
+use crate::{errors::EmptyFrameError, simd, Result};
+
+/// What `--empty-frames` does about a fully transparent frame.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmptyFrames {
+    /// Log a warning and keep the frame.
+    Warn,
+    /// Fail the run.
+    Error,
+    /// Leave the frame off the sheet.
+    Skip,
+}
+
+impl EmptyFrames {
+    pub const NAMES: &'static [&'static str] = &["warn", "error", "skip"];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "warn" => Some(EmptyFrames::Warn),
+            "error" => Some(EmptyFrames::Error),
+            "skip" => Some(EmptyFrames::Skip),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            EmptyFrames::Warn => "warn",
+            EmptyFrames::Error => "error",
+            EmptyFrames::Skip => "skip",
+        }
+    }
+}
+
+/// Whether every pixel of `image` has zero alpha.
+pub fn is_empty(image: &RgbaImage) -> bool {
+    simd::opaque_span(image).is_none()
+}
+
+/// Whether to keep the empty frame at `path`, failing if `policy` says so.
+pub fn keep(policy: EmptyFrames, path: &Path) -> Result<bool> {
+    match policy {
+        EmptyFrames::Warn => {
+            warn!("{} is fully transparent", path.display());
+            Ok(true)
+        }
+        EmptyFrames::Error => Err(EmptyFrameError {
+            path: path.to_path_buf(),
+        }
+        .into()),
+        EmptyFrames::Skip => Ok(false),
+    }
+}
@@ -0,0 +1,56 @@
+//! PNG encoding settings shared by the in-memory and streaming paths.
+
+use serde::Serialize;
+use std::io::Write;
+
+/// How hard to compress the sheet.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    /// A balance of speed and size.
+    Default,
+    /// Quick to write for iteration builds, but noticeably larger.
+    Fast,
+    /// The smallest files, for release assets.
+    Best,
+    /// A deflate level from 0 (store only) to 9.
+    Level(u8),
+}
+
+impl Compression {
+    pub const MAX_LEVEL: u8 = 9;
+
+    /// The same compression as an explicit deflate level, so the bytes
+    /// written don't depend on what the png crate's presets mean.
+    pub fn fixed(self) -> Self {
+        match self {
+            Compression::Default => Compression::Level(6),
+            Compression::Fast => Compression::Level(1),
+            Compression::Best => Compression::Level(9),
+            level => level,
+        }
+    }
+}
+
+/// An encoder for an 8-bit RGBA image writing to `out`. It writes no
+/// ancillary chunks, so there are no timestamps or text to vary between runs.
+pub fn encoder<W: Write>(
+    out: W,
+    (width, height): (u32, u32),
+    compression: Compression,
+) -> png::Encoder<'static, W> {
+    let mut encoder = png::Encoder::new(out, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    match compression {
+        Compression::Default => encoder.set_compression(png::Compression::Balanced),
+        Compression::Fast => encoder.set_compression(png::Compression::Fast),
+        Compression::Best => encoder.set_compression(png::Compression::High),
+        Compression::Level(0) => encoder.set_compression(png::Compression::NoCompression),
+        Compression::Level(level) => {
+            encoder.set_deflate_compression(png::DeflateCompression::Level(level));
+            encoder.set_filter(png::Filter::Adaptive);
+        }
+    }
+    encoder
+}
@@ -1,34 +1,483 @@
-use std::{error, fmt};
+//! Everything that can make an assembly fail. Library users match on
+//! [`AssemblerError`]; the structs are the variants that carry more than a
+//! source error.
 
-#[derive(Debug, Clone)]
-pub struct ImageFormatError;
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
 
-impl fmt::Display for ImageFormatError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Images should be 8-bit RGBA")
+use crate::report::Gap;
+
+/// Any error from an assembly.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum AssemblerError {
+    #[error(transparent)]
+    ImageFormat(#[from] ImageFormatError),
+    #[error(transparent)]
+    InconsistentSize(#[from] InconsistentSizeError),
+    #[error(transparent)]
+    NoImages(#[from] NoImagesError),
+    #[error(transparent)]
+    OutputExists(#[from] OutputExistsError),
+    #[error(transparent)]
+    Template(#[from] TemplateError),
+    #[error(transparent)]
+    UnsafeCleanup(#[from] UnsafeCleanupError),
+    #[error(transparent)]
+    SheetTooLarge(#[from] SheetTooLargeError),
+    #[error(transparent)]
+    SheetLimit(#[from] SheetLimitError),
+    #[error(transparent)]
+    MetadataFormat(#[from] MetadataFormatError),
+    #[error(transparent)]
+    Interlaced(#[from] InterlacedImageError),
+    #[error(transparent)]
+    Option(#[from] OptionError),
+    #[error(transparent)]
+    Filter(#[from] FilterError),
+    #[error(transparent)]
+    Durations(#[from] DurationsError),
+    #[error(transparent)]
+    Sequence(#[from] SequenceError),
+    #[error(transparent)]
+    NamePattern(#[from] NamePatternError),
+    #[error(transparent)]
+    Markers(#[from] MarkersError),
+    #[error(transparent)]
+    NineSlice(#[from] NineSliceError),
+    #[error(transparent)]
+    DuplicateFrameName(#[from] DuplicateFrameNameError),
+    #[error(transparent)]
+    Confinement(#[from] ConfinementError),
+    #[error(transparent)]
+    EmptyFrame(#[from] EmptyFrameError),
+    #[error(transparent)]
+    SequenceGap(#[from] SequenceGapError),
+    #[error(transparent)]
+    MixedFormat(#[from] MixedFormatError),
+    #[error(transparent)]
+    Locked(#[from] LockedError),
+    #[error(transparent)]
+    Video(#[from] VideoError),
+    #[error(transparent)]
+    PivotMarker(#[from] PivotMarkerError),
+    #[error(transparent)]
+    MissingPass(#[from] MissingPassError),
+    #[error(transparent)]
+    MissingSheet(#[from] MissingSheetError),
+    /// Reading or writing a file failed. `phase` and `path` are known for
+    /// the frames and outputs the assembler handles itself.
+    #[error("{}", with_context(source, *phase, path.as_deref()))]
+    Io {
+        phase: Option<Phase>,
+        path: Option<PathBuf>,
+        source: io::Error,
+    },
+    /// A frame couldn't be decoded.
+    #[error("{}", with_context(source, Some(Phase::Read), path.as_deref()))]
+    Image {
+        path: Option<PathBuf>,
+        source: image::ImageError,
+    },
+    /// `--low-memory` couldn't read a frame's PNG stream.
+    #[error("{}", with_context(source, Some(Phase::Read), path.as_deref()))]
+    Png {
+        path: Option<PathBuf>,
+        source: png::DecodingError,
+    },
+    /// The sheet couldn't be encoded.
+    #[error(transparent)]
+    Encode(#[from] png::EncodingError),
+    /// The cache, report or a metadata file couldn't be serialized.
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[cfg(feature = "fs")]
+    #[error(transparent)]
+    Walk(#[from] walkdir::Error),
+    /// The run was stopped with a [`CancelHandle`](crate::CancelHandle).
+    #[error("The run was cancelled")]
+    Cancelled,
+    /// An `--exclude` pattern isn't a valid glob.
+    #[cfg(feature = "fs")]
+    #[error(transparent)]
+    Glob(#[from] globset::Error),
+}
+
+impl AssemblerError {
+    /// The file the error is about, if there is one.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            AssemblerError::ImageFormat(err) => Some(&err.path),
+            AssemblerError::InconsistentSize(err) => Some(&err.path),
+            AssemblerError::OutputExists(err) => Some(&err.path),
+            AssemblerError::UnsafeCleanup(err) => Some(&err.frames),
+            AssemblerError::Interlaced(err) => Some(&err.path),
+            AssemblerError::Durations(err) => Some(&err.path),
+            AssemblerError::Markers(err) => Some(&err.path),
+            AssemblerError::EmptyFrame(err) => Some(&err.path),
+            AssemblerError::Io { path, .. }
+            | AssemblerError::Image { path, .. }
+            | AssemblerError::Png { path, .. } => path.as_deref(),
+            #[cfg(feature = "fs")]
+            AssemblerError::Walk(err) => err.path(),
+            _ => None,
+        }
+    }
+
+    /// The message without the path in front, for reports that already
+    /// list it.
+    pub(crate) fn reason(&self) -> String {
+        match self {
+            AssemblerError::Io { source, .. } => source.to_string(),
+            AssemblerError::Image { source, .. } => source.to_string(),
+            AssemblerError::Png { source, .. } => source.to_string(),
+            err => err.to_string(),
+        }
     }
 }
 
-impl error::Error for ImageFormatError {}
+impl From<io::Error> for AssemblerError {
+    fn from(source: io::Error) -> Self {
+        AssemblerError::Io {
+            phase: None,
+            path: None,
+            source,
+        }
+    }
+}
 
-#[derive(Debug, Clone)]
-pub struct InconsistentSizeError;
+impl From<image::ImageError> for AssemblerError {
+    fn from(source: image::ImageError) -> Self {
+        AssemblerError::Image { path: None, source }
+    }
+}
 
-impl fmt::Display for InconsistentSizeError {
+impl From<png::DecodingError> for AssemblerError {
+    fn from(source: png::DecodingError) -> Self {
+        AssemblerError::Png { path: None, source }
+    }
+}
+
+/// The step an IO error happened in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Phase {
+    /// Reading a frame.
+    Read,
+    /// Writing the sheet, its metadata or another output.
+    Write,
+    /// Removing the frames folder for `--cleanup`.
+    Cleanup,
+}
+
+impl fmt::Display for Phase {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Images should all be the same size")
+        f.write_str(match self {
+            Phase::Read => "reading",
+            Phase::Write => "writing",
+            Phase::Cleanup => "removing",
+        })
     }
 }
 
-impl error::Error for InconsistentSizeError {}
+fn with_context(source: &dyn fmt::Display, phase: Option<Phase>, path: Option<&Path>) -> String {
+    match (phase, path) {
+        (Some(phase), Some(path)) => format!("Failed {} {}: {}", phase, path.display(), source),
+        (None, Some(path)) => format!("{}: {}", path.display(), source),
+        _ => source.to_string(),
+    }
+}
 
-#[derive(Debug, Clone)]
-pub struct NoImagesError;
+/// Attaches the phase and file to an IO error.
+pub(crate) trait IoContext<T> {
+    fn context(self, phase: Phase, path: &Path) -> Result<T, AssemblerError>;
+}
 
-impl fmt::Display for NoImagesError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "No images found")
+impl<T> IoContext<T> for Result<T, io::Error> {
+    fn context(self, phase: Phase, path: &Path) -> Result<T, AssemblerError> {
+        self.map_err(|source| AssemblerError::Io {
+            phase: Some(phase),
+            path: Some(path.to_path_buf()),
+            source,
+        })
     }
 }
 
-impl error::Error for NoImagesError {}
+/// A frame isn't 8-bit RGBA.
+#[derive(Debug, Clone, Error)]
+#[error("Images should be 8-bit RGBA, but {} isn't", .path.display())]
+pub struct ImageFormatError {
+    pub path: PathBuf,
+}
+
+/// `path` doesn't match the size of the `first` frame.
+#[derive(Debug, Clone, Error)]
+#[error(
+    "Images should all be the same size, but {} is {}x{}px and {} is {}x{}px",
+    .first.display(),
+    .first_size.0,
+    .first_size.1,
+    .path.display(),
+    .size.0,
+    .size.1
+)]
+pub struct InconsistentSizeError {
+    pub first: PathBuf,
+    pub first_size: (u32, u32),
+    pub path: PathBuf,
+    pub size: (u32, u32),
+}
+
+#[derive(Debug, Clone, Error)]
+#[error("No images found")]
+pub struct NoImagesError;
+
+#[derive(Debug, Clone, Error)]
+#[error(
+    "{} already exists, pass --force to overwrite it or --backup to keep a copy",
+    .path.display()
+)]
+pub struct OutputExistsError {
+    pub path: PathBuf,
+}
+
+/// Another run holds the lock on the root.
+#[derive(Debug, Clone, Error)]
+#[error(
+    "Another assembly is running in {}; pass --wait to wait for it to finish",
+    .root.display()
+)]
+pub struct LockedError {
+    pub root: PathBuf,
+}
+
+#[derive(Debug, Clone, Error)]
+#[error("Unknown or malformed token {{{token}}} in '{template}'")]
+pub struct TemplateError {
+    pub template: String,
+    pub token: String,
+}
+
+#[derive(Debug, Clone, Error)]
+#[error(
+    "Refusing to --cleanup {}, the sheet would be written inside it",
+    .frames.display()
+)]
+pub struct UnsafeCleanupError {
+    pub frames: PathBuf,
+}
+
+#[derive(Debug, Clone, Error)]
+#[error("A {width}x{height}px sheet is too large to assemble")]
+pub struct SheetTooLargeError {
+    pub width: u64,
+    pub height: u64,
+}
+
+/// A page is bigger than `--fail-if-larger-than` allows. `suggestions`
+/// are the changes that would make it fit.
+#[derive(Debug, Clone, Error)]
+#[error(
+    "The sheet would be {width}x{height}px, over the {}x{}px limit{}",
+    .limit.0,
+    .limit.1,
+    suggest(.suggestions)
+)]
+pub struct SheetLimitError {
+    pub width: u32,
+    pub height: u32,
+    pub limit: (u32, u32),
+    pub suggestions: Vec<String>,
+}
+
+fn suggest(suggestions: &[String]) -> String {
+    suggestions
+        .iter()
+        .enumerate()
+        .map(|(index, suggestion)| {
+            let lead = if index == 0 {
+                ". It would fit with"
+            } else {
+                "; or"
+            };
+            format!("{} {}", lead, suggestion)
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Error)]
+#[error("Unknown metadata format '{name}'")]
+pub struct MetadataFormatError {
+    pub name: String,
+}
+
+/// Interlaced PNGs store their rows out of order, so they can't be streamed.
+#[derive(Debug, Clone, Error)]
+#[error(
+    "{} is interlaced, which --low-memory can't read; re-save it without interlacing",
+    .path.display()
+)]
+pub struct InterlacedImageError {
+    pub path: PathBuf,
+}
+
+/// [`Assembler::option`](crate::Assembler::option) was given an unknown
+/// name or a value the option can't take.
+#[derive(Debug, Clone, Error)]
+#[error("Can't set option {name} to '{value}', expected {expected}")]
+pub struct OptionError {
+    pub name: String,
+    pub value: String,
+    pub expected: String,
+}
+
+/// A `--filter-chain` entry couldn't be parsed, or a filter misbehaved.
+#[derive(Debug, Clone, Error)]
+#[error("Filter '{filter}' {message}")]
+pub struct FilterError {
+    pub filter: String,
+    pub message: String,
+}
+
+/// The frames folder's `durations.json` couldn't be read or holds something
+/// other than tick counts.
+#[derive(Debug, Clone, Error)]
+#[error("{}: {message}", .path.display())]
+pub struct DurationsError {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+/// A `--sequence` couldn't be parsed, or names a frame the sheet doesn't
+/// have.
+#[derive(Debug, Clone, Error)]
+#[error("Can't play sequence '{spec}', {message}")]
+pub struct SequenceError {
+    pub spec: String,
+    pub message: String,
+}
+
+/// A `--name-pattern` couldn't be compiled, or has nowhere for the frame
+/// number.
+#[derive(Debug, Clone, Error)]
+#[error("Can't find frame numbers with '{pattern}', it {message}")]
+pub struct NamePatternError {
+    pub pattern: String,
+    pub message: String,
+}
+
+/// `--strict-format` found frames of different bit depths or colour spaces.
+#[derive(Debug, Clone, Error)]
+#[error("{message}")]
+pub struct MixedFormatError {
+    pub message: String,
+}
+
+/// `--strict-sequence error` found frame numbers missing or repeated.
+#[derive(Debug, Clone, Error)]
+#[error(
+    "{}, which usually means a render stopped partway or was run twice",
+    crate::gaps::describe(.gap)
+)]
+pub struct SequenceGapError {
+    pub gap: Gap,
+}
+
+/// ffmpeg couldn't make the `--preview-video` clip.
+#[derive(Debug, Clone, Error)]
+#[error("ffmpeg couldn't write {}: {message}", .path.display())]
+pub struct VideoError {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+/// A frame has more than one pixel of the `--pivot-marker` colour.
+#[derive(Debug, Clone, Error)]
+#[error(
+    "{} has {count} pixels of the pivot marker colour, but should have one",
+    .path.display()
+)]
+pub struct PivotMarkerError {
+    pub path: PathBuf,
+    pub count: usize,
+}
+
+/// A frame's file in another `--pass` wasn't found.
+#[derive(Debug, Clone, Error)]
+#[error(
+    "{} has no {pass} pass, which should be at {}",
+    .frame.display(),
+    .path.display()
+)]
+pub struct MissingPassError {
+    pub pass: String,
+    pub frame: PathBuf,
+    pub path: PathBuf,
+}
+
+/// `--metadata-only` found no sheet where the frames' sheet should be, or
+/// one of another size.
+#[derive(Debug, Clone, Error)]
+#[error(
+    "{} {reason}, so there's no sheet to write metadata for; assemble it without --metadata-only first",
+    .path.display()
+)]
+pub struct MissingSheetError {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+/// A `--markers` file couldn't be read or isn't a list of markers.
+#[derive(Debug, Clone, Error)]
+#[error("{}: {message}", .path.display())]
+pub struct MarkersError {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+/// A `--nine-slices` file couldn't be read, or a frame's 9-slice insets
+/// don't fit it.
+#[derive(Debug, Clone, Error)]
+#[error("{what}: {message}")]
+pub struct NineSliceError {
+    /// The file, or the frame.
+    pub what: String,
+    pub message: String,
+}
+
+/// `--frame-name` gives two frames of a sheet the same name, so the
+/// metadata couldn't tell them apart.
+#[derive(Debug, Clone, Error)]
+#[error(
+    "--frame-name '{template}' names both {} and {} '{name}', add a token such as {{anim}} or {{index}} to tell them apart",
+    .first.display(),
+    .second.display()
+)]
+pub struct DuplicateFrameNameError {
+    pub template: String,
+    pub name: String,
+    pub first: PathBuf,
+    pub second: PathBuf,
+}
+
+/// An output would be written outside the folder the assembler is
+/// confined to.
+#[derive(Debug, Clone, Error)]
+#[error("{} is outside the folder jobs may use, {}", .path.display(), .dir.display())]
+pub struct ConfinementError {
+    pub path: PathBuf,
+    pub dir: PathBuf,
+}
+
+/// A frame is fully transparent and `--empty-frames error` was given.
+#[derive(Debug, Clone, Error)]
+#[error(
+    "{} is fully transparent, which usually means a render layer is broken",
+    .path.display()
+)]
+pub struct EmptyFrameError {
+    pub path: PathBuf,
+}
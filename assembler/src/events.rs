@@ -0,0 +1,53 @@
+//! Progress events for hosts that draw their own progress bars and log
+//! panels, such as the Blender add-on. Register a handler with
+//! [`Assembler::on_event`](crate::Assembler::on_event).
+
+use serde::Serialize;
+use std::{fmt, path::PathBuf, sync::Arc};
+
+/// Something that happened during an assembly. Events may come from any of
+/// the assembler's threads, so counts can arrive slightly out of order.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum Event {
+    /// `done` of the `total` frames have been decoded, or with
+    /// `--low-memory`, checked.
+    FramesDecoded { done: u64, total: u64 },
+    /// A frame was left out, and why.
+    FrameSkipped { path: PathBuf, reason: String },
+    /// Page `page` (counting from 0) of `pages` has been composited.
+    PageComposited { page: usize, pages: usize },
+    /// `bytes` of encoded sheet have been written so far.
+    BytesWritten { bytes: u64 },
+    /// An output file is in place.
+    OutputWritten { path: PathBuf, bytes: u64 },
+}
+
+/// Where events go. The default drops them.
+#[derive(Clone, Default)]
+pub(crate) struct Events(Option<Arc<Handler>>);
+
+type Handler = dyn Fn(&Event) + Send + Sync;
+
+impl Events {
+    pub fn new(handler: impl Fn(&Event) + Send + Sync + 'static) -> Self {
+        Events(Some(Arc::new(handler)))
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.0.is_some()
+    }
+
+    pub fn emit(&self, event: Event) {
+        if let Some(handler) = &self.0 {
+            handler(&event);
+        }
+    }
+}
+
+impl fmt::Debug for Events {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(if self.enabled() { "Events" } else { "None" })
+    }
+}
@@ -0,0 +1,67 @@
+//! Exit codes, which stay stable so the Blender add-on, CI wrappers and
+//! programs using the C API can branch on the kind of failure.
+
+use crate::AssemblerError;
+
+pub const SUCCESS: i32 = 0;
+/// Anything not covered below.
+pub const FAILURE: i32 = 1;
+/// Bad command line arguments, environment variables or templates.
+pub const INVALID_OPTIONS: i32 = 2;
+pub const NO_IMAGES: i32 = 3;
+pub const INCONSISTENT_SIZE: i32 = 4;
+/// A frame couldn't be decoded, was empty with `--empty-frames error`,
+/// differed in format with `--strict-format` or had more than one pivot
+/// marker, or the sheet couldn't be encoded.
+pub const IMAGE: i32 = 5;
+/// Reading or writing files failed.
+pub const IO: i32 = 6;
+pub const SIZE_LIMIT: i32 = 7;
+/// An output exists and neither `--force` nor `--backup` was given.
+pub const OUTPUT_EXISTS: i32 = 8;
+/// The run was interrupted or cancelled, and what it had written removed.
+pub const CANCELLED: i32 = 9;
+/// Another run is assembling in the same root, and `--wait` wasn't given.
+pub const LOCKED: i32 = 10;
+
+/// The exit code for a failed assembly.
+pub fn code(err: &AssemblerError) -> i32 {
+    match err {
+        AssemblerError::NoImages(_) => NO_IMAGES,
+        AssemblerError::InconsistentSize(_) => INCONSISTENT_SIZE,
+        AssemblerError::Image { .. }
+        | AssemblerError::ImageFormat(_)
+        | AssemblerError::Interlaced(_)
+        | AssemblerError::Png { .. }
+        | AssemblerError::Encode(_)
+        | AssemblerError::EmptyFrame(_)
+        | AssemblerError::PivotMarker(_)
+        | AssemblerError::MixedFormat(_) => IMAGE,
+        AssemblerError::Io { .. } => IO,
+        #[cfg(feature = "fs")]
+        AssemblerError::Walk(_) => IO,
+        AssemblerError::SheetTooLarge(_) | AssemblerError::SheetLimit(_) => SIZE_LIMIT,
+        AssemblerError::OutputExists(_) => OUTPUT_EXISTS,
+        AssemblerError::Template(_)
+        | AssemblerError::UnsafeCleanup(_)
+        | AssemblerError::MetadataFormat(_)
+        | AssemblerError::Option(_)
+        | AssemblerError::Filter(_)
+        | AssemblerError::Durations(_)
+        | AssemblerError::Sequence(_)
+        | AssemblerError::NamePattern(_)
+        | AssemblerError::Markers(_)
+        | AssemblerError::NineSlice(_)
+        | AssemblerError::DuplicateFrameName(_)
+        | AssemblerError::Confinement(_) => INVALID_OPTIONS,
+        #[cfg(feature = "fs")]
+        AssemblerError::Glob(_) => INVALID_OPTIONS,
+        AssemblerError::Cancelled => CANCELLED,
+        AssemblerError::Locked(_) => LOCKED,
+        AssemblerError::SequenceGap(_)
+        | AssemblerError::MissingPass(_)
+        | AssemblerError::MissingSheet(_)
+        | AssemblerError::Video(_)
+        | AssemblerError::Json(_) => FAILURE,
+    }
+}
@@ -0,0 +1,56 @@
+//! `--facings`: characters rendered from several camera angles for top-down
+//! and isometric games, with frames in `<facing>/<animation>/` folders such
+//! as `south/walk/0001.png`. Each facing's animation gets a row of its own,
+//! and the metadata names the facing and the angle it looks towards.
+
+use serde::Serialize;
+use std::path::{Component, Path};
+
+/// What a tag shows when the frames have facings.
+#[derive(Debug, Clone, Serialize)]
+pub struct View {
+    /// The animation, without its facing.
+    pub animation: String,
+    pub facing: String,
+    /// Degrees counterclockwise from screen right, if the facing is a
+    /// compass direction or an angle.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub angle: Option<f64>,
+}
+
+/// The facing and animation folders of the frame at `relative` to the
+/// frames folder, if it's in both.
+pub fn split(relative: &Path) -> Option<(&str, &str)> {
+    let mut folders = relative.components().map(|component| match component {
+        Component::Normal(name) => name.to_str(),
+        _ => None,
+    });
+    let facing = folders.next()??;
+    let animation = folders.next()??;
+    folders.next()?;
+    Some((facing, animation))
+}
+
+/// The angle of a facing named like `north`, `sw` or `up`, or by the angle
+/// itself like `45`, measured counterclockwise from screen right.
+pub fn angle(facing: &str) -> Option<f64> {
+    if let Ok(angle) = facing.trim().parse::<f64>() {
+        return Some(angle.rem_euclid(360.0)).filter(|angle| angle.is_finite());
+    }
+    let name = facing
+        .chars()
+        .filter(|c| c.is_ascii_alphabetic())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    Some(match name.as_str() {
+        "e" | "east" | "right" => 0.0,
+        "ne" | "northeast" | "upright" => 45.0,
+        "n" | "north" | "up" => 90.0,
+        "nw" | "northwest" | "upleft" => 135.0,
+        "w" | "west" | "left" => 180.0,
+        "sw" | "southwest" | "downleft" => 225.0,
+        "s" | "south" | "down" => 270.0,
+        "se" | "southeast" | "downright" => 315.0,
+        _ => return None,
+    })
+}
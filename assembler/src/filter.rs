@@ -0,0 +1,186 @@
+//! Processing passes run on every frame between decoding it and placing it
+//! on the sheet, in the order given by `--filter-chain`.
+
+use image::{Rgba, RgbaImage};
+use serde::Serializer;
+use std::{fmt, sync::Arc};
+
+use crate::{errors::FilterError, simd, Result};
+
+/// A pass over each decoded frame. Library users can implement this for
+/// their own effects and add them with
+/// [`Assembler::filter`](crate::Assembler::filter).
+pub trait FrameFilter: fmt::Debug + Send + Sync {
+    /// How the filter would be written in a `--filter-chain`. The cache and
+    /// provenance record use it to tell runs with different filters apart.
+    fn spec(&self) -> String;
+
+    /// Processes a frame. The result must be the same size as `frame`.
+    fn apply(&self, frame: RgbaImage) -> Result<RgbaImage>;
+}
+
+/// Multiplies every pixel by a colour, alpha included.
+#[derive(Debug, Clone)]
+pub struct Tint(pub Rgba<u8>);
+
+impl FrameFilter for Tint {
+    fn spec(&self) -> String {
+        format!("tint:{}", hex(self.0))
+    }
+
+    fn apply(&self, mut frame: RgbaImage) -> Result<RgbaImage> {
+        simd::tint(&mut frame, (self.0).0);
+        Ok(frame)
+    }
+}
+
+/// Draws a `width` pixel outline in a colour around the opaque parts of the
+/// frame, over the transparent pixels next to them.
+#[derive(Debug, Clone)]
+pub struct Outline {
+    pub color: Rgba<u8>,
+    pub width: u32,
+}
+
+impl FrameFilter for Outline {
+    fn spec(&self) -> String {
+        format!("outline:{}:{}", hex(self.color), self.width)
+    }
+
+    fn apply(&self, mut frame: RgbaImage) -> Result<RgbaImage> {
+        let (width, height) = frame.dimensions();
+        for _ in 0..self.width {
+            let edge = frame
+                .enumerate_pixels()
+                .filter(|(x, y, pixel)| {
+                    pixel[3] == 0
+                        && [(-1, 0), (1, 0), (0, -1), (0, 1)].iter().any(|(dx, dy)| {
+                            let (nx, ny) = (i64::from(*x) + dx, i64::from(*y) + dy);
+                            nx >= 0
+                                && ny >= 0
+                                && nx < i64::from(width)
+                                && ny < i64::from(height)
+                                && frame.get_pixel(nx as u32, ny as u32)[3] != 0
+                        })
+                })
+                .map(|(x, y, _)| (x, y))
+                .collect::<Vec<_>>();
+            for (x, y) in edge {
+                frame.put_pixel(x, y, self.color);
+            }
+        }
+        Ok(frame)
+    }
+}
+
+/// Mirrors the frame, for characters rendered facing only one way.
+#[derive(Debug, Clone)]
+pub struct Flip {
+    pub horizontal: bool,
+}
+
+impl FrameFilter for Flip {
+    fn spec(&self) -> String {
+        format!("flip:{}", if self.horizontal { "x" } else { "y" })
+    }
+
+    fn apply(&self, frame: RgbaImage) -> Result<RgbaImage> {
+        Ok(if self.horizontal {
+            image::imageops::flip_horizontal(&frame)
+        } else {
+            image::imageops::flip_vertical(&frame)
+        })
+    }
+}
+
+/// Parses a `--filter-chain` such as `tint:#ff8080,outline:#000000:2,flip:x`
+/// into the built-in filters.
+pub fn parse_chain(chain: &str) -> Result<Vec<Arc<dyn FrameFilter>>> {
+    chain
+        .split(',')
+        .map(str::trim)
+        .filter(|spec| !spec.is_empty())
+        .map(parse)
+        .collect()
+}
+
+fn parse(spec: &str) -> Result<Arc<dyn FrameFilter>> {
+    let invalid = |message: &str| FilterError {
+        filter: spec.to_owned(),
+        message: message.to_owned(),
+    };
+    let mut parts = spec.split(':');
+    let name = parts.next().unwrap_or_default();
+    let args = parts.collect::<Vec<_>>();
+    let filter: Arc<dyn FrameFilter> = match (name, args.as_slice()) {
+        ("tint", [color]) => Arc::new(Tint(
+            parse_color(color).ok_or_else(|| invalid("needs a colour like #ff8080"))?,
+        )),
+        ("outline", [color]) | ("outline", [color, _]) => Arc::new(Outline {
+            color: parse_color(color).ok_or_else(|| invalid("needs a colour like #000000"))?,
+            width: match args.get(1) {
+                Some(width) => width
+                    .parse()
+                    .map_err(|_| invalid("needs a width in pixels"))?,
+                None => 1,
+            },
+        }),
+        ("flip", ["x"]) => Arc::new(Flip { horizontal: true }),
+        ("flip", ["y"]) => Arc::new(Flip { horizontal: false }),
+        ("flip", _) => return Err(invalid("needs x or y").into()),
+        ("tint", _) | ("outline", _) => {
+            return Err(invalid("has the wrong number of arguments").into())
+        }
+        _ => return Err(invalid("isn't one of tint, outline or flip").into()),
+    };
+    Ok(filter)
+}
+
+/// Runs `frame` through every filter in order.
+pub fn apply(filters: &[Arc<dyn FrameFilter>], mut frame: RgbaImage) -> Result<RgbaImage> {
+    let size = frame.dimensions();
+    for filter in filters {
+        frame = filter.apply(frame)?;
+        if frame.dimensions() != size {
+            return Err(FilterError {
+                filter: filter.spec(),
+                message: "changed the frame's size".to_owned(),
+            }
+            .into());
+        }
+    }
+    Ok(frame)
+}
+
+/// Writes filters as their specs, for the cache and provenance record.
+pub fn serialize<S: Serializer>(
+    filters: &[Arc<dyn FrameFilter>],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(filters.iter().map(|filter| filter.spec()))
+}
+
+/// The whole chain as one `--filter-chain` string.
+pub fn chain(filters: &[Arc<dyn FrameFilter>]) -> String {
+    filters
+        .iter()
+        .map(|filter| filter.spec())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// `#rrggbb` or `#rrggbbaa`, with or without the `#`.
+fn parse_color(text: &str) -> Option<Rgba<u8>> {
+    let text = text.trim_start_matches('#');
+    if !(text.len() == 6 || text.len() == 8) || !text.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(text.get(i * 2..i * 2 + 2)?, 16).ok();
+    let alpha = if text.len() == 8 { channel(3)? } else { 255 };
+    Some(Rgba([channel(0)?, channel(1)?, channel(2)?, alpha]))
+}
+
+fn hex(color: Rgba<u8>) -> String {
+    let [r, g, b, a] = color.0;
+    format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a)
+}
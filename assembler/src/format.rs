@@ -0,0 +1,126 @@
+//! Frames that disagree on bit depth or colour space. Every frame is
+//! decoded to 8-bit RGBA and drawn as if it were sRGB, so a mix is silently
+//! made uniform: 16-bit frames lose precision, and ICC profiles and gamma
+//! are ignored, which can shift the colours of some frames but not others.
+
+use log::warn;
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, Seek},
+    path::{Path, PathBuf},
+};
+
+use crate::{errors::MixedFormatError, Result};
+
+/// What a PNG says about how its samples should be read.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Format {
+    /// 16, or 8 for anything of 8 bits or fewer per sample.
+    bits: u8,
+    space: Space,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Space {
+    /// Marked as sRGB, or not marked at all and so assumed to be.
+    Srgb,
+    /// A gAMA chunk other than sRGB's, in hundred-thousandths.
+    Gamma(u32),
+    /// An embedded ICC profile.
+    Icc,
+}
+
+/// sRGB's gamma as a gAMA chunk stores it.
+const SRGB_GAMMA: u32 = 45455;
+
+impl Format {
+    /// Reads the format from a PNG's header, or `None` if it isn't one.
+    pub fn read(reader: impl BufRead + Seek) -> Option<Self> {
+        let decoder = png::Decoder::new(reader).read_info().ok()?;
+        let info = decoder.info();
+        let space = if info.icc_profile.is_some() {
+            Space::Icc
+        } else {
+            match info.gama_chunk.map(png::ScaledFloat::into_scaled) {
+                Some(gamma) if info.srgb.is_none() && gamma.abs_diff(SRGB_GAMMA) > 100 => {
+                    Space::Gamma(gamma)
+                }
+                _ => Space::Srgb,
+            }
+        };
+        Some(Format {
+            bits: if info.bit_depth == png::BitDepth::Sixteen {
+                16
+            } else {
+                8
+            },
+            space,
+        })
+    }
+}
+
+/// Warns if the frames don't all share a bit depth, or a colour space, or
+/// fails if `strict`. Frames whose format is `None` aren't counted.
+pub fn check(frames: &[(&Path, Option<Format>)], strict: bool) -> Result<()> {
+    let mut mixes = Vec::new();
+    let bits = group(frames, |format| format.bits);
+    if bits.len() > 1 {
+        mixes.push(format!(
+            "mix {}; all are reduced to 8 bits",
+            list(&bits, |bits| format!("{}-bit", bits))
+        ));
+    }
+    let spaces = group(frames, |format| format.space);
+    if spaces.len() > 1 {
+        mixes.push(format!(
+            "mix {}; all are drawn as sRGB, so some may change colour",
+            list(&spaces, |space| match space {
+                Space::Srgb => "sRGB".to_owned(),
+                Space::Gamma(gamma) => format!("gamma {}", f64::from(gamma) / 100_000.0),
+                Space::Icc => "ICC profiles".to_owned(),
+            })
+        ));
+    }
+    if mixes.is_empty() {
+        return Ok(());
+    }
+    let message = format!("The frames {}", mixes.join(", and "));
+    if strict {
+        return Err(MixedFormatError { message }.into());
+    }
+    warn!("{}", message);
+    Ok(())
+}
+
+/// The frames with each value of `key`.
+fn group<K: Ord>(
+    frames: &[(&Path, Option<Format>)],
+    key: impl Fn(Format) -> K,
+) -> BTreeMap<K, Vec<PathBuf>> {
+    let mut groups = BTreeMap::<K, Vec<PathBuf>>::new();
+    for (path, format) in frames {
+        if let Some(format) = format {
+            groups
+                .entry(key(*format))
+                .or_default()
+                .push(path.to_path_buf());
+        }
+    }
+    groups
+}
+
+/// Each group named with its first frame, like `16-bit (walk_0001.png and
+/// 3 more) and 8-bit (run_0001.png)`.
+fn list<K: Copy>(groups: &BTreeMap<K, Vec<PathBuf>>, name: impl Fn(K) -> String) -> String {
+    groups
+        .iter()
+        .map(|(key, paths)| {
+            let first = paths[0].display();
+            match paths.len() {
+                1 => format!("{} ({})", name(*key), first),
+                n => format!("{} ({} and {} more)", name(*key), first, n - 1),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" and ")
+}
@@ -0,0 +1,109 @@
+//! `--strict-sequence`: frame numbers missing from, or repeated in, an
+//! animation, the usual sign of a render that crashed partway, which would
+//! otherwise just make the animation shorter.
+
+use log::warn;
+use serde::Serialize;
+use std::{collections::BTreeMap, path::PathBuf};
+
+use crate::{errors::SequenceGapError, report::Gap, timing, Result};
+
+/// What `--strict-sequence` does about gaps in the numbering.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StrictSequence {
+    /// Log a warning and carry on.
+    Warn,
+    /// Fail the run.
+    Error,
+}
+
+impl StrictSequence {
+    pub const NAMES: &'static [&'static str] = &["warn", "error"];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "warn" => Some(StrictSequence::Warn),
+            "error" => Some(StrictSequence::Error),
+            _ => None,
+        }
+    }
+}
+
+/// Checks the numbered frames of each animation run from the first number
+/// to the last without missing or repeating one. `relative` are the frames'
+/// paths relative to the frames folder. Returns the gaps found, having
+/// warned about them, unless `policy` fails on the first.
+pub fn check(policy: StrictSequence, relative: &[PathBuf]) -> Result<Vec<Gap>> {
+    let mut numbers = BTreeMap::<&str, Vec<u64>>::new();
+    for path in relative {
+        if let (animation, Some(number)) = timing::animation_at(path) {
+            numbers.entry(animation).or_default().push(number);
+        }
+    }
+    let mut gaps = Vec::new();
+    for (animation, mut numbers) in numbers {
+        numbers.sort_unstable();
+        let mut gap = Gap {
+            animation: animation.to_owned(),
+            missing: Vec::new(),
+            repeated: Vec::new(),
+        };
+        for pair in numbers.windows(2) {
+            if pair[0] == pair[1] {
+                if gap.repeated.last() != Some(&pair[0]) {
+                    gap.repeated.push(pair[0]);
+                }
+            } else {
+                gap.missing.extend(pair[0] + 1..pair[1]);
+            }
+        }
+        if gap.missing.is_empty() && gap.repeated.is_empty() {
+            continue;
+        }
+        if policy == StrictSequence::Error {
+            return Err(SequenceGapError { gap }.into());
+        }
+        warn!("{}", describe(&gap));
+        gaps.push(gap);
+    }
+    Ok(gaps)
+}
+
+/// Says which frames of the animation are missing or repeated.
+pub(crate) fn describe(gap: &Gap) -> String {
+    let name = match gap.animation.as_str() {
+        "" => "The frames".to_owned(),
+        animation => format!("Animation '{}'", animation),
+    };
+    let mut problems = Vec::new();
+    if !gap.missing.is_empty() {
+        problems.push(format!("is missing frame(s) {}", ranges(&gap.missing)));
+    }
+    if !gap.repeated.is_empty() {
+        problems.push(format!("has more than one frame {}", ranges(&gap.repeated)));
+    }
+    format!("{} {}", name, problems.join(" and "))
+}
+
+/// Sorted numbers as ranges, like `3, 7-9`.
+fn ranges(numbers: &[u64]) -> String {
+    let mut ranges = Vec::<(u64, u64)>::new();
+    for &number in numbers {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == number => *end = number,
+            _ => ranges.push((number, number)),
+        }
+    }
+    ranges
+        .iter()
+        .map(|&(start, end)| {
+            if start == end {
+                start.to_string()
+            } else {
+                format!("{}-{}", start, end)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
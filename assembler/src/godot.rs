@@ -0,0 +1,329 @@
+//! `--godot-scene`: a Godot 4 scene, `<sheet>.tscn`, ready to drop into a
+//! project next to the sheet. It holds an `AnimatedSprite2D` playing each
+//! animation, and an `Area2D` with a `CollisionPolygon2D` per traced part
+//! whose points an `AnimationPlayer` switches along with the frames.
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{collision::Point, metadata::Atlas, timing::Direction, Result};
+
+/// `hero.png` gets `hero.tscn`.
+pub fn path(sheet: &Path) -> PathBuf {
+    sheet.with_extension("tscn")
+}
+
+struct SceneFrame {
+    /// Index into [`Scene::pages`].
+    page: usize,
+    region: (u32, u32, u32, u32),
+    duration: u32,
+    /// In pixels from the frame's top left.
+    pivot: (f64, f64),
+    polygons: Vec<Vec<Point>>,
+}
+
+struct SceneAnimation {
+    name: String,
+    /// Indices into [`Scene::frames`].
+    frames: Vec<usize>,
+    direction: Direction,
+    fps: u32,
+}
+
+/// The frames and animations of every page, gathered as they're written.
+pub struct Scene {
+    name: String,
+    /// Each page's filename, relative to the scene.
+    pages: Vec<String>,
+    frames: Vec<SceneFrame>,
+    animations: Vec<SceneAnimation>,
+}
+
+impl Scene {
+    pub fn new(name: &str) -> Self {
+        Scene {
+            name: name.to_owned(),
+            pages: Vec::new(),
+            frames: Vec::new(),
+            animations: Vec::new(),
+        }
+    }
+
+    /// Adds a page whose frames are described by `atlas`.
+    pub fn add_page(&mut self, atlas: &Atlas) {
+        let page = self.pages.len();
+        let first = self.frames.len();
+        self.pages.push(atlas.image.clone());
+        for frame in &atlas.frames {
+            let (x, y) = frame.pivot.unwrap_or((0.5, 0.5));
+            self.frames.push(SceneFrame {
+                page,
+                region: (frame.x, frame.y, frame.width, frame.height),
+                duration: frame.duration,
+                pivot: (x * f64::from(frame.width), y * f64::from(frame.height)),
+                polygons: frame.polygons.clone(),
+            });
+        }
+        for tag in &atlas.tags {
+            let frames = (first + tag.from..=first + tag.to).collect::<Vec<_>>();
+            // An animation that runs onto the next page continues its tag.
+            match self.animations.last_mut() {
+                Some(last)
+                    if last.name == tag.name
+                        && last.frames.last() == Some(&(first + tag.from - 1)) =>
+                {
+                    last.frames.extend(frames)
+                }
+                _ => self.animations.push(SceneAnimation {
+                    name: tag.name.clone(),
+                    frames,
+                    direction: tag.direction,
+                    fps: tag.fps,
+                }),
+            }
+        }
+    }
+
+    /// Writes the scene to `out`. Without any tagged animations, it plays
+    /// the frames in `sequence` as Godot's `default` animation.
+    pub fn write(mut self, sequence: &[usize], fps: u32, out: &mut dyn Write) -> Result<()> {
+        if self.animations.is_empty() {
+            self.animations.push(SceneAnimation {
+                name: "default".to_owned(),
+                frames: sequence.to_vec(),
+                direction: Direction::Forward,
+                fps,
+            });
+        }
+        let parts = self
+            .frames
+            .iter()
+            .map(|frame| frame.polygons.len())
+            .max()
+            .unwrap_or(0);
+        let animations = self
+            .animations
+            .iter()
+            .map(|animation| (animation, playback(animation)))
+            .collect::<Vec<_>>();
+
+        let resources = self.pages.len() + self.frames.len() + animations.len() + 2;
+        writeln!(out, "[gd_scene load_steps={} format=3]\n", resources + 1)?;
+        for (index, page) in self.pages.iter().enumerate() {
+            writeln!(
+                out,
+                "[ext_resource type=\"Texture2D\" path={} id=\"page_{}\"]\n",
+                quote(page),
+                index
+            )?;
+        }
+        for (index, frame) in self.frames.iter().enumerate() {
+            let (x, y, width, height) = frame.region;
+            writeln!(
+                out,
+                "[sub_resource type=\"AtlasTexture\" id=\"frame_{}\"]",
+                index
+            )?;
+            writeln!(out, "atlas = ExtResource(\"page_{}\")", frame.page)?;
+            writeln!(out, "region = Rect2({}, {}, {}, {})\n", x, y, width, height)?;
+        }
+
+        writeln!(
+            out,
+            "[sub_resource type=\"SpriteFrames\" id=\"sprite_frames\"]"
+        )?;
+        let sprite_animations = animations
+            .iter()
+            .map(|(animation, frames)| {
+                let frames = frames
+                    .iter()
+                    .map(|frame| {
+                        format!(
+                            "{{\n\"duration\": {:.1},\n\"texture\": SubResource(\"frame_{}\")\n}}",
+                            f64::from(self.frames[*frame].duration),
+                            frame
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                format!(
+                    "{{\n\"frames\": [{}],\n\"loop\": true,\n\"name\": &{},\n\"speed\": {:.1}\n}}",
+                    frames.join(", "),
+                    quote(&animation.name),
+                    f64::from(animation.fps)
+                )
+            })
+            .collect::<Vec<_>>();
+        writeln!(out, "animations = [{}]\n", sprite_animations.join(", "))?;
+
+        for (index, (animation, frames)) in animations.iter().enumerate() {
+            let mut times = Vec::with_capacity(frames.len());
+            let mut ticks = 0;
+            for frame in frames {
+                times.push(f64::from(ticks) / f64::from(animation.fps));
+                ticks += self.frames[*frame].duration;
+            }
+            let length = f64::from(ticks) / f64::from(animation.fps);
+            writeln!(
+                out,
+                "[sub_resource type=\"Animation\" id=\"animation_{}\"]",
+                index
+            )?;
+            writeln!(out, "resource_name = {}", quote(&animation.name))?;
+            writeln!(out, "length = {}", length)?;
+            writeln!(out, "loop_mode = 1")?;
+            let mut tracks = vec![
+                (
+                    "AnimatedSprite2D:animation".to_owned(),
+                    vec![0.0],
+                    vec![format!("&{}", quote(&animation.name))],
+                ),
+                (
+                    "AnimatedSprite2D:frame".to_owned(),
+                    times.clone(),
+                    (0..frames.len()).map(|frame| frame.to_string()).collect(),
+                ),
+                (
+                    "AnimatedSprite2D:offset".to_owned(),
+                    times.clone(),
+                    frames
+                        .iter()
+                        .map(|frame| {
+                            let (x, y) = self.frames[*frame].pivot;
+                            format!("Vector2({}, {})", -x, -y)
+                        })
+                        .collect(),
+                ),
+            ];
+            for part in 0..parts {
+                tracks.push((
+                    format!("Collision/{}:polygon", polygon_node(part)),
+                    times.clone(),
+                    frames
+                        .iter()
+                        .map(|frame| {
+                            let frame = &self.frames[*frame];
+                            let points = frame.polygons.get(part).map_or(&[][..], |p| &p[..]);
+                            vector_array(points, frame.pivot)
+                        })
+                        .collect(),
+                ));
+            }
+            for (track, (path, times, values)) in tracks.iter().enumerate() {
+                let times = times
+                    .iter()
+                    .map(|time| time.to_string())
+                    .collect::<Vec<_>>();
+                let transitions = vec!["1"; times.len()];
+                writeln!(out, "tracks/{}/type = \"value\"", track)?;
+                writeln!(out, "tracks/{}/imported = false", track)?;
+                writeln!(out, "tracks/{}/enabled = true", track)?;
+                writeln!(out, "tracks/{}/path = NodePath({})", track, quote(path))?;
+                writeln!(out, "tracks/{}/interp = 1", track)?;
+                writeln!(out, "tracks/{}/loop_wrap = true", track)?;
+                writeln!(out, "tracks/{}/keys = {{", track)?;
+                writeln!(out, "\"times\": PackedFloat32Array({}),", times.join(", "))?;
+                writeln!(
+                    out,
+                    "\"transitions\": PackedFloat32Array({}),",
+                    transitions.join(", ")
+                )?;
+                // Discrete, so shapes and frames switch rather than blend.
+                writeln!(out, "\"update\": 1,")?;
+                writeln!(out, "\"values\": [{}]", values.join(", "))?;
+                writeln!(out, "}}")?;
+            }
+            writeln!(out)?;
+        }
+
+        writeln!(
+            out,
+            "[sub_resource type=\"AnimationLibrary\" id=\"library\"]"
+        )?;
+        let library = animations
+            .iter()
+            .enumerate()
+            .map(|(index, (animation, _))| {
+                format!(
+                    "&{}: SubResource(\"animation_{}\")",
+                    quote(&animation.name),
+                    index
+                )
+            })
+            .collect::<Vec<_>>();
+        writeln!(out, "_data = {{\n{}\n}}\n", library.join(",\n"))?;
+
+        let first = &animations[0].0;
+        let pivot = animations[0]
+            .1
+            .first()
+            .map_or((0.0, 0.0), |frame| self.frames[*frame].pivot);
+        writeln!(out, "[node name={} type=\"Node2D\"]\n", quote(&self.name))?;
+        writeln!(
+            out,
+            "[node name=\"AnimatedSprite2D\" type=\"AnimatedSprite2D\" parent=\".\"]"
+        )?;
+        writeln!(out, "sprite_frames = SubResource(\"sprite_frames\")")?;
+        writeln!(out, "animation = &{}", quote(&first.name))?;
+        writeln!(out, "centered = false")?;
+        writeln!(out, "offset = Vector2({}, {})\n", -pivot.0, -pivot.1)?;
+        writeln!(
+            out,
+            "[node name=\"Collision\" type=\"Area2D\" parent=\".\"]\n"
+        )?;
+        for part in 0..parts {
+            writeln!(
+                out,
+                "[node name=\"{}\" type=\"CollisionPolygon2D\" parent=\"Collision\"]\n",
+                polygon_node(part)
+            )?;
+        }
+        writeln!(
+            out,
+            "[node name=\"AnimationPlayer\" type=\"AnimationPlayer\" parent=\".\"]"
+        )?;
+        writeln!(out, "libraries = {{\n\"\": SubResource(\"library\")\n}}")?;
+        writeln!(out, "autoplay = {}", quote(&first.name))?;
+        Ok(())
+    }
+}
+
+/// The frames an animation shows in one loop, for its direction, since
+/// Godot only plays forwards.
+fn playback(animation: &SceneAnimation) -> Vec<usize> {
+    let frames = &animation.frames;
+    match animation.direction {
+        Direction::Forward => frames.clone(),
+        Direction::Reverse => frames.iter().rev().copied().collect(),
+        Direction::PingPong => {
+            let back = frames.iter().rev().skip(1);
+            let back = back.take(frames.len().saturating_sub(2));
+            frames.iter().chain(back).copied().collect()
+        }
+    }
+}
+
+/// Godot's names for a node's copies.
+fn polygon_node(part: usize) -> String {
+    match part {
+        0 => "CollisionPolygon2D".to_owned(),
+        part => format!("CollisionPolygon2D{}", part + 1),
+    }
+}
+
+/// `points` relative to `pivot`, as Godot's packed array.
+fn vector_array(points: &[Point], pivot: (f64, f64)) -> String {
+    let coordinates = points
+        .iter()
+        .flat_map(|(x, y)| [f64::from(*x) - pivot.0, f64::from(*y) - pivot.1])
+        .map(|coordinate| coordinate.to_string())
+        .collect::<Vec<_>>();
+    format!("PackedVector2Array({})", coordinates.join(", "))
+}
+
+/// `text` as a Godot string literal.
+pub fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
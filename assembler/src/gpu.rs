@@ -0,0 +1,262 @@
+//! `--gpu`: compositing the sheet on the graphics card with wgpu. Each
+//! frame is uploaded as a texture and copied into its cell of a texture of
+//! the whole page, which is then read back to be encoded. Pages too big for
+//! the card, and every page when there's no card to use, are composited on
+//! the CPU as usual, with the same result.
+
+use image::RgbaImage;
+use log::{debug, info, warn};
+use std::sync::mpsc;
+
+use crate::{collect::Frame, Dims};
+
+/// A graphics card to composite on.
+pub struct Gpu {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    limits: wgpu::Limits,
+}
+
+impl Gpu {
+    /// Opens the fastest graphics card wgpu can find, or `None` if there's
+    /// none to use.
+    pub fn open() -> Option<Self> {
+        let instance =
+            wgpu::Instance::new(wgpu::InstanceDescriptor::new_without_display_handle_from_env());
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            ..Default::default()
+        }));
+        let adapter = match adapter {
+            Ok(adapter) => adapter,
+            Err(err) => {
+                warn!(
+                    "Compositing on the CPU, since there's no graphics card to use: {}",
+                    err
+                );
+                return None;
+            }
+        };
+        // As big as the card allows, since sheets can be huge.
+        let limits = adapter.limits();
+        let device = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+            label: Some("spritesheet"),
+            required_limits: limits.clone(),
+            ..Default::default()
+        }));
+        let (device, queue) = match device {
+            Ok(device) => device,
+            Err(err) => {
+                warn!(
+                    "Compositing on the CPU, since the graphics card couldn't be opened: {}",
+                    err
+                );
+                return None;
+            }
+        };
+        info!("Compositing on {}", adapter.get_info().name);
+        Some(Gpu {
+            device,
+            queue,
+            limits,
+        })
+    }
+
+    /// Composites a page like [`composite`](crate::assembler::composite)
+    /// does, drawing `frames` into their `cells` of a `width` by `height`
+    /// page over `base`, or returns `None` if the card can't.
+    pub fn composite(
+        &self,
+        base: Option<&RgbaImage>,
+        frames: &[Frame],
+        cells: &[usize],
+        (width, height): (u32, u32),
+        tiles: Dims,
+        dims: Dims,
+    ) -> Option<RgbaImage> {
+        let row_bytes = width * 4;
+        // Rows read back from a texture are padded to the copy alignment.
+        let alignment = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_row_bytes = row_bytes.div_ceil(alignment) * alignment;
+        let readback_bytes = u64::from(padded_row_bytes) * u64::from(height);
+        if width.max(height) > self.limits.max_texture_dimension_2d
+            || readback_bytes > self.limits.max_buffer_size
+        {
+            debug!(
+                "A {}x{}px page is too big for the graphics card, compositing it on the CPU",
+                width, height
+            );
+            return None;
+        }
+        let size = extent((width, height));
+        // A new texture starts out transparent, like a new image.
+        let sheet = self.texture("sheet", size);
+        if let Some(base) = base {
+            self.queue
+                .write_texture(at(&sheet, (0, 0)), base, rows(row_bytes), size);
+        }
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        let frame_size = extent((dims.x as u32, dims.y as u32));
+        // Kept until the copies have been submitted.
+        let _uploaded = frames
+            .iter()
+            .zip(cells)
+            .filter_map(|(frame, cell)| Some((frame.image.as_ref()?, *cell)))
+            .map(|(image, cell)| {
+                let texture = self.texture("frame", frame_size);
+                let frame_row_bytes = dims.x as u32 * 4;
+                self.queue.write_texture(
+                    at(&texture, (0, 0)),
+                    image,
+                    rows(frame_row_bytes),
+                    frame_size,
+                );
+                let origin = (
+                    (cell % tiles.x * dims.x) as u32,
+                    (cell / tiles.x * dims.y) as u32,
+                );
+                encoder.copy_texture_to_texture(
+                    at(&texture, (0, 0)),
+                    at(&sheet, origin),
+                    frame_size,
+                );
+                texture
+            })
+            .collect::<Vec<_>>();
+        let readback = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback"),
+            size: readback_bytes,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            at(&sheet, (0, 0)),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &readback,
+                layout: rows(padded_row_bytes),
+            },
+            size,
+        );
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = readback.slice(..);
+        let (sender, mapped) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        let read = self
+            .device
+            .poll(wgpu::PollType::wait_indefinitely())
+            .map_err(|err| err.to_string())
+            .and_then(|_| mapped.recv().map_err(|err| err.to_string()))
+            .and_then(|result| result.map_err(|err| err.to_string()))
+            .and_then(|_| slice.get_mapped_range().map_err(|err| err.to_string()));
+        let padded = match read {
+            Ok(padded) => padded,
+            Err(err) => {
+                warn!(
+                    "Compositing on the CPU, since the graphics card failed: {}",
+                    err
+                );
+                return None;
+            }
+        };
+        let mut pixels = Vec::with_capacity(row_bytes as usize * height as usize);
+        for row in padded.chunks(padded_row_bytes as usize) {
+            pixels.extend_from_slice(&row[..row_bytes as usize]);
+        }
+        drop(padded);
+        readback.unmap();
+        RgbaImage::from_raw(width, height, pixels)
+    }
+
+    fn texture(&self, label: &str, size: wgpu::Extent3d) -> wgpu::Texture {
+        self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        })
+    }
+}
+
+fn extent((width, height): (u32, u32)) -> wgpu::Extent3d {
+    wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    }
+}
+
+/// The part of `texture` from `(x, y)` on.
+fn at(texture: &wgpu::Texture, (x, y): (u32, u32)) -> wgpu::TexelCopyTextureInfo<'_> {
+    wgpu::TexelCopyTextureInfo {
+        texture,
+        mip_level: 0,
+        origin: wgpu::Origin3d { x, y, z: 0 },
+        aspect: wgpu::TextureAspect::All,
+    }
+}
+
+/// Rows starting `bytes` apart.
+fn rows(bytes: u32) -> wgpu::TexelCopyBufferLayout {
+    wgpu::TexelCopyBufferLayout {
+        offset: 0,
+        bytes_per_row: Some(bytes),
+        rows_per_image: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assembler, progress::Progress};
+    use std::path::PathBuf;
+
+    /// A 5x3 frame of bytes depending on `seed`.
+    fn frame(seed: u8) -> Frame {
+        let pixels = (0..5 * 3 * 4).map(|i| seed.wrapping_mul(31) ^ i).collect();
+        Frame {
+            path: PathBuf::new(),
+            sha256: String::new(),
+            relative: PathBuf::new(),
+            width: 5,
+            height: 3,
+            image: RgbaImage::from_raw(5, 3, pixels),
+            duration: 1,
+            pivot: None,
+        }
+    }
+
+    #[test]
+    fn composites_like_the_cpu() {
+        let gpu = match Gpu::open() {
+            Some(gpu) => gpu,
+            // Nothing to compare on a machine without a graphics card.
+            None => return,
+        };
+        let frames = (1..=7).map(frame).collect::<Vec<_>>();
+        // Out of order, with a gap, over a sheet that isn't empty.
+        let cells = [0, 2, 3, 1, 5, 6, 8];
+        let (tiles, dims) = (Dims { x: 3, y: 3 }, Dims { x: 5, y: 3 });
+        let base = RgbaImage::from_pixel(15, 9, image::Rgba([9, 8, 7, 6]));
+        let progress = Progress::new("Compositing", 0).hidden();
+        let cpu = assembler::composite(
+            Some(base.clone()),
+            &frames,
+            &cells,
+            (15, 9),
+            tiles,
+            dims,
+            &progress,
+        );
+        let drawn = gpu.composite(Some(&base), &frames, &cells, (15, 9), tiles, dims);
+        assert_eq!(drawn.unwrap().into_raw(), cpu.into_raw());
+    }
+}
@@ -0,0 +1,238 @@
+//! `assembler serve --http`: a small REST API so CI systems and asset
+//! dashboards can start assemblies and follow them over HTTP.
+//!
+//! - `POST /assemble` takes the same `{"root": DIR, "options": {...}}` as the
+//!   JSON-RPC `assemble` method, starts the job and answers `202 Accepted`
+//!   with its `id`.
+//! - `GET /jobs` lists the jobs kept, and `GET /jobs/{id}` shows one: its `state`
+//!   (`running`, `succeeded`, `failed` or `cancelled`), and once it's done
+//!   the report, plus the error and exit code if it didn't succeed.
+//! - `POST /jobs/{id}/cancel` stops a running job and removes its partial
+//!   outputs.
+//!
+//! A server started with `--token` answers `401 Unauthorized` to requests
+//! without an `Authorization: Bearer TOKEN` header. Only the last
+//! [`KEEP_FINISHED`] finished jobs are kept.
+
+use log::{debug, info, warn};
+use serde_json::{json, Value};
+use spritesheet_assembler::{exit, CancelHandle, Report};
+use std::{
+    collections::BTreeMap,
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, Mutex},
+    thread,
+};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::{serve, BoxResult};
+
+/// The running jobs and the last finished ones, by id.
+type Jobs = Arc<Mutex<BTreeMap<u64, Job>>>;
+
+/// How many finished jobs are kept to be looked at; older ones are forgotten.
+const KEEP_FINISHED: usize = 100;
+
+struct Job {
+    root: String,
+    cancel: CancelHandle,
+    /// `None` while the job is running.
+    result: Option<Result<Report, (Report, String, i32)>>,
+}
+
+impl Job {
+    fn to_json(&self, id: u64) -> Value {
+        let mut job = json!({ "id": id, "root": self.root });
+        match &self.result {
+            None => job["state"] = json!("running"),
+            Some(Ok(report)) => {
+                job["state"] = json!("succeeded");
+                job["report"] = json!(report);
+            }
+            Some(Err((report, error, code))) => {
+                job["state"] = json!(if *code == exit::CANCELLED {
+                    "cancelled"
+                } else {
+                    "failed"
+                });
+                job["report"] = json!(report);
+                job["error"] = json!(error);
+                job["exit_code"] = json!(code);
+            }
+        }
+        job
+    }
+}
+
+/// Serves the API on `addr` until interrupted. A bare `:8080` only listens
+/// on this machine.
+pub fn serve(addr: &str, access: serve::Access) -> BoxResult<()> {
+    let server = Server::http(serve::address(addr))?;
+    let addr = server
+        .server_addr()
+        .to_ip()
+        .ok_or("Can only serve HTTP over TCP")?;
+    access.check_exposure(addr)?;
+    info!("Serving HTTP on {}", addr);
+    let jobs = Jobs::default();
+    let mut next_id = 1;
+    for mut request in server.incoming_requests() {
+        debug!("{} {}", request.method(), request.url());
+        if !authorized(&request, &access) {
+            warn!("Refusing a request without the server's token");
+            respond(request, 401, &json!({ "error": "Unauthorized" }));
+            continue;
+        }
+        let (status, body) = match (request.method(), request.url()) {
+            (Method::Post, "/assemble") => match start(&mut request, &jobs, next_id, &access) {
+                Ok(()) => {
+                    let id = next_id;
+                    next_id += 1;
+                    (202, json!({ "id": id, "url": format!("/jobs/{}", id) }))
+                }
+                Err(err) => (400, json!({ "error": err.to_string() })),
+            },
+            (Method::Get, "/jobs") => {
+                let jobs = jobs.lock().unwrap();
+                let list = jobs
+                    .iter()
+                    .map(|(id, job)| job.to_json(*id))
+                    .collect::<Vec<_>>();
+                (200, json!(list))
+            }
+            (Method::Post, url) if url.starts_with("/jobs/") && url.ends_with("/cancel") => {
+                let id = &url["/jobs/".len()..url.len() - "/cancel".len()];
+                let jobs = jobs.lock().unwrap();
+                match id
+                    .parse::<u64>()
+                    .ok()
+                    .and_then(|id| Some((id, jobs.get(&id)?)))
+                {
+                    Some((id, job)) if job.result.is_none() => {
+                        info!("Job {}: cancelling", id);
+                        job.cancel.cancel();
+                        (202, job.to_json(id))
+                    }
+                    Some(_) => (409, json!({ "error": "The job has already finished" })),
+                    None => (404, json!({ "error": "No such job" })),
+                }
+            }
+            (Method::Get, url) if url.starts_with("/jobs/") => {
+                let job = url["/jobs/".len()..].parse::<u64>().ok().and_then(|id| {
+                    let jobs = jobs.lock().unwrap();
+                    jobs.get(&id).map(|job| job.to_json(id))
+                });
+                match job {
+                    Some(job) => (200, job),
+                    None => (404, json!({ "error": "No such job" })),
+                }
+            }
+            (_, "/assemble") | (_, "/jobs") => (405, json!({ "error": "Method not allowed" })),
+            _ => (404, json!({ "error": "Not found" })),
+        };
+        respond(request, status, &body);
+    }
+    Ok(())
+}
+
+/// Starts the job described by the body of a `POST /assemble`.
+fn start(request: &mut Request, jobs: &Jobs, id: u64, access: &serve::Access) -> BoxResult<()> {
+    let params = serde_json::from_reader::<_, Value>(request.as_reader())?;
+    let assembler = serve::assembler(&params, access)?;
+    let root = assembler.root().display().to_string();
+    info!("Job {}: assembling {}", id, root);
+    let cancel = assembler.cancel_handle();
+    jobs.lock().unwrap().insert(
+        id,
+        Job {
+            root,
+            cancel,
+            result: None,
+        },
+    );
+    let jobs = Arc::clone(jobs);
+    thread::spawn(move || {
+        let mut report = Report::default();
+        // A job that panics still finishes, so it isn't left running.
+        let ran = panic::catch_unwind(AssertUnwindSafe(|| assembler.run_with(&mut report)));
+        let result = match ran {
+            Ok(Ok(())) => Ok(report),
+            Ok(Err(err)) => {
+                warn!("Job {} failed: {}", id, err);
+                Err((report, err.to_string(), exit::code(&err)))
+            }
+            Err(panic) => {
+                let message = serve::panic_message(&*panic);
+                warn!("Job {} failed: {}", id, message);
+                Err((report, message, exit::FAILURE))
+            }
+        };
+        let mut jobs = jobs.lock().unwrap();
+        if let Some(job) = jobs.get_mut(&id) {
+            job.result = Some(result);
+        }
+        forget_finished(&mut jobs);
+    });
+    Ok(())
+}
+
+/// Whether `request` carries the server's token, if it has one.
+fn authorized(request: &Request, access: &serve::Access) -> bool {
+    if access.token.is_none() {
+        return true;
+    }
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Authorization"))
+        .and_then(|header| header.value.as_str().strip_prefix("Bearer "))
+        .is_some_and(|token| access.authorized(token))
+}
+
+/// Drops the oldest finished jobs beyond [`KEEP_FINISHED`].
+fn forget_finished(jobs: &mut BTreeMap<u64, Job>) {
+    let finished = jobs
+        .iter()
+        .filter(|(_, job)| job.result.is_some())
+        .map(|(id, _)| *id)
+        .collect::<Vec<_>>();
+    for id in &finished[..finished.len().saturating_sub(KEEP_FINISHED)] {
+        jobs.remove(id);
+    }
+}
+
+fn respond(request: Request, status: u16, body: &Value) {
+    let content_type = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
+        .expect("the header is valid");
+    let response = Response::from_string(body.to_string())
+        .with_status_code(status)
+        .with_header(content_type);
+    if let Err(err) = request.respond(response) {
+        warn!("Couldn't send a response: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(finished: bool) -> Job {
+        Job {
+            root: String::new(),
+            cancel: CancelHandle::default(),
+            result: Some(Ok(Report::default())).filter(|_| finished),
+        }
+    }
+
+    #[test]
+    fn forgets_the_oldest_finished_jobs_only() {
+        let mut jobs = (1..=KEEP_FINISHED as u64 + 3)
+            .map(|id| (id, job(id != 2)))
+            .collect::<BTreeMap<_, _>>();
+        forget_finished(&mut jobs);
+        assert_eq!(jobs.len(), KEEP_FINISHED + 1);
+        assert!(jobs.contains_key(&2), "running jobs are kept");
+        assert!(!jobs.contains_key(&1) && !jobs.contains_key(&3));
+        assert!(jobs.contains_key(&4));
+    }
+}
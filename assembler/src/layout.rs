@@ -0,0 +1,385 @@
+//! Arranging frames into cells of one or more sheets.
+
+use std::{cmp::max, convert::TryFrom};
+
+use crate::{errors::SheetTooLargeError, report, Dims, Result};
+
+/// Many engines and texture importers fail to load anything wider or
+/// taller than this.
+pub const DEFAULT_MAX_SIZE: u32 = 16384;
+
+/// Where every frame goes: `tiles` cells of `dims` pixels on each page.
+pub struct Layout {
+    pub dims: Dims,
+    pub tiles: Dims,
+    pub frames: usize,
+    /// What was done to keep the sheet within the size limit, if anything.
+    pub fallback: Option<String>,
+    /// How many frames each row holds, when groups of frames start rows of
+    /// their own rather than filling every row.
+    pub rows: Option<Vec<usize>>,
+    /// The most frames a page holds, if fewer than fit.
+    pub per_page: Option<usize>,
+}
+
+/// One sheet of a layout, holding frames `first..first + count`.
+pub struct Page {
+    pub index: usize,
+    pub first: usize,
+    pub count: usize,
+    /// The last page only has as many rows as it needs.
+    pub tiles: Dims,
+    pub size: (u32, u32),
+    /// The cell each frame goes in, counting across then down.
+    pub cells: Vec<usize>,
+}
+
+/// Lays `count` frames out in a single row, unless that would be wider than
+/// `max_size`, in which case they wrap into a grid and then onto more pages.
+/// With `per_page`, each page is laid out for that many frames at most, and
+/// the rest go onto more pages.
+pub fn plan(
+    count: usize,
+    dims: Dims,
+    max_size: Option<u32>,
+    per_page: Option<usize>,
+) -> Result<Layout> {
+    let per_page = per_page.filter(|per_page| *per_page < count);
+    let layout = |tiles, fallback| Layout {
+        dims,
+        tiles,
+        frames: count,
+        fallback,
+        rows: None,
+        per_page,
+    };
+    // From here on, what a single page has to hold.
+    let total = count;
+    let count = per_page.unwrap_or(count);
+    let row = Dims { x: count, y: 1 };
+    let limit = match max_size {
+        Some(limit) => limit as usize,
+        None => return Ok(layout(row, None)),
+    };
+    if dims.x > limit || dims.y > limit {
+        return Err(SheetTooLargeError {
+            width: dims.x as u64,
+            height: dims.y as u64,
+        }
+        .into());
+    }
+    let width = count as u64 * dims.x as u64;
+    if width <= limit as u64 {
+        return Ok(layout(row, None));
+    }
+
+    let grid = optimal_stacking(count, dims);
+    if grid.x * dims.x <= limit && grid.y * dims.y <= limit {
+        let fallback = format!(
+            "A single row would be {}px wide, over the {}px limit, so the frames wrap into {} columns",
+            width, limit, grid.x
+        );
+        return Ok(layout(grid, Some(fallback)));
+    }
+
+    let page = Dims {
+        x: limit / dims.x,
+        y: limit / dims.y,
+    };
+    let pages = total.div_ceil((page.x * page.y).min(count));
+    let fallback = format!(
+        "{} frames of {}x{}px don't fit within the {}px limit, so they are split across {} pages of up to {}x{} frames",
+        total, dims.x, dims.y, limit, pages, page.x, page.y
+    );
+    Ok(layout(page, Some(fallback)))
+}
+
+/// Lays frames out in `rows` of the given lengths, padding the shorter ones
+/// with empty cells. The rows go onto more pages if there are too many for
+/// `max_size`, or more than `per_page` frames, but a row too wide for it is
+/// an error.
+pub fn plan_rows(
+    rows: Vec<usize>,
+    dims: Dims,
+    max_size: Option<u32>,
+    per_page: Option<usize>,
+) -> Result<Layout> {
+    let columns = rows.iter().copied().max().unwrap_or(0);
+    let mut tiles = Dims {
+        x: columns,
+        y: rows.len(),
+    };
+    let mut fallback = None;
+    if let Some(limit) = max_size.map(|limit| limit as usize) {
+        if dims.y > limit || columns * dims.x > limit {
+            return Err(SheetTooLargeError {
+                width: columns as u64 * dims.x as u64,
+                height: dims.y as u64,
+            }
+            .into());
+        }
+        if tiles.y * dims.y > limit {
+            tiles.y = limit / dims.y;
+            fallback = Some(format!(
+                "{} rows of {}px don't fit within the {}px limit, so they are split across {} pages of up to {} rows",
+                rows.len(),
+                dims.y,
+                limit,
+                rows.len().div_ceil(tiles.y),
+                tiles.y
+            ));
+        }
+    }
+    Ok(Layout {
+        dims,
+        tiles,
+        frames: rows.iter().sum(),
+        fallback,
+        rows: Some(rows),
+        per_page,
+    })
+}
+
+impl Layout {
+    pub fn pages(&self) -> Result<Vec<Page>> {
+        if let Some(rows) = &self.rows {
+            return self.row_pages(rows);
+        }
+        let per_page = (self.tiles.x * self.tiles.y).min(self.per_page.unwrap_or(usize::MAX));
+        (0..self.frames)
+            .step_by(per_page.max(1))
+            .enumerate()
+            .map(|(index, first)| {
+                let count = per_page.min(self.frames - first);
+                let tiles = Dims {
+                    x: self.tiles.x,
+                    y: y_from_x(self.tiles.x, count),
+                };
+                Ok(Page {
+                    index,
+                    first,
+                    count,
+                    tiles,
+                    size: sheet_size(tiles, self.dims)?,
+                    cells: (0..count).collect(),
+                })
+            })
+            .collect()
+    }
+
+    fn row_pages(&self, rows: &[usize]) -> Result<Vec<Page>> {
+        // Whole rows, as many as fit on a page.
+        let mut pages = Vec::<&[usize]>::new();
+        let mut start = 0;
+        for end in 1..=rows.len() {
+            let frames = rows[start..end].iter().sum::<usize>();
+            let full = end - start > self.tiles.y.max(1)
+                || self.per_page.is_some_and(|per_page| frames > per_page);
+            if full && end - start > 1 {
+                pages.push(&rows[start..end - 1]);
+                start = end - 1;
+            }
+        }
+        if start < rows.len() {
+            pages.push(&rows[start..]);
+        }
+        let mut first = 0;
+        pages
+            .into_iter()
+            .enumerate()
+            .map(|(index, rows)| {
+                let count = rows.iter().sum();
+                let tiles = Dims {
+                    x: self.tiles.x,
+                    y: rows.len(),
+                };
+                let cells = rows
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(row, length)| {
+                        (0..*length).map(move |column| row * tiles.x + column)
+                    })
+                    .collect();
+                let page = Page {
+                    index,
+                    first,
+                    count,
+                    tiles,
+                    size: sheet_size(tiles, self.dims)?,
+                    cells,
+                };
+                first += count;
+                Ok(page)
+            })
+            .collect()
+    }
+
+    /// The layout as reported, with the size of the first (largest) page.
+    pub fn report(&self, pages: &[Page]) -> report::Sheet {
+        let (width, height) = pages.first().map(|page| page.size).unwrap_or_default();
+        report::Sheet {
+            width,
+            height,
+            tile_width: self.dims.x as u32,
+            tile_height: self.dims.y as u32,
+            columns: self.tiles.x as u32,
+            rows: pages
+                .first()
+                .map(|page| page.tiles.y as u32)
+                .unwrap_or_default(),
+            frames: self.frames,
+            pages: pages.len(),
+            fallback: self.fallback.clone(),
+        }
+    }
+}
+
+/// Top-left corner of the `index`th cell.
+pub fn cell_position(index: usize, tiles: Dims, dims: Dims) -> (u32, u32) {
+    let x = (index % tiles.x) * dims.x;
+    let y = (index / tiles.x) * dims.y;
+    (x as u32, y as u32)
+}
+
+/// The grid closest to square for `count` cells of `dims`.
+fn optimal_stacking(count: usize, dims: Dims) -> Dims {
+    struct Min {
+        dim: usize,
+        x: usize,
+    }
+    let Min { x, .. } = (1..=count).fold(
+        Min {
+            dim: usize::MAX,
+            x: 0,
+        },
+        |min, x| {
+            let y = y_from_x(x, count);
+            let dim = max(y * dims.y, x * dims.x);
+            if dim < min.dim {
+                Min { x, dim }
+            } else {
+                min
+            }
+        },
+    );
+    Dims {
+        x,
+        y: y_from_x(x, count),
+    }
+}
+
+/// The sheet's size in pixels, as long as a buffer that big can exist.
+fn sheet_size(tiles: Dims, dims: Dims) -> Result<(u32, u32)> {
+    let too_large = || SheetTooLargeError {
+        width: tiles.x as u64 * dims.x as u64,
+        height: tiles.y as u64 * dims.y as u64,
+    };
+    let width = tiles.x.checked_mul(dims.x).ok_or_else(too_large)?;
+    let height = tiles.y.checked_mul(dims.y).ok_or_else(too_large)?;
+    width
+        .checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(4))
+        .ok_or_else(too_large)?;
+    Ok((
+        u32::try_from(width).map_err(|_| too_large())?,
+        u32::try_from(height).map_err(|_| too_large())?,
+    ))
+}
+
+fn y_from_x(x: usize, count: usize) -> usize {
+    (count as f32 / x as f32).ceil() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CELL: Dims = Dims { x: 10, y: 10 };
+
+    /// Each page's first frame, frame count and size.
+    fn summary(layout: &Layout) -> Vec<(usize, usize, (u32, u32))> {
+        layout
+            .pages()
+            .unwrap()
+            .iter()
+            .map(|page| (page.first, page.count, page.size))
+            .collect()
+    }
+
+    #[test]
+    fn single_row_within_the_limit() {
+        let layout = plan(5, CELL, Some(50), None).unwrap();
+        assert_eq!((layout.tiles.x, layout.tiles.y), (5, 1));
+        assert!(layout.fallback.is_none());
+        assert_eq!(summary(&layout), vec![(0, 5, (50, 10))]);
+    }
+
+    #[test]
+    fn single_row_without_a_limit() {
+        let layout = plan(100, CELL, None, None).unwrap();
+        assert_eq!(summary(&layout), vec![(0, 100, (1000, 10))]);
+    }
+
+    #[test]
+    fn wraps_into_a_grid_past_the_limit() {
+        let layout = plan(6, CELL, Some(50), None).unwrap();
+        assert_eq!((layout.tiles.x, layout.tiles.y), (2, 3));
+        assert!(layout.fallback.is_some());
+        assert_eq!(summary(&layout), vec![(0, 6, (20, 30))]);
+    }
+
+    #[test]
+    fn splits_into_pages_when_no_grid_fits() {
+        let layout = plan(10, CELL, Some(20), None).unwrap();
+        assert_eq!((layout.tiles.x, layout.tiles.y), (2, 2));
+        assert_eq!(
+            summary(&layout),
+            vec![(0, 4, (20, 20)), (4, 4, (20, 20)), (8, 2, (20, 10)),]
+        );
+    }
+
+    #[test]
+    fn fails_when_one_frame_is_over_the_limit() {
+        assert!(plan(1, Dims { x: 30, y: 10 }, Some(20), None).is_err());
+    }
+
+    #[test]
+    fn caps_frames_per_page() {
+        let layout = plan(5, CELL, None, Some(2)).unwrap();
+        assert_eq!(
+            summary(&layout),
+            vec![(0, 2, (20, 10)), (2, 2, (20, 10)), (4, 1, (20, 10))]
+        );
+    }
+
+    #[test]
+    fn ignores_a_per_page_cap_above_the_frame_count() {
+        let layout = plan(3, CELL, None, Some(8)).unwrap();
+        assert!(layout.per_page.is_none());
+        assert_eq!(summary(&layout), vec![(0, 3, (30, 10))]);
+    }
+
+    #[test]
+    fn pads_short_rows() {
+        let layout = plan_rows(vec![3, 1, 2], CELL, None, None).unwrap();
+        let pages = layout.pages().unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].size, (30, 30));
+        assert_eq!(pages[0].cells, vec![0, 1, 2, 3, 6, 7]);
+    }
+
+    #[test]
+    fn moves_whole_rows_onto_more_pages() {
+        let layout = plan_rows(vec![2, 2, 2], CELL, Some(20), None).unwrap();
+        assert!(layout.fallback.is_some());
+        assert_eq!(summary(&layout), vec![(0, 4, (20, 20)), (4, 2, (20, 10))]);
+
+        let layout = plan_rows(vec![2, 1, 2], CELL, None, Some(3)).unwrap();
+        assert_eq!(summary(&layout), vec![(0, 3, (20, 20)), (3, 2, (20, 10))]);
+    }
+
+    #[test]
+    fn fails_when_a_row_is_over_the_limit() {
+        assert!(plan_rows(vec![1, 3], CELL, Some(20), None).is_err());
+    }
+}
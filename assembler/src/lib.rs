@@ -0,0 +1,284 @@
+//! Combines rendered animation frames into spritesheets, along with
+//! metadata describing where each frame ended up.
+//!
+//! This is the library behind the `assembler` command line tool and the
+//! Blender add-on. Configure a run with the [`Assembler`] builder:
+//!
+//! ```no_run
+//! use spritesheet_assembler::{Assembler, MetadataFormat, Overwrite};
+//!
+//! let report = Assembler::new("renders/hero")
+//!     .frames("temp")
+//!     .output("{name}_{frames}f.png")
+//!     .metadata(MetadataFormat::Json)
+//!     .overwrite(Overwrite::Force)
+//!     .run()?;
+//! println!("wrote {}", report.outputs[0].path.display());
+//! # Ok::<(), spritesheet_assembler::AssemblerError>(())
+//! ```
+
+use serde::Serialize;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+mod assembler;
+mod boxes;
+mod cache;
+mod cancel;
+mod checkpoint;
+mod checksum;
+mod collect;
+mod collision;
+mod debug;
+mod duplicates;
+mod empty;
+mod encode;
+pub mod errors;
+mod events;
+pub mod exit;
+mod facing;
+pub mod filter;
+mod format;
+mod gaps;
+mod godot;
+#[cfg(feature = "gpu")]
+mod gpu;
+mod layout;
+mod limit;
+mod lock;
+mod mask;
+mod memory;
+mod mesh;
+mod metadata;
+mod nine_slice;
+mod number;
+mod numbering;
+mod output;
+mod pad;
+mod palette;
+mod passes;
+mod paths;
+mod pivot;
+mod preview;
+mod progress;
+mod provenance;
+pub mod report;
+mod simd;
+mod stats;
+mod stream;
+mod template;
+mod timing;
+mod trim;
+mod video;
+
+pub use crate::assembler::Assembler;
+pub use cancel::CancelHandle;
+pub use checksum::Checksums;
+pub use collision::Collision;
+pub use empty::EmptyFrames;
+pub use encode::Compression;
+pub use events::Event;
+pub use filter::FrameFilter;
+pub use gaps::StrictSequence;
+pub use layout::DEFAULT_MAX_SIZE;
+pub use limit::Target;
+pub use mask::Mask;
+pub use memory::{EncodedFrame, EncodedSheet};
+pub use metadata::MetadataFormat;
+pub use nine_slice::Insets;
+pub use output::Overwrite;
+pub use pad::Anchor;
+pub use paths::resolve as resolve_path;
+pub use progress::disable as disable_progress;
+pub use report::Report;
+pub use timing::{Direction, Marker};
+pub use trim::Trim;
+
+pub use errors::{AssemblerError, Phase};
+
+pub type Result<T, E = AssemblerError> = std::result::Result<T, E>;
+
+#[derive(Debug, Copy, Clone)]
+struct Dims {
+    x: usize,
+    y: usize,
+}
+
+/// Settings for one assembly, filled in by the [`Assembler`] builder.
+#[derive(Debug, Clone, Serialize)]
+struct Options {
+    root: String,
+    /// Folder holding the frames, relative to the root or absolute.
+    frames: String,
+    /// Frame files to use instead of searching the frames folder.
+    inputs: Vec<PathBuf>,
+    /// Template for the sheet's filename, relative to the root.
+    output: String,
+    /// Substituted for `{name}` in the output template.
+    name: Option<String>,
+    exclude: Vec<String>,
+    /// How many levels of subfolders below the frames folder to search.
+    max_depth: Option<usize>,
+    follow_symlinks: bool,
+    /// Where the frame number is in each filename, ordering the frames.
+    name_pattern: Option<numbering::NamePattern>,
+    /// The first and last frame numbers to use.
+    start: Option<i64>,
+    end: Option<i64>,
+    overwrite: Overwrite,
+    /// Delete the frames folder after a successful run.
+    cleanup: bool,
+    /// Wait for another run in the same root instead of failing.
+    wait: bool,
+    /// Metadata files to write next to the sheet.
+    metadata: Vec<MetadataFormat>,
+    /// Include the sheet itself in generated code.
+    embed_sheet: bool,
+    /// Template for frame names in the metadata.
+    frame_name: String,
+    /// Playback directions by animation, overriding the folders' suffixes.
+    directions: Vec<(String, Direction)>,
+    /// Indices of the frames in playback order, if they don't just play in
+    /// sheet order.
+    sequence: Option<timing::Sequence>,
+    /// Events to attach to the frames they mark.
+    markers: Vec<Marker>,
+    /// Shapes to trace around the frames' opaque pixels, if any.
+    collision: Option<Collision>,
+    /// How far the traced outlines may stray from the pixels.
+    collision_tolerance: f64,
+    /// Write which pixels of each frame are solid to a sidecar, and how.
+    collision_mask: Option<Mask>,
+    /// Write a Godot scene switching collision shapes with the frames.
+    godot_scene: bool,
+    /// The frames are in `<facing>/<animation>/` folders, and each facing's
+    /// animation gets its own row.
+    facings: bool,
+    /// Export the box around each frame's visible pixels in the metadata.
+    opaque_bounds: bool,
+    /// Mesh the frames' visible pixels into the metadata.
+    mesh: bool,
+    /// Folder of the pass the boxes are painted on, mirroring the frames
+    /// folder, relative to the root or absolute.
+    boxes: Option<String>,
+    /// The boxes' labels and colours, if not the defaults.
+    box_colours: Vec<(String, [u8; 3])>,
+    /// Suffixes of the render passes saved side by side, the first being
+    /// the one assembled as usual.
+    passes: Vec<String>,
+    /// 9-slice insets of every frame, unless it has its own.
+    nine_slice: Option<Insets>,
+    /// 9-slice insets by frame or animation name.
+    nine_slices: nine_slice::NineSlices,
+    /// Playback speed in frames per second.
+    fps: u32,
+    /// Playback speeds of animations that don't play at `fps`.
+    animation_fps: Vec<(String, u32)>,
+    /// Write a copy of the sheet with its layout drawn on top.
+    debug_overlay: bool,
+    /// Write a copy of the sheet with each frame's index drawn on it.
+    number_frames: bool,
+    /// Size of those indices, in multiples of the font's pixels.
+    number_size: u32,
+    /// Which corner of the cell the indices go in.
+    number_corner: Anchor,
+    /// Write a labelled contact sheet of the frames next to the sheet.
+    preview: bool,
+    /// Where to write an animated preview of the frames, relative to the root.
+    preview_gif: Option<String>,
+    /// Where to write a page with a player for the frames, relative to the root.
+    preview_html: Option<String>,
+    /// Where to write a video clip of the frames, relative to the root.
+    preview_video: Option<String>,
+    /// Frame rate of the clip, if not `fps`.
+    preview_video_fps: Option<u32>,
+    /// How much larger to make the clip than the frames.
+    preview_video_scale: f64,
+    /// Where to write the colours the sheet uses, relative to the root.
+    palette: Option<String>,
+    /// How many colours the palette may have, if not all of them.
+    palette_colors: Option<usize>,
+    /// Record how the sheet was made in a sidecar file.
+    provenance: bool,
+    /// Checksum files to write next to the outputs.
+    checksums: Option<Checksums>,
+    /// Blender scene the frames were rendered from, for the provenance record.
+    scene: Option<String>,
+    /// Stream frames from disk instead of decoding them all up front.
+    low_memory: bool,
+    /// Composite on the graphics card, if there's one to use.
+    gpu: bool,
+    compression: Compression,
+    /// Write the same bytes for the same inputs on any machine.
+    deterministic: bool,
+    /// Largest width or height of a sheet before frames wrap onto more rows
+    /// or pages, or `None` for no limit.
+    max_size: Option<u32>,
+    /// The most frames on a sheet before the rest go onto more pages.
+    max_frames_per_page: Option<usize>,
+    /// Fail rather than write a sheet wider or taller than this.
+    fail_if_larger_than: Option<(u32, u32)>,
+    /// Switch to streaming when decoding up front would need more bytes than this.
+    max_memory: Option<u64>,
+    /// Reuse unchanged cells of the previous sheet.
+    cache: bool,
+    /// Save progress as the sheet is streamed, and pick up where an
+    /// interrupted run stopped.
+    resume: bool,
+    /// Only write the metadata for the sheet an earlier run wrote.
+    metadata_only: bool,
+    /// Write a sheet per animation subfolder instead of one for everything.
+    split_by_animation: bool,
+    /// The animation this run is assembling, when splitting by animation.
+    animation: Option<String>,
+    /// Pad frames smaller than the largest instead of failing.
+    pad_to_max: bool,
+    /// Where padded frames sit on the bigger canvas.
+    pad_anchor: Anchor,
+    /// How to cut the frames down to their visible pixels, if at all.
+    trim: Option<trim::Trim>,
+    /// Pivot of frames that don't have their own, if pivots are exported.
+    pivot: Option<Anchor>,
+    /// Colour of the pixel marking each frame's pivot, if they're marked.
+    pivot_marker: Option<[u8; 3]>,
+    /// What to do about fully transparent frames, if anything.
+    empty_frames: Option<EmptyFrames>,
+    /// Fail, rather than warn, if frames differ in bit depth or colour space.
+    strict_format: bool,
+    /// What to do about frame numbers an animation skips or repeats, if
+    /// anything.
+    strict_sequence: Option<StrictSequence>,
+    /// Report duplicate frames, and consecutive ones nearly the same.
+    find_duplicates: bool,
+    /// How alike consecutive frames must be, from 0 to 1, to be reported.
+    similarity: f64,
+    /// Run on every frame after decoding, in order.
+    #[serde(serialize_with = "filter::serialize")]
+    filters: Vec<Arc<dyn FrameFilter>>,
+    #[serde(skip)]
+    events: events::Events,
+    #[serde(skip)]
+    cancel: CancelHandle,
+    /// The only folder outputs may be written to, if set.
+    #[serde(skip)]
+    confine: Option<PathBuf>,
+    /// Where a resumable run saves its progress, once it's streaming.
+    #[serde(skip)]
+    checkpoint: Option<Arc<checkpoint::Checkpoint>>,
+}
+
+impl Options {
+    /// Where the individual frames are read from.
+    fn frames_dir(&self) -> PathBuf {
+        // Joining an absolute path replaces the root entirely.
+        Path::new(&self.root).join(&self.frames)
+    }
+
+    /// Where the frames of the pass with the boxes are read from, if any.
+    fn boxes_dir(&self) -> Option<PathBuf> {
+        let boxes = self.boxes.as_ref()?;
+        Some(Path::new(&self.root).join(boxes))
+    }
+}
@@ -0,0 +1,157 @@
+//! `--fail-if-larger-than`: stops before writing a sheet the target GPU
+//! would refuse to load, and says what would make it fit.
+
+use serde::Serialize;
+
+use crate::{
+    collect::Frame,
+    debug,
+    errors::SheetLimitError,
+    layout::{self, Layout, Page},
+    timing, Options, Result,
+};
+
+/// Platforms with a well-known largest texture size.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Target {
+    /// What every WebGL implementation can be relied on to load.
+    Webgl,
+    /// The minimum for OpenGL ES 2 GPUs, still common on low-end phones.
+    Mobile,
+    /// Desktop GPUs from the last decade.
+    Desktop,
+}
+
+impl Target {
+    pub const NAMES: &'static [&'static str] = &["webgl", "mobile", "desktop"];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "webgl" => Some(Target::Webgl),
+            "mobile" => Some(Target::Mobile),
+            "desktop" => Some(Target::Desktop),
+            _ => None,
+        }
+    }
+
+    /// The largest sheet, in pixels, the target loads.
+    pub fn size(self) -> (u32, u32) {
+        match self {
+            Target::Webgl => (4096, 4096),
+            Target::Mobile => (2048, 2048),
+            Target::Desktop => (16384, 16384),
+        }
+    }
+}
+
+/// A size such as `4096x2048`.
+pub fn parse_size(text: &str) -> Option<(u32, u32)> {
+    let (width, height) = text.trim().split_once(['x', 'X'])?;
+    Some((width.trim().parse().ok()?, height.trim().parse().ok()?))
+}
+
+/// Fails if any page is bigger than the limit, listing what would help.
+pub fn check(options: &Options, layout: &Layout, pages: &[Page], frames: &[Frame]) -> Result<()> {
+    let limit = match options.fail_if_larger_than {
+        Some(limit) => limit,
+        None => return Ok(()),
+    };
+    let fits = |(width, height): (u32, u32)| width <= limit.0 && height <= limit.1;
+    let size = match pages.iter().map(|page| page.size).find(|size| !fits(*size)) {
+        Some(size) => size,
+        None => return Ok(()),
+    };
+    let dims = layout.dims;
+    let mut suggestions = Vec::new();
+
+    // More, smaller pages, as long as a single frame fits.
+    let square = limit.0.min(limit.1);
+    if fits((dims.x as u32, dims.y as u32)) {
+        if let Ok(pages) = layout::plan(layout.frames, dims, Some(square), layout.per_page)
+            .and_then(|layout| layout.pages())
+        {
+            suggestions.push(format!(
+                "--max-size {} to spread the frames over {} sheet(s) of at most {}x{}px",
+                square,
+                pages.len(),
+                square,
+                square
+            ));
+        }
+    }
+
+    // A sheet per animation, if the largest animation fits on one.
+    let mut counts = Vec::<(&str, usize)>::new();
+    for frame in frames {
+        let animation = timing::animation(frame).0;
+        match counts.iter_mut().find(|(name, _)| *name == animation) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((animation, 1)),
+        }
+    }
+    let largest = counts.iter().map(|(_, count)| *count).max().unwrap_or(0);
+    if !options.split_by_animation && counts.len() > 1 {
+        let largest = layout::plan(largest, dims, options.max_size, options.max_frames_per_page)
+            .and_then(|layout| layout.pages())
+            .ok()
+            .and_then(|pages| pages.first().map(|page| page.size));
+        if let Some(largest) = largest.filter(|size| fits(*size)) {
+            suggestions.push(format!(
+                "--split-by-animation to write a sheet per animation, the largest {}x{}px",
+                largest.0, largest.1
+            ));
+        }
+    }
+
+    // Cropping every frame to the area any of them covers.
+    if let Some((width, height)) = opaque_size(frames).filter(|size| *size != (dims.x, dims.y)) {
+        let page = &pages[0];
+        let trimmed = (
+            (page.tiles.x * width) as u32,
+            (page.tiles.y * height) as u32,
+        );
+        if fits(trimmed) {
+            suggestions.push(format!(
+                "cropping the renders to {}x{}px, which holds every frame's opaque pixels, \
+                 by tightening the camera or render border, for a {}x{}px sheet",
+                width, height, trimmed.0, trimmed.1
+            ));
+        }
+    }
+
+    // Rendering smaller, at the same layout.
+    let scale =
+        (f64::from(limit.0) / f64::from(size.0)).min(f64::from(limit.1) / f64::from(size.1));
+    let percent = (scale * 100.0).floor();
+    if percent >= 1.0 {
+        suggestions.push(format!(
+            "rendering at {}% of the current resolution or less",
+            percent
+        ));
+    }
+
+    Err(SheetLimitError {
+        width: size.0,
+        height: size.1,
+        limit,
+        suggestions,
+    }
+    .into())
+}
+
+/// The smallest size that holds the opaque pixels of every frame, if every
+/// frame has been decoded.
+fn opaque_size(frames: &[Frame]) -> Option<(usize, usize)> {
+    let mut bounds: Option<(u32, u32, u32, u32)> = None;
+    for frame in frames {
+        let image = frame.image.as_ref()?;
+        let opaque = debug::opaque_bounds(image, 0, 0, image.width(), image.height());
+        bounds = match (bounds, opaque) {
+            (Some(a), Some(b)) => Some((a.0.min(b.0), a.1.min(b.1), a.2.max(b.2), a.3.max(b.3))),
+            (a, b) => a.or(b),
+        };
+    }
+    let (left, top, right, bottom) = bounds?;
+    Some(((right - left + 1) as usize, (bottom - top + 1) as usize))
+}
@@ -0,0 +1,92 @@
+//! An advisory lock on the root, so the Blender add-on and someone at the
+//! command line can't assemble into the same folder at once and interleave
+//! their writes. The operating system releases it if the process dies, so
+//! a crashed run never leaves the root locked. The lock file is kept in the
+//! system's temporary folder, named after the root, so nothing is left in
+//! the user's output folder.
+
+use log::{info, warn};
+use std::{
+    env,
+    fs::{self, File, OpenOptions, TryLockError},
+    io,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    errors::{IoContext, LockedError, Phase},
+    report, Options, Result,
+};
+
+/// How often to try again while waiting for another run.
+const POLL: Duration = Duration::from_millis(100);
+
+/// Holds the lock until dropped.
+pub struct Lock(#[allow(dead_code)] Option<File>);
+
+/// Locks the root, failing straight away if another run holds it, or
+/// waiting for that run to finish if `options.wait` is set.
+pub fn acquire(options: &Options) -> Result<Lock> {
+    let root = Path::new(&options.root);
+    let path = match fs::canonicalize(root) {
+        Ok(canonical) => path(&canonical),
+        // Without a root there is nothing to protect, and the run fails later.
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Lock(None)),
+        Err(err) => return Err(err).context(Phase::Read, root),
+    };
+    let opened = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path);
+    let file = match opened {
+        Ok(file) => file,
+        // Another user's run made it, which can still be locked for reading.
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied => {
+            File::open(&path).context(Phase::Read, &path)?
+        }
+        Err(err) => return Err(err).context(Phase::Write, &path),
+    };
+    let mut waiting = false;
+    loop {
+        match file.try_lock() {
+            Ok(()) => return Ok(Lock(Some(file))),
+            Err(TryLockError::WouldBlock) if options.wait => {
+                if !waiting {
+                    info!(
+                        "Waiting for another assembly in {} to finish",
+                        root.display()
+                    );
+                    waiting = true;
+                }
+                options.cancel.check()?;
+                thread::sleep(POLL);
+            }
+            Err(TryLockError::WouldBlock) => {
+                return Err(LockedError {
+                    root: root.to_path_buf(),
+                }
+                .into())
+            }
+            // Some network shares can't lock files at all.
+            Err(TryLockError::Error(err)) if err.kind() == io::ErrorKind::Unsupported => {
+                warn!(
+                    "Can't lock {}, so not guarding against another run: {}",
+                    path.display(),
+                    err
+                );
+                return Ok(Lock(None));
+            }
+            Err(TryLockError::Error(err)) => return Err(err).context(Phase::Write, &path),
+        }
+    }
+}
+
+/// The lock file for the root at `canonical`, the same for every path
+/// leading to it.
+fn path(canonical: &Path) -> PathBuf {
+    let root = report::sha256(canonical.to_string_lossy().as_bytes());
+    env::temp_dir().join(format!("spritesheet-{}.lock", &root[..16]))
+}
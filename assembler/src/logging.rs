@@ -0,0 +1,47 @@
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        // Other crates' messages, like wgpu's about each graphics backend it
+        // tries, are only of use when debugging.
+        let ours = ["assembler", "spritesheet_assembler"]
+            .iter()
+            .any(|name| metadata.target().split("::").next() == Some(name));
+        metadata.level() <= log::max_level() && (ours || log::max_level() >= LevelFilter::Debug)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let label = match record.level() {
+            Level::Error => "error",
+            Level::Warn => "warning",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        };
+        eprintln!("{}: {}", label, record.args());
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+/// Installs the stderr logger. `verbosity` is the number of `-v` flags,
+/// or -1 for `-q`; warnings are shown by default.
+pub fn init(verbosity: i64) {
+    let level = match verbosity {
+        v if v < 0 => LevelFilter::Error,
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
+}
@@ -1,119 +1,454 @@
-use image::RgbaImage;
-use std::{cmp::max, path::PathBuf};
+use log::{error, warn};
+use spritesheet_assembler::{
+    disable_progress, exit, Anchor, Assembler, AssemblerError, CancelHandle, Checksums, Collision,
+    Compression, EmptyFrames, Mask, MetadataFormat, Overwrite, Report, StrictSequence, Target,
+    Trim, DEFAULT_MAX_SIZE,
+};
+use std::{
+    convert::TryFrom,
+    error::Error,
+    path::{Path, PathBuf},
+};
 
-mod errors;
-use errors::{ImageFormatError, InconsistentSizeError, NoImagesError};
+mod append;
+mod batch;
+mod cli;
+mod config;
+mod diff;
+mod http;
+mod logging;
+mod merge;
+mod serve;
+mod sheet;
+mod slice;
+mod terminal;
+mod update;
+mod verify;
+mod watch;
+use config::{Config, ConflictError, EnvVarError, MissingArgumentError};
 
+/// The command line tool's own errors sit alongside the library's
+/// [`AssemblerError`]s.
+type BoxResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// How a run reports on itself, beyond logging.
 #[derive(Debug, Copy, Clone)]
-struct Dims {
-    x: usize,
-    y: usize,
+struct Reporting {
+    json: bool,
+    /// Print time and bytes per phase on stderr.
+    timings: bool,
 }
 
-type BoxResult<T> = Result<T, Box<dyn std::error::Error>>;
-
-fn main() -> BoxResult<()> {
-    let matches = clap::App::new("assembler")
-        .about("Combined PNGs into a spritesheet")
-        .arg(
-            clap::Arg::with_name("root")
-                .short("r")
-                .long("root")
-                .value_name("DIR")
-                .help("Where to search for spritesheet tiles")
-                .takes_value(true)
-                .required(true),
-        )
-        .arg(
-            clap::Arg::with_name("output")
-                .short("o")
-                .long("out")
-                .value_name("PNG_FILENAME")
-                .help("Spritesheet output filename")
-                .takes_value(true),
-        )
-        .get_matches();
+fn main() {
+    let code = match try_main() {
+        Ok(()) => exit::SUCCESS,
+        Err(err) => {
+            // Errors can happen before the logger is set up from the flags.
+            logging::init(0);
+            error!("{}", err);
+            exit_code(&*err)
+        }
+    };
+    std::process::exit(code);
+}
 
-    let root = matches.value_of("root").unwrap();
-    let images = collect_images(root);
-    let dims = dims(&images)?;
-    let tiles = optimal_stacking(images.len(), dims);
-    let width = (tiles.x * dims.x) as u32;
-    let height = (tiles.y * dims.y) as u32;
+fn try_main() -> BoxResult<()> {
+    let matches = match cli::app().get_matches_safe() {
+        Ok(matches) => matches,
+        Err(err) if err.use_stderr() => {
+            eprintln!("{}", err.message);
+            std::process::exit(exit::INVALID_OPTIONS);
+        }
+        Err(err) => err.exit(),
+    };
+    if let Some(completions) = matches.subcommand_matches("completions") {
+        let shell = completions
+            .value_of("shell")
+            .unwrap()
+            .parse::<clap::Shell>()?;
+        cli::app().gen_completions_to("assembler", shell, &mut std::io::stdout());
+        return Ok(());
+    }
 
-    let mut out: RgbaImage = image::ImageBuffer::new(width, height);
-    for (i, img) in images.iter().enumerate() {
-        let x = (i % tiles.x) * dims.x;
-        let y = (i / tiles.x) * dims.y;
-        image::imageops::replace(&mut out, img, x as u32, y as u32);
+    let config = Config::new(&matches);
+    config.check_conflicts(cli::CONFLICTS)?;
+    if config.flag("quiet") {
+        logging::init(-1);
+        disable_progress();
+    } else {
+        logging::init(config.occurrences("verbose") as i64);
     }
 
-    let output = matches.value_of("output").unwrap_or("out.png");
-    let out_path: PathBuf = [root, output].iter().collect();
-    out.save(out_path)?;
+    if let Some(serve) = matches.subcommand_matches("serve") {
+        // Responses may go to stdout, and there's no one watching a progress bar.
+        disable_progress();
+        let token = Config::new(serve).value("token");
+        let base = match serve.value_of("base") {
+            Some(base) => Some(Path::new(base)),
+            // Whoever starts it on stdio is the only client.
+            None if serve.is_present("stdio") => None,
+            None => Some(Path::new(".")),
+        };
+        let access = serve::Access::new(token, base)?;
+        return if let Some(addr) = serve.value_of("listen") {
+            serve::tcp(addr, access)
+        } else if let Some(path) = serve.value_of("socket") {
+            serve::unix(path, access)
+        } else if let Some(addr) = serve.value_of("http") {
+            http::serve(addr, access)
+        } else {
+            serve::stdio(access)
+        };
+    }
 
-    Ok(())
-}
+    if let Some(batch) = matches.subcommand_matches("batch") {
+        let reporting = Reporting {
+            json: config.choice("report", &["json"])?.is_some(),
+            timings: config.flag("timings"),
+        };
+        return batch::run(Path::new(batch.value_of("jobs").unwrap()), reporting);
+    }
 
-fn dims(images: &[RgbaImage]) -> BoxResult<Dims> {
-    let mut iter = images.iter();
-    let first = iter.next().ok_or_else(|| NoImagesError)?;
-    let dims = first.dimensions();
-    if images.iter().all(|next| next.dimensions() == dims) {
-        Ok(Dims {
-            x: dims.0 as usize,
-            y: dims.1 as usize,
-        })
+    if let Some(append) = matches.subcommand_matches("append") {
+        let frames = append
+            .values_of_os("frames")
+            .unwrap()
+            .map(PathBuf::from)
+            .collect::<Vec<_>>();
+        return append::run(Path::new(append.value_of_os("sheet").unwrap()), &frames);
+    }
+
+    if let Some(update) = matches.subcommand_matches("update") {
+        let frames = update
+            .values_of("frames")
+            .unwrap()
+            .map(str::to_owned)
+            .collect::<Vec<_>>();
+        return update::run(Path::new(update.value_of_os("sheet").unwrap()), &frames);
+    }
+
+    if let Some(verify) = matches.subcommand_matches("verify") {
+        let frames = Path::new(verify.value_of_os("root").unwrap())
+            .join(verify.value_of_os("frames-dir").unwrap());
+        return verify::run(
+            &frames,
+            Path::new(verify.value_of_os("metadata").unwrap()),
+            verify.value_of_os("sheet").map(Path::new),
+            verify.value_of("tolerance").unwrap().parse()?,
+        );
+    }
+
+    if let Some(diff) = matches.subcommand_matches("diff") {
+        return diff::run(
+            Path::new(diff.value_of_os("old").unwrap()),
+            Path::new(diff.value_of_os("new").unwrap()),
+            diff.value_of("threshold").unwrap().parse()?,
+            diff.value_of_os("output").map(Path::new),
+            config.choice("report", &["json"])?.is_some(),
+        );
+    }
+
+    if let Some(slice) = matches.subcommand_matches("slice") {
+        let sheet = slice.value_of_os("sheet").map(Path::new);
+        let out = Path::new(slice.value_of_os("out").unwrap());
+        let force = slice.is_present("force");
+        return match slice.value_of_os("metadata") {
+            Some(metadata) => {
+                slice::by_metadata(Path::new(metadata), sheet, out, force, |_| Ok(()))
+            }
+            None => slice::grid(
+                sheet.unwrap(),
+                slice::parse_size(slice.value_of("frame-size").unwrap()).unwrap(),
+                out,
+                force,
+            ),
+        }
+        .map(|_| ());
+    }
+
+    if let Some(merge) = matches.subcommand_matches("merge") {
+        let sheets = merge
+            .values_of_os("sheets")
+            .unwrap()
+            .map(PathBuf::from)
+            .collect::<Vec<_>>();
+        let out = Path::new(merge.value_of_os("out").unwrap());
+        return merge::run(&sheets, out, merge.is_present("force"));
+    }
+
+    if let Some(preview) = matches.subcommand_matches("preview") {
+        return terminal::run(
+            Path::new(preview.value_of_os("sheet").unwrap()),
+            &terminal::Preview {
+                protocol: terminal::Protocol::parse(preview.value_of("protocol").unwrap()).unwrap(),
+                play: preview.is_present("play"),
+                tag: preview.value_of("tag"),
+                loops: preview.value_of("loops").unwrap().parse()?,
+                size: preview.value_of("size").unwrap().parse()?,
+            },
+        );
+    }
+
+    let root = config.value("root").ok_or_else(|| MissingArgumentError {
+        name: "root".to_owned(),
+    })?;
+    let mut overwrite = if config.flag("backup") {
+        Overwrite::Backup
+    } else if config.flag("force") {
+        Overwrite::Force
     } else {
-        Err(InconsistentSizeError.into())
+        Overwrite::Refuse
+    };
+    let mut assembler = Assembler::new(root)
+        .output(config.value("out").unwrap_or_else(|| "out.png".to_owned()))
+        .frames(
+            config
+                .value("frames-dir")
+                .unwrap_or_else(|| "temp".to_owned()),
+        )
+        .max_depth(if config.flag("no-recurse") {
+            Some(0)
+        } else {
+            config.number("max-depth")?
+        })
+        .follow_symlinks(config.flag("follow-symlinks"))
+        .overwrite(overwrite)
+        .cleanup(config.flag("cleanup") && !config.flag("keep-temp"))
+        .wait(config.flag("wait"))
+        .embed_sheet(config.flag("embed-sheet"))
+        .frame_name(
+            config
+                .value("frame-name")
+                .unwrap_or_else(|| "{anim}/{stem}".to_owned()),
+        )
+        .debug_overlay(config.flag("debug-overlay"))
+        .number_frames(config.flag("number-frames"))
+        .preview(config.flag("preview"))
+        .provenance(config.flag("provenance"))
+        .godot_scene(config.flag("godot-scene"))
+        .mesh(config.flag("mesh"))
+        .opaque_bounds(config.flag("opaque-bounds"))
+        .facings(config.flag("facings"))
+        .low_memory(config.flag("low-memory"))
+        .gpu(config.flag("gpu"))
+        .max_memory(config.size("max-memory")?)
+        .max_size(match config.number("max-size")? {
+            Some(0) => None,
+            Some(size) => Some(u32::try_from(size).unwrap_or(u32::MAX)),
+            None => Some(DEFAULT_MAX_SIZE),
+        })
+        .max_frames_per_page(config.number("max-frames-per-page")?)
+        .compression(if config.flag("fast") {
+            Compression::Fast
+        } else if config.flag("best") {
+            Compression::Best
+        } else {
+            match config.number("compression")? {
+                Some(level) if level <= usize::from(Compression::MAX_LEVEL) => {
+                    Compression::Level(level as u8)
+                }
+                Some(level) => {
+                    return Err(EnvVarError {
+                        name: config::env_name("compression"),
+                        value: level.to_string(),
+                        expected: format!("a level from 0 to {}", Compression::MAX_LEVEL),
+                    }
+                    .into())
+                }
+                None => Compression::Default,
+            }
+        })
+        .deterministic(config.flag("deterministic"))
+        .cache(config.flag("cache"))
+        .resume(config.flag("resume"))
+        .metadata_only(config.flag("metadata-only"))
+        .split_by_animation(config.flag("split-by-animation"))
+        .pad_to_max(config.flag("pad-to-max"))
+        .find_duplicates(config.flag("find-duplicates"))
+        .strict_format(config.flag("strict-format"));
+    if let Some(name) = config.value("name") {
+        assembler = assembler.name(name);
     }
-}
+    if let Some(size) = config.value("fail-if-larger-than") {
+        assembler = assembler.option("fail-if-larger-than", &size)?;
+    }
+    if let Some(target) = config.choice("target", Target::NAMES)? {
+        assembler = assembler.option("target", &target)?;
+    }
+    if let Some(checksums) = config.choice("checksums", Checksums::NAMES)? {
+        assembler = assembler.option("checksums", &checksums)?;
+    }
+    if let Some(gif) = config.value("preview-gif") {
+        assembler = assembler.preview_gif(gif);
+    }
+    if let Some(html) = config.value("preview-html") {
+        assembler = assembler.preview_html(html);
+    }
+    if let Some(video) = config.value("preview-video") {
+        assembler = assembler.preview_video(video);
+    }
+    if let Some(palette) = config.value("palette") {
+        assembler = assembler.palette(palette);
+    }
+    for name in &["preview-video-fps", "preview-video-scale", "palette-colors"] {
+        if let Some(value) = config.value(name) {
+            assembler = assembler.option(name, &value)?;
+        }
+    }
+    if let Some(scene) = config.value("scene") {
+        assembler = assembler.scene(scene);
+    }
+    for glob in config.values("exclude") {
+        assembler = assembler.exclude(glob);
+    }
+    if let Some(pattern) = config.value("name-pattern") {
+        assembler = assembler.name_pattern(&pattern)?;
+    }
+    for name in ["start", "end"] {
+        if let Some(frame) = config.value(name) {
+            assembler = assembler.option(name, &frame)?;
+        }
+    }
+    if let Some(sequence) = config.value("sequence") {
+        assembler = assembler.sequence(&sequence)?;
+    }
+    if let Some(markers) = config.value("markers") {
+        assembler = assembler.markers(markers)?;
+    }
+    if let Some(shape) = config.choice("collision", Collision::NAMES)? {
+        assembler = assembler.option("collision", &shape)?;
+    }
+    if let Some(tolerance) = config.value("collision-tolerance") {
+        assembler = assembler.option("collision-tolerance", &tolerance)?;
+    }
+    if let Some(mask) = config.choice("collision-mask", Mask::NAMES)? {
+        assembler = assembler.option("collision-mask", &mask)?;
+    }
+    if let Some(dir) = config.value("boxes") {
+        assembler = assembler.option("boxes", &dir)?;
+    }
+    for colour in config.values("box-colour") {
+        assembler = assembler.option("box-colour", &colour)?;
+    }
+    for suffix in config.values("pass") {
+        assembler = assembler.option("pass", &suffix)?;
+    }
+    if let Some(insets) = config.value("nine-slice") {
+        assembler = assembler.option("nine-slice", &insets)?;
+    }
+    if let Some(slices) = config.value("nine-slices") {
+        assembler = assembler.nine_slices(slices)?;
+    }
+    for fps in config.values("fps") {
+        assembler = assembler.option("fps", &fps)?;
+    }
+    for direction in config.values("direction") {
+        assembler = assembler.option("direction", &direction)?;
+    }
+    if let Some(size) = config.value("number-size") {
+        assembler = assembler.option("number-size", &size)?;
+    }
+    if let Some(corner) = config.choice("number-corner", Anchor::NAMES)? {
+        assembler = assembler.option("number-corner", &corner)?;
+    }
+    if let Some(anchor) = config.choice("pad-anchor", Anchor::NAMES)? {
+        assembler = assembler.option("pad-anchor", &anchor)?;
+    }
+    if let Some(trim) = config.choice("trim", Trim::NAMES)? {
+        assembler = assembler.option("trim", &trim)?;
+    }
+    if let Some(anchor) = config.choice("pivot", Anchor::NAMES)? {
+        assembler = assembler.option("pivot", &anchor)?;
+    }
+    if let Some(colour) = config.value("pivot-marker") {
+        assembler = assembler.option("pivot-marker", &colour)?;
+    }
+    if let Some(policy) = config.choice("empty-frames", EmptyFrames::NAMES)? {
+        assembler = assembler.option("empty-frames", &policy)?;
+    }
+    if let Some(policy) = config.choice("strict-sequence", StrictSequence::NAMES)? {
+        assembler = assembler.option("strict-sequence", &policy)?;
+    } else if config.flag("strict-sequence") {
+        assembler = assembler.strict_sequence(Some(StrictSequence::Error));
+    }
+    if let Some(similarity) = config.value("similarity") {
+        assembler = assembler.option("similarity", &similarity)?;
+    }
+    if let Some(chain) = config.value("filter-chain") {
+        assembler = assembler.filter_chain(&chain)?;
+    }
+    for name in config.values("metadata") {
+        assembler = assembler.metadata(MetadataFormat::parse(&name)?);
+    }
+    let reporting = Reporting {
+        json: config.choice("report", &["json"])?.is_some(),
+        timings: config.flag("timings"),
+    };
 
-fn optimal_stacking(count: usize, dims: Dims) -> Dims {
-    struct Min {
-        dim: usize,
-        x: usize,
-    }
-    let Min { x, .. } = (1..=count).fold(
-        Min {
-            dim: std::usize::MAX,
-            x: 0,
-        },
-        |min, x| {
-            let y = y_from_x(x, count);
-            let dim = max(y * dims.y, x * dims.x);
-            if dim < min.dim {
-                Min { x, dim }
-            } else {
-                min
+    let cancel = assembler.cancel_handle();
+    cancel_on_signal(vec![cancel.clone()])?;
+    if config.flag("watch") {
+        let root = assembler.root().to_path_buf();
+        let frames = assembler.frames_dir();
+        return watch::watch(&root, &frames, &cancel, || {
+            match assemble(&assembler, reporting) {
+                // Rebuilds replace the sheet this session has already written.
+                Ok(()) if overwrite == Overwrite::Refuse => {
+                    overwrite = Overwrite::Force;
+                    assembler = assembler.clone().overwrite(overwrite);
+                }
+                Ok(()) => {}
+                Err(err) => error!("{}", err),
             }
-        },
-    );
-    Dims {
-        x: count,
-        y: 1,
+        });
     }
+    assemble(&assembler, reporting)
 }
 
-fn y_from_x(x: usize, count: usize) -> usize {
-    (count as f32 / x as f32).ceil() as usize
+/// Like [`exit::code`], but also knowing about bad command line arguments.
+fn exit_code(err: &(dyn Error + 'static)) -> i32 {
+    if let Some(err) = err.downcast_ref::<batch::BatchError>() {
+        err.code
+    } else if err.is::<EnvVarError>()
+        || err.is::<ConflictError>()
+        || err.is::<MissingArgumentError>()
+        || err.is::<batch::JobsFileError>()
+        || err.is::<sheet::SheetFileError>()
+        || err.is::<std::num::ParseIntError>()
+        || err.is::<clap::Error>()
+    {
+        exit::INVALID_OPTIONS
+    } else if let Some(err) = err.downcast_ref::<AssemblerError>() {
+        exit::code(err)
+    } else {
+        exit::FAILURE
+    }
 }
 
-fn collect_images(root: &str) -> Vec<RgbaImage> {
-    let temporary: PathBuf = [root, "temp"].iter().collect();
-    walkdir::WalkDir::new(temporary)
-        .sort_by(|a, b| a.file_name().cmp(b.file_name()))
-        .into_iter()
-        .filter_map(|e| match image_filter(e) {
-            Ok(img) => Some(img),
-            Err(_) => None,
-        })
-        .collect::<Vec<_>>()
+/// Cancels the runs behind `handles` on Ctrl+C or SIGTERM, so they stop
+/// and remove their partial outputs. A second signal exits straight away.
+fn cancel_on_signal(handles: Vec<CancelHandle>) -> BoxResult<()> {
+    ctrlc::set_handler(move || {
+        if handles.iter().any(CancelHandle::is_cancelled) {
+            std::process::exit(exit::CANCELLED);
+        }
+        warn!("Interrupted, stopping");
+        for handle in &handles {
+            handle.cancel();
+        }
+    })?;
+    Ok(())
 }
 
-fn image_filter(entry: Result<walkdir::DirEntry, walkdir::Error>) -> BoxResult<RgbaImage> {
-    match image::open(entry?.path())? {
-        image::ImageRgba8(img) => Ok(img),
-        _ => Err(ImageFormatError.into()),
+/// Runs one assembly, printing the report if one was requested.
+fn assemble(assembler: &Assembler, reporting: Reporting) -> BoxResult<()> {
+    let mut report = Report::default();
+    let result = assembler.run_with(&mut report);
+    if reporting.timings {
+        report.print_timings();
+    }
+    if reporting.json {
+        report.print_json()?;
     }
+    Ok(result?)
 }
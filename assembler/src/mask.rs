@@ -0,0 +1,126 @@
+//! `--collision-mask`: which pixels of each frame are solid, one bit each,
+//! for pixel-perfect collision. A mask covers the frame's rectangle in the
+//! metadata, so engines can index it with the same coordinates.
+//!
+//! `raw` writes `<sheet>.mask`: a little-endian `u32` frame count, then for
+//! each frame in metadata order its width and height as `u32`s and its rows,
+//! each padded to a whole byte with the leftmost pixel in the highest bit.
+//! `rle` writes `<sheet>.mask.json`, listing for each frame the lengths of
+//! its alternating runs of clear and solid pixels, row after row, starting
+//! with a clear one.
+
+use image::RgbaImage;
+use serde::Serialize;
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{collision::ALPHA_THRESHOLD, metadata::Atlas, Result};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mask {
+    Raw,
+    Rle,
+}
+
+impl Mask {
+    pub const NAMES: &'static [&'static str] = &["raw", "rle"];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "raw" => Some(Mask::Raw),
+            "rle" => Some(Mask::Rle),
+            _ => None,
+        }
+    }
+
+    /// `hero.png` gets `hero.mask` or `hero.mask.json`.
+    pub fn path(self, sheet: &Path) -> PathBuf {
+        sheet.with_extension(match self {
+            Mask::Raw => "mask",
+            Mask::Rle => "mask.json",
+        })
+    }
+
+    /// Writes the masks of the frames of `atlas`, as drawn on `sheet`.
+    pub fn write(self, sheet: &RgbaImage, atlas: &Atlas, out: &mut dyn Write) -> Result<()> {
+        match self {
+            Mask::Raw => write_raw(sheet, atlas, out),
+            Mask::Rle => write_rle(sheet, atlas, out),
+        }
+    }
+}
+
+fn write_raw(sheet: &RgbaImage, atlas: &Atlas, out: &mut dyn Write) -> Result<()> {
+    out.write_all(&(atlas.frames.len() as u32).to_le_bytes())?;
+    for frame in &atlas.frames {
+        out.write_all(&frame.width.to_le_bytes())?;
+        out.write_all(&frame.height.to_le_bytes())?;
+        let stride = frame.width.div_ceil(8) as usize;
+        let mut row = vec![0u8; stride];
+        for y in frame.y..frame.y + frame.height {
+            row.iter_mut().for_each(|byte| *byte = 0);
+            for x in 0..frame.width {
+                if solid(sheet, frame.x + x, y) {
+                    row[x as usize / 8] |= 0x80 >> (x % 8);
+                }
+            }
+            out.write_all(&row)?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct Document<'a> {
+    /// Pixels at least this opaque are solid.
+    threshold: u8,
+    frames: Vec<Runs<'a>>,
+}
+
+#[derive(Serialize)]
+struct Runs<'a> {
+    name: &'a str,
+    width: u32,
+    height: u32,
+    runs: Vec<u32>,
+}
+
+fn write_rle(sheet: &RgbaImage, atlas: &Atlas, out: &mut dyn Write) -> Result<()> {
+    let frames = atlas
+        .frames
+        .iter()
+        .map(|frame| {
+            let mut runs = vec![0];
+            let mut current = false;
+            for y in frame.y..frame.y + frame.height {
+                for x in frame.x..frame.x + frame.width {
+                    if solid(sheet, x, y) != current {
+                        current = !current;
+                        runs.push(0);
+                    }
+                    *runs.last_mut().expect("there's always a run") += 1;
+                }
+            }
+            Runs {
+                name: &frame.name,
+                width: frame.width,
+                height: frame.height,
+                runs,
+            }
+        })
+        .collect();
+    let document = Document {
+        threshold: ALPHA_THRESHOLD,
+        frames,
+    };
+    serde_json::to_writer(&mut *out, &document)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+fn solid(sheet: &RgbaImage, x: u32, y: u32) -> bool {
+    sheet.get_pixel(x, y)[3] >= ALPHA_THRESHOLD
+}
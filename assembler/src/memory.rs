@@ -0,0 +1,199 @@
+//! Assembly from and to buffers, for hosts without a filesystem such as
+//! the WebAssembly build.
+
+use rayon::prelude::*;
+use std::{
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    assembler::{atlas, composite, dims, frame_names, plan},
+    collect::{self, Frame},
+    empty, encode,
+    errors::AssemblerError,
+    events::Event,
+    filter,
+    format::{self, Format},
+    gaps, limit,
+    metadata::MetadataFormat,
+    pad, pivot,
+    progress::{Progress, ProgressWriter},
+    report, template,
+    template::OutputTokens,
+    timing, trim, Options, Result,
+};
+
+/// A frame passed in as an encoded image rather than a file.
+#[derive(Debug, Clone)]
+pub struct EncodedFrame {
+    /// Stands in for the frame's path relative to the frames folder, so it
+    /// is what the `--frame-name` tokens are taken from.
+    pub name: String,
+    pub bytes: Vec<u8>,
+}
+
+/// One page of a sheet assembled in memory.
+#[derive(Debug, Clone)]
+pub struct EncodedSheet {
+    /// The filename the output template gives this page.
+    pub name: String,
+    /// The encoded PNG.
+    pub png: Vec<u8>,
+    /// The page's metadata in each requested format, in the order asked for.
+    pub metadata: Vec<(MetadataFormat, Vec<u8>)>,
+}
+
+pub fn run(options: &Options, frames: &[EncodedFrame]) -> Result<Vec<EncodedSheet>> {
+    template::FrameTokens::check(&options.frame_name)?;
+    let formats = frames
+        .iter()
+        .map(|frame| {
+            let format = Format::read(Cursor::new(&frame.bytes[..]));
+            (Path::new(&frame.name), format)
+        })
+        .collect::<Vec<_>>();
+    format::check(&formats, options.strict_format)?;
+    if let Some(policy) = options.strict_sequence {
+        let names = frames
+            .iter()
+            .map(|frame| PathBuf::from(&frame.name))
+            .collect::<Vec<_>>();
+        gaps::check(policy, &names)?;
+    }
+    let total = frames.len() as u64;
+    let progress = Progress::new("Decoding", total).emitting(&options.events, move |done| {
+        Event::FramesDecoded { done, total }
+    });
+    let mut images = frames
+        .par_iter()
+        .map(|frame| {
+            options.cancel.check()?;
+            let image = collect::image_filter(Path::new(&frame.name), &frame.bytes)?;
+            let (width, height) = image.dimensions();
+            let path = PathBuf::from(&frame.name);
+            progress.inc(1);
+            Ok(Frame {
+                relative: path.clone(),
+                path,
+                sha256: report::sha256(&frame.bytes),
+                width,
+                height,
+                image: Some(image),
+                duration: 1,
+                pivot: None,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    progress.finish();
+    // Padding needs every frame's size, so filters run once they're known.
+    let canvas = images
+        .iter()
+        .map(|frame| (frame.width, frame.height))
+        .reduce(|a, b| (a.0.max(b.0), a.1.max(b.1)))
+        .filter(|_| options.pad_to_max);
+    images.par_iter_mut().try_for_each(|frame| {
+        if let Some(mut image) = frame.image.take() {
+            let mut offset = (0, 0);
+            if let Some(canvas) = canvas {
+                offset = options.pad_anchor.offset(image.dimensions(), canvas);
+                image = pad::pad(image, canvas, options.pad_anchor);
+                frame.width = canvas.0;
+                frame.height = canvas.1;
+            }
+            let mut image = filter::apply(&options.filters, image)?;
+            let marked = match options.pivot_marker {
+                Some(colour) => pivot::take_marker(&mut image, colour, &frame.path)?,
+                None => None,
+            };
+            frame.pivot = pivot::resolve(marked, &frame.relative, offset);
+            frame.image = Some(image);
+        }
+        Ok::<_, AssemblerError>(())
+    })?;
+    if let Some(policy) = options.empty_frames {
+        let mut kept = Vec::with_capacity(images.len());
+        for frame in images {
+            if !frame.image.as_ref().is_some_and(empty::is_empty)
+                || empty::keep(policy, &frame.path)?
+            {
+                kept.push(frame);
+            }
+        }
+        images = kept;
+    }
+    timing::apply(&mut images, None);
+    let trimmed = options
+        .trim
+        .and_then(|_| trim::union(&mut images, options.pivot));
+
+    let dims = dims(&images)?;
+    if let Some(sequence) = &options.sequence {
+        sequence.check(images.len())?;
+    }
+    let layout = plan(options, &images, dims)?;
+    let pages = layout.pages()?;
+    limit::check(options, &layout, &pages, &images)?;
+    let names = frame_names(options, &images)?;
+    let output = if pages.len() > 1 && !template::uses(&options.output, "page") {
+        template::paged(&options.output)
+    } else {
+        options.output.clone()
+    };
+    let rows = pages.iter().map(|page| u64::from(page.size.1)).sum();
+    let progress = Progress::new("Compositing", rows);
+    let encoded = Progress::bytes("Encoding")
+        .hidden()
+        .emitting(&options.events, |bytes| Event::BytesWritten { bytes });
+    let sheets = pages
+        .par_iter()
+        .map(|page| {
+            options.cancel.check()?;
+            let frames = &images[page.first..][..page.count];
+            let (size, tiles) = (page.size, page.tiles);
+            let sheet = composite(None, frames, &page.cells, size, tiles, dims, &progress);
+            options.events.emit(Event::PageComposited {
+                page: page.index,
+                pages: pages.len(),
+            });
+            let mut png = Vec::new();
+            encode::encoder(
+                ProgressWriter::new(&mut png, &encoded),
+                page.size,
+                options.compression,
+            )
+            .write_header()?
+            .write_image_data(&sheet)?;
+            let sha256 = report::sha256(&png);
+            let name = OutputTokens {
+                name: options.name.as_deref().unwrap_or_default(),
+                anim: "",
+                frames: images.len(),
+                width: page.size.0,
+                height: page.size.1,
+                page: page.index,
+                hash: Some(&sha256[..8]),
+            }
+            .render(&output)?;
+            let mut atlas = atlas(options, &images, &names, page, Path::new(&name), dims)?;
+            atlas.trim = trimmed;
+            atlas.pages = pages.len();
+            let metadata = options
+                .metadata
+                .iter()
+                .map(|format| {
+                    let mut out = Vec::new();
+                    format.write(&atlas, &mut out)?;
+                    Ok((*format, out))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Ok(EncodedSheet {
+                name,
+                png,
+                metadata,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    progress.finish();
+    Ok(sheets)
+}
@@ -0,0 +1,97 @@
+//! `assembler merge SHEET.json...`: repacks sheets written by earlier runs,
+//! such as one per character, into a single atlas, for instance one per
+//! level. Each sheet's frames are cut out of its image using its JSON
+//! metadata and laid out again, so the frames must all be the same size.
+//!
+//! Frames are renamed `<sheet>/<frame>` so names from different sheets
+//! can't collide, and each sheet becomes a tag in the merged metadata.
+
+use image::{GenericImageView, RgbaImage};
+use log::info;
+use spritesheet_assembler::{
+    errors::OutputExistsError, AssemblerError, EncodedFrame, MetadataFormat,
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    sheet::{Rect, Sheet},
+    BoxResult,
+};
+
+/// Merges the sheets described by the JSON files in `sheets` into `out`,
+/// writing its JSON metadata next to it.
+pub fn run(sheets: &[PathBuf], out: &Path, force: bool) -> BoxResult<()> {
+    let mut frames = Vec::new();
+    for path in sheets {
+        frames.extend(read_sheet(path)?);
+    }
+    info!(
+        "Merging {} frames from {} sheets",
+        frames.len(),
+        sheets.len()
+    );
+
+    let name = out
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let merged = spritesheet_assembler::Assembler::new(".")
+        .name(name)
+        .output(out.to_string_lossy())
+        .frame_name("{anim}/{stem}")
+        .metadata(MetadataFormat::Json)
+        .run_in_memory(&frames)?;
+    for sheet in merged {
+        let png = PathBuf::from(&sheet.name);
+        let mut files = vec![(png.clone(), sheet.png)];
+        for (format, metadata) in sheet.metadata {
+            files.push((format.path(&png), metadata));
+        }
+        for (path, bytes) in files {
+            if !force && path.exists() {
+                return Err(AssemblerError::from(OutputExistsError { path }).into());
+            }
+            fs::write(&path, bytes)?;
+            info!("Wrote {}", path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Cuts every frame listed in the metadata at `path` out of its sheet.
+fn read_sheet(path: &Path) -> BoxResult<Vec<EncodedFrame>> {
+    let sheet = Sheet::open(path)?;
+    let prefix = sheet.name();
+    let fps = sheet.fps();
+    sheet
+        .frames
+        .iter()
+        .map(|frame| {
+            let Rect { x, y, w, h } = frame.rect;
+            let pixels: RgbaImage = sheet.image.view(x, y, w, h).to_image();
+            let mut bytes = Vec::new();
+            image::png::PNGEncoder::new(&mut bytes).encode(
+                &pixels,
+                w,
+                h,
+                image::ColorType::RGBA(8),
+            )?;
+            // Held frames keep their duration through a `@N` suffix.
+            let ticks = frame
+                .duration
+                .map_or(1, |millis| (millis * fps / 1000.0).round().max(1.0) as u32);
+            let hold = if ticks > 1 {
+                format!("@{}", ticks)
+            } else {
+                String::new()
+            };
+            Ok(EncodedFrame {
+                name: format!("{}/{}{}.png", prefix, frame.name, hold),
+                bytes,
+            })
+        })
+        .collect()
+}
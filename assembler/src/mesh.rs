@@ -0,0 +1,137 @@
+//! `--mesh`: triangles covering each frame's visible pixels, as in
+//! TexturePacker's polygon sprites, so engines drawing big, mostly clear
+//! sprites skip their empty space. Unlike the collision outlines, the mesh
+//! keeps every faintly visible pixel and follows their edges exactly, so it
+//! never cuts anything off.
+
+use image::RgbaImage;
+use rayon::prelude::*;
+
+use crate::{
+    collision::{self, Point},
+    metadata::Atlas,
+};
+
+/// A frame's triangles, as corners of the pixel grid from its top left.
+pub struct Mesh {
+    pub vertices: Vec<Point>,
+    /// Each triangle's vertices, by index, clockwise on screen.
+    pub triangles: Vec<[usize; 3]>,
+}
+
+/// Meshes the frames of `atlas` on `sheet`, its page.
+pub fn trace(sheet: &RgbaImage, atlas: &mut Atlas) {
+    atlas.frames.par_iter_mut().for_each(|frame| {
+        let visible = |x: i64, y: i64| {
+            x >= 0
+                && y >= 0
+                && x < i64::from(frame.width)
+                && y < i64::from(frame.height)
+                && sheet.get_pixel(frame.x + x as u32, frame.y + y as u32)[3] > 0
+        };
+        let outlines = collision::outlines(frame.width, frame.height, visible);
+        let triangulated = outlines
+            .iter()
+            .map(|outline| triangulate(outline))
+            .collect::<Vec<_>>();
+        let mut mesh = Mesh {
+            vertices: Vec::new(),
+            triangles: Vec::new(),
+        };
+        for (index, (outline, triangles)) in outlines.iter().zip(&triangulated).enumerate() {
+            // A part inside another's hole is covered by that part's
+            // triangles already, and drawing it twice would blend it twice.
+            let inner = triangles.first().is_none_or(|triangle| {
+                let centre = centroid(outline, triangle);
+                outlines
+                    .iter()
+                    .enumerate()
+                    .any(|(other, around)| other != index && contains(around, centre))
+            });
+            if inner {
+                continue;
+            }
+            let first = mesh.vertices.len();
+            mesh.vertices.extend(outline);
+            mesh.triangles.extend(
+                triangles
+                    .iter()
+                    .map(|triangle| triangle.map(|vertex| first + vertex)),
+            );
+        }
+        frame.mesh = Some(mesh);
+    });
+}
+
+/// Cuts a clockwise `outline` into triangles by clipping ears: corners
+/// whose triangle holds no other point of the outline.
+fn triangulate(outline: &[Point]) -> Vec<[usize; 3]> {
+    let point = |index: usize| (i64::from(outline[index].0), i64::from(outline[index].1));
+    let turn = |a: usize, b: usize, c: usize| {
+        let (a, b, c) = (point(a), point(b), point(c));
+        (b.0 - a.0) * (c.1 - b.1) - (b.1 - a.1) * (c.0 - b.0)
+    };
+    let mut remaining = (0..outline.len()).collect::<Vec<_>>();
+    let mut triangles = Vec::with_capacity(outline.len().saturating_sub(2));
+    let mut at = 0;
+    while remaining.len() >= 3 {
+        let count = remaining.len();
+        let corner = |offset: usize| {
+            let index = (at + offset) % count;
+            (
+                remaining[(index + count - 1) % count],
+                remaining[index],
+                remaining[(index + 1) % count],
+            )
+        };
+        // Try each corner from where the last ear was, so the search
+        // mostly stops straight away.
+        let ear = (0..count).find(|offset| {
+            let (a, b, c) = corner(*offset);
+            turn(a, b, c) > 0
+                && !remaining.iter().any(|other| {
+                    let p = point(*other);
+                    p != point(a)
+                        && p != point(b)
+                        && p != point(c)
+                        && turn(a, b, *other) >= 0
+                        && turn(b, c, *other) >= 0
+                        && turn(c, a, *other) >= 0
+                })
+        });
+        // Only straight runs are left, which cover nothing.
+        let offset = match ear {
+            Some(offset) => offset,
+            None => break,
+        };
+        let (a, b, c) = corner(offset);
+        triangles.push([a, b, c]);
+        at = (at + offset) % count;
+        remaining.remove(at);
+        at %= remaining.len().max(1);
+    }
+    triangles
+}
+
+fn centroid(outline: &[Point], triangle: &[usize; 3]) -> (f64, f64) {
+    let sum = triangle.iter().fold((0.0, 0.0), |sum, vertex| {
+        let (x, y) = outline[*vertex];
+        (sum.0 + f64::from(x), sum.1 + f64::from(y))
+    });
+    (sum.0 / 3.0, sum.1 / 3.0)
+}
+
+/// Whether `point` is inside `outline`, by counting the edges a ray from
+/// it crosses.
+fn contains(outline: &[Point], point: (f64, f64)) -> bool {
+    let mut inside = false;
+    for (a, b) in outline.iter().zip(outline.iter().cycle().skip(1)) {
+        let (ax, ay) = (f64::from(a.0), f64::from(a.1));
+        let (bx, by) = (f64::from(b.0), f64::from(b.1));
+        if (ay > point.1) != (by > point.1) && point.0 < ax + (point.1 - ay) / (by - ay) * (bx - ax)
+        {
+            inside = !inside;
+        }
+    }
+    inside
+}
@@ -0,0 +1,155 @@
+//! Frame metadata written next to the sheet, in formats engines can import.
+
+use serde::Serialize;
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    boxes::HitBox, collision::Point, errors::MetadataFormatError, facing::View, mesh::Mesh,
+    nine_slice::Insets, timing::Direction, trim::Trimmed, Result,
+};
+
+mod godot;
+mod json;
+mod rust;
+mod unity;
+
+/// Everything the exporters know about an assembled sheet.
+pub struct Atlas {
+    /// File name of the sheet, relative to the metadata file.
+    pub image: String,
+    /// Which of how many pages the sheet is.
+    pub page: usize,
+    pub pages: usize,
+    pub width: u32,
+    pub height: u32,
+    /// Playback speed in frames per second, unless an animation has its own.
+    pub fps: u32,
+    pub frames: Vec<Frame>,
+    /// Runs of frames belonging to one animation, in order.
+    pub tags: Vec<Tag>,
+    /// Names of the frames in the order they play, across every page, when
+    /// that isn't sheet order.
+    pub sequence: Vec<String>,
+    /// Each other render pass and its sheet's file name, relative to the
+    /// metadata file.
+    pub passes: Vec<(String, String)>,
+    /// Where the frames were cut from on their original canvas, if they
+    /// were trimmed.
+    pub trim: Option<Trimmed>,
+    /// Generated code includes the sheet itself, not just its file name.
+    pub embed_image: bool,
+}
+
+/// A frame's name and its rectangle within the sheet.
+pub struct Frame {
+    pub name: String,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    /// How many ticks the frame is shown for.
+    pub duration: u32,
+    /// Ticks per second of the frame's animation.
+    pub fps: u32,
+    /// Names of the markers on this frame.
+    pub events: Vec<String>,
+    /// Outlines of the frame's opaque parts, in pixels from its top left,
+    /// if they were traced.
+    pub polygons: Vec<Vec<Point>>,
+    /// Triangles covering its visible pixels, if they were meshed.
+    pub mesh: Option<Mesh>,
+    /// The smallest box holding its visible pixels, `(x, y, width,
+    /// height)` from its top left and all 0 if it has none, if measured.
+    pub opaque_bounds: Option<(u32, u32, u32, u32)>,
+    /// Labelled hitboxes and the like, if a pass of them was given.
+    pub boxes: Vec<HitBox>,
+    /// 9-slice borders, if the frame has them.
+    pub nine_slice: Option<Insets>,
+    /// The point the engine places the frame by, as fractions of its width
+    /// and height from its top left, if pivots are exported.
+    pub pivot: Option<(f64, f64)>,
+}
+
+/// An animation's frames, `from` and `to` inclusive, as indices into
+/// [`Atlas::frames`].
+pub struct Tag {
+    pub name: String,
+    /// The animation and facing it shows, with `--facings`.
+    pub view: Option<View>,
+    pub from: usize,
+    pub to: usize,
+    pub direction: Direction,
+    pub fps: u32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MetadataFormat {
+    /// TexturePacker's JSON array format, understood by most 2D engines.
+    Json,
+    /// A Rust module of constants, for games that build the atlas in.
+    Rust,
+    /// Unity's import settings for the sheet, slicing it into sprites with
+    /// their pivots and borders.
+    Unity,
+    /// A Godot 4 theme with a `StyleBoxTexture` per frame, margined by its
+    /// 9-slice borders.
+    Godot,
+}
+
+impl MetadataFormat {
+    pub const NAMES: &'static [&'static str] = &["json", "rust", "unity", "godot"];
+
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "json" => Ok(MetadataFormat::Json),
+            "rust" => Ok(MetadataFormat::Rust),
+            "unity" => Ok(MetadataFormat::Unity),
+            "godot" => Ok(MetadataFormat::Godot),
+            _ => Err(MetadataFormatError {
+                name: name.to_owned(),
+            }
+            .into()),
+        }
+    }
+
+    /// Extension of the file written next to the sheet.
+    pub fn extension(self) -> &'static str {
+        match self {
+            MetadataFormat::Json => "json",
+            MetadataFormat::Rust => "rs",
+            MetadataFormat::Unity => "meta",
+            MetadataFormat::Godot => "tres",
+        }
+    }
+
+    /// The file written next to the sheet at `sheet`. Unity finds an
+    /// asset's settings by adding `.meta` to its whole name.
+    pub fn path(self, sheet: &Path) -> PathBuf {
+        match self {
+            MetadataFormat::Unity => {
+                let mut path = sheet.as_os_str().to_owned();
+                path.push(".meta");
+                PathBuf::from(path)
+            }
+            _ => sheet.with_extension(self.extension()),
+        }
+    }
+
+    pub fn write(self, atlas: &Atlas, out: &mut dyn Write) -> Result<()> {
+        match self {
+            MetadataFormat::Json => json::write(atlas, out),
+            MetadataFormat::Rust => rust::write(atlas, out),
+            MetadataFormat::Unity => unity::write(atlas, out),
+            MetadataFormat::Godot => godot::write(atlas, out),
+        }
+    }
+}
+
+/// A duration in ticks as whole milliseconds.
+fn millis(ticks: u32, fps: u32) -> u32 {
+    ((f64::from(ticks) * 1000.0 / f64::from(fps)).round()) as u32
+}
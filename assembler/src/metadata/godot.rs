@@ -0,0 +1,132 @@
+use std::{io::Write, path::Path};
+
+use super::Atlas;
+use crate::{godot::quote, Result};
+
+/// Writes a Godot 4 theme holding each frame as a `StyleBoxTexture` of its
+/// region of the sheet, with its 9-slice borders as the texture margins, so
+/// panels and buttons can use them as they are. The styles belong to a
+/// theme type named after the sheet, and are named after the frames, each
+/// with anything but letters, digits and underscores made an underscore:
+/// `get_theme_stylebox("button_0001", "ui")` for `ui.png`.
+pub fn write(atlas: &Atlas, out: &mut dyn Write) -> Result<()> {
+    let stem = Path::new(&atlas.image).file_stem().map_or_else(
+        || atlas.image.clone(),
+        |stem| stem.to_string_lossy().into_owned(),
+    );
+    let theme_type = identifier(&stem);
+
+    writeln!(
+        out,
+        "[gd_resource type=\"Theme\" load_steps={} format=3]\n",
+        atlas.frames.len() + 2
+    )?;
+    writeln!(
+        out,
+        "[ext_resource type=\"Texture2D\" path={} id=\"sheet\"]\n",
+        quote(&atlas.image)
+    )?;
+    for (index, frame) in atlas.frames.iter().enumerate() {
+        writeln!(
+            out,
+            "[sub_resource type=\"StyleBoxTexture\" id=\"frame_{}\"]",
+            index
+        )?;
+        writeln!(out, "texture = ExtResource(\"sheet\")")?;
+        if let Some(insets) = frame.nine_slice {
+            writeln!(out, "texture_margin_left = {:.1}", f64::from(insets.left))?;
+            writeln!(out, "texture_margin_top = {:.1}", f64::from(insets.top))?;
+            writeln!(out, "texture_margin_right = {:.1}", f64::from(insets.right))?;
+            writeln!(
+                out,
+                "texture_margin_bottom = {:.1}",
+                f64::from(insets.bottom)
+            )?;
+        }
+        writeln!(
+            out,
+            "region_rect = Rect2({}, {}, {}, {})\n",
+            frame.x, frame.y, frame.width, frame.height
+        )?;
+    }
+    writeln!(out, "[resource]")?;
+    for (index, frame) in atlas.frames.iter().enumerate() {
+        writeln!(
+            out,
+            "{}/styles/{} = SubResource(\"frame_{}\")",
+            theme_type,
+            identifier(&frame.name),
+            index
+        )?;
+    }
+    Ok(())
+}
+
+/// `name` as Godot allows theme types and items to be named.
+fn identifier(name: &str) -> String {
+    let name = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    match name.chars().next() {
+        Some(c) if !c.is_ascii_digit() => name,
+        _ => format!("_{}", name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{metadata::Frame, nine_slice::Insets};
+
+    #[test]
+    fn margins_the_styles() {
+        let frame = |name: &str, x: u32, nine_slice| Frame {
+            name: name.to_owned(),
+            x,
+            y: 0,
+            width: 16,
+            height: 16,
+            duration: 1,
+            fps: 12,
+            events: Vec::new(),
+            polygons: Vec::new(),
+            mesh: None,
+            opaque_bounds: None,
+            boxes: Vec::new(),
+            nine_slice,
+            pivot: None,
+        };
+        let atlas = Atlas {
+            image: "main-ui.png".to_owned(),
+            page: 0,
+            pages: 1,
+            width: 32,
+            height: 16,
+            fps: 12,
+            frames: vec![
+                frame("button/0001", 0, Insets::parse("4,6,3,5")),
+                frame("0002", 16, None),
+            ],
+            tags: Vec::new(),
+            sequence: Vec::new(),
+            passes: Vec::new(),
+            trim: None,
+            embed_image: false,
+        };
+        let mut theme = Vec::new();
+        write(&atlas, &mut theme).unwrap();
+        let theme = String::from_utf8(theme).unwrap();
+        assert!(theme.starts_with("[gd_resource type=\"Theme\" load_steps=4 format=3]\n"));
+        assert!(theme.contains("path=\"main-ui.png\" id=\"sheet\""));
+        assert!(theme.contains(
+            "texture_margin_left = 4.0\ntexture_margin_top = 6.0\n\
+             texture_margin_right = 3.0\ntexture_margin_bottom = 5.0\n\
+             region_rect = Rect2(0, 0, 16, 16)\n"
+        ));
+        assert!(theme
+            .contains("texture = ExtResource(\"sheet\")\nregion_rect = Rect2(16, 0, 16, 16)\n"));
+        assert!(theme.contains("main_ui/styles/button_0001 = SubResource(\"frame_0\")\n"));
+        assert!(theme.contains("main_ui/styles/_0002 = SubResource(\"frame_1\")\n"));
+    }
+}
@@ -0,0 +1,312 @@
+use serde::Serialize;
+use std::io::Write;
+
+use super::{millis, Atlas};
+use crate::{collision, facing::View, trim::Trimmed, Result};
+
+#[derive(Serialize)]
+struct Document<'a> {
+    frames: Vec<Frame<'a>>,
+    meta: Meta<'a>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Frame<'a> {
+    filename: &'a str,
+    frame: Rect,
+    rotated: bool,
+    trimmed: bool,
+    sprite_source_size: Rect,
+    source_size: Size,
+    /// Aseprite's per-frame duration in milliseconds.
+    duration: u32,
+    /// Collision outlines, each a list of `[x, y]` points.
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    polygons: &'a [Vec<collision::Point>],
+    /// TexturePacker's polygon sprite: the mesh's points in the frame and
+    /// on the sheet, and its triangles as indices into them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vertices: Option<&'a [collision::Point]>,
+    #[serde(rename = "verticesUV", skip_serializing_if = "Option::is_none")]
+    vertices_uv: Option<Vec<collision::Point>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    triangles: Option<&'a [[usize; 3]]>,
+    /// The box around the frame's visible pixels, within the frame.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    opaque_bounds: Option<Rect>,
+    /// Labelled boxes, for combat and other gameplay data.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    boxes: Vec<HitBox<'a>>,
+    /// TexturePacker's 9-slice flag and borders, as the rectangle that
+    /// stretches.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scale9_enabled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scale9_borders: Option<Rect>,
+    /// Unity's sprite border: left, bottom, right and top.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    border: Option<Border>,
+    /// Godot's `StyleBoxTexture` texture margins.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    texture_margins: Option<Margins>,
+    /// TexturePacker's pivot, as fractions of the frame's size.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pivot: Option<Point>,
+}
+
+#[derive(Serialize)]
+struct HitBox<'a> {
+    label: &'a str,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    /// Its outline, a list of `[x, y]` points.
+    polygon: &'a [collision::Point],
+}
+
+#[derive(Serialize)]
+struct Border {
+    x: u32,
+    y: u32,
+    z: u32,
+    w: u32,
+}
+
+#[derive(Serialize)]
+struct Margins {
+    left: u32,
+    top: u32,
+    right: u32,
+    bottom: u32,
+}
+
+#[derive(Serialize)]
+struct Point {
+    x: f64,
+    y: f64,
+}
+
+#[derive(Serialize)]
+struct Rect {
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+}
+
+#[derive(Serialize)]
+struct Size {
+    w: u32,
+    h: u32,
+}
+
+#[derive(Serialize)]
+struct Meta<'a> {
+    app: &'static str,
+    version: &'static str,
+    image: &'a str,
+    format: &'static str,
+    size: Size,
+    scale: &'static str,
+    /// Which page the sheet is, counting from 0, and how many there are,
+    /// when the frames are split across several.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    page: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pages: Option<usize>,
+    /// Frames per second, for importers that don't read the durations.
+    #[serde(rename = "frameRate")]
+    frame_rate: u32,
+    /// Aseprite's animation tags, which most importers of this format read.
+    #[serde(rename = "frameTags", skip_serializing_if = "Vec::is_empty")]
+    frame_tags: Vec<FrameTag<'a>>,
+    /// Aseprite's layers, here just one holding each frame's events as cel
+    /// user data.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    layers: Vec<Layer>,
+    /// The frames by name in the order they play, which may repeat some.
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    sequence: &'a [String],
+    /// The sheets of the other render passes, laid out like this one.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    passes: Vec<Pass<'a>>,
+}
+
+#[derive(Serialize)]
+struct Pass<'a> {
+    name: &'a str,
+    image: &'a str,
+}
+
+#[derive(Serialize)]
+struct FrameTag<'a> {
+    name: &'a str,
+    from: usize,
+    to: usize,
+    direction: &'static str,
+    #[serde(rename = "frameRate")]
+    frame_rate: u32,
+    /// The animation, facing and angle, with `--facings`.
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    view: Option<&'a View>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Layer {
+    name: &'static str,
+    opacity: u8,
+    blend_mode: &'static str,
+    cels: Vec<Cel>,
+}
+
+#[derive(Serialize)]
+struct Cel {
+    frame: usize,
+    /// Comma-separated event names.
+    data: String,
+}
+
+/// Writes TexturePacker's "JSON (Array)" format.
+pub fn write(atlas: &Atlas, out: &mut dyn Write) -> Result<()> {
+    let document = Document {
+        frames: atlas
+            .frames
+            .iter()
+            .map(|frame| Frame {
+                filename: &frame.name,
+                frame: Rect {
+                    x: frame.x,
+                    y: frame.y,
+                    w: frame.width,
+                    h: frame.height,
+                },
+                rotated: false,
+                trimmed: atlas.trim.is_some(),
+                sprite_source_size: Rect {
+                    x: atlas.trim.map_or(0, |trim| trim.x),
+                    y: atlas.trim.map_or(0, |trim| trim.y),
+                    w: frame.width,
+                    h: frame.height,
+                },
+                source_size: match atlas.trim {
+                    Some(Trimmed { source: (w, h), .. }) => Size { w, h },
+                    None => Size {
+                        w: frame.width,
+                        h: frame.height,
+                    },
+                },
+                duration: millis(frame.duration, frame.fps),
+                polygons: &frame.polygons,
+                vertices: frame.mesh.as_ref().map(|mesh| &mesh.vertices[..]),
+                vertices_uv: frame.mesh.as_ref().map(|mesh| {
+                    mesh.vertices
+                        .iter()
+                        .map(|(x, y)| (frame.x + x, frame.y + y))
+                        .collect()
+                }),
+                triangles: frame.mesh.as_ref().map(|mesh| &mesh.triangles[..]),
+                opaque_bounds: frame.opaque_bounds.map(|(x, y, w, h)| Rect { x, y, w, h }),
+                boxes: frame
+                    .boxes
+                    .iter()
+                    .map(|hit_box| HitBox {
+                        label: &hit_box.label,
+                        x: hit_box.x,
+                        y: hit_box.y,
+                        w: hit_box.width,
+                        h: hit_box.height,
+                        polygon: &hit_box.polygon,
+                    })
+                    .collect(),
+                scale9_enabled: frame.nine_slice.map(|_| true),
+                scale9_borders: frame.nine_slice.map(|insets| Rect {
+                    x: insets.left,
+                    y: insets.top,
+                    w: frame
+                        .width
+                        .saturating_sub(insets.left)
+                        .saturating_sub(insets.right),
+                    h: frame
+                        .height
+                        .saturating_sub(insets.top)
+                        .saturating_sub(insets.bottom),
+                }),
+                border: frame.nine_slice.map(|insets| Border {
+                    x: insets.left,
+                    y: insets.bottom,
+                    z: insets.right,
+                    w: insets.top,
+                }),
+                texture_margins: frame.nine_slice.map(|insets| Margins {
+                    left: insets.left,
+                    top: insets.top,
+                    right: insets.right,
+                    bottom: insets.bottom,
+                }),
+                pivot: frame.pivot.map(|(x, y)| Point { x, y }),
+            })
+            .collect(),
+        meta: Meta {
+            app: env!("CARGO_PKG_NAME"),
+            version: env!("CARGO_PKG_VERSION"),
+            image: &atlas.image,
+            format: "RGBA8888",
+            size: Size {
+                w: atlas.width,
+                h: atlas.height,
+            },
+            scale: "1",
+            page: Some(atlas.page).filter(|_| atlas.pages > 1),
+            pages: Some(atlas.pages).filter(|pages| *pages > 1),
+            frame_rate: atlas.fps,
+            frame_tags: atlas
+                .tags
+                .iter()
+                .map(|tag| FrameTag {
+                    name: &tag.name,
+                    from: tag.from,
+                    to: tag.to,
+                    direction: tag.direction.name(),
+                    frame_rate: tag.fps,
+                    view: tag.view.as_ref(),
+                })
+                .collect(),
+            layers: events(atlas),
+            sequence: &atlas.sequence,
+            passes: atlas
+                .passes
+                .iter()
+                .map(|(name, image)| Pass { name, image })
+                .collect(),
+        },
+    };
+    serde_json::to_writer_pretty(&mut *out, &document)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// The frames' events as an `events` layer, or nothing if there are none.
+fn events(atlas: &Atlas) -> Vec<Layer> {
+    let cels = atlas
+        .frames
+        .iter()
+        .enumerate()
+        .filter(|(_, frame)| !frame.events.is_empty())
+        .map(|(index, frame)| Cel {
+            frame: index,
+            data: frame.events.join(","),
+        })
+        .collect::<Vec<_>>();
+    if cels.is_empty() {
+        return Vec::new();
+    }
+    vec![Layer {
+        name: "events",
+        opacity: 255,
+        blend_mode: "normal",
+        cels,
+    }]
+}
@@ -0,0 +1,327 @@
+use std::io::Write;
+
+use super::{millis, Atlas};
+use crate::{timing::Direction, Result};
+
+/// The types the tables are made of, the same in every module.
+const TYPES: &str = r#"/// A rectangle in pixels, from the top left.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+/// A frame and where it is on the sheet.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Frame {
+    pub name: &'static str,
+    pub rect: Rect,
+    /// Where the frame's top left was on its original canvas, if trimmed.
+    pub offset: (u32, u32),
+    /// The size of the frame's original canvas.
+    pub source_size: (u32, u32),
+    /// How long the frame is shown, in milliseconds.
+    pub duration: u32,
+    /// The point the frame is placed by, as fractions of its width and
+    /// height from its top left.
+    pub pivot: Option<(f32, f32)>,
+    /// Names of the markers on the frame.
+    pub events: &'static [&'static str],
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Forward,
+    Reverse,
+    /// Forwards, then backwards, and repeat.
+    PingPong,
+}
+
+/// An animation's frames, `from` and `to` inclusive, as indices into
+/// [`FRAMES`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Animation {
+    pub name: &'static str,
+    pub from: usize,
+    pub to: usize,
+    pub direction: Direction,
+    /// Frames per second.
+    pub fps: u32,
+}
+
+impl Animation {
+    pub fn frames(&self) -> &'static [Frame] {
+        &FRAMES[self.from..=self.to]
+    }
+}
+
+/// The frame called `name`.
+pub fn frame(name: &str) -> Option<&'static Frame> {
+    FRAMES.iter().find(|frame| frame.name == name)
+}
+
+/// The animation called `name`.
+pub fn animation(name: &str) -> Option<&'static Animation> {
+    ANIMATIONS.iter().find(|animation| animation.name == name)
+}
+"#;
+
+/// Writes a Rust module holding the frames and animations as constants,
+/// and with [`Atlas::embed_image`] the sheet itself, for games that build
+/// the atlas in rather than parse it at run time.
+pub fn write(atlas: &Atlas, out: &mut dyn Write) -> Result<()> {
+    writeln!(
+        out,
+        "//! The frames of `{}`, written by {} {} along with the sheet.\n\
+         //! Changes here are lost when it's assembled again.\n\
+         \n\
+         #![allow(dead_code)]\n",
+        atlas.image,
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION")
+    )?;
+    out.write_all(TYPES.as_bytes())?;
+    writeln!(out)?;
+    writeln!(out, "/// The sheet's file name, relative to this file.")?;
+    writeln!(out, "pub const IMAGE: &str = {:?};", atlas.image)?;
+    if atlas.embed_image {
+        writeln!(out, "/// The sheet's PNG.")?;
+        writeln!(
+            out,
+            "pub const PNG: &[u8] = include_bytes!({:?});",
+            atlas.image
+        )?;
+    }
+    writeln!(out, "/// The sheet's width and height in pixels.")?;
+    writeln!(
+        out,
+        "pub const SIZE: (u32, u32) = ({}, {});",
+        atlas.width, atlas.height
+    )?;
+    writeln!(
+        out,
+        "/// Which of how many pages the sheet is, counting from 0."
+    )?;
+    writeln!(out, "pub const PAGE: usize = {};", atlas.page)?;
+    writeln!(out, "pub const PAGES: usize = {};", atlas.pages)?;
+    writeln!(
+        out,
+        "/// Frames per second, unless an animation has its own."
+    )?;
+    writeln!(out, "pub const FPS: u32 = {};", atlas.fps)?;
+    writeln!(
+        out,
+        "/// Each other render pass and its sheet's file name, laid out like this one."
+    )?;
+    write!(out, "pub const PASSES: &[(&str, &str)] = &[")?;
+    for (name, image) in &atlas.passes {
+        write!(out, "\n    ({:?}, {:?}),", name, image)?;
+    }
+    if atlas.passes.is_empty() {
+        writeln!(out, "];")?;
+    } else {
+        writeln!(out, "\n];")?;
+    }
+
+    writeln!(out)?;
+    write!(out, "pub const FRAMES: &[Frame] = &[")?;
+    for frame in &atlas.frames {
+        let (offset, source_size) = match atlas.trim {
+            Some(trim) => ((trim.x, trim.y), trim.source),
+            None => ((0, 0), (frame.width, frame.height)),
+        };
+        write!(
+            out,
+            "\n    Frame {{\n        \
+                 name: {:?},\n        \
+                 rect: Rect {{\n            \
+                     x: {},\n            \
+                     y: {},\n            \
+                     w: {},\n            \
+                     h: {},\n        \
+                 }},\n        \
+                 offset: ({}, {}),\n        \
+                 source_size: ({}, {}),\n        \
+                 duration: {},\n        ",
+            frame.name,
+            frame.x,
+            frame.y,
+            frame.width,
+            frame.height,
+            offset.0,
+            offset.1,
+            source_size.0,
+            source_size.1,
+            millis(frame.duration, frame.fps),
+        )?;
+        match frame.pivot {
+            // As `f32`s, so they're written no more precisely than they're read.
+            Some((x, y)) => write!(out, "pivot: Some(({:?}, {:?})),", x as f32, y as f32)?,
+            None => write!(out, "pivot: None,")?,
+        }
+        write!(out, "\n        events: &[")?;
+        for (index, event) in frame.events.iter().enumerate() {
+            match index {
+                0 => write!(out, "{:?}", event)?,
+                _ => write!(out, ", {:?}", event)?,
+            }
+        }
+        write!(out, "],\n    }},")?;
+    }
+    if atlas.frames.is_empty() {
+        writeln!(out, "];")?;
+    } else {
+        writeln!(out, "\n];")?;
+    }
+
+    writeln!(out)?;
+    write!(out, "pub const ANIMATIONS: &[Animation] = &[")?;
+    for tag in &atlas.tags {
+        let direction = match tag.direction {
+            Direction::Forward => "Forward",
+            Direction::Reverse => "Reverse",
+            Direction::PingPong => "PingPong",
+        };
+        write!(
+            out,
+            "\n    Animation {{\n        \
+                 name: {:?},\n        \
+                 from: {},\n        \
+                 to: {},\n        \
+                 direction: Direction::{},\n        \
+                 fps: {},\n    \
+             }},",
+            tag.name, tag.from, tag.to, direction, tag.fps
+        )?;
+    }
+    if atlas.tags.is_empty() {
+        writeln!(out, "];")?;
+    } else {
+        writeln!(out, "\n];")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{metadata::Frame, metadata::Tag, trim::Trimmed};
+    use std::{env, fs, process::Command};
+
+    fn frame(name: &str, x: u32, events: &[&str], pivot: Option<(f64, f64)>) -> Frame {
+        Frame {
+            name: name.to_owned(),
+            x,
+            y: 0,
+            width: 16,
+            height: 16,
+            duration: 2,
+            fps: 12,
+            events: events.iter().map(|event| event.to_string()).collect(),
+            polygons: Vec::new(),
+            mesh: None,
+            opaque_bounds: None,
+            boxes: Vec::new(),
+            nine_slice: None,
+            pivot,
+        }
+    }
+
+    fn atlas(frames: Vec<Frame>, tags: Vec<Tag>, embed_image: bool) -> Atlas {
+        Atlas {
+            image: "hero.png".to_owned(),
+            page: 0,
+            pages: 1,
+            width: 16 * frames.len() as u32,
+            height: 16,
+            fps: 12,
+            frames,
+            tags,
+            sequence: Vec::new(),
+            passes: vec![("normal".to_owned(), "hero_normal.png".to_owned())],
+            trim: Some(Trimmed {
+                x: 1,
+                y: 2,
+                width: 16,
+                height: 16,
+                source: (20, 20),
+            }),
+            embed_image,
+        }
+    }
+
+    /// Writes `atlas` as a module in a folder of its own, next to a
+    /// stand-in sheet, and has rustc check it.
+    fn check_compiles(name: &str, atlas: &Atlas) {
+        let dir = env::temp_dir().join(format!("spritesheet-rust-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(&atlas.image), b"not really a png").unwrap();
+        let mut module = Vec::new();
+        write(atlas, &mut module).unwrap();
+        let path = dir.join("hero.rs");
+        fs::write(&path, &module).unwrap();
+        let output = Command::new(env::var("RUSTC").unwrap_or_else(|_| "rustc".to_owned()))
+            .args([
+                "--edition",
+                "2018",
+                "--crate-type",
+                "lib",
+                "--emit",
+                "metadata",
+            ])
+            .arg("--out-dir")
+            .arg(&dir)
+            .arg(&path)
+            .output()
+            .expect("rustc runs");
+        let _ = fs::remove_dir_all(&dir);
+        assert!(
+            output.status.success(),
+            "{}\n{}",
+            String::from_utf8_lossy(&module),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    #[test]
+    fn generated_module_compiles() {
+        let frames = vec![
+            frame("walk_0001", 0, &["step", "dust"], Some((0.5, 1.0))),
+            frame("walk_0002", 16, &[], None),
+        ];
+        let tags = vec![Tag {
+            name: "walk".to_owned(),
+            view: None,
+            from: 0,
+            to: 1,
+            direction: Direction::PingPong,
+            fps: 12,
+        }];
+        check_compiles("full", &atlas(frames, tags, true));
+    }
+
+    #[test]
+    fn generated_module_compiles_without_frames() {
+        let mut atlas = atlas(Vec::new(), Vec::new(), false);
+        atlas.passes.clear();
+        check_compiles("empty", &atlas);
+    }
+
+    #[test]
+    fn writes_the_tables() {
+        let mut module = Vec::new();
+        let frames = vec![frame("idle", 0, &["blink"], None)];
+        write(&atlas(frames, Vec::new(), false), &mut module).unwrap();
+        let module = String::from_utf8(module).unwrap();
+        assert!(module.contains("pub const IMAGE: &str = \"hero.png\";"));
+        assert!(!module.contains("include_bytes!"));
+        assert!(module.contains("pub const SIZE: (u32, u32) = (16, 16);"));
+        assert!(module.contains("(\"normal\", \"hero_normal.png\"),"));
+        assert!(module.contains("offset: (1, 2),"));
+        assert!(module.contains("source_size: (20, 20),"));
+        assert!(module.contains("events: &[\"blink\"],"));
+        assert!(module.contains("pub const ANIMATIONS: &[Animation] = &[];"));
+    }
+}
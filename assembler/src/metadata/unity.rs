@@ -0,0 +1,110 @@
+use std::io::Write;
+
+use super::Atlas;
+use crate::{report, Result};
+
+/// Writes the sheet's `.meta` file, which Unity reads its import settings
+/// from: a sprite sheet sliced into the frames, each with its pivot and
+/// 9-slice border. Unity counts rows from the bottom, and borders go left,
+/// bottom, right and top.
+pub fn write(atlas: &Atlas, out: &mut dyn Write) -> Result<()> {
+    writeln!(out, "fileFormatVersion: 2")?;
+    // The same sheet keeps the same guid, so references to its sprites
+    // survive it being assembled again.
+    let guid = report::sha256(atlas.image.as_bytes());
+    writeln!(out, "guid: {}", &guid[..32])?;
+    writeln!(out, "TextureImporter:")?;
+    writeln!(out, "  serializedVersion: 12")?;
+    writeln!(out, "  mipmaps:")?;
+    writeln!(out, "    enableMipMap: 0")?;
+    writeln!(out, "  alphaIsTransparency: 1")?;
+    // A sprite, sliced into several.
+    writeln!(out, "  textureType: 8")?;
+    writeln!(out, "  spriteMode: 2")?;
+    writeln!(out, "  spriteSheet:")?;
+    writeln!(out, "    serializedVersion: 2")?;
+    if atlas.frames.is_empty() {
+        writeln!(out, "    sprites: []")?;
+    } else {
+        writeln!(out, "    sprites:")?;
+    }
+    for frame in &atlas.frames {
+        let (pivot_x, pivot_y) = frame.pivot.unwrap_or((0.5, 0.5));
+        let border = frame.nine_slice.unwrap_or_default();
+        writeln!(out, "    - serializedVersion: 2")?;
+        writeln!(out, "      name: {}", serde_json::to_string(&frame.name)?)?;
+        writeln!(out, "      rect:")?;
+        writeln!(out, "        serializedVersion: 2")?;
+        writeln!(out, "        x: {}", frame.x)?;
+        writeln!(
+            out,
+            "        y: {}",
+            atlas.height.saturating_sub(frame.y + frame.height)
+        )?;
+        writeln!(out, "        width: {}", frame.width)?;
+        writeln!(out, "        height: {}", frame.height)?;
+        // Custom, at `pivot`.
+        writeln!(out, "      alignment: 9")?;
+        writeln!(out, "      pivot: {{x: {}, y: {}}}", pivot_x, 1.0 - pivot_y)?;
+        writeln!(
+            out,
+            "      border: {{x: {}, y: {}, z: {}, w: {}}}",
+            border.left, border.bottom, border.right, border.top
+        )?;
+    }
+    writeln!(out, "  userData:")?;
+    writeln!(out, "  assetBundleName:")?;
+    writeln!(out, "  assetBundleVariant:")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{metadata::Frame, nine_slice::Insets};
+
+    #[test]
+    fn slices_the_sheet_from_the_bottom() {
+        let frame = |name: &str, y: u32, nine_slice| Frame {
+            name: name.to_owned(),
+            x: 0,
+            y,
+            width: 16,
+            height: 16,
+            duration: 1,
+            fps: 12,
+            events: Vec::new(),
+            polygons: Vec::new(),
+            mesh: None,
+            opaque_bounds: None,
+            boxes: Vec::new(),
+            nine_slice,
+            pivot: None,
+        };
+        let atlas = Atlas {
+            image: "panel.png".to_owned(),
+            page: 0,
+            pages: 1,
+            width: 16,
+            height: 32,
+            fps: 12,
+            frames: vec![
+                frame("idle", 0, None),
+                frame("panel", 16, Insets::parse("4,6,3,5")),
+            ],
+            tags: Vec::new(),
+            sequence: Vec::new(),
+            passes: Vec::new(),
+            trim: None,
+            embed_image: false,
+        };
+        let mut meta = Vec::new();
+        write(&atlas, &mut meta).unwrap();
+        let meta = String::from_utf8(meta).unwrap();
+        assert!(meta.contains("      name: \"idle\"\n      rect:\n        serializedVersion: 2\n        x: 0\n        y: 16\n"));
+        assert!(meta.contains("      name: \"panel\"\n      rect:\n        serializedVersion: 2\n        x: 0\n        y: 0\n"));
+        assert!(meta.contains("border: {x: 0, y: 0, z: 0, w: 0}"));
+        assert!(meta.contains("border: {x: 4, y: 5, z: 3, w: 6}"));
+        assert!(meta.contains("pivot: {x: 0.5, y: 0.5}"));
+    }
+}
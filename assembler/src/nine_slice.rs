@@ -0,0 +1,140 @@
+//! 9-slice borders, for UI panels that stretch their middle and keep their
+//! corners. Insets are given for every frame with `--nine-slice`, or per
+//! frame or animation in a JSON file like `{"panel": 8, "button_0001":
+//! [4, 6, 4, 6]}`, and exported in the forms TexturePacker, Unity and
+//! Godot read: in the JSON, and as Unity's import settings and a Godot
+//! theme with `--metadata unity` and `--metadata godot`.
+
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::Path};
+
+use crate::{errors::NineSliceError, Result};
+
+/// How far each stretchable edge is from the frame's sides, in pixels.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "Spec", into = "[u32; 4]")]
+pub struct Insets {
+    pub left: u32,
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32,
+}
+
+/// One inset for every side, or `[left, top, right, bottom]`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Spec {
+    All(u32),
+    Sides([u32; 4]),
+}
+
+impl From<Spec> for Insets {
+    fn from(spec: Spec) -> Self {
+        let [left, top, right, bottom] = match spec {
+            Spec::All(inset) => [inset; 4],
+            Spec::Sides(sides) => sides,
+        };
+        Insets {
+            left,
+            top,
+            right,
+            bottom,
+        }
+    }
+}
+
+impl From<Insets> for [u32; 4] {
+    fn from(insets: Insets) -> Self {
+        [insets.left, insets.top, insets.right, insets.bottom]
+    }
+}
+
+impl Insets {
+    /// Parses `8` for every side, or `left,top,right,bottom`.
+    pub fn parse(value: &str) -> Option<Self> {
+        let sides = value
+            .split(',')
+            .map(|side| side.trim().parse::<u32>().ok())
+            .collect::<Option<Vec<_>>>()?;
+        match sides[..] {
+            [inset] => Some(Spec::All(inset).into()),
+            [left, top, right, bottom] => Some(Spec::Sides([left, top, right, bottom]).into()),
+            _ => None,
+        }
+    }
+
+    /// Fails unless the insets leave something to stretch in a `width` by
+    /// `height` frame named `name`.
+    pub fn check(self, name: &str, width: u32, height: u32) -> Result<Self> {
+        let fits = |before: u32, after: u32, size: u32| {
+            before
+                .checked_add(after)
+                .is_some_and(|insets| insets < size)
+        };
+        if fits(self.left, self.right, width) && fits(self.top, self.bottom, height) {
+            return Ok(self);
+        }
+        Err(NineSliceError {
+            what: name.to_owned(),
+            message: format!(
+                "insets {},{},{},{} don't fit in {}x{}px",
+                self.left, self.top, self.right, self.bottom, width, height
+            ),
+        }
+        .into())
+    }
+}
+
+/// Insets by frame or animation name.
+pub type NineSlices = BTreeMap<String, Insets>;
+
+/// Reads a JSON file mapping frame or animation names to insets.
+pub fn load(path: &Path) -> Result<NineSlices> {
+    let invalid = |message: String| NineSliceError {
+        what: path.display().to_string(),
+        message,
+    };
+    let bytes = fs::read(path).map_err(|err| invalid(err.to_string()))?;
+    Ok(serde_json::from_slice(&bytes).map_err(|err| {
+        invalid(format!(
+            "should map frame or animation names to an inset or [left, top, right, bottom]: {}",
+            err
+        ))
+    })?)
+}
+
+/// The insets of the frame called `name` in `animation`: its own, else its
+/// animation's, else `every` frame's.
+pub fn find(
+    slices: &NineSlices,
+    every: Option<Insets>,
+    name: &str,
+    animation: &str,
+) -> Option<Insets> {
+    slices
+        .get(name)
+        .or_else(|| slices.get(animation))
+        .copied()
+        .or(every)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_insets_fit() {
+        let insets = Insets::parse("4,6,4,6").unwrap();
+        assert!(insets.check("panel", 9, 13).is_ok());
+        assert!(insets.check("panel", 8, 13).is_err());
+        assert!(insets.check("panel", 9, 12).is_err());
+    }
+
+    #[test]
+    fn rejects_insets_too_big_to_add_up() {
+        let insets = Insets::parse("4294967295").unwrap();
+        assert!(insets.check("panel", u32::MAX, u32::MAX).is_err());
+        let insets = Insets::parse("1,0,4294967295,0").unwrap();
+        assert!(insets.check("panel", u32::MAX, 1).is_err());
+    }
+}
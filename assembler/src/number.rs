@@ -0,0 +1,89 @@
+//! `--number-frames`: a copy of the sheet with each frame's index burned
+//! into a corner of its cell, so QA can say exactly which frame shows an
+//! artifact.
+
+use image::{Rgba, RgbaImage};
+use log::warn;
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    encode::{self, Compression},
+    metadata::Atlas,
+    pad::Anchor,
+    preview, Result,
+};
+
+/// The index's size, in multiples of the font's 3x5 pixels, unless set.
+pub const DEFAULT_SIZE: u32 = 2;
+
+/// Behind the index, so it can be read over any frame.
+const BACKING: Rgba<u8> = Rgba([0, 0, 0, 192]);
+
+/// `hero.png` gets `hero_numbered.png`.
+pub fn path(sheet: &Path) -> PathBuf {
+    let stem = sheet.file_stem().unwrap_or_default().to_string_lossy();
+    sheet.with_file_name(format!("{}_numbered.png", stem))
+}
+
+/// Draws the index of each frame on the page described by `atlas`, the
+/// first being frame `first` of the whole sheet, at `size` in the `corner`
+/// of its cell, and writes the result to `out` as a PNG. A `size` too big
+/// for the cells is shrunk to the biggest that fits.
+pub fn write(
+    sheet: &RgbaImage,
+    atlas: &Atlas,
+    first: usize,
+    size: u32,
+    corner: Anchor,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let mut numbered = sheet.clone();
+    let mut shrunk = None;
+    for (index, frame) in atlas.frames.iter().enumerate() {
+        let label = (first + index).to_string();
+        let size = match fitting(&label, (frame.width, frame.height)) {
+            fits if fits < size => {
+                shrunk = Some(shrunk.map_or(fits, |smallest: u32| smallest.min(fits)));
+                fits
+            }
+            _ => size,
+        };
+        let (width, height) = preview::text_size(&label, size);
+        // A pixel of backing around the digits at the smallest size.
+        let backing = (width + 2 * size, height + 2 * size);
+        let (x, y) = corner.offset(backing, (frame.width, frame.height));
+        let (x, y) = (frame.x + x, frame.y + y);
+        let right = (x + backing.0).min(frame.x + frame.width);
+        let bottom = (y + backing.1).min(frame.y + frame.height);
+        for py in y..bottom {
+            for px in x..right {
+                let under = *numbered.get_pixel(px, py);
+                numbered.put_pixel(px, py, preview::blend(BACKING, under));
+            }
+        }
+        preview::text(&mut numbered, x + size, y + size, &label, size);
+    }
+    if let Some(fits) = shrunk {
+        warn!(
+            "--number-size {} doesn't fit in the cells, so frame numbers are drawn as big as \
+             fits, down to {}",
+            size, fits
+        );
+    }
+    encode::encoder(out, numbered.dimensions(), Compression::Fast)
+        .write_header()?
+        .write_image_data(&numbered)?;
+    Ok(())
+}
+
+/// The biggest size `label` can be drawn at with its backing in a `cell`,
+/// though never less than 1.
+fn fitting(label: &str, (width, height): (u32, u32)) -> u32 {
+    let characters = label.chars().count() as u32;
+    // The text and a pixel of backing either side, in font pixels.
+    let (across, down) = (characters.saturating_mul(4).saturating_add(1), 7);
+    (width / across).min(height / down).max(1)
+}
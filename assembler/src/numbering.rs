@@ -0,0 +1,241 @@
+//! `--name-pattern`: where the frame number is in each filename, such as
+//! `render_####.png`, for names the trailing digits don't number reliably.
+//! Blender pads numbers to at least as many digits as there are `#`s but
+//! writes longer ones in full, gives negative frames a minus sign, and may
+//! put the scene name in front, so sorting the names puts `render_-002`
+//! after `render_-001` and `render_10000` before `render_9999`. With a
+//! pattern the frames are ordered by their numbers instead, and `--start`
+//! and `--end` keep a range of them either way.
+
+use log::warn;
+use regex::Regex;
+use serde::Serialize;
+use std::{
+    convert::TryFrom,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    errors::NamePatternError,
+    events::Event,
+    passes,
+    report::{Report, Skipped},
+    template, timing, Options, Result,
+};
+
+/// A `--name-pattern`, as given and compiled.
+#[derive(Debug, Clone, Serialize)]
+pub struct NamePattern {
+    pub spec: String,
+    #[serde(skip)]
+    regex: Regex,
+}
+
+impl NamePattern {
+    /// Reads a pattern in which a run of `#` stands for the frame number
+    /// and `*` for any text, matched against the whole filename, or else a
+    /// regular expression found anywhere in the filename, whose `frame`
+    /// group, or first group, is the number.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let invalid = |message: String| NamePatternError {
+            pattern: spec.to_owned(),
+            message,
+        };
+        let source = if spec.contains('#') {
+            translate(spec)
+        } else {
+            spec.to_owned()
+        };
+        let regex = Regex::new(&source).map_err(|err| invalid(err.to_string()))?;
+        if regex.captures_len() < 2 {
+            return Err(invalid("has no group capturing the frame number".to_owned()).into());
+        }
+        Ok(NamePattern {
+            spec: spec.to_owned(),
+            regex,
+        })
+    }
+
+    /// The frame number in `name`, and the rest of the name, which tells
+    /// animations in the same folder apart.
+    fn find(&self, name: &str) -> Option<(i64, String)> {
+        let captures = self.regex.captures(name)?;
+        let number = captures.name("frame").or_else(|| captures.get(1))?;
+        let rest = format!("{}{}", &name[..number.start()], &name[number.end()..]);
+        Some((number.as_str().parse().ok()?, rest))
+    }
+}
+
+/// `render_####.png` as the regular expression `^render_(-?[0-9]+)\.png$`.
+fn translate(spec: &str) -> String {
+    let mut source = "^".to_owned();
+    let mut chars = spec.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '#' => {
+                while chars.peek() == Some(&'#') {
+                    chars.next();
+                }
+                source.push_str("(-?[0-9]+)");
+            }
+            // Lazily, so `shot*####` leaves every digit to the number.
+            '*' => source.push_str(".*?"),
+            c => source.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    source.push('$');
+    source
+}
+
+/// Orders `paths` by their frame numbers if there's a `--name-pattern`,
+/// keeping each folder's animations apart, and leaves out frames numbered
+/// outside `--start` and `--end`. Frames whose number can't be found are
+/// skipped.
+pub fn select(options: &Options, report: &mut Report, paths: Vec<PathBuf>) -> Vec<PathBuf> {
+    let ranged = options.start.is_some() || options.end.is_some();
+    if options.name_pattern.is_none() && !ranged {
+        return paths;
+    }
+    let frames_dir = options.frames_dir();
+    let mut numbered = Vec::with_capacity(paths.len());
+    for path in paths {
+        let relative = path.strip_prefix(&frames_dir).unwrap_or(&path);
+        let name = bare_name(&options.passes, relative);
+        let found = match &options.name_pattern {
+            Some(pattern) => pattern
+                .find(&name)
+                .ok_or_else(|| format!("its name doesn't match --name-pattern '{}'", pattern.spec)),
+            None => trailing_number(&name)
+                .ok_or_else(|| "its name has no frame number for --start or --end".to_owned()),
+        };
+        let (number, rest) = match found {
+            Ok(found) => found,
+            Err(reason) => {
+                skip(options, report, path, reason);
+                continue;
+            }
+        };
+        let reason = match (options.start, options.end) {
+            (Some(start), _) if number < start => {
+                format!("frame {} is before --start {}", number, start)
+            }
+            (_, Some(end)) if number > end => format!("frame {} is after --end {}", number, end),
+            _ => {
+                let folder = relative.parent().map(Path::to_path_buf);
+                numbered.push((folder, rest, number, path));
+                continue;
+            }
+        };
+        skip(options, report, path, reason);
+    }
+    if options.name_pattern.is_some() {
+        // Stable, so each frame's other passes stay next to it.
+        numbered.sort_by(|a, b| (&a.0, &a.1, a.2).cmp(&(&b.0, &b.1, b.2)));
+    }
+    numbered.into_iter().map(|(.., path)| path).collect()
+}
+
+fn skip(options: &Options, report: &mut Report, path: PathBuf, reason: String) {
+    warn!("Skipping {}: {}", path.display(), reason);
+    options.events.emit(Event::FrameSkipped {
+        path: path.clone(),
+        reason: reason.clone(),
+    });
+    report.skipped.push(Skipped { path, reason });
+}
+
+/// The filename of the frame at `relative` without its render pass, hold
+/// or pivot suffixes, which every pass of a frame shares.
+fn bare_name(passes: &[String], relative: &Path) -> String {
+    let relative = passes::bare(passes, relative);
+    let stem = relative
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or_default();
+    let stem = timing::split_suffix(stem).0;
+    match relative
+        .extension()
+        .and_then(|extension| extension.to_str())
+    {
+        Some(extension) => format!("{}.{}", stem, extension),
+        None => stem.to_owned(),
+    }
+}
+
+/// The number at the end of `name`'s stem, as when there's no pattern.
+fn trailing_number(name: &str) -> Option<(i64, String)> {
+    let stem = Path::new(name).file_stem()?.to_str()?;
+    let (base, number) = template::split_number(stem);
+    Some((i64::try_from(number?).ok()?, base.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(spec: &str, name: &str) -> Option<(i64, String)> {
+        NamePattern::parse(spec).unwrap().find(name)
+    }
+
+    #[test]
+    fn translates_hashes_and_stars() {
+        assert_eq!(translate("render_####.png"), r"^render_(-?[0-9]+)\.png$");
+        assert_eq!(translate("*_#.png"), r"^.*?_(-?[0-9]+)\.png$");
+    }
+
+    #[test]
+    fn reads_padded_and_longer_numbers() {
+        assert_eq!(find("render_####.png", "render_0042.png").unwrap().0, 42);
+        assert_eq!(
+            find("render_####.png", "render_10000.png").unwrap().0,
+            10000
+        );
+        assert_eq!(find("render_####.png", "render_7.png").unwrap().0, 7);
+    }
+
+    #[test]
+    fn reads_negative_frames() {
+        assert_eq!(find("render_####.png", "render_-002.png").unwrap().0, -2);
+    }
+
+    #[test]
+    fn star_leaves_every_digit_to_the_number() {
+        assert_eq!(
+            find("shot*####.png", "shot12.png"),
+            Some((12, "shot.png".to_owned()))
+        );
+        assert_eq!(find("shot*####.png", "shot_a_0012.png").unwrap().0, 12);
+    }
+
+    #[test]
+    fn keeps_scene_prefixes_apart() {
+        let (number, rest) = find("*_####.png", "Scene_0003.png").unwrap();
+        assert_eq!((number, rest.as_str()), (3, "Scene_.png"));
+        let (_, other) = find("*_####.png", "Other_0003.png").unwrap();
+        assert_ne!(rest, other);
+    }
+
+    #[test]
+    fn skips_names_that_dont_match() {
+        assert_eq!(find("render_####.png", "render_0001.exr"), None);
+        assert_eq!(find("render_####.png", "other_0001.png"), None);
+    }
+
+    #[test]
+    fn takes_regular_expressions_by_group() {
+        let spec = r"f(?P<frame>-?\d+)_v(\d+)";
+        assert_eq!(find(spec, "take_f12_v3.png").unwrap().0, 12);
+        assert_eq!(find(r"_(\d+)$", "walk_0005").unwrap().0, 5);
+        assert!(NamePattern::parse("walk").is_err());
+        assert!(NamePattern::parse("(").is_err());
+    }
+
+    #[test]
+    fn falls_back_to_trailing_numbers() {
+        assert_eq!(
+            trailing_number("walk_0012.png"),
+            Some((12, "walk".to_owned()))
+        );
+        assert_eq!(trailing_number("idle.png"), None);
+    }
+}
@@ -0,0 +1,145 @@
+use log::info;
+use serde::Serialize;
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    errors::{IoContext, OutputExistsError, Phase},
+    report::{self, HashingWriter},
+    Result,
+};
+
+/// What to do when an output file is already there.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Overwrite {
+    Refuse,
+    Force,
+    Backup,
+}
+
+/// Fails if `path` exists and may not be replaced. Called before any work is
+/// done so a typo in `--out` costs nothing.
+pub fn check(path: &Path, overwrite: Overwrite) -> Result<()> {
+    if overwrite == Overwrite::Refuse && path.exists() {
+        return Err(OutputExistsError {
+            path: path.to_path_buf(),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Makes way for a new file at `path`, renaming the old one to `<path>.bak`
+/// when backups were asked for.
+pub fn prepare(path: &Path, overwrite: Overwrite) -> Result<()> {
+    check(path, overwrite)?;
+    if overwrite == Overwrite::Backup && path.exists() {
+        let mut backup = path.as_os_str().to_owned();
+        backup.push(".bak");
+        fs::rename(path, &backup).context(Phase::Write, path)?;
+        info!(
+            "Moved previous {} to {}",
+            path.display(),
+            Path::new(&backup).display()
+        );
+    }
+    Ok(())
+}
+
+/// Writes a file by handing `write` a buffered temporary file next to `path`,
+/// which is renamed into place only once everything has been written.
+pub fn write_atomic<T>(
+    path: &Path,
+    overwrite: Overwrite,
+    write: impl FnOnce(&mut BufWriter<File>) -> Result<T>,
+) -> Result<T> {
+    let mut pending = PendingFile::create(path)?;
+    let value = write(pending.writer())?;
+    pending.persist(path, overwrite)?;
+    Ok(value)
+}
+
+/// Atomically writes a small output such as a metadata file, returning its
+/// entry for the report.
+pub fn write_file(
+    path: &Path,
+    overwrite: Overwrite,
+    write: impl FnOnce(&mut dyn Write) -> Result<()>,
+) -> Result<report::Output> {
+    let (bytes, sha256) = write_atomic(path, overwrite, |file| {
+        let mut writer = HashingWriter::new(file);
+        write(&mut writer)?;
+        let (_, bytes, sha256) = writer.finish();
+        Ok((bytes, sha256))
+    })?;
+    info!("Wrote {}", path.display());
+    Ok(report::Output {
+        path: path.to_path_buf(),
+        bytes,
+        sha256,
+    })
+}
+
+/// A hidden temporary file that becomes a real output with `persist`, and is
+/// deleted if dropped before that.
+pub struct PendingFile {
+    path: PathBuf,
+    writer: Option<BufWriter<File>>,
+}
+
+impl PendingFile {
+    /// Creates the temporary file as a sibling of `near`, so the final rename
+    /// stays on one filesystem.
+    pub fn create(near: &Path) -> Result<Self> {
+        let mut name = OsString::from(".");
+        name.push(near.file_name().unwrap_or_default());
+        name.push(format!(".{}.tmp", std::process::id()));
+        let path = near.with_file_name(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context(Phase::Write, parent)?;
+        }
+        let writer = BufWriter::new(File::create(&path).context(Phase::Write, near)?);
+        Ok(PendingFile {
+            path,
+            writer: Some(writer),
+        })
+    }
+
+    /// Where the file is being written, for a tool that writes it itself.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn writer(&mut self) -> &mut BufWriter<File> {
+        self.writer
+            .as_mut()
+            .expect("pending file already persisted")
+    }
+
+    /// Flushes and syncs the data, then moves it to `path`.
+    pub fn persist(mut self, path: &Path, overwrite: Overwrite) -> Result<()> {
+        let mut writer = self.writer.take().expect("pending file already persisted");
+        writer.flush().context(Phase::Write, path)?;
+        writer.get_ref().sync_all().context(Phase::Write, path)?;
+        drop(writer);
+        prepare(path, overwrite)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context(Phase::Write, parent)?;
+        }
+        fs::rename(&self.path, path).context(Phase::Write, path)?;
+        Ok(())
+    }
+}
+
+impl Drop for PendingFile {
+    fn drop(&mut self) {
+        if self.writer.take().is_some() {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! `--pad-to-max`: frames smaller than the largest are placed on a canvas of
+//! its size instead of failing the run, since a frame or two a pixel short
+//! usually just means Blender's render border was nudged.
+
+use image::{GenericImage, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+/// Where a smaller frame sits on the padded canvas.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Anchor {
+    TopLeft,
+    TopCenter,
+    TopRight,
+    CenterLeft,
+    Center,
+    CenterRight,
+    BottomLeft,
+    /// Keeps characters' feet on the same line.
+    BottomCenter,
+    BottomRight,
+}
+
+impl Anchor {
+    pub const NAMES: &'static [&'static str] = &[
+        "top-left",
+        "top-center",
+        "top-right",
+        "center-left",
+        "center",
+        "center-right",
+        "bottom-left",
+        "bottom-center",
+        "bottom-right",
+    ];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "top-left" => Anchor::TopLeft,
+            "top-center" => Anchor::TopCenter,
+            "top-right" => Anchor::TopRight,
+            "center-left" => Anchor::CenterLeft,
+            "center" => Anchor::Center,
+            "center-right" => Anchor::CenterRight,
+            "bottom-left" => Anchor::BottomLeft,
+            "bottom-center" => Anchor::BottomCenter,
+            "bottom-right" => Anchor::BottomRight,
+            _ => return None,
+        })
+    }
+
+    /// Where a `size` frame's top left corner goes on a `canvas`, as
+    /// fractions of the space left over.
+    pub fn offset(self, size: (u32, u32), canvas: (u32, u32)) -> (u32, u32) {
+        let (x, y) = self.halves();
+        (
+            canvas.0.saturating_sub(size.0) * x / 2,
+            canvas.1.saturating_sub(size.1) * y / 2,
+        )
+    }
+
+    /// Where the anchor is on a frame, as fractions of its width and height
+    /// from its top left.
+    pub fn fraction(self) -> (f64, f64) {
+        let (x, y) = self.halves();
+        (f64::from(x) / 2.0, f64::from(y) / 2.0)
+    }
+
+    /// How many halves of the way across and down the anchor is.
+    fn halves(self) -> (u32, u32) {
+        match self {
+            Anchor::TopLeft => (0, 0),
+            Anchor::TopCenter => (1, 0),
+            Anchor::TopRight => (2, 0),
+            Anchor::CenterLeft => (0, 1),
+            Anchor::Center => (1, 1),
+            Anchor::CenterRight => (2, 1),
+            Anchor::BottomLeft => (0, 2),
+            Anchor::BottomCenter => (1, 2),
+            Anchor::BottomRight => (2, 2),
+        }
+    }
+}
+
+/// Places `frame` on a transparent `canvas` at `anchor`, or returns it as it
+/// is if it's already that size.
+pub fn pad(frame: RgbaImage, canvas: (u32, u32), anchor: Anchor) -> RgbaImage {
+    if frame.dimensions() == canvas {
+        return frame;
+    }
+    let (x, y) = anchor.offset(frame.dimensions(), canvas);
+    let mut padded = RgbaImage::new(canvas.0, canvas.1);
+    padded.copy_from(&frame, x, y);
+    padded
+}
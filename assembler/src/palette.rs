@@ -0,0 +1,184 @@
+//! `--palette`: the colours the sheet uses, as a GIMP `.gpl`, JASC `.pal`
+//! or `.hex` list, so artists can load the exact in-game palette into their
+//! 2D tools.
+
+use image::RgbaImage;
+use std::{collections::HashMap, io::Write, path::Path};
+
+use crate::{errors::OptionError, Result};
+
+/// How many colours a GIMP palette shows on a row.
+const GPL_COLUMNS: usize = 16;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Format {
+    Gpl,
+    Pal,
+    Hex,
+}
+
+impl Format {
+    fn of(path: &str) -> Option<Self> {
+        let extension = Path::new(path).extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "gpl" => Some(Format::Gpl),
+            "pal" => Some(Format::Pal),
+            "hex" => Some(Format::Hex),
+            _ => None,
+        }
+    }
+}
+
+/// Fails unless `path` names a `.gpl`, `.pal` or `.hex` file, so a typo is
+/// caught before any work is done.
+pub fn check(path: &str) -> Result<()> {
+    match Format::of(path) {
+        Some(_) => Ok(()),
+        None => Err(OptionError {
+            name: "palette".to_owned(),
+            value: path.to_owned(),
+            expected: "a .gpl, .pal or .hex filename".to_owned(),
+        }
+        .into()),
+    }
+}
+
+type Colour = [u8; 3];
+
+/// How many pixels of each colour the sheet's pages have, ignoring fully
+/// transparent ones. Partly transparent pixels count as their colour.
+#[derive(Debug, Default)]
+pub struct Palette {
+    counts: HashMap<Colour, u64>,
+}
+
+impl Palette {
+    pub fn add(&mut self, sheet: &RgbaImage) {
+        for pixel in sheet.pixels().filter(|pixel| pixel[3] > 0) {
+            *self
+                .counts
+                .entry([pixel[0], pixel[1], pixel[2]])
+                .or_default() += 1;
+        }
+    }
+
+    /// Every colour, the most used first, or at most `limit` of them picked
+    /// by median cut when there are more.
+    fn colours(&self, limit: Option<usize>) -> Vec<Colour> {
+        let mut counted = self
+            .counts
+            .iter()
+            .map(|(colour, count)| (*colour, *count))
+            .collect::<Vec<_>>();
+        if let Some(limit) = limit.filter(|limit| counted.len() > *limit) {
+            counted = median_cut(counted, limit);
+        }
+        // By colour too, so ties come out the same on every run.
+        counted.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        counted.into_iter().map(|(colour, _)| colour).collect()
+    }
+
+    /// Writes the colours to `out` in the format `path` names, titled
+    /// `name` where the format has room for it.
+    pub fn write(
+        &self,
+        limit: Option<usize>,
+        name: &str,
+        path: &Path,
+        out: &mut dyn Write,
+    ) -> Result<()> {
+        let colours = self.colours(limit);
+        match Format::of(&path.to_string_lossy()).unwrap_or(Format::Hex) {
+            Format::Gpl => {
+                writeln!(out, "GIMP Palette")?;
+                writeln!(out, "Name: {}", name)?;
+                writeln!(out, "Columns: {}", GPL_COLUMNS.min(colours.len().max(1)))?;
+                writeln!(out, "#")?;
+                for [r, g, b] in colours {
+                    writeln!(out, "{:3} {:3} {:3}\t{}", r, g, b, hex([r, g, b]))?;
+                }
+            }
+            Format::Pal => {
+                writeln!(out, "JASC-PAL\r")?;
+                writeln!(out, "0100\r")?;
+                writeln!(out, "{}\r", colours.len())?;
+                for [r, g, b] in colours {
+                    writeln!(out, "{} {} {}\r", r, g, b)?;
+                }
+            }
+            Format::Hex => {
+                for colour in colours {
+                    writeln!(out, "{}", hex(colour))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn hex([r, g, b]: Colour) -> String {
+    format!("{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/// Reduces `counted` to `limit` colours by repeatedly splitting the group
+/// with the widest range of any channel at its median pixel, then taking
+/// each group's average.
+fn median_cut(counted: Vec<(Colour, u64)>, limit: usize) -> Vec<(Colour, u64)> {
+    let mut groups = vec![counted];
+    while groups.len() < limit {
+        let widest = groups
+            .iter()
+            .enumerate()
+            .filter(|(_, group)| group.len() > 1)
+            .map(|(index, group)| (index, widest_channel(group)))
+            .max_by_key(|(index, (_, range))| (*range, std::cmp::Reverse(*index)));
+        let (index, (channel, _)) = match widest {
+            Some(widest) => widest,
+            None => break,
+        };
+        let mut group = groups.swap_remove(index);
+        group.sort_by_key(|(colour, _)| (colour[channel], *colour));
+        let half = group.iter().map(|(_, count)| count).sum::<u64>() / 2;
+        let mut seen = 0;
+        let mut split = group
+            .iter()
+            .position(|(_, count)| {
+                seen += count;
+                seen > half
+            })
+            .unwrap_or(0);
+        // Both halves need a colour.
+        split = split.clamp(1, group.len() - 1);
+        let upper = group.split_off(split);
+        groups.push(group);
+        groups.push(upper);
+    }
+    groups.iter().map(|group| average(group)).collect()
+}
+
+/// The channel whose values are furthest apart, and how far.
+fn widest_channel(group: &[(Colour, u64)]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let values = group.iter().map(|(colour, _)| colour[channel]);
+            let range = values.clone().max().unwrap_or(0) - values.min().unwrap_or(0);
+            (channel, range)
+        })
+        .max_by_key(|(channel, range)| (*range, std::cmp::Reverse(*channel)))
+        .unwrap_or((0, 0))
+}
+
+/// The group's colours averaged by how often they're used, and how many
+/// pixels that is.
+fn average(group: &[(Colour, u64)]) -> (Colour, u64) {
+    let total = group.iter().map(|(_, count)| count).sum::<u64>();
+    let mut colour = [0; 3];
+    for (channel, value) in colour.iter_mut().enumerate() {
+        let sum = group
+            .iter()
+            .map(|(colour, count)| u64::from(colour[channel]) * count)
+            .sum::<u64>();
+        *value = ((sum + total / 2) / total.max(1)) as u8;
+    }
+    (colour, total)
+}
@@ -0,0 +1,120 @@
+//! `--pass`: render passes saved side by side, such as
+//! `frame_0001_color.png`, `frame_0001_emission.png` and
+//! `frame_0001_ao.png`, for 2D lighting whose shaders sample every pass at
+//! the same coordinates. The first pass is assembled as usual, and each of
+//! the others is copied cell for cell onto a sheet of its own, laid out the
+//! same way and listed in the shared metadata.
+
+use log::warn;
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    errors::MissingPassError,
+    events::Event,
+    report::{Report, Skipped},
+    Options, Result,
+};
+
+/// `hero.png` gets `hero_emission.png` for the `emission` pass.
+pub fn path(sheet: &Path, pass: &str) -> PathBuf {
+    let stem = sheet.file_stem().unwrap_or_default().to_string_lossy();
+    sheet.with_file_name(format!("{}_{}{}", stem, pass, extension(sheet)))
+}
+
+/// `relative` without the first pass's suffix, so frames are named and
+/// numbered as if it weren't there: `walk_0005@3_color.png` becomes
+/// `walk_0005@3.png`.
+pub fn unsuffixed(passes: &[String], relative: &Path) -> PathBuf {
+    match split_stem(relative, first(passes)) {
+        (base, Some(_)) => relative.with_file_name(format!("{}{}", base, extension(relative))),
+        _ => relative.to_path_buf(),
+    }
+}
+
+/// `relative` without the suffix of whichever pass it's from, so every
+/// pass's file of a frame has the same name.
+pub fn bare(passes: &[String], relative: &Path) -> PathBuf {
+    match split_stem(relative, passes) {
+        (base, Some(_)) => relative.with_file_name(format!("{}{}", base, extension(relative))),
+        _ => relative.to_path_buf(),
+    }
+}
+
+/// The file of `pass` rendered along with the first pass's frame at
+/// `path`, whether or not that frame has the first pass's suffix.
+pub fn counterpart(passes: &[String], path: &Path, pass: &str) -> PathBuf {
+    let base = split_stem(path, first(passes)).0;
+    path.with_file_name(format!("{}_{}{}", base, pass, extension(path)))
+}
+
+/// Leaves the files of every pass but the first out of `paths`, after
+/// checking each frame has one in every pass. Files of the other passes
+/// without a frame to go with are skipped.
+pub fn split(options: &Options, report: &mut Report, paths: Vec<PathBuf>) -> Result<Vec<PathBuf>> {
+    let others = options.passes.get(1..).unwrap_or_default();
+    if others.is_empty() {
+        return Ok(paths);
+    }
+    let (other_passes, frames) = paths
+        .into_iter()
+        .partition::<Vec<_>, _>(|path| split_stem(path, others).1.is_some());
+    let found = other_passes.iter().collect::<HashSet<_>>();
+    let mut wanted = HashSet::new();
+    for frame in &frames {
+        for pass in others {
+            let path = counterpart(&options.passes, frame, pass);
+            if !found.contains(&path) {
+                return Err(MissingPassError {
+                    pass: pass.clone(),
+                    frame: frame.clone(),
+                    path,
+                }
+                .into());
+            }
+            wanted.insert(path);
+        }
+    }
+    for path in other_passes {
+        if wanted.contains(&path) {
+            continue;
+        }
+        let reason = format!("there's no {} frame to go with it", options.passes[0]);
+        warn!("Skipping {}: {}", path.display(), reason);
+        options.events.emit(Event::FrameSkipped {
+            path: path.clone(),
+            reason: reason.clone(),
+        });
+        report.skipped.push(Skipped { path, reason });
+    }
+    Ok(frames)
+}
+
+fn first(passes: &[String]) -> &[String] {
+    &passes[..passes.len().min(1)]
+}
+
+/// The stem of `path` without a `_{pass}` suffix naming one of `passes`,
+/// and that pass.
+fn split_stem<'a, 'b>(path: &'a Path, passes: &'b [String]) -> (&'a str, Option<&'b str>) {
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or_default();
+    passes
+        .iter()
+        .find_map(|pass| {
+            let base = stem.strip_suffix(pass.as_str())?.strip_suffix('_')?;
+            Some((base, Some(pass.as_str())))
+        })
+        .unwrap_or((stem, None))
+}
+
+fn extension(path: &Path) -> String {
+    match path.extension() {
+        Some(extension) => format!(".{}", extension.to_string_lossy()),
+        None => String::new(),
+    }
+}
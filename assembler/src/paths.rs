@@ -0,0 +1,57 @@
+//! Paths as Windows render farms hand them over: UNC shares, names in any
+//! script, and folders nested deeper than the old 260 character limit.
+
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+/// Rewrites a Windows extended-length path, `\\?\C:\...` or
+/// `\\?\UNC\server\share\...`, in its ordinary form, which frame and output
+/// names can be joined to with `/`. The standard library adds the prefix
+/// back by itself whenever a path is too long to open without it.
+pub fn simplify(path: String) -> String {
+    if !cfg!(windows) {
+        return path;
+    }
+    if let Some(share) = path.strip_prefix(r"\\?\UNC\") {
+        return format!(r"\\{}", share);
+    }
+    match path.strip_prefix(r"\\?\") {
+        Some(drive) if drive.as_bytes().get(1) == Some(&b':') => drive.to_owned(),
+        _ => path,
+    }
+}
+
+/// Where `path` really is, following links in the part of it that exists.
+pub fn resolve(path: &Path) -> PathBuf {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            // Up from where a link leads, not from the link.
+            Component::ParentDir => {
+                normal = fs::canonicalize(&normal).unwrap_or(normal);
+                normal.pop();
+            }
+            component => normal.push(component),
+        }
+    }
+    let mut missing = Vec::new();
+    let mut existing = normal.as_path();
+    loop {
+        if let Ok(real) = fs::canonicalize(existing) {
+            return missing
+                .iter()
+                .rev()
+                .fold(real, |path, name| path.join(name));
+        }
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                missing.push(name);
+                existing = parent;
+            }
+            _ => return normal,
+        }
+    }
+}
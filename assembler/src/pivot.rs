@@ -0,0 +1,70 @@
+//! Pivots, the point of each frame an engine places and rotates it about,
+//! such as a character's feet. They come from a `@pivot=X,Y` suffix on the
+//! filename, like `walk_0005@pivot=16,30.png` (after any `@3` hold), from
+//! a single pixel of the `--pivot-marker` colour in the frame, which is
+//! then made transparent, or else from `--pivot` for every frame.
+
+use image::RgbaImage;
+use std::path::Path;
+
+use crate::{errors::PivotMarkerError, Result};
+
+const SUFFIX: &str = "@pivot=";
+
+/// Splits a `@pivot=X,Y` suffix off a file stem: `walk_0005@pivot=16,30`
+/// becomes `walk_0005` and `(16, 30)`.
+pub fn split_suffix(stem: &str) -> (&str, Option<(u32, u32)>) {
+    let at = match stem.rfind(SUFFIX) {
+        Some(at) => at,
+        None => return (stem, None),
+    };
+    let point = stem[at + SUFFIX.len()..]
+        .split_once(',')
+        .and_then(|(x, y)| Some((x.parse().ok()?, y.parse().ok()?)));
+    match point {
+        Some(point) => (&stem[..at], Some(point)),
+        None => (stem, None),
+    }
+}
+
+/// Finds the pixel of `colour` in `image`, makes it transparent and returns
+/// its centre, or `None` if there isn't one. Fails if there's more than
+/// one, since the pivot would be ambiguous.
+pub fn take_marker(
+    image: &mut RgbaImage,
+    colour: [u8; 3],
+    path: &Path,
+) -> Result<Option<(f64, f64)>> {
+    let marked = image
+        .enumerate_pixels()
+        .filter(|(_, _, pixel)| pixel.0 == [colour[0], colour[1], colour[2], 255])
+        .map(|(x, y, _)| (x, y))
+        .collect::<Vec<_>>();
+    match marked[..] {
+        [] => Ok(None),
+        [(x, y)] => {
+            image.get_pixel_mut(x, y).0 = [0; 4];
+            Ok(Some((f64::from(x) + 0.5, f64::from(y) + 0.5)))
+        }
+        _ => Err(PivotMarkerError {
+            path: path.to_path_buf(),
+            count: marked.len(),
+        }
+        .into()),
+    }
+}
+
+/// The pivot of the frame at `relative` to the frames folder, in pixels
+/// from the top left of its cell: where its marker was, or else the point
+/// its filename names, moved by `offset` if the frame was padded.
+pub fn resolve(
+    marked: Option<(f64, f64)>,
+    relative: &Path,
+    offset: (u32, u32),
+) -> Option<(f64, f64)> {
+    marked.or_else(|| {
+        let stem = relative.file_stem()?.to_str()?;
+        let (x, y) = split_suffix(stem).1?;
+        Some((f64::from(x + offset.0), f64::from(y + offset.1)))
+    })
+}
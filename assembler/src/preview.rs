@@ -0,0 +1,495 @@
+//! `--preview`: a contact sheet for review, with every frame on a
+//! checkerboard and labelled with its index and name, so frames that are
+//! out of order, missing or accidentally transparent stand out at a glance.
+//! And `--preview-gif` and `--preview-html`: the animation itself, small
+//! enough to post in a chat or pull request, or with a player to step
+//! through it.
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use gif::SetParameter;
+use image::{imageops, FilterType, GenericImageView, Rgba, RgbaImage};
+use serde::Serialize;
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    encode::{self, Compression},
+    metadata::Atlas,
+    timing::Direction,
+    Result,
+};
+
+/// Frames narrower than this are scaled up, by whole pixels, so pixel art
+/// is still readable, and centred in a cell this wide so labels have room.
+const MIN_CELL_WIDTH: u32 = 96;
+/// The most frames are scaled up by.
+const MAX_SCALE: u32 = 8;
+/// Space between cells, showing the background.
+const GAP: u32 = 2;
+/// Size of the checkerboard squares.
+const CHECKER: u32 = 8;
+/// How many times larger than the font's 3x5 pixels text is drawn.
+const TEXT_SCALE: u32 = 2;
+/// Height of the label under each cell: two lines of text with a margin.
+const LABEL_HEIGHT: u32 = 2 + 5 * TEXT_SCALE + 2 + 5 * TEXT_SCALE + 2;
+
+const BACKGROUND: Rgba<u8> = Rgba([64, 64, 64, 255]);
+const LABEL: Rgba<u8> = Rgba([32, 32, 32, 255]);
+const TEXT: Rgba<u8> = Rgba([255, 255, 255, 255]);
+const LIGHT: [u8; 3] = [255, 255, 255];
+const DARK: [u8; 3] = [204, 204, 204];
+
+/// Frames of the animated preview are scaled down to fit in this.
+const GIF_SIZE: u32 = 256;
+/// The shortest delay, in hundredths of a second, browsers play as written.
+const MIN_DELAY: u32 = 2;
+/// How hard to look for each frame's palette, from 1 (best) to 30 (fastest).
+const GIF_SPEED: i32 = 10;
+
+/// A frame cut out of the sheet, for the animated previews.
+pub struct Cell {
+    pub image: RgbaImage,
+    /// Ticks at `fps`.
+    pub duration: u32,
+    pub fps: u32,
+}
+
+impl Cell {
+    /// How long the frame shows, in seconds.
+    pub fn seconds(&self) -> f64 {
+        f64::from(self.duration) / f64::from(self.fps.max(1))
+    }
+}
+
+/// `hero.png` gets `hero.preview.png`.
+pub fn path(sheet: &Path) -> PathBuf {
+    sheet.with_extension("preview.png")
+}
+
+/// Draws the preview of a page whose frames are described by `atlas`, the
+/// first of them being frame `first` of the whole sheet, and writes it to
+/// `out` as a PNG.
+pub fn write(sheet: &RgbaImage, atlas: &Atlas, first: usize, out: &mut dyn Write) -> Result<()> {
+    let preview = draw(sheet, atlas, first);
+    encode::encoder(out, preview.dimensions(), Compression::Fast)
+        .write_header()?
+        .write_image_data(&preview)?;
+    Ok(())
+}
+
+fn draw(sheet: &RgbaImage, atlas: &Atlas, first: usize) -> RgbaImage {
+    let (width, height) = match atlas.frames.first() {
+        Some(frame) => (frame.width.max(1), frame.height.max(1)),
+        None => return RgbaImage::from_pixel(1, 1, BACKGROUND),
+    };
+    let scale = (MIN_CELL_WIDTH / width).clamp(1, MAX_SCALE);
+    let cell = (
+        (width * scale).max(MIN_CELL_WIDTH),
+        height * scale + LABEL_HEIGHT,
+    );
+    let margin = (cell.0 - width * scale) / 2;
+    let columns = (atlas.width / width).max(1);
+    let rows = (atlas.height / height).max(1);
+    let mut preview = RgbaImage::from_pixel(
+        columns * (cell.0 + GAP) + GAP,
+        rows * (cell.1 + GAP) + GAP,
+        BACKGROUND,
+    );
+
+    for (index, frame) in atlas.frames.iter().enumerate() {
+        let left = GAP + frame.x / width * (cell.0 + GAP);
+        let top = GAP + frame.y / height * (cell.1 + GAP);
+        for y in 0..cell.1 - LABEL_HEIGHT {
+            for x in 0..cell.0 {
+                let under = checker(x, y);
+                let pixel = match x.checked_sub(margin).filter(|x| *x < width * scale) {
+                    Some(x) => over(
+                        sheet.get_pixel(frame.x + x / scale, frame.y + y / scale),
+                        under,
+                    ),
+                    None => Rgba([under[0], under[1], under[2], 255]),
+                };
+                preview.put_pixel(left + x, top + y, pixel);
+            }
+        }
+
+        let label = top + cell.1 - LABEL_HEIGHT;
+        for y in label..top + cell.1 {
+            for x in left..left + cell.0 {
+                preview.put_pixel(x, y, LABEL);
+            }
+        }
+        let fits = ((cell.0 - 2) / (4 * TEXT_SCALE)) as usize;
+        let index = (first + index).to_string();
+        text(
+            &mut preview,
+            left + 2,
+            label + 2,
+            &truncate(&index, fits),
+            TEXT_SCALE,
+        );
+        let name = truncate(&frame.name, fits);
+        text(
+            &mut preview,
+            left + 2,
+            label + 4 + 5 * TEXT_SCALE,
+            &name,
+            TEXT_SCALE,
+        );
+    }
+    preview
+}
+
+/// The frames of a page, cut out of its sheet.
+pub fn cells(sheet: &RgbaImage, atlas: &Atlas) -> Vec<Cell> {
+    atlas
+        .frames
+        .iter()
+        .map(|frame| Cell {
+            image: sheet
+                .view(frame.x, frame.y, frame.width, frame.height)
+                .to_image(),
+            duration: frame.duration,
+            fps: frame.fps,
+        })
+        .collect()
+}
+
+/// Writes `frames` to `out` as a GIF that loops forever, scaled down to
+/// fit in [`GIF_SIZE`].
+pub fn write_gif<'a>(
+    frames: impl IntoIterator<Item = &'a Cell>,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let mut frames = frames.into_iter().peekable();
+    let (width, height) = match frames.peek() {
+        Some(frame) => frame.image.dimensions(),
+        None => return Ok(()),
+    };
+    let largest = width.max(height);
+    let size = |side: u32| (side * GIF_SIZE / largest).max(1);
+    let (width, height) = if largest > GIF_SIZE {
+        (size(width), size(height))
+    } else {
+        (width, height)
+    };
+    let mut encoder = gif::Encoder::new(out, width as u16, height as u16, &[])?;
+    encoder.set(gif::Repeat::Infinite)?;
+    for frame in frames {
+        let image = if largest > GIF_SIZE {
+            imageops::resize(&frame.image, width, height, FilterType::Triangle)
+        } else {
+            frame.image.clone()
+        };
+        // GIF pixels are either transparent or opaque.
+        let mut pixels = image.into_raw();
+        for pixel in pixels.chunks_exact_mut(4) {
+            pixel[3] = if pixel[3] < 128 { 0 } else { 255 };
+        }
+        let mut gif_frame =
+            gif::Frame::from_rgba_speed(width as u16, height as u16, &mut pixels, GIF_SPEED);
+        let delay = (frame.seconds() * 100.0).round() as u32;
+        gif_frame.delay = delay.clamp(MIN_DELAY, u32::from(u16::MAX)) as u16;
+        // Clear each frame before the next, so transparent pixels don't
+        // show the one before through.
+        gif_frame.dispose = gif::DisposalMethod::Background;
+        encoder.write_frame(&gif_frame)?;
+    }
+    Ok(())
+}
+
+/// The page `--preview-html` writes, with the player's data left out.
+const HTML: &str = include_str!("preview.html");
+
+/// Everything the HTML player shows, with the sheets embedded so the page
+/// can be shared on its own.
+#[derive(Serialize)]
+pub struct Player {
+    #[serde(skip)]
+    title: String,
+    /// Size of every frame.
+    width: u32,
+    height: u32,
+    /// Each page of the sheet as a data URL.
+    sheets: Vec<String>,
+    frames: Vec<PlayerFrame>,
+    animations: Vec<PlayerAnimation>,
+}
+
+#[derive(Serialize)]
+struct PlayerFrame {
+    name: String,
+    /// Index into [`Player::sheets`].
+    sheet: usize,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    /// Ticks at the animation's frame rate.
+    duration: u32,
+}
+
+#[derive(Serialize)]
+struct PlayerAnimation {
+    name: String,
+    /// Indices into [`Player::frames`].
+    frames: Vec<usize>,
+    direction: Direction,
+    fps: u32,
+}
+
+impl Player {
+    pub fn new(title: &str) -> Self {
+        Player {
+            title: title.to_owned(),
+            width: 0,
+            height: 0,
+            sheets: Vec::new(),
+            frames: Vec::new(),
+            animations: Vec::new(),
+        }
+    }
+
+    /// Adds a page, encoded as `png`, whose frames are described by `atlas`.
+    pub fn add_page(&mut self, png: &[u8], atlas: &Atlas) {
+        let sheet = self.sheets.len();
+        let first = self.frames.len();
+        self.sheets
+            .push(format!("data:image/png;base64,{}", BASE64.encode(png)));
+        for frame in &atlas.frames {
+            self.width = frame.width;
+            self.height = frame.height;
+            self.frames.push(PlayerFrame {
+                name: frame.name.clone(),
+                sheet,
+                x: frame.x,
+                y: frame.y,
+                w: frame.width,
+                h: frame.height,
+                duration: frame.duration,
+            });
+        }
+        for tag in &atlas.tags {
+            let frames = (first + tag.from..=first + tag.to).collect::<Vec<_>>();
+            // An animation that runs onto the next page continues its tag.
+            match self.animations.last_mut() {
+                Some(last)
+                    if last.name == tag.name
+                        && last.frames.last() == Some(&(first + tag.from - 1)) =>
+                {
+                    last.frames.extend(frames)
+                }
+                _ => self.animations.push(PlayerAnimation {
+                    name: tag.name.clone(),
+                    frames,
+                    direction: tag.direction,
+                    fps: tag.fps,
+                }),
+            }
+        }
+    }
+
+    /// Writes the page to `out`, offering every frame in `sequence` as the
+    /// first animation, then each tagged animation.
+    pub fn write(mut self, sequence: Vec<usize>, fps: u32, out: &mut dyn Write) -> Result<()> {
+        self.animations.insert(
+            0,
+            PlayerAnimation {
+                name: "All frames".to_owned(),
+                frames: sequence,
+                direction: Direction::Forward,
+                fps,
+            },
+        );
+        // Nothing in the data may close the script early.
+        let data = serde_json::to_string(&self)?.replace("</", "<\\/");
+        let html = HTML
+            .replace("{{title}}", &escape(&self.title))
+            .replace("{{data}}", &data);
+        out.write_all(html.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// `text` safe to put in HTML.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The checkerboard's colour at `(x, y)`, which transparent pixels are
+/// drawn over.
+pub fn checker(x: u32, y: u32) -> [u8; 3] {
+    if (x / CHECKER + y / CHECKER).is_multiple_of(2) {
+        LIGHT
+    } else {
+        DARK
+    }
+}
+
+/// A translucent `top` drawn over `under`.
+pub fn blend(top: Rgba<u8>, under: Rgba<u8>) -> Rgba<u8> {
+    let (a, b) = (f64::from(top[3]) / 255.0, f64::from(under[3]) / 255.0);
+    let alpha = a + b * (1.0 - a);
+    if alpha == 0.0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+    let channel = |index: usize| {
+        let value = f64::from(top[index]) * a + f64::from(under[index]) * b * (1.0 - a);
+        (value / alpha).round() as u8
+    };
+    Rgba([
+        channel(0),
+        channel(1),
+        channel(2),
+        (alpha * 255.0).round() as u8,
+    ])
+}
+
+/// `pixel` drawn over an opaque colour.
+pub fn over(pixel: &Rgba<u8>, under: [u8; 3]) -> Rgba<u8> {
+    let alpha = u32::from(pixel[3]);
+    let blend = |channel: usize| {
+        let value = u32::from(pixel[channel]) * alpha + u32::from(under[channel]) * (255 - alpha);
+        (value / 255) as u8
+    };
+    Rgba([blend(0), blend(1), blend(2), 255])
+}
+
+/// The end of `text` if it's longer than `fits` characters, since that's
+/// where frame numbers are.
+fn truncate(text: &str, fits: usize) -> String {
+    let length = text.chars().count();
+    if length <= fits {
+        return text.to_owned();
+    }
+    let (prefix, keep) = if fits > 2 {
+        ("..", fits - 2)
+    } else {
+        ("", fits)
+    };
+    let tail = text.chars().skip(length - keep).collect::<String>();
+    format!("{}{}", prefix, tail)
+}
+
+/// Draws `text` in white in the built-in font, `scale` times its 3x5
+/// pixels, with its top left corner at `(x, y)`. Whatever falls outside
+/// `image` is left out.
+pub fn text(image: &mut RgbaImage, x: u32, y: u32, text: &str, scale: u32) {
+    let (width, height) = image.dimensions();
+    // The `n`th of `scale` pixel wide steps from `start`, clipped to `end`.
+    let span = |start: u32, n: u32, end: u32| {
+        let from = start.saturating_add(n.saturating_mul(scale)).min(end);
+        from..from.saturating_add(scale).min(end)
+    };
+    for (position, character) in text.chars().enumerate() {
+        let left = x.saturating_add((position as u32).saturating_mul(4).saturating_mul(scale));
+        if left >= width {
+            break;
+        }
+        for (row, bits) in glyph(character).iter().enumerate() {
+            for column in 0..3 {
+                if bits & (0b100 >> column) == 0 {
+                    continue;
+                }
+                for py in span(y, row as u32, height) {
+                    for px in span(left, column, width) {
+                        image.put_pixel(px, py, TEXT);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The width and height of `text` drawn at `scale`, or as much of them
+/// as a `u32` holds.
+pub fn text_size(text: &str, scale: u32) -> (u32, u32) {
+    let characters = text.chars().count() as u32;
+    (
+        characters
+            .saturating_mul(4)
+            .saturating_sub(1)
+            .saturating_mul(scale),
+        scale.saturating_mul(5),
+    )
+}
+
+/// The rows of a character in a 3x5 pixel font, the leftmost pixel in the
+/// highest bit. Lowercase letters are drawn as capitals, and anything the
+/// font doesn't have as `?`.
+fn glyph(character: char) -> [u8; 5] {
+    match character.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '@' => [0b010, 0b101, 0b111, 0b100, 0b011],
+        '(' => [0b001, 0b010, 0b010, 0b010, 0b001],
+        ')' => [0b100, 0b010, 0b010, 0b010, 0b100],
+        ' ' => [0b000; 5],
+        _ => [0b111, 0b001, 0b010, 0b000, 0b010],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clips_huge_text_to_the_image() {
+        let mut image = RgbaImage::new(6, 4);
+        text(&mut image, 1, 0, "10", u32::MAX);
+        // Only the top left pixel of the 1 reaches the image, and it's blank.
+        assert!(image.pixels().all(|pixel| pixel[3] == 0));
+        text(&mut image, 0, 0, "0", 100_000);
+        assert!(image.pixels().all(|pixel| *pixel == TEXT));
+    }
+
+    #[test]
+    fn saturates_huge_text_sizes() {
+        assert_eq!(text_size("12", 3), (21, 15));
+        assert_eq!(text_size("12", u32::MAX), (u32::MAX, u32::MAX));
+        assert_eq!(text_size("", 3), (0, 15));
+    }
+}
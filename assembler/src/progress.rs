@@ -0,0 +1,161 @@
+use std::{
+    io::{self, Write},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::events::{Event, Events};
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+const WIDTH: u64 = 30;
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Turns progress bars off for the rest of the run, e.g. for `--quiet`.
+pub fn disable() {
+    ENABLED.store(false, Ordering::Relaxed);
+}
+
+/// A single-line progress bar drawn on stderr. Does nothing when stderr
+/// isn't a terminal, so piped output and logs stay clean.
+pub struct Progress {
+    label: &'static str,
+    total: Option<u64>,
+    current: AtomicU64,
+    enabled: bool,
+    last_draw: Mutex<Option<Instant>>,
+    /// Turns the count so far into an event for library users.
+    emit: Option<(Events, Box<ToEvent>)>,
+}
+
+type ToEvent = dyn Fn(u64) -> Event + Send + Sync;
+
+impl Progress {
+    /// A bar counting up to `total` items.
+    pub fn new(label: &'static str, total: u64) -> Self {
+        Self::with_total(label, Some(total))
+    }
+
+    /// A counter of bytes with no known total.
+    pub fn bytes(label: &'static str) -> Self {
+        Self::with_total(label, None)
+    }
+
+    fn with_total(label: &'static str, total: Option<u64>) -> Self {
+        Progress {
+            label,
+            total,
+            current: AtomicU64::new(0),
+            enabled: ENABLED.load(Ordering::Relaxed) && atty::is(atty::Stream::Stderr),
+            last_draw: Mutex::new(None),
+            emit: None,
+        }
+    }
+
+    /// Also sends `event(count so far)` to `events` on every step.
+    pub fn emitting(
+        mut self,
+        events: &Events,
+        event: impl Fn(u64) -> Event + Send + Sync + 'static,
+    ) -> Self {
+        if events.enabled() {
+            self.emit = Some((events.clone(), Box::new(event)));
+        }
+        self
+    }
+
+    /// Counts without drawing, when another bar is already on screen.
+    pub fn hidden(mut self) -> Self {
+        self.enabled = false;
+        self
+    }
+
+    pub fn inc(&self, n: u64) {
+        let current = self.current.fetch_add(n, Ordering::Relaxed) + n;
+        if let Some((events, event)) = &self.emit {
+            events.emit(event(current));
+        }
+        if !self.enabled {
+            return;
+        }
+        let mut last_draw = self.last_draw.lock().unwrap();
+        let now = Instant::now();
+        if last_draw.is_none_or(|last| now - last >= REDRAW_INTERVAL) {
+            *last_draw = Some(now);
+            self.draw(current);
+        }
+    }
+
+    /// Draws the final state and moves to a fresh line.
+    pub fn finish(&self) {
+        if self.enabled {
+            self.draw(self.current.load(Ordering::Relaxed));
+            eprintln!();
+        }
+    }
+
+    fn draw(&self, current: u64) {
+        let line = match self.total {
+            Some(total) => {
+                let filled = (current.min(total) * WIDTH)
+                    .checked_div(total)
+                    .unwrap_or(WIDTH);
+                format!(
+                    "{:>12} [{}{}] {}/{}",
+                    self.label,
+                    "#".repeat(filled as usize),
+                    "-".repeat((WIDTH - filled) as usize),
+                    current,
+                    total
+                )
+            }
+            None => format!("{:>12} {}", self.label, format_bytes(current)),
+        };
+        let stderr = io::stderr();
+        let mut stderr = stderr.lock();
+        let _ = write!(stderr, "\r{}\x1b[K", line);
+        let _ = stderr.flush();
+    }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Passes writes through to `inner`, advancing `progress` by the bytes written.
+pub struct ProgressWriter<'a, W> {
+    inner: W,
+    progress: &'a Progress,
+}
+
+impl<'a, W: Write> ProgressWriter<'a, W> {
+    pub fn new(inner: W, progress: &'a Progress) -> Self {
+        ProgressWriter { inner, progress }
+    }
+}
+
+impl<'a, W: Write> Write for ProgressWriter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.progress.inc(written as u64);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
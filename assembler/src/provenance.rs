@@ -0,0 +1,115 @@
+//! A record of exactly how a sheet was produced, for asset audits.
+
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{collect::Frame, report, Options, Result};
+
+#[derive(Serialize)]
+struct Provenance<'a> {
+    assembler: &'static str,
+    version: &'static str,
+    /// Seconds since the Unix epoch, left out of deterministic records.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    created: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scene: Option<&'a str>,
+    /// Without the root in deterministic records, which only hold paths
+    /// relative to it.
+    options: Value,
+    inputs: Vec<Input<'a>>,
+    outputs: Vec<Output<'a>>,
+}
+
+#[derive(Serialize)]
+struct Input<'a> {
+    path: String,
+    sha256: &'a str,
+}
+
+#[derive(Serialize)]
+struct Output<'a> {
+    path: String,
+    sha256: &'a str,
+}
+
+/// How `path` appears in the record: as it is, or in deterministic records
+/// relative to the root (or the frames folder, if that's elsewhere) and with
+/// `/` between components on every platform.
+fn recorded(options: &Options, path: &Path) -> String {
+    if !options.deterministic {
+        return path.to_string_lossy().into_owned();
+    }
+    let relative = match path
+        .strip_prefix(&options.root)
+        .or_else(|_| path.strip_prefix(options.frames_dir()))
+    {
+        Ok(relative) => relative,
+        Err(_) => return path.to_string_lossy().replace('\\', "/"),
+    };
+    relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// `hero.png` gets `hero.provenance.json`.
+pub fn path(sheet: &Path) -> PathBuf {
+    sheet.with_extension("provenance.json")
+}
+
+pub fn write(
+    options: &Options,
+    frames: &[Frame],
+    outputs: &[report::Output],
+    out: &mut dyn Write,
+) -> Result<()> {
+    let mut provenance = Provenance {
+        assembler: env!("CARGO_PKG_NAME"),
+        version: env!("CARGO_PKG_VERSION"),
+        created: if options.deterministic {
+            None
+        } else {
+            Some(
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|elapsed| elapsed.as_secs())
+                    .unwrap_or_default(),
+            )
+        },
+        scene: options.scene.as_deref(),
+        options: serde_json::to_value(options)?,
+        inputs: frames
+            .iter()
+            .map(|frame| Input {
+                path: recorded(options, &frame.path),
+                sha256: &frame.sha256,
+            })
+            .collect(),
+        outputs: outputs
+            .iter()
+            .map(|output| Output {
+                path: recorded(options, &output.path),
+                sha256: &output.sha256,
+            })
+            .collect(),
+    };
+    if options.deterministic {
+        if let Some(options) = provenance.options.as_object_mut() {
+            options.remove("root");
+        }
+        // By the text written rather than by `OsStr`, which orders
+        // differently on Windows.
+        provenance.inputs.sort_by(|a, b| a.path.cmp(&b.path));
+        provenance.outputs.sort_by(|a, b| a.path.cmp(&b.path));
+    }
+    serde_json::to_writer_pretty(&mut *out, &provenance)?;
+    writeln!(out)?;
+    Ok(())
+}
@@ -0,0 +1,262 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::progress::format_bytes;
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+    path::PathBuf,
+    time::Duration,
+};
+
+/// Summary of a run, printed as JSON on stdout for `--report json`.
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+    pub success: bool,
+    /// The run was cancelled, and the outputs it had written were removed.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub cancelled: bool,
+    /// Nothing changed since the last run, so no outputs were written.
+    pub up_to_date: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub inputs: Vec<PathBuf>,
+    pub skipped: Vec<Skipped>,
+    /// Indices, among the frames found, of those that are fully transparent,
+    /// when `--empty-frames` checks for them.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub empty: Vec<usize>,
+    /// Frames with the same pixels as an earlier one, for `--find-duplicates`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub duplicates: Vec<Duplicate>,
+    /// Frames nearly the same as the one before, for `--find-duplicates`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub similar: Vec<Similar>,
+    /// Frames `--pad-to-max` put on a bigger canvas.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub padded: Vec<Padded>,
+    /// Animations with frame numbers missing or repeated, when
+    /// `--strict-sequence warn` checks for them.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub gaps: Vec<Gap>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sheet: Option<Sheet>,
+    /// How well the frames use the sheet, unless it was up to date.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stats: Option<Stats>,
+    pub outputs: Vec<Output>,
+    /// Intermediate files deleted by `--cleanup`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub removed: Vec<PathBuf>,
+    pub timings: Timings,
+    pub bytes: Bytes,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Skipped {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Duplicate {
+    pub path: PathBuf,
+    /// The first frame with the same pixels.
+    pub of: PathBuf,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Similar {
+    pub path: PathBuf,
+    pub previous: PathBuf,
+    /// From 0 for nothing alike to 1 for identical.
+    pub similarity: f64,
+}
+
+/// A frame that was smaller than the others, and its size before padding.
+#[derive(Debug, Serialize)]
+pub struct Padded {
+    pub path: PathBuf,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Frame numbers an animation skips or has more than one frame for.
+#[derive(Debug, Clone, Serialize)]
+pub struct Gap {
+    pub animation: String,
+    pub missing: Vec<u64>,
+    pub repeated: Vec<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Sheet {
+    pub width: u32,
+    pub height: u32,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub columns: u32,
+    pub rows: u32,
+    pub frames: usize,
+    pub pages: usize,
+    /// How the layout was changed to stay within `--max-size`.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub fallback: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Stats {
+    /// Area of every page.
+    pub page_pixels: u64,
+    /// Area of every frame's cell.
+    pub frame_pixels: u64,
+    /// Percentage of the pages' area taken up by frames.
+    pub occupancy: f64,
+    /// Pixels of the pages outside any cell, such as the unused end of the
+    /// last row.
+    pub wasted_pixels: u64,
+    /// Transparent pixels around each frame's opaque ones, which trimming
+    /// would cut away, if every frame was decoded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trimmable_pixels: Option<u64>,
+    pub animations: Vec<AnimationFrames>,
+    /// Bytes written, by file extension.
+    pub formats: BTreeMap<String, u64>,
+}
+
+/// How many frames an animation has, the frames outside any animation
+/// counting under an empty name.
+#[derive(Debug, Clone, Serialize)]
+pub struct AnimationFrames {
+    pub name: String,
+    pub frames: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Output {
+    pub path: PathBuf,
+    pub bytes: u64,
+    pub sha256: String,
+}
+
+/// Wall time per phase, in milliseconds.
+#[derive(Debug, Default, Serialize)]
+pub struct Timings {
+    pub walk: f64,
+    pub decode: f64,
+    /// Working out the layout.
+    pub pack: f64,
+    pub composite: f64,
+    pub encode: f64,
+    pub total: f64,
+}
+
+/// Data handled per phase.
+#[derive(Debug, Default, Serialize)]
+pub struct Bytes {
+    /// Frame files read from disk.
+    pub decode: u64,
+    /// Pixels copied into the sheet.
+    pub composite: u64,
+    /// The encoded sheet.
+    pub encode: u64,
+}
+
+pub fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+impl Report {
+    pub fn print_json(&self) -> serde_json::Result<()> {
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        serde_json::to_writer_pretty(&mut stdout, self)?;
+        let _ = writeln!(stdout);
+        Ok(())
+    }
+
+    /// Prints a table of time and bytes per phase on stderr, for `--timings`.
+    pub fn print_timings(&self) {
+        let Timings {
+            walk,
+            decode,
+            pack,
+            composite,
+            encode,
+            total,
+        } = self.timings;
+        let phases = [
+            ("walk", walk, None),
+            ("decode", decode, Some(self.bytes.decode)),
+            ("pack", pack, None),
+            ("composite", composite, Some(self.bytes.composite)),
+            ("encode", encode, Some(self.bytes.encode)),
+        ];
+        eprintln!(
+            "{:<10} {:>10} {:>10} {:>12}",
+            "phase", "time", "bytes", "rate"
+        );
+        for (phase, millis, bytes) in phases.iter() {
+            let (bytes, rate) = match bytes {
+                Some(bytes) if *millis > 0.0 => (
+                    format_bytes(*bytes),
+                    format!(
+                        "{}/s",
+                        format_bytes((*bytes as f64 / millis * 1000.0) as u64)
+                    ),
+                ),
+                Some(bytes) => (format_bytes(*bytes), "-".to_owned()),
+                None => ("-".to_owned(), "-".to_owned()),
+            };
+            eprintln!(
+                "{:<10} {:>7.2} ms {:>10} {:>12}",
+                phase, millis, bytes, rate
+            );
+        }
+        eprintln!("{:<10} {:>7.2} ms", "total", total);
+    }
+}
+
+/// Hex SHA-256 of `bytes`.
+pub fn sha256(bytes: &[u8]) -> String {
+    hex(&Sha256::digest(bytes))
+}
+
+fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Hashes and counts everything written through it.
+pub struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+    bytes: u64,
+}
+
+impl<W: Write> HashingWriter<W> {
+    pub fn new(inner: W) -> Self {
+        HashingWriter {
+            inner,
+            hasher: Sha256::new(),
+            bytes: 0,
+        }
+    }
+
+    /// Returns the inner writer, the number of bytes written and their hex SHA-256.
+    pub fn finish(self) -> (W, u64, String) {
+        (self.inner, self.bytes, hex(&self.hasher.finalize()))
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.bytes += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
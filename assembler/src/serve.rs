@@ -0,0 +1,727 @@
+//! `assembler serve`: a long-running process taking JSON-RPC 2.0 requests,
+//! one per line, so the Blender add-on can send job after job without
+//! starting a process each time and get full reports back instead of exit
+//! codes. Requests come from stdin, or from connections to a TCP or Unix
+//! socket so a workstation can hand jobs to the machine holding the frames.
+//!
+//! Methods:
+//!
+//! - `assemble` takes `{"root": DIR, "options": {...}}`, with options named
+//!   like the command line ones (`"out"`, `"metadata"`, `"force"`, ...), and
+//!   answers with the run's report once it's done. With `"return_outputs":
+//!   true` the report also has a `files` list holding every written file's
+//!   path and base64 `data`. Jobs run in the background, so other requests
+//!   are answered in the meantime.
+//! - `assemble_frames` takes `{"frames": [{"name": NAME, "data": BASE64}],
+//!   "options": {...}}` and assembles them without touching the disk,
+//!   answering with a `sheets` list of each page's `name`, base64 `png` and
+//!   `metadata` by format.
+//! - `slice` takes `{"sheet": PNG, "frame_size": "64x64", "out": DIR}` to
+//!   cut a grid, or `{"metadata": JSON_OR_ATLAS, "out": DIR}` to cut by the
+//!   frames listed there, plus `"force": true` to overwrite, like `assembler
+//!   slice`, and answers with the `files` it wrote.
+//! - `status` lists the jobs still running, by request id.
+//! - `cancel` takes `{"id": ID}` of a running job and stops it. The job's
+//!   own request then fails with code -32002, and its partial outputs are
+//!   removed.
+//! - `shutdown` answers once running jobs have finished, then exits, or over
+//!   a socket closes the connection.
+//! - `authenticate` takes `{"token": TOKEN}`. A server started with
+//!   `--token` answers every other request with code -32003 until it's
+//!   sent, and closes the connection if the token is wrong.
+//!
+//! A bare `:7878` only listens on this machine. Listening on any other
+//! address needs `--token`, since jobs can write and delete files. Jobs'
+//! roots, and the files and folders their options name, must be within
+//! `--base`, which over a socket defaults to the current folder.
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use log::{debug, info, warn};
+use serde_json::{json, Map, Value};
+use spritesheet_assembler::{
+    exit, resolve_path, Assembler, AssemblerError, CancelHandle, EncodedFrame, Report,
+};
+use std::{
+    any::Any,
+    collections::BTreeMap,
+    fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener},
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use crate::{slice, BoxResult};
+
+/// JSON-RPC's own error codes.
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// An assembly failed; `data` holds the exit code and the report.
+const ASSEMBLY_FAILED: i64 = -32000;
+/// The job to cancel isn't running.
+const CANNOT_CANCEL: i64 = -32001;
+/// The job was cancelled; `data` holds the exit code and the report.
+const CANCELLED: i64 = -32002;
+/// The client hasn't sent the server's token.
+const UNAUTHORIZED: i64 = -32003;
+/// A job panicked, which is a bug in the assembler.
+const INTERNAL_ERROR: i64 = -32603;
+
+/// Options naming files and folders, relative to the root, which have to
+/// stay within the base folder. `name` is among them since `{name}` can be
+/// part of the output's path; the paths are checked again once tokens
+/// like it are filled in.
+const PATH_OPTIONS: &[&str] = &[
+    "out",
+    "name",
+    "frames-dir",
+    "preview-gif",
+    "preview-html",
+    "preview-video",
+    "palette",
+    "markers",
+    "boxes",
+    "nine-slices",
+];
+
+/// Where responses go, shared with the jobs running in the background.
+type Output = Arc<Mutex<Box<dyn Write + Send>>>;
+
+/// Who may use the server, and where its jobs may read and write.
+#[derive(Debug, Default)]
+pub struct Access {
+    /// Clients have to present this before anything else, if set.
+    pub token: Option<String>,
+    /// Jobs may only touch files within this folder, if set. Relative
+    /// roots are found in it.
+    base: Option<PathBuf>,
+}
+
+impl Access {
+    pub fn new(token: Option<String>, base: Option<&Path>) -> BoxResult<Self> {
+        let base = base
+            .map(|base| {
+                fs::canonicalize(base)
+                    .map_err(|err| format!("Can't use {} as --base: {}", base.display(), err))
+            })
+            .transpose()?;
+        Ok(Access { token, base })
+    }
+
+    /// Whether `token` is the server's, compared in constant time.
+    pub fn authorized(&self, token: &str) -> bool {
+        match &self.token {
+            Some(expected) => {
+                expected.len() == token.len()
+                    && expected
+                        .bytes()
+                        .zip(token.bytes())
+                        .fold(0, |diff, (a, b)| diff | (a ^ b))
+                        == 0
+            }
+            None => true,
+        }
+    }
+
+    /// Refuses to listen beyond this machine without a token.
+    pub fn check_exposure(&self, addr: SocketAddr) -> BoxResult<()> {
+        if self.token.is_none() && !addr.ip().is_loopback() {
+            return Err(format!(
+                "Listening on {} lets other machines write and delete files; pass --token, \
+                 or listen on 127.0.0.1",
+                addr
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// A job's root, found in the base folder if there is one.
+    fn root(&self, root: &str) -> BoxResult<PathBuf> {
+        let base = match &self.base {
+            Some(base) => base,
+            None => return Ok(PathBuf::from(root)),
+        };
+        let joined = base.join(root);
+        let root = fs::canonicalize(&joined)
+            .map_err(|err| format!("Can't use {} as the root: {}", joined.display(), err))?;
+        self.check_within(&root)?;
+        Ok(root)
+    }
+
+    /// A path a request names, found in the base folder if there is one.
+    fn path(&self, path: &str) -> BoxResult<PathBuf> {
+        let path = match &self.base {
+            Some(base) => base.join(path),
+            None => PathBuf::from(path),
+        };
+        self.check_within(&path)?;
+        Ok(path)
+    }
+
+    /// Fails if `path` is outside the base folder.
+    fn check_within(&self, path: &Path) -> BoxResult<()> {
+        match &self.base {
+            Some(base) if !resolve_path(path).starts_with(base) => Err(format!(
+                "{} is outside the folder jobs may use, {}",
+                path.display(),
+                base.display()
+            )
+            .into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// `addr` with a bare port, like `:7878`, bound to this machine only.
+pub fn address(addr: &str) -> String {
+    if addr.starts_with(':') {
+        format!("127.0.0.1{}", addr)
+    } else {
+        addr.to_owned()
+    }
+}
+
+/// Serves requests from stdin until it closes or `shutdown` is called.
+pub fn stdio(access: Access) -> BoxResult<()> {
+    info!("Serving JSON-RPC on stdin");
+    let stdin = io::stdin();
+    session(stdin.lock(), Box::new(io::stdout()), &Arc::new(access))
+}
+
+/// Serves every connection to `addr`, such as `127.0.0.1:7878` or `:7878`,
+/// until interrupted.
+pub fn tcp(addr: &str, access: Access) -> BoxResult<()> {
+    let listener = TcpListener::bind(address(addr))?;
+    access.check_exposure(listener.local_addr()?)?;
+    info!("Serving JSON-RPC on {}", listener.local_addr()?);
+    let access = Arc::new(access);
+    for stream in listener.incoming() {
+        match stream.and_then(|stream| Ok((stream.try_clone()?, stream))) {
+            Ok((input, output)) => {
+                let peer = output
+                    .peer_addr()
+                    .map(|peer| peer.to_string())
+                    .unwrap_or_default();
+                connection(peer, input, Box::new(output), &access);
+            }
+            Err(err) => warn!("Couldn't accept a connection: {}", err),
+        }
+    }
+    Ok(())
+}
+
+/// Serves every connection to a Unix socket at `path` until interrupted.
+#[cfg(unix)]
+pub fn unix(path: &str, access: Access) -> BoxResult<()> {
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    // A server that was killed leaves its socket behind; replace it as long
+    // as nothing is listening on it anymore.
+    if fs::metadata(path).is_ok() && UnixStream::connect(path).is_err() {
+        fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    info!("Serving JSON-RPC on {}", path);
+    let access = Arc::new(access);
+    for stream in listener.incoming() {
+        match stream.and_then(|stream| Ok((stream.try_clone()?, stream))) {
+            Ok((input, output)) => connection(path.to_owned(), input, Box::new(output), &access),
+            Err(err) => warn!("Couldn't accept a connection: {}", err),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn unix(_: &str, _: Access) -> BoxResult<()> {
+    Err("Unix sockets aren't available on this platform, use --listen".into())
+}
+
+/// Serves one connection on its own thread.
+fn connection(
+    peer: String,
+    input: impl Read + Send + 'static,
+    output: Box<dyn Write + Send>,
+    access: &Arc<Access>,
+) {
+    info!("Connection from {}", peer);
+    let access = Arc::clone(access);
+    thread::spawn(move || {
+        if let Err(err) = session(BufReader::new(input), output, &access) {
+            warn!("Connection from {} failed: {}", peer, err);
+        }
+        debug!("Connection from {} closed", peer);
+    });
+}
+
+/// Answers requests read from `input` on `output`, one line each.
+pub fn session(
+    input: impl BufRead,
+    output: Box<dyn Write + Send>,
+    access: &Arc<Access>,
+) -> BoxResult<()> {
+    let output: Output = Arc::new(Mutex::new(output));
+    let mut authenticated = access.token.is_none();
+    // Request ids of running jobs, keyed by their JSON, with the root being
+    // assembled and a handle to cancel the job.
+    let running = Arc::new(Mutex::new(
+        BTreeMap::<String, (Value, String, CancelHandle)>::new(),
+    ));
+    let mut jobs = Vec::new();
+    let mut shutdown = None;
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request = match serde_json::from_str::<Value>(&line) {
+            Ok(request) => request,
+            Err(err) => {
+                send(
+                    &output,
+                    error(Value::Null, PARSE_ERROR, err.to_string(), None),
+                );
+                continue;
+            }
+        };
+        let id = request.get("id").cloned().unwrap_or(Value::Null);
+        let method = match request.get("method").and_then(Value::as_str) {
+            Some(method) => method,
+            None => {
+                let message = "Requests need a method".to_owned();
+                send(&output, error(id, INVALID_REQUEST, message, None));
+                continue;
+            }
+        };
+        let params = request.get("params").cloned().unwrap_or(Value::Null);
+        debug!("Request {}: {}", id, method);
+        if method == "authenticate" {
+            let token = params.get("token").and_then(Value::as_str);
+            if token.is_some_and(|token| access.authorized(token)) {
+                authenticated = true;
+                send(&output, result(id, Value::Bool(true)));
+                continue;
+            }
+            warn!("A client sent the wrong token");
+            let message = "Wrong token".to_owned();
+            send(&output, error(id, UNAUTHORIZED, message, None));
+            break;
+        }
+        if !authenticated {
+            let message = "Send the server's token with authenticate first".to_owned();
+            send(&output, error(id, UNAUTHORIZED, message, None));
+            continue;
+        }
+        match method {
+            "assemble" => {
+                let assembler = match assembler(&params, access) {
+                    Ok(assembler) => assembler,
+                    Err(err) => {
+                        send(&output, error(id, INVALID_PARAMS, err.to_string(), None));
+                        continue;
+                    }
+                };
+                let key = id.to_string();
+                let root = assembler.root().display().to_string();
+                let cancel = assembler.cancel_handle();
+                running
+                    .lock()
+                    .unwrap()
+                    .insert(key.clone(), (id.clone(), root, cancel));
+                let return_outputs = params
+                    .get("return_outputs")
+                    .and_then(Value::as_bool)
+                    .unwrap_or(false);
+                let output = Arc::clone(&output);
+                let running = Arc::clone(&running);
+                jobs.push(thread::spawn(move || {
+                    let response =
+                        guarded(&id, || assemble(id.clone(), &assembler, return_outputs));
+                    running.lock().unwrap().remove(&key);
+                    send(&output, response);
+                }));
+            }
+            "assemble_frames" => {
+                let job = frames(&params).and_then(|frames| {
+                    let root = access.root("")?;
+                    let assembler = options(&root, &params, access)?;
+                    Ok((assembler, frames))
+                });
+                let (assembler, frames) = match job {
+                    Ok(job) => job,
+                    Err(err) => {
+                        send(&output, error(id, INVALID_PARAMS, err.to_string(), None));
+                        continue;
+                    }
+                };
+                let key = id.to_string();
+                let root = format!("{} frames", frames.len());
+                let cancel = assembler.cancel_handle();
+                running
+                    .lock()
+                    .unwrap()
+                    .insert(key.clone(), (id.clone(), root, cancel));
+                let output = Arc::clone(&output);
+                let running = Arc::clone(&running);
+                jobs.push(thread::spawn(move || {
+                    let response =
+                        guarded(&id, || assemble_frames(id.clone(), &assembler, &frames));
+                    running.lock().unwrap().remove(&key);
+                    send(&output, response);
+                }));
+            }
+            "slice" => {
+                let (slicing, out, force) = match Slicing::parse(&params, access) {
+                    Ok(job) => job,
+                    Err(err) => {
+                        send(&output, error(id, INVALID_PARAMS, err.to_string(), None));
+                        continue;
+                    }
+                };
+                let output = Arc::clone(&output);
+                let access = Arc::clone(access);
+                jobs.push(thread::spawn(move || {
+                    let response = guarded(&id, || match slicing.run(&out, force, &access) {
+                        Ok(files) => result(id.clone(), json!({ "files": files })),
+                        Err(err) => error(id.clone(), ASSEMBLY_FAILED, err.to_string(), None),
+                    });
+                    send(&output, response);
+                }));
+            }
+            "status" => {
+                let running = running
+                    .lock()
+                    .unwrap()
+                    .values()
+                    .map(|(id, root, _)| json!({ "id": id, "root": root }))
+                    .collect::<Vec<_>>();
+                let status = json!({
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "running": running,
+                });
+                send(&output, result(id, status));
+            }
+            "cancel" => {
+                let target = params.get("id").cloned().unwrap_or(Value::Null);
+                match running.lock().unwrap().get(&target.to_string()) {
+                    Some((_, root, cancel)) => {
+                        info!("Cancelling job {} ({})", target, root);
+                        cancel.cancel();
+                        send(&output, result(id, Value::Bool(true)));
+                    }
+                    None => {
+                        let message = format!("No job {} is running", target);
+                        send(&output, error(id, CANNOT_CANCEL, message, None));
+                    }
+                }
+            }
+            "shutdown" => {
+                shutdown = Some(id);
+                break;
+            }
+            _ => {
+                let message = format!("Unknown method {}", method);
+                send(&output, error(id, METHOD_NOT_FOUND, message, None));
+            }
+        }
+        jobs.retain(|job| !job.is_finished());
+    }
+    for job in jobs {
+        let _ = job.join();
+    }
+    if let Some(id) = shutdown {
+        send(&output, result(id, Value::Null));
+    }
+    Ok(())
+}
+
+/// What a `slice` request cuts up.
+enum Slicing {
+    Grid {
+        sheet: PathBuf,
+        size: (u32, u32),
+    },
+    Metadata {
+        metadata: PathBuf,
+        sheet: Option<PathBuf>,
+    },
+}
+
+impl Slicing {
+    /// Reads a `slice` request's params, along with the folder to write to
+    /// and whether to overwrite.
+    fn parse(params: &Value, access: &Access) -> BoxResult<(Self, PathBuf, bool)> {
+        let path = |name| {
+            params
+                .get(name)
+                .and_then(Value::as_str)
+                .map(|path| access.path(path))
+                .transpose()
+        };
+        let sheet = path("sheet")?;
+        let slicing = match (path("metadata")?, params.get("frame_size")) {
+            (Some(metadata), _) => Slicing::Metadata { metadata, sheet },
+            (None, Some(size)) => Slicing::Grid {
+                sheet: sheet.ok_or("slice needs a sheet to cut by frame_size")?,
+                size: size
+                    .as_str()
+                    .and_then(slice::parse_size)
+                    .ok_or("frame_size must be a size like 64x64")?,
+            },
+            (None, None) => return Err("slice needs a frame_size or metadata".into()),
+        };
+        let out = path("out")?.ok_or("slice needs an out folder")?;
+        let force = params
+            .get("force")
+            .and_then(Value::as_bool)
+            .unwrap_or(false);
+        Ok((slicing, out, force))
+    }
+
+    fn run(&self, out: &Path, force: bool, access: &Access) -> BoxResult<Vec<PathBuf>> {
+        match self {
+            Slicing::Grid { sheet, size } => slice::grid(sheet, *size, out, force),
+            Slicing::Metadata { metadata, sheet } => {
+                slice::by_metadata(metadata, sheet.as_deref(), out, force, |sheet| {
+                    access.check_within(sheet)
+                })
+            }
+        }
+    }
+}
+
+/// Builds the assembler for an `assemble` request's params.
+pub fn assembler(params: &Value, access: &Access) -> BoxResult<Assembler> {
+    let root = params
+        .get("root")
+        .and_then(Value::as_str)
+        .ok_or("assemble needs a root")?;
+    options(&access.root(root)?, params, access)
+}
+
+/// Decodes the frames of an `assemble_frames` request's params.
+fn frames(params: &Value) -> BoxResult<Vec<EncodedFrame>> {
+    let frames = params
+        .get("frames")
+        .and_then(Value::as_array)
+        .ok_or("assemble_frames needs a list of frames")?;
+    frames
+        .iter()
+        .enumerate()
+        .map(|(index, frame)| {
+            let name = frame
+                .get("name")
+                .and_then(Value::as_str)
+                .map(str::to_owned)
+                .unwrap_or_else(|| format!("{:04}.png", index));
+            let data = frame
+                .get("data")
+                .and_then(Value::as_str)
+                .ok_or_else(|| format!("Frame {} has no data", name))?;
+            let bytes = BASE64
+                .decode(data)
+                .map_err(|err| format!("Frame {} isn't valid base64: {}", name, err))?;
+            Ok(EncodedFrame { name, bytes })
+        })
+        .collect()
+}
+
+/// Builds an assembler for `root` with the `options` of a request's params.
+fn options(root: &Path, params: &Value, access: &Access) -> BoxResult<Assembler> {
+    let mut assembler = Assembler::new(root.to_string_lossy());
+    // Outputs are checked again once their tokens are filled in, which the
+    // options alone can't be.
+    if let Some(base) = &access.base {
+        assembler = assembler.confine(base);
+    }
+    let empty = Map::new();
+    let options = match params.get("options") {
+        Some(Value::Object(options)) => options,
+        None | Some(Value::Null) => &empty,
+        Some(_) => return Err("options should be an object".into()),
+    };
+    for (name, value) in options {
+        let values = match value {
+            Value::Array(values) => values.as_slice(),
+            value => std::slice::from_ref(value),
+        };
+        for value in values {
+            let value = match value {
+                Value::Null => continue,
+                Value::Bool(flag) => if *flag { "1" } else { "0" }.to_owned(),
+                Value::String(text) => text.clone(),
+                value => value.to_string(),
+            };
+            if name == "name" && (value.contains(['/', '\\']) || value.contains("..")) {
+                return Err(format!("name can't be a path, but is {}", value).into());
+            }
+            if PATH_OPTIONS.contains(&name.as_str()) {
+                access.check_within(&root.join(&value))?;
+            }
+            assembler = assembler.option(name, &value)?;
+        }
+    }
+    Ok(assembler)
+}
+
+fn assemble(id: Value, assembler: &Assembler, return_outputs: bool) -> Value {
+    let mut report = Report::default();
+    match assembler.run_with(&mut report) {
+        Ok(()) if return_outputs => {
+            let files = report
+                .outputs
+                .iter()
+                .map(|output| {
+                    let data = fs::read(&output.path)?;
+                    Ok(json!({ "path": output.path, "data": BASE64.encode(data) }))
+                })
+                .collect::<io::Result<Vec<_>>>();
+            match files {
+                Ok(files) => {
+                    let mut report = json!(report);
+                    report["files"] = Value::Array(files);
+                    result(id, report)
+                }
+                Err(err) => error(id, ASSEMBLY_FAILED, err.to_string(), None),
+            }
+        }
+        Ok(()) => result(id, json!(report)),
+        Err(err) => {
+            warn!("{}: {}", assembler.root().display(), err);
+            let data = json!({ "exit_code": exit::code(&err), "report": report });
+            error(id, failure_code(&err), err.to_string(), Some(data))
+        }
+    }
+}
+
+fn assemble_frames(id: Value, assembler: &Assembler, frames: &[EncodedFrame]) -> Value {
+    match assembler.run_in_memory(frames) {
+        Ok(sheets) => {
+            let sheets = sheets
+                .iter()
+                .map(|sheet| {
+                    let metadata = sheet
+                        .metadata
+                        .iter()
+                        .map(|(format, bytes)| {
+                            let text = String::from_utf8_lossy(bytes).into_owned();
+                            (format.extension().to_owned(), Value::String(text))
+                        })
+                        .collect::<Map<_, _>>();
+                    json!({
+                        "name": sheet.name,
+                        "png": BASE64.encode(&sheet.png),
+                        "metadata": metadata,
+                    })
+                })
+                .collect::<Vec<_>>();
+            result(id, json!({ "sheets": sheets }))
+        }
+        Err(err) => {
+            let data = json!({ "exit_code": exit::code(&err) });
+            error(id, failure_code(&err), err.to_string(), Some(data))
+        }
+    }
+}
+
+fn failure_code(err: &AssemblerError) -> i64 {
+    match err {
+        AssemblerError::Cancelled => CANCELLED,
+        _ => ASSEMBLY_FAILED,
+    }
+}
+
+/// Runs the job answering `id`, turning a panic into an internal error so
+/// the job still answers and stops being listed as running.
+fn guarded(id: &Value, job: impl FnOnce() -> Value) -> Value {
+    panic::catch_unwind(AssertUnwindSafe(job)).unwrap_or_else(|panic| {
+        let message = panic_message(&*panic);
+        warn!("Job {} failed: {}", id, message);
+        error(id.clone(), INTERNAL_ERROR, message, None)
+    })
+}
+
+/// Describes what a panicking job panicked with.
+pub fn panic_message(panic: &(dyn Any + Send)) -> String {
+    let cause = panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("no message");
+    format!(
+        "The job panicked, which is a bug in the assembler: {}",
+        cause
+    )
+}
+
+fn result(id: Value, result: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "result": result })
+}
+
+fn error(id: Value, code: i64, message: String, data: Option<Value>) -> Value {
+    let mut error = json!({ "code": code, "message": message });
+    if let Some(data) = data {
+        error["data"] = data;
+    }
+    json!({ "jsonrpc": "2.0", "id": id, "error": error })
+}
+
+/// Writes a response on its own line. A client that has gone away can't be
+/// told anything, so write errors are only logged.
+fn send(output: &Output, response: Value) {
+    let mut output = output.lock().unwrap();
+    let written = serde_json::to_writer(&mut *output, &response)
+        .map_err(io::Error::from)
+        .and_then(|()| writeln!(output))
+        .and_then(|()| output.flush());
+    if let Err(err) = written {
+        warn!("Couldn't send a response: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn keeps_rendered_outputs_within_the_base() {
+        let base = env::temp_dir().join(format!("serve-base-{}", std::process::id()));
+        let root = base.join("hero");
+        fs::create_dir_all(root.join("temp")).unwrap();
+        image::RgbaImage::new(2, 2)
+            .save(root.join("temp/0001.png"))
+            .unwrap();
+        let access = Access::new(None, Some(&base)).unwrap();
+
+        let params = json!({ "options": { "name": "..", "out": "{name}/{name}/escape.png" } });
+        assert!(options(&root, &params, &access).is_err());
+        // Even a name that got through can't take the sheet out of the base.
+        let escaping = options(&root, &Value::Null, &access)
+            .unwrap()
+            .name("..")
+            .output("{name}/{name}/escape.png");
+        let err = escaping.run().unwrap_err();
+        assert!(matches!(err, AssemblerError::Confinement(_)), "{}", err);
+        assert!(!base.join("../escape.png").exists());
+        assert!(options(&root, &Value::Null, &access).unwrap().run().is_ok());
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn answers_for_jobs_that_panic() {
+        let id = json!(7);
+        let answer = guarded(&id, || result(json!(7), Value::Null));
+        assert_eq!(answer["result"], Value::Null);
+        let answer = guarded(&id, || panic!("index out of bounds"));
+        assert_eq!(answer["id"], id);
+        assert_eq!(answer["error"]["code"], INTERNAL_ERROR);
+        let message = answer["error"]["message"].as_str().unwrap();
+        assert!(message.ends_with(": index out of bounds"), "{}", message);
+    }
+}
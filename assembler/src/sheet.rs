@@ -0,0 +1,188 @@
+//! Sheets written by earlier runs, read back through their JSON metadata
+//! for the subcommands that combine or edit them.
+
+use image::RgbaImage;
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    error, fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::BoxResult;
+
+/// A sheet's metadata couldn't be read, or doesn't match its image.
+#[derive(Debug, Clone)]
+pub struct SheetFileError {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl fmt::Display for SheetFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.message)
+    }
+}
+
+impl error::Error for SheetFileError {}
+
+/// A sheet and its metadata. The metadata is kept as it was read, so
+/// rewriting it keeps whatever the subcommand didn't change.
+pub struct Sheet {
+    /// The JSON metadata file.
+    pub path: PathBuf,
+    pub image_path: PathBuf,
+    pub metadata: Value,
+    pub image: RgbaImage,
+    pub frames: Vec<Frame>,
+}
+
+/// The parts of a frame's entry the subcommands need.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Frame {
+    #[serde(rename = "filename")]
+    pub name: String,
+    #[serde(rename = "frame")]
+    pub rect: Rect,
+    /// How long the frame is shown, in milliseconds.
+    #[serde(default)]
+    pub duration: Option<f64>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+impl Sheet {
+    /// Reads the metadata at `path` and the sheet it names.
+    pub fn open(path: &Path) -> BoxResult<Self> {
+        Self::open_with(path, None)
+    }
+
+    /// Reads the metadata at `path` and the sheet at `image`, or the one it
+    /// names if that isn't given.
+    pub fn open_with(path: &Path, image: Option<&Path>) -> BoxResult<Self> {
+        let text = fs::read_to_string(path).map_err(|err| invalid(path, err.to_string()))?;
+        let metadata = serde_json::from_str::<Value>(&text)
+            .map_err(|err| invalid(path, format!("isn't JSON: {}", err)))?;
+        let frames = Vec::<Frame>::deserialize(&metadata["frames"])
+            .map_err(|err| invalid(path, format!("has no valid frames list: {}", err)))?;
+        let image_path = match image {
+            Some(image) => image.to_path_buf(),
+            None => {
+                let image = metadata["meta"]["image"].as_str().ok_or_else(|| {
+                    invalid(path, "doesn't name its sheet in meta.image".to_owned())
+                })?;
+                path.parent().unwrap_or_else(|| Path::new("")).join(image)
+            }
+        };
+        let image = image::open(&image_path)
+            .map_err(|err| {
+                invalid(
+                    path,
+                    format!("can't read {}: {}", image_path.display(), err),
+                )
+            })?
+            .to_rgba();
+        let sheet = Sheet {
+            path: path.to_path_buf(),
+            image_path,
+            metadata,
+            image,
+            frames,
+        };
+        if let Some(frame) = sheet
+            .frames
+            .iter()
+            .find(|frame| !sheet.contains(frame.rect))
+        {
+            return Err(sheet
+                .invalid(format!("{} lies outside the sheet", frame.name))
+                .into());
+        }
+        Ok(sheet)
+    }
+
+    /// The sheet's name, from its metadata's filename.
+    pub fn name(&self) -> String {
+        self.path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    /// The frames per second the durations were written at.
+    pub fn fps(&self) -> f64 {
+        self.metadata["meta"]["frameRate"]
+            .as_f64()
+            .filter(|fps| *fps > 0.0)
+            .unwrap_or(24.0)
+    }
+
+    pub fn contains(&self, rect: Rect) -> bool {
+        rect.x + rect.w <= self.image.width() && rect.y + rect.h <= self.image.height()
+    }
+
+    pub fn invalid(&self, message: String) -> SheetFileError {
+        invalid(&self.path, message)
+    }
+
+    /// Writes the image, then the metadata, each through a temporary file so
+    /// a failure leaves the old one in place.
+    pub fn save(&self) -> BoxResult<()> {
+        self.save_image()?;
+        let pending = temporary(&self.path);
+        let mut json = serde_json::to_vec_pretty(&self.metadata)?;
+        json.push(b'\n');
+        fs::write(&pending, json)?;
+        fs::rename(&pending, &self.path)?;
+        Ok(())
+    }
+
+    /// Writes just the image, for changes that leave the metadata as it is.
+    pub fn save_image(&self) -> BoxResult<()> {
+        let pending = temporary(&self.image_path);
+        image::save_buffer_with_format(
+            &pending,
+            &self.image,
+            self.image.width(),
+            self.image.height(),
+            image::ColorType::RGBA(8),
+            image::ImageFormat::PNG,
+        )?;
+        fs::rename(&pending, &self.image_path)?;
+        Ok(())
+    }
+}
+
+/// The JSON metadata for `path`, a sheet or its metadata, if it has any,
+/// and the image to read with it if that isn't the one the metadata names.
+pub fn metadata_for(path: &Path) -> Option<(PathBuf, Option<&Path>)> {
+    if path
+        .extension()
+        .is_some_and(|extension| extension == "json")
+    {
+        return Some((path.to_path_buf(), None));
+    }
+    Some(path.with_extension("json"))
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| (metadata, Some(path)))
+}
+
+fn invalid(path: &Path, message: String) -> SheetFileError {
+    SheetFileError {
+        path: path.to_path_buf(),
+        message,
+    }
+}
+
+/// Where a new version of `path` is written before replacing it.
+fn temporary(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    path.with_file_name(name)
+}
@@ -0,0 +1,179 @@
+//! SIMD fast paths for the per-pixel loops over RGBA bytes that dominate
+//! the time spent on large frames: the tint filter and the search for
+//! opaque pixels that trimming, empty-frame checks and opaque bounds do.
+//! AVX2 is used when the CPU has it, found at runtime so one build runs
+//! everywhere; otherwise, and off x86-64, the scalar loops give the same
+//! results.
+
+/// Multiplies every channel of the RGBA `pixels` by `tint`'s, as if each
+/// were a fraction of 255, rounding down.
+pub fn tint(pixels: &mut [u8], tint: [u8; 4]) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") {
+            // Safe, since the CPU has just been checked for AVX2.
+            unsafe { avx2::tint(pixels, tint) };
+            return;
+        }
+    }
+    scalar::tint(pixels, tint)
+}
+
+/// The first and last pixels of the RGBA `row` with any alpha, or `None`
+/// if it's fully transparent.
+pub fn opaque_span(row: &[u8]) -> Option<(usize, usize)> {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") {
+            // Safe, since the CPU has just been checked for AVX2.
+            return unsafe { avx2::opaque_span(row) };
+        }
+    }
+    scalar::opaque_span(row)
+}
+
+mod scalar {
+    pub fn tint(pixels: &mut [u8], tint: [u8; 4]) {
+        for pixel in pixels.chunks_exact_mut(4) {
+            for (channel, tint) in pixel.iter_mut().zip(&tint) {
+                *channel = (u16::from(*channel) * u16::from(*tint) / 255) as u8;
+            }
+        }
+    }
+
+    pub fn opaque_span(row: &[u8]) -> Option<(usize, usize)> {
+        let mut alphas = row.chunks_exact(4).map(|pixel| pixel[3]);
+        let first = alphas.position(|alpha| alpha != 0)?;
+        let last = row.chunks_exact(4).rposition(|pixel| pixel[3] != 0)?;
+        Some((first, last))
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod avx2 {
+    use std::arch::x86_64::*;
+
+    /// Bytes, and pixels, in a 256-bit register.
+    const BYTES: usize = 32;
+    const PIXELS: usize = BYTES / 4;
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn tint(pixels: &mut [u8], tint: [u8; 4]) {
+        let zero = _mm256_setzero_si256();
+        let one = _mm256_set1_epi16(1);
+        let tints = _mm256_set1_epi32(i32::from_le_bytes(tint));
+        // Unpacking works within each 128-bit half, so unpacking the tints
+        // the same way keeps every channel lined up with its tint.
+        let (tint_low, tint_high) = (
+            _mm256_unpacklo_epi8(tints, zero),
+            _mm256_unpackhi_epi8(tints, zero),
+        );
+        // x / 255 for x up to 255 * 255, without dividing.
+        let divide = |x: __m256i| {
+            let x = _mm256_add_epi16(_mm256_add_epi16(x, one), _mm256_srli_epi16(x, 8));
+            _mm256_srli_epi16(x, 8)
+        };
+        let mut chunks = pixels.chunks_exact_mut(BYTES);
+        for chunk in &mut chunks {
+            let bytes = _mm256_loadu_si256(chunk.as_ptr() as *const __m256i);
+            let low = _mm256_mullo_epi16(_mm256_unpacklo_epi8(bytes, zero), tint_low);
+            let high = _mm256_mullo_epi16(_mm256_unpackhi_epi8(bytes, zero), tint_high);
+            let tinted = _mm256_packus_epi16(divide(low), divide(high));
+            _mm256_storeu_si256(chunk.as_mut_ptr() as *mut __m256i, tinted);
+        }
+        super::scalar::tint(chunks.into_remainder(), tint);
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn opaque_span(row: &[u8]) -> Option<(usize, usize)> {
+        let chunks = row.len() / BYTES;
+        // A bit per pixel of the chunk at `index`, set if it has any alpha.
+        let opaque = |index: usize| {
+            let bytes = _mm256_loadu_si256(row[index * BYTES..].as_ptr() as *const __m256i);
+            let alpha = _mm256_and_si256(bytes, _mm256_set1_epi32(0xff00_0000_u32 as i32));
+            let clear = _mm256_cmpeq_epi32(alpha, _mm256_setzero_si256());
+            !_mm256_movemask_ps(_mm256_castsi256_ps(clear)) as u32 & 0xff
+        };
+        let tail = &row[chunks * BYTES..];
+        let tail_span = || {
+            super::scalar::opaque_span(tail)
+                .map(|(first, last)| (chunks * PIXELS + first, chunks * PIXELS + last))
+        };
+        let first = match (0..chunks).find(|&index| opaque(index) != 0) {
+            Some(index) => index * PIXELS + opaque(index).trailing_zeros() as usize,
+            // Nothing with any alpha before the tail, so it holds both ends.
+            None => return tail_span(),
+        };
+        let last = match tail_span() {
+            Some((_, last)) => last,
+            None => {
+                let index = (0..chunks).rev().find(|&index| opaque(index) != 0)?;
+                index * PIXELS + 31 - opaque(index).leading_zeros() as usize
+            }
+        };
+        Some((first, last))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bytes of every value in a scattered order, long enough to cover
+    /// whole registers and a tail.
+    fn bytes(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 167 + 13) as u8).collect()
+    }
+
+    #[test]
+    fn tints_like_the_scalar_loop() {
+        for tint_colour in [[255, 255, 255, 255], [0, 0, 0, 0], [255, 128, 64, 200]] {
+            for len in [0, 4, 28, 32, 36, 1028] {
+                let mut fast = bytes(len);
+                let mut slow = fast.clone();
+                tint(&mut fast, tint_colour);
+                scalar::tint(&mut slow, tint_colour);
+                assert_eq!(fast, slow, "{:?} over {} bytes", tint_colour, len);
+            }
+        }
+    }
+
+    #[test]
+    fn tints_every_product_exactly() {
+        let mut pixels = (0..=255).flat_map(|value| [value; 4]).collect::<Vec<u8>>();
+        let original = pixels.clone();
+        tint(&mut pixels, [0, 1, 254, 255]);
+        for (pixel, original) in pixels.chunks(4).zip(original.chunks(4)) {
+            let value = u16::from(original[0]);
+            assert_eq!(
+                pixel,
+                [
+                    0,
+                    (value / 255) as u8,
+                    (value * 254 / 255) as u8,
+                    value as u8
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn finds_the_opaque_span() {
+        let width = 37;
+        let transparent = vec![0; width * 4];
+        assert_eq!(opaque_span(&transparent), None);
+        assert_eq!(opaque_span(&[]), None);
+        for first in 0..width {
+            for last in first..width {
+                let mut row = vec![255; width * 4];
+                for (index, pixel) in row.chunks_mut(4).enumerate() {
+                    if index < first || index > last {
+                        pixel[3] = 0;
+                    }
+                }
+                assert_eq!(opaque_span(&row), Some((first, last)));
+                assert_eq!(scalar::opaque_span(&row), Some((first, last)));
+            }
+        }
+    }
+}
@@ -0,0 +1,477 @@
+//! `assembler slice`: cuts a sheet back into its frames, for when only the
+//! sheet survived and the renders it was made from are gone. A sheet laid
+//! out as a grid is cut by `--frame-size`; any other is cut by the frame
+//! rects in its metadata, from TexturePacker or Aseprite JSON or a libGDX or
+//! Spine `.atlas`, with trimmed frames put back on their original canvas.
+
+use image::{imageops, GenericImage, GenericImageView, RgbaImage};
+use log::info;
+use serde::Deserialize;
+use spritesheet_assembler::{errors::OutputExistsError, AssemblerError};
+use std::{
+    collections::{BTreeMap, HashSet},
+    convert::TryFrom,
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{
+    sheet::{Rect, SheetFileError},
+    BoxResult,
+};
+
+/// A size such as `64x64`.
+pub fn parse_size(text: &str) -> Option<(u32, u32)> {
+    let (width, height) = text.trim().split_once(['x', 'X'])?;
+    let size = (width.trim().parse().ok()?, height.trim().parse().ok()?);
+    Some(size).filter(|(width, height)| *width > 0 && *height > 0)
+}
+
+/// A frame's place on the sheet, and on the canvas it was cut from.
+struct Cut {
+    name: String,
+    /// Where the frame's pixels are on the sheet, as stored.
+    rect: Rect,
+    rotation: Rotation,
+    /// Where the pixels go on the canvas, from its top left corner.
+    offset: (u32, u32),
+    canvas: (u32, u32),
+}
+
+/// How a frame was turned to pack it, which cutting it out undoes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Rotation {
+    None,
+    /// TexturePacker turns frames clockwise.
+    Clockwise,
+    /// libGDX and Spine turn them anticlockwise.
+    Anticlockwise,
+}
+
+/// A sheet image and the frames on it.
+struct Page {
+    path: PathBuf,
+    cuts: Vec<Cut>,
+}
+
+/// Cuts the sheet at `path` into `size` cells, left to right and top to
+/// bottom, and writes each to `out` as `<sheet>_0000.png` and so on,
+/// returning their paths. Fully transparent cells after the last frame are
+/// left out.
+pub fn grid(path: &Path, size: (u32, u32), out: &Path, force: bool) -> BoxResult<Vec<PathBuf>> {
+    let image = open(path)?;
+    let (width, height) = image.dimensions();
+    if width % size.0 != 0 || height % size.1 != 0 {
+        return Err(invalid(
+            path,
+            format!(
+                "is {}x{}px, which isn't a whole number of {}x{}px frames",
+                width, height, size.0, size.1
+            ),
+        )
+        .into());
+    }
+    let mut cells = (0..height / size.1)
+        .flat_map(|row| {
+            (0..width / size.0).map(move |column| Rect {
+                x: column * size.0,
+                y: row * size.1,
+                w: size.0,
+                h: size.1,
+            })
+        })
+        .collect::<Vec<_>>();
+    // The last row of a grid is often only partly filled.
+    while cells.last().is_some_and(|cell| is_empty(&image, *cell)) {
+        cells.pop();
+    }
+
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let cuts = cells
+        .into_iter()
+        .enumerate()
+        .map(|(index, rect)| Cut {
+            name: format!("{}_{:04}", stem, index),
+            rect,
+            rotation: Rotation::None,
+            offset: (0, 0),
+            canvas: size,
+        })
+        .collect();
+    write(vec![(image, cuts)], out, force)
+}
+
+/// Cuts out every frame listed in the JSON or `.atlas` file at `metadata`,
+/// from the sheet at `image` if given or else the sheet it names, writing
+/// each to `out` under its own name and returning their paths. `allow`
+/// vets each sheet before it's read.
+pub fn by_metadata(
+    metadata: &Path,
+    image: Option<&Path>,
+    out: &Path,
+    force: bool,
+    allow: impl Fn(&Path) -> BoxResult<()>,
+) -> BoxResult<Vec<PathBuf>> {
+    let text = fs::read_to_string(metadata).map_err(|err| invalid(metadata, err.to_string()))?;
+    let dir = metadata.parent().unwrap_or_else(|| Path::new(""));
+    let mut pages = if metadata.extension().is_some_and(|ext| ext == "atlas") {
+        parse_atlas(metadata, dir, &text)?
+    } else {
+        vec![parse_json(metadata, dir, &text)?]
+    };
+    if let Some(image) = image {
+        match pages.as_mut_slice() {
+            [page] => page.path = image.to_path_buf(),
+            _ => {
+                return Err(invalid(
+                    metadata,
+                    "describes several pages, so the sheet can't be given".to_owned(),
+                )
+                .into())
+            }
+        }
+    }
+    // Frames with the same name would be written over each other.
+    let mut files = HashSet::new();
+    if let Some(cut) = pages
+        .iter()
+        .flat_map(|page| &page.cuts)
+        .find(|cut| !files.insert(file_name(&cut.name)))
+    {
+        return Err(invalid(
+            metadata,
+            format!("has more than one frame named {}", cut.name),
+        )
+        .into());
+    }
+
+    let mut images = Vec::new();
+    for page in pages {
+        allow(&page.path)?;
+        let image = open(&page.path)?;
+        for cut in &page.cuts {
+            let (w, h) = match cut.rotation {
+                Rotation::None => (cut.rect.w, cut.rect.h),
+                _ => (cut.rect.h, cut.rect.w),
+            };
+            let fits = |start: u32, length: u32, limit: u32| {
+                start.checked_add(length).is_some_and(|end| end <= limit)
+            };
+            if !fits(cut.rect.x, cut.rect.w, image.width())
+                || !fits(cut.rect.y, cut.rect.h, image.height())
+                || !fits(cut.offset.0, w, cut.canvas.0)
+                || !fits(cut.offset.1, h, cut.canvas.1)
+            {
+                return Err(invalid(
+                    metadata,
+                    format!("{} doesn't fit on {}", cut.name, page.path.display()),
+                )
+                .into());
+            }
+        }
+        images.push((image, page.cuts));
+    }
+    write(images, out, force)
+}
+
+/// TexturePacker's JSON, as a list of frames or a map of them by name,
+/// which Aseprite writes too.
+fn parse_json(path: &Path, dir: &Path, text: &str) -> BoxResult<Page> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Frames {
+        List(Vec<Frame>),
+        Named(BTreeMap<String, Frame>),
+    }
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Frame {
+        #[serde(default)]
+        filename: String,
+        frame: Rect,
+        #[serde(default)]
+        rotated: bool,
+        sprite_source_size: Option<Rect>,
+        source_size: Option<Size>,
+    }
+    #[derive(Deserialize)]
+    struct Size {
+        w: u32,
+        h: u32,
+    }
+    #[derive(Deserialize)]
+    struct Meta {
+        image: String,
+    }
+    #[derive(Deserialize)]
+    struct Document {
+        frames: Frames,
+        meta: Meta,
+    }
+
+    let document = serde_json::from_str::<Document>(text).map_err(|err| {
+        invalid(
+            path,
+            format!("isn't TexturePacker or Aseprite JSON: {}", err),
+        )
+    })?;
+    let frames = match document.frames {
+        Frames::List(frames) => frames,
+        Frames::Named(frames) => frames
+            .into_iter()
+            .map(|(name, frame)| Frame {
+                filename: name,
+                ..frame
+            })
+            .collect(),
+    };
+    let cuts = frames
+        .into_iter()
+        .map(|frame| {
+            // `frame` has the size before rotating.
+            let rect = if frame.rotated {
+                Rect {
+                    w: frame.frame.h,
+                    h: frame.frame.w,
+                    ..frame.frame
+                }
+            } else {
+                frame.frame
+            };
+            let trim = frame.sprite_source_size.unwrap_or(Rect {
+                x: 0,
+                y: 0,
+                ..frame.frame
+            });
+            Cut {
+                name: frame.filename,
+                rect,
+                rotation: if frame.rotated {
+                    Rotation::Clockwise
+                } else {
+                    Rotation::None
+                },
+                offset: (trim.x, trim.y),
+                canvas: frame
+                    .source_size
+                    .map_or((frame.frame.w, frame.frame.h), |size| (size.w, size.h)),
+            }
+        })
+        .collect();
+    Ok(Page {
+        path: dir.join(document.meta.image),
+        cuts,
+    })
+}
+
+/// A libGDX or Spine `.atlas`: each page's image name on a line of its own
+/// after a blank line, its settings, then its regions, each a name followed
+/// by `key: value` lines.
+fn parse_atlas(path: &Path, dir: &Path, text: &str) -> BoxResult<Vec<Page>> {
+    let mut pages = Vec::<Page>::new();
+    let mut region = None::<Region>;
+    let mut new_page = true;
+    let numbers = |value: &str| {
+        value
+            .split(',')
+            .map(|number| number.trim().parse::<i64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| invalid(path, format!("has a bad number in '{}'", value)))
+    };
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            finish(&mut pages, region.take(), path)?;
+            new_page = true;
+            continue;
+        }
+        match line.split_once(':') {
+            None if new_page => {
+                pages.push(Page {
+                    path: dir.join(line),
+                    cuts: Vec::new(),
+                });
+                new_page = false;
+            }
+            None => {
+                finish(&mut pages, region.take(), path)?;
+                region = Some(Region::new(line));
+            }
+            Some((key, value)) => {
+                // Page settings don't matter here.
+                let region = match region.as_mut() {
+                    Some(region) => region,
+                    None => continue,
+                };
+                let value = value.trim();
+                match key.trim() {
+                    "rotate" => {
+                        region.rotated = match value {
+                            "true" => true,
+                            "false" => false,
+                            degrees => numbers(degrees)?[0] % 360 != 0,
+                        }
+                    }
+                    "xy" => region.xy = numbers(value)?,
+                    "size" => region.size = numbers(value)?,
+                    "orig" => region.orig = numbers(value)?,
+                    "offset" => region.offset = numbers(value)?,
+                    "bounds" => {
+                        let bounds = numbers(value)?;
+                        region.xy = bounds.iter().take(2).copied().collect();
+                        region.size = bounds.iter().skip(2).copied().collect();
+                    }
+                    "offsets" => {
+                        let offsets = numbers(value)?;
+                        region.offset = offsets.iter().take(2).copied().collect();
+                        region.orig = offsets.iter().skip(2).copied().collect();
+                    }
+                    "index" => region.index = numbers(value)?[0],
+                    _ => {}
+                }
+            }
+        }
+    }
+    finish(&mut pages, region.take(), path)?;
+    Ok(pages)
+}
+
+/// A region of an `.atlas` as read so far.
+struct Region {
+    name: String,
+    rotated: bool,
+    xy: Vec<i64>,
+    /// The size before rotating.
+    size: Vec<i64>,
+    orig: Vec<i64>,
+    /// From the bottom left of the original canvas.
+    offset: Vec<i64>,
+    index: i64,
+}
+
+impl Region {
+    fn new(name: &str) -> Self {
+        Region {
+            name: name.to_owned(),
+            rotated: false,
+            xy: Vec::new(),
+            size: Vec::new(),
+            orig: Vec::new(),
+            offset: Vec::new(),
+            index: -1,
+        }
+    }
+}
+
+/// Adds a finished region to the last page.
+fn finish(pages: &mut [Page], region: Option<Region>, path: &Path) -> BoxResult<()> {
+    let region = match region {
+        Some(region) => region,
+        None => return Ok(()),
+    };
+    let pair = |values: &[i64]| match values {
+        [a, b, ..] => u32::try_from(*a).ok().zip(u32::try_from(*b).ok()),
+        _ => None,
+    };
+    let bad = || invalid(path, format!("has no valid position for {}", region.name));
+    let (x, y) = pair(&region.xy).ok_or_else(bad)?;
+    let (w, h) = pair(&region.size).ok_or_else(bad)?;
+    let canvas = pair(&region.orig).unwrap_or((w, h));
+    let (left, bottom) = pair(&region.offset).unwrap_or((0, 0));
+    let name = match region.index {
+        index if index >= 0 => format!("{}_{}", region.name, index),
+        _ => region.name,
+    };
+    let cut = Cut {
+        name,
+        rect: Rect {
+            x,
+            y,
+            w: if region.rotated { h } else { w },
+            h: if region.rotated { w } else { h },
+        },
+        rotation: if region.rotated {
+            Rotation::Anticlockwise
+        } else {
+            Rotation::None
+        },
+        offset: (left, canvas.1.saturating_sub(h + bottom)),
+        canvas,
+    };
+    pages
+        .last_mut()
+        .ok_or_else(|| invalid(path, "lists a region before any page".to_owned()))?
+        .cuts
+        .push(cut);
+    Ok(())
+}
+
+/// Writes each frame to `out` as `<name>.png`, checking none exist first
+/// unless `force` is set.
+fn write(pages: Vec<(RgbaImage, Vec<Cut>)>, out: &Path, force: bool) -> BoxResult<Vec<PathBuf>> {
+    let frames = pages
+        .iter()
+        .flat_map(|(image, cuts)| cuts.iter().map(move |cut| (image, cut)))
+        .map(|(image, cut)| (out.join(file_name(&cut.name)), image, cut))
+        .collect::<Vec<_>>();
+    if !force {
+        if let Some((path, ..)) = frames.iter().find(|(path, ..)| path.exists()) {
+            return Err(AssemblerError::from(OutputExistsError { path: path.clone() }).into());
+        }
+    }
+    for (path, image, cut) in &frames {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let Rect { x, y, w, h } = cut.rect;
+        let pixels = image.view(x, y, w, h).to_image();
+        let pixels = match cut.rotation {
+            Rotation::None => pixels,
+            Rotation::Clockwise => imageops::rotate270(&pixels),
+            Rotation::Anticlockwise => imageops::rotate90(&pixels),
+        };
+        let mut canvas = RgbaImage::new(cut.canvas.0, cut.canvas.1);
+        canvas.copy_from(&pixels, cut.offset.0, cut.offset.1);
+        canvas.save(path)?;
+    }
+    info!("Wrote {} frames to {}", frames.len(), out.display());
+    Ok(frames.into_iter().map(|(path, ..)| path).collect())
+}
+
+/// Where a frame named `name` is written below the output folder: its name
+/// with `.png` added unless it has it, and any `..` or root left out.
+fn file_name(name: &str) -> PathBuf {
+    let path = Path::new(name)
+        .components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .collect::<PathBuf>();
+    if path.extension().is_some_and(|ext| ext == "png") {
+        return path;
+    }
+    let mut file = path.into_os_string();
+    file.push(".png");
+    PathBuf::from(file)
+}
+
+fn open(path: &Path) -> BoxResult<RgbaImage> {
+    Ok(image::open(path)
+        .map_err(|err| invalid(path, err.to_string()))?
+        .to_rgba())
+}
+
+fn invalid(path: &Path, message: String) -> SheetFileError {
+    SheetFileError {
+        path: path.to_path_buf(),
+        message,
+    }
+}
+
+fn is_empty(image: &RgbaImage, rect: Rect) -> bool {
+    image
+        .view(rect.x, rect.y, rect.w, rect.h)
+        .pixels()
+        .all(|(_, _, pixel)| pixel[3] == 0)
+}
@@ -0,0 +1,116 @@
+//! How well the frames use the sheet, for the report: the numbers behind
+//! decisions about trimming, page sizes and output formats.
+
+use log::info;
+use std::{collections::BTreeMap, path::Path};
+
+use crate::{
+    collect::Frame,
+    debug,
+    layout::Page,
+    report::{self, AnimationFrames, Stats},
+    timing, Dims,
+};
+
+/// Measures the pages and the frames on them, before anything is written.
+pub fn measure(pages: &[Page], dims: Dims, frames: &[Frame]) -> Stats {
+    let page_pixels = pages
+        .iter()
+        .map(|page| u64::from(page.size.0) * u64::from(page.size.1))
+        .sum::<u64>();
+    let frame_pixels = frames.len() as u64 * dims.x as u64 * dims.y as u64;
+    let mut animations = Vec::<AnimationFrames>::new();
+    for frame in frames {
+        let name = timing::animation(frame).0;
+        match animations
+            .iter_mut()
+            .find(|animation| animation.name == name)
+        {
+            Some(animation) => animation.frames += 1,
+            None => animations.push(AnimationFrames {
+                name: name.to_owned(),
+                frames: 1,
+            }),
+        }
+    }
+    let stats = Stats {
+        page_pixels,
+        frame_pixels,
+        occupancy: occupancy(frame_pixels, page_pixels),
+        wasted_pixels: page_pixels - frame_pixels,
+        trimmable_pixels: trimmable(frames),
+        animations,
+        formats: BTreeMap::new(),
+    };
+    info!(
+        "Frames cover {:.1}% of the sheet, leaving {} pixels unused{}",
+        stats.occupancy,
+        stats.wasted_pixels,
+        match stats.trimmable_pixels {
+            Some(pixels) => format!(", and {} transparent pixels could be trimmed", pixels),
+            None => String::new(),
+        }
+    );
+    stats
+}
+
+/// Adds up the outputs' sizes by extension, such as `png` or `json`.
+pub fn formats(stats: &mut Stats, outputs: &[report::Output]) {
+    stats.formats.clear();
+    for output in outputs {
+        *stats.formats.entry(format_of(&output.path)).or_default() += output.bytes;
+    }
+}
+
+/// Combines the stats of sheets written by one run.
+pub fn merge(stats: &mut Option<Stats>, part: Stats) {
+    let stats = match stats {
+        Some(stats) => stats,
+        None => {
+            *stats = Some(part);
+            return;
+        }
+    };
+    stats.page_pixels += part.page_pixels;
+    stats.frame_pixels += part.frame_pixels;
+    stats.occupancy = occupancy(stats.frame_pixels, stats.page_pixels);
+    stats.wasted_pixels += part.wasted_pixels;
+    stats.trimmable_pixels = stats
+        .trimmable_pixels
+        .zip(part.trimmable_pixels)
+        .map(|(a, b)| a + b);
+    stats.animations.extend(part.animations);
+    for (format, bytes) in part.formats {
+        *stats.formats.entry(format).or_default() += bytes;
+    }
+}
+
+/// Percentage of the pages' area taken up by frames.
+fn occupancy(frame_pixels: u64, page_pixels: u64) -> f64 {
+    if page_pixels == 0 {
+        return 0.0;
+    }
+    frame_pixels as f64 / page_pixels as f64 * 100.0
+}
+
+/// The transparent pixels around each frame's opaque ones, which trimming
+/// would cut away, or `None` unless every frame has been decoded.
+fn trimmable(frames: &[Frame]) -> Option<u64> {
+    let mut pixels = 0;
+    for frame in frames {
+        let image = frame.image.as_ref()?;
+        let bounds = debug::opaque_bounds(image, 0, 0, image.width(), image.height());
+        let kept = bounds.map_or(0, |(left, top, right, bottom)| {
+            u64::from(right - left + 1) * u64::from(bottom - top + 1)
+        });
+        pixels += u64::from(image.width()) * u64::from(image.height()) - kept;
+    }
+    Some(pixels)
+}
+
+/// The file type, from its extension.
+fn format_of(path: &Path) -> String {
+    path.extension()
+        .map(|extension| extension.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default()
+}
@@ -0,0 +1,218 @@
+//! Low-memory assembly for `--low-memory`: frames are read straight from
+//! disk one row at a time and the sheet is encoded as it is composited, so
+//! peak memory stays at a row of the sheet plus a decoder per column. Rows
+//! of tiles with more than [`MAX_OPEN_FRAMES`] columns are read a group of
+//! columns at a time instead, into a buffer of the whole row of tiles.
+
+use log::{debug, info};
+use std::{
+    fs::File,
+    io::{self, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cancel::CancellableWriter,
+    collect::Frame,
+    encode,
+    errors::{
+        AssemblerError, ImageFormatError, InconsistentSizeError, InterlacedImageError, IoContext,
+        Phase,
+    },
+    layout::Page,
+    output::PendingFile,
+    progress::{Progress, ProgressWriter},
+    report::{self, HashingWriter},
+    Dims, Options, Result,
+};
+
+type Rows = png::Reader<BufReader<File>>;
+
+/// The most frames read at once, each holding a file open, so a sheet of
+/// many narrow frames doesn't run out of file descriptors.
+const MAX_OPEN_FRAMES: usize = 64;
+
+/// Reads a frame's size, then hashes the file without decoding any pixels,
+/// returning the size, the file's length and its SHA-256.
+pub fn probe(path: &Path) -> Result<((u32, u32), u64, String)> {
+    let size = {
+        let rows = open(path)?;
+        let info = rows.info();
+        (info.width, info.height)
+    };
+    let mut hasher = HashingWriter::new(io::sink());
+    io::copy(&mut File::open(path)?, &mut hasher).context(Phase::Read, path)?;
+    let (_, bytes, sha256) = hasher.finish();
+    Ok((size, bytes, sha256))
+}
+
+/// Opens a frame for reading row by row, as long as its rows can be copied
+/// into the sheet untouched.
+fn open(path: &Path) -> Result<Rows> {
+    let file = File::open(path).context(Phase::Read, path)?;
+    let mut decoder = png::Decoder::new(BufReader::new(file));
+    decoder.set_transformations(png::Transformations::IDENTITY);
+    let rows = decoder.read_info().map_err(|source| AssemblerError::Png {
+        path: Some(path.to_path_buf()),
+        source,
+    })?;
+    let info = rows.info();
+    if info.color_type != png::ColorType::Rgba || info.bit_depth != png::BitDepth::Eight {
+        return Err(ImageFormatError {
+            path: path.to_path_buf(),
+        }
+        .into());
+    }
+    if info.interlaced {
+        return Err(InterlacedImageError {
+            path: path.to_path_buf(),
+        }
+        .into());
+    }
+    Ok(rows)
+}
+
+/// Composites and encodes the sheet next to `near` in a single pass, then
+/// moves it to the path `name` picks given the SHA-256 of the encoded file.
+/// `frames` are those on `page`. `encoded` counts the bytes written.
+pub fn save_png<'a>(
+    frames: &'a [Frame],
+    page: &Page,
+    dims: Dims,
+    options: &Options,
+    near: &Path,
+    encoded: &Progress,
+    name: impl FnOnce(&str) -> Result<PathBuf>,
+) -> Result<report::Output> {
+    let checkpoint = options.checkpoint.as_deref();
+    if let Some(written) = checkpoint.and_then(|checkpoint| checkpoint.sheet(near)) {
+        info!(
+            "Keeping {} from the interrupted run",
+            written.path.display()
+        );
+        return Ok(written);
+    }
+    let tiles = page.tiles;
+    // `sheet_size` has already checked these fit.
+    let (width, height) = ((tiles.x * dims.x) as u32, (tiles.y * dims.y) as u32);
+    let progress = Progress::new("Streaming", u64::from(height));
+    let mut pending = PendingFile::create(near)?;
+    let writer = CancellableWriter::new(pending.writer(), &options.cancel);
+    let mut writer = HashingWriter::new(ProgressWriter::new(writer, encoded));
+    {
+        let mut png =
+            encode::encoder(&mut writer, (width, height), options.compression).write_header()?;
+        let mut sheet = png.stream_writer()?;
+        let frame_row_bytes = dims.x * 4;
+        let mut row = vec![0; width as usize * 4];
+        let mut grid = vec![None; tiles.x * tiles.y];
+        for (frame, cell) in frames.iter().zip(&page.cells) {
+            grid[*cell] = Some(frame);
+        }
+        for (tile_row, band) in grid.chunks(tiles.x.max(1)).enumerate() {
+            options.cancel.check()?;
+            let saved = checkpoint.and_then(|checkpoint| checkpoint.band(near, tile_row));
+            if let Some(saved) = saved.filter(|saved| saved.len() == row.len() * dims.y) {
+                debug!("Copying tile row {} from the checkpoint", tile_row);
+                sheet.write_all(&saved)?;
+                progress.inc(dims.y as u64);
+                continue;
+            }
+            debug!("Streaming tile row {}", tile_row);
+            let cells = band
+                .iter()
+                .enumerate()
+                .filter_map(|(column, frame)| Some((column, (*frame)?)))
+                .collect::<Vec<_>>();
+            let open_cells = |cells: &[(usize, &'a Frame)]| {
+                cells
+                    .iter()
+                    .map(|&(column, frame)| {
+                        let rows = open(&frame.path)?;
+                        // The frame may have been re-rendered since it was probed.
+                        let size = (rows.info().width, rows.info().height);
+                        if size != (dims.x as u32, dims.y as u32) {
+                            return Err(InconsistentSizeError {
+                                first: frames[0].path.clone(),
+                                first_size: (dims.x as u32, dims.y as u32),
+                                path: frame.path.clone(),
+                                size,
+                            }
+                            .into());
+                        }
+                        Ok((column, frame, rows))
+                    })
+                    .collect::<Result<Vec<_>>>()
+            };
+            let mut band = checkpoint
+                .map(|checkpoint| checkpoint.begin_band(near, tile_row, (width, dims.y as u32)))
+                .transpose()?;
+            let mut emit = |row: &[u8]| -> Result<()> {
+                sheet.write_all(row)?;
+                if let Some(band) = &mut band {
+                    band.write(row)?;
+                }
+                progress.inc(1);
+                Ok(())
+            };
+            // Empty cells, such as the end of a short last row of tiles,
+            // leave the sheet empty there.
+            if cells.len() <= MAX_OPEN_FRAMES {
+                let mut columns = open_cells(&cells)?;
+                row.iter_mut().for_each(|byte| *byte = 0);
+                for _ in 0..dims.y {
+                    next_row(&mut columns, &mut row, frame_row_bytes)?;
+                    emit(&row)?;
+                }
+            } else {
+                let mut rows = vec![0; row.len() * dims.y];
+                for group in cells.chunks(MAX_OPEN_FRAMES) {
+                    let mut columns = open_cells(group)?;
+                    for row in rows.chunks_mut(row.len()) {
+                        next_row(&mut columns, row, frame_row_bytes)?;
+                    }
+                }
+                for row in rows.chunks(row.len()) {
+                    emit(row)?;
+                }
+            }
+            if let (Some(band), Some(checkpoint)) = (band, checkpoint) {
+                band.finish(checkpoint)?;
+            }
+        }
+        sheet.finish()?;
+    }
+    let (_, bytes, sha256) = writer.finish();
+    let path = name(&sha256)?;
+    pending.persist(&path, options.overwrite)?;
+    progress.finish();
+    let written = report::Output {
+        path,
+        bytes,
+        sha256,
+    };
+    if let Some(checkpoint) = checkpoint {
+        checkpoint.finish_sheet(near, &written)?;
+    }
+    Ok(written)
+}
+
+/// Copies the next row of each of `columns`' frames into its cell of `row`.
+fn next_row(
+    columns: &mut [(usize, &Frame, Rows)],
+    row: &mut [u8],
+    frame_row_bytes: usize,
+) -> Result<()> {
+    for (column, frame, rows) in columns.iter_mut() {
+        let source = rows
+            .next_row()
+            .map_err(|source| AssemblerError::Png {
+                path: Some(frame.path.clone()),
+                source,
+            })?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "the frame ended early"))
+            .context(Phase::Read, &frame.path)?;
+        row[*column * frame_row_bytes..][..frame_row_bytes].copy_from_slice(source.data());
+    }
+    Ok(())
+}
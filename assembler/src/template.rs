@@ -0,0 +1,301 @@
+use crate::{collect::Frame, errors::TemplateError, timing};
+
+/// The widest a number can be padded to.
+const MAX_WIDTH: usize = 16;
+
+/// A value substituted for a `{token}`.
+pub enum Value {
+    Text(String),
+    Number(u64),
+}
+
+/// Expands `{token}` and `{token:03}` (zero-padded to three digits, up to
+/// [`MAX_WIDTH`]) using `lookup`. `{{` and `}}` produce literal braces.
+pub fn render(
+    template: &str,
+    lookup: impl Fn(&str) -> Option<Value>,
+) -> Result<String, TemplateError> {
+    let error = |token: &str| TemplateError {
+        template: template.to_owned(),
+        token: token.to_owned(),
+    };
+    let mut out = String::with_capacity(template.len());
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                out.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                out.push('}');
+            }
+            '{' => {
+                let mut token = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => token.push(c),
+                        None => return Err(error(&token)),
+                    }
+                }
+                let (key, width) = match token.find(':') {
+                    Some(i) => {
+                        let width = token[i + 1..]
+                            .parse::<usize>()
+                            .ok()
+                            .filter(|width| *width <= MAX_WIDTH)
+                            .ok_or_else(|| error(&token))?;
+                        (&token[..i], width)
+                    }
+                    None => (token.as_str(), 0),
+                };
+                match lookup(key) {
+                    Some(Value::Text(text)) => out.push_str(&text),
+                    Some(Value::Number(n)) => {
+                        out.push_str(&format!("{:0width$}", n, width = width))
+                    }
+                    None => return Err(error(&token)),
+                }
+            }
+            c => out.push(c),
+        }
+    }
+    Ok(out)
+}
+
+/// Adds a `_{page}` suffix before the extension of a template that has no
+/// `{page}` token, so the pages don't overwrite each other.
+pub fn paged(template: &str) -> String {
+    suffixed(template, "page")
+}
+
+/// Adds a `_{key}` suffix before the extension of `template`.
+pub fn suffixed(template: &str, key: &str) -> String {
+    let name_start = template.rfind(['/', '\\']).map_or(0, |i| i + 1);
+    match template[name_start..].rfind('.') {
+        Some(dot) if dot > 0 => {
+            let dot = name_start + dot;
+            format!("{}_{{{}}}{}", &template[..dot], key, &template[dot..])
+        }
+        _ => format!("{}_{{{}}}", template, key),
+    }
+}
+
+/// Whether `template` refers to `{key}`, with or without a width.
+pub fn uses(template: &str, key: &str) -> bool {
+    template.contains(&format!("{{{}}}", key)) || template.contains(&format!("{{{}:", key))
+}
+
+/// Values available to the `--out` template.
+#[derive(Clone, Copy)]
+pub struct OutputTokens<'a> {
+    pub name: &'a str,
+    /// The animation a `--split-by-animation` sheet holds, or empty.
+    pub anim: &'a str,
+    pub frames: usize,
+    pub width: u32,
+    pub height: u32,
+    pub page: usize,
+    /// Short content hash of the encoded sheet, once it's known.
+    pub hash: Option<&'a str>,
+}
+
+impl<'a> OutputTokens<'a> {
+    pub fn render(&self, template: &str) -> Result<String, TemplateError> {
+        render(template, |key| match key {
+            "name" => Some(Value::Text(self.name.to_owned())),
+            "anim" => Some(Value::Text(self.anim.to_owned())),
+            "frames" => Some(Value::Number(self.frames as u64)),
+            "w" => Some(Value::Number(u64::from(self.width))),
+            "h" => Some(Value::Number(u64::from(self.height))),
+            "page" => Some(Value::Number(self.page as u64)),
+            "hash" => Some(Value::Text(self.hash.unwrap_or("hash").to_owned())),
+            _ => None,
+        })
+    }
+
+    /// The animation, or the sheet's name if it holds every animation:
+    /// what previews and scenes of the sheet are called.
+    pub fn title(&self) -> &'a str {
+        if self.anim.is_empty() {
+            self.name
+        } else {
+            self.anim
+        }
+    }
+}
+
+/// Values available to the `--frame-name` template.
+pub struct FrameTokens<'a> {
+    /// Folder the frame was found in, relative to the frames folder.
+    anim: String,
+    index: usize,
+    stem: &'a str,
+    file: &'a str,
+}
+
+impl<'a> FrameTokens<'a> {
+    pub fn new(frame: &'a Frame, index: usize) -> Self {
+        let relative = &frame.relative;
+        FrameTokens {
+            // Nor is an animation folder's `@pingpong` direction.
+            anim: relative
+                .parent()
+                .map(|parent| {
+                    parent
+                        .iter()
+                        .map(|folder| {
+                            timing::split_direction(&folder.to_string_lossy())
+                                .0
+                                .to_owned()
+                        })
+                        .collect::<Vec<_>>()
+                        .join("/")
+                })
+                .unwrap_or_default(),
+            index,
+            // Nor are `@3` hold and `@pivot=X,Y` suffixes.
+            stem: timing::split_suffix(
+                relative
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .unwrap_or_default(),
+            )
+            .0,
+            file: relative
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Fails if `template` uses anything that isn't a frame token.
+    pub fn check(template: &str) -> Result<(), TemplateError> {
+        let tokens = FrameTokens {
+            anim: String::new(),
+            index: 0,
+            stem: "",
+            file: "",
+        };
+        tokens.render(template).map(|_| ())
+    }
+
+    /// Renders the name, dropping separators left dangling by empty tokens.
+    pub fn render(&self, template: &str) -> Result<String, TemplateError> {
+        let (base, number) = split_number(self.stem);
+        let name = render(template, |key| match key {
+            "anim" => Some(Value::Text(self.anim.clone())),
+            "index" => Some(Value::Number(self.index as u64)),
+            "number" => Some(Value::Number(number.unwrap_or(self.index as u64))),
+            "base" => Some(Value::Text(base.to_owned())),
+            "stem" => Some(Value::Text(self.stem.to_owned())),
+            "file" => Some(Value::Text(self.file.to_owned())),
+            _ => None,
+        })?;
+        Ok(name
+            .split('/')
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join("/"))
+    }
+}
+
+/// Splits a trailing frame number off a file stem, along with any separator
+/// before it: `walk_0012` becomes `walk` and 12.
+pub fn split_number(stem: &str) -> (&str, Option<u64>) {
+    let digits = stem.len() - stem.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    if digits == 0 {
+        return (stem, None);
+    }
+    let (base, number) = stem.split_at(stem.len() - digits);
+    let base = base.trim_end_matches(['_', '-', '.', ' ']);
+    (base, number.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn lookup(key: &str) -> Option<Value> {
+        match key {
+            "name" => Some(Value::Text("hero".to_owned())),
+            "frame" => Some(Value::Number(7)),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn substitutes_text_and_numbers() {
+        assert_eq!(render("{name}_{frame}.png", lookup).unwrap(), "hero_7.png");
+    }
+
+    #[test]
+    fn pads_numbers_to_a_width() {
+        assert_eq!(render("{frame:03}", lookup).unwrap(), "007");
+        assert_eq!(render("{frame:1}", lookup).unwrap(), "7");
+    }
+
+    #[test]
+    fn doubled_braces_are_literal() {
+        assert_eq!(render("{{name}}_}}", lookup).unwrap(), "{name}_}");
+    }
+
+    #[test]
+    fn rejects_unknown_tokens() {
+        let err = render("{name}_{nope}.png", lookup).unwrap_err();
+        assert_eq!(err.token, "nope");
+        assert_eq!(err.template, "{name}_{nope}.png");
+    }
+
+    #[test]
+    fn rejects_bad_widths() {
+        assert_eq!(render("{frame:x}", lookup).unwrap_err().token, "frame:x");
+        assert_eq!(render("{frame:}", lookup).unwrap_err().token, "frame:");
+        assert_eq!(render("{frame:16}", lookup).unwrap(), "0000000000000007");
+        let err = render("{frame:99999}", lookup).unwrap_err();
+        assert_eq!(err.token, "frame:99999");
+    }
+
+    #[test]
+    fn rejects_unclosed_braces() {
+        assert_eq!(render("{name", lookup).unwrap_err().token, "name");
+        assert_eq!(render("out_{", lookup).unwrap_err().token, "");
+    }
+
+    #[test]
+    fn suffixes_before_the_extension() {
+        assert_eq!(paged("out/hero.png"), "out/hero_{page}.png");
+        assert_eq!(paged("out.d/hero"), "out.d/hero_{page}");
+        assert_eq!(suffixed(".hidden", "anim"), ".hidden_{anim}");
+    }
+
+    #[test]
+    fn splits_trailing_frame_numbers() {
+        assert_eq!(split_number("walk_0012"), ("walk", Some(12)));
+        assert_eq!(split_number("idle"), ("idle", None));
+    }
+
+    #[test]
+    fn names_frames_by_their_animation() {
+        let frame = |relative: &str| Frame {
+            path: PathBuf::new(),
+            sha256: String::new(),
+            relative: PathBuf::from(relative),
+            width: 1,
+            height: 1,
+            image: None,
+            duration: 1,
+            pivot: None,
+        };
+        let name = |relative| {
+            FrameTokens::new(&frame(relative), 0)
+                .render("{anim}/{stem}")
+                .unwrap()
+        };
+        assert_eq!(name("walk@pingpong/0001@2.png"), "walk/0001");
+        assert_eq!(name("idle_0001.png"), "idle_0001");
+    }
+}
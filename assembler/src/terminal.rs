@@ -0,0 +1,278 @@
+//! `assembler preview --terminal SHEET`: shows a sheet, or plays its
+//! animation, right in the terminal with the sixel, kitty or iTerm2 image
+//! protocol, so a sheet on a render farm node can be checked over SSH
+//! without copying it anywhere first.
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use image::{imageops, FilterType, GenericImageView, Rgba, RgbaImage};
+use std::{
+    collections::BTreeMap,
+    env,
+    io::{self, Write},
+    path::Path,
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    sheet::{self, Sheet},
+    BoxResult,
+};
+
+/// What transparent pixels are drawn over while playing, so each frame
+/// covers the last.
+const BACKGROUND: Rgba<u8> = Rgba([32, 32, 32, 255]);
+
+/// How the image gets to the terminal.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Protocol {
+    Sixel,
+    Kitty,
+    ITerm,
+}
+
+impl Protocol {
+    pub const NAMES: &'static [&'static str] = &["auto", "sixel", "kitty", "iterm"];
+
+    /// The protocol named, or for `auto` the one the terminal is likely to
+    /// understand, going by the variables it sets and SSH passes on.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "sixel" => Some(Protocol::Sixel),
+            "kitty" => Some(Protocol::Kitty),
+            "iterm" => Some(Protocol::ITerm),
+            "auto" => Some(Protocol::detect()),
+            _ => None,
+        }
+    }
+
+    fn detect() -> Self {
+        let var = |name| env::var(name).unwrap_or_default();
+        if var("TERM") == "xterm-kitty" || !var("KITTY_WINDOW_ID").is_empty() {
+            Protocol::Kitty
+        } else if var("LC_TERMINAL") == "iTerm2"
+            || matches!(var("TERM_PROGRAM").as_str(), "iTerm.app" | "WezTerm")
+        {
+            Protocol::ITerm
+        } else {
+            Protocol::Sixel
+        }
+    }
+}
+
+/// What `preview` shows and how.
+pub struct Preview<'a> {
+    pub protocol: Protocol,
+    /// Play the animation rather than show the whole sheet.
+    pub play: bool,
+    /// Only play this animation.
+    pub tag: Option<&'a str>,
+    /// How many times to play it, 0 for until interrupted.
+    pub loops: u32,
+    /// The longest side the image is shown at, in pixels.
+    pub size: u32,
+}
+
+/// Shows the sheet at `path`, a PNG or its JSON metadata, in the terminal.
+pub fn run(path: &Path, preview: &Preview) -> BoxResult<()> {
+    let out = io::stdout();
+    let mut out = out.lock();
+    if !preview.play {
+        let image = match sheet::metadata_for(path) {
+            Some((metadata, image)) => Sheet::open_with(&metadata, image)?.image,
+            None => image::open(path)
+                .map_err(|err| sheet::SheetFileError {
+                    path: path.to_path_buf(),
+                    message: err.to_string(),
+                })?
+                .to_rgba(),
+        };
+        let scale = scale(image.dimensions(), preview.size);
+        draw(&mut out, preview.protocol, &resize(&image, scale))?;
+        return Ok(());
+    }
+
+    let (metadata, image) = sheet::metadata_for(path).ok_or_else(|| sheet::SheetFileError {
+        path: path.to_path_buf(),
+        message: "has no JSON metadata next to it to play the frames from".to_owned(),
+    })?;
+    let sheet = Sheet::open_with(&metadata, image)?;
+    let frames = match preview.tag {
+        Some(tag) => {
+            let tag = sheet.metadata["meta"]["frameTags"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .find(|found| found["name"] == tag)
+                .ok_or_else(|| sheet.invalid(format!("has no animation '{}'", tag)))?;
+            let index = |key| tag[key].as_u64().map(|index| index as usize);
+            match (index("from"), index("to")) {
+                (Some(from), Some(to)) if from <= to && to < sheet.frames.len() => {
+                    &sheet.frames[from..=to]
+                }
+                _ => return Err(sheet.invalid(format!("has a broken tag {}", tag)).into()),
+            }
+        }
+        None => &sheet.frames[..],
+    };
+    let largest = frames.iter().fold((1, 1), |largest, frame| {
+        (largest.0.max(frame.rect.w), largest.1.max(frame.rect.h))
+    });
+    let scale = scale(largest, preview.size);
+    let fps = sheet.fps();
+    // Each frame is drawn over the last, from the top left of the screen.
+    write!(out, "\x1b[2J")?;
+    let mut played = 0;
+    while preview.loops == 0 || played < preview.loops {
+        for frame in frames {
+            let rect = frame.rect;
+            let mut cell = RgbaImage::from_pixel(rect.w, rect.h, BACKGROUND);
+            imageops::overlay(
+                &mut cell,
+                &sheet.image.view(rect.x, rect.y, rect.w, rect.h).to_image(),
+                0,
+                0,
+            );
+            if preview.protocol == Protocol::Kitty {
+                // Otherwise every frame stays on screen under the next.
+                write!(out, "\x1b_Ga=d,q=2\x1b\\")?;
+            }
+            write!(out, "\x1b[H")?;
+            draw(&mut out, preview.protocol, &resize(&cell, scale))?;
+            let millis = frame.duration.unwrap_or(1000.0 / fps);
+            thread::sleep(Duration::from_secs_f64(millis.max(1.0) / 1000.0));
+        }
+        played += 1;
+    }
+    Ok(())
+}
+
+/// How much to scale an image so its longest side is at most
+/// `fit`: by a whole number when enlarging, so pixel art stays sharp.
+fn scale((width, height): (u32, u32), fit: u32) -> f64 {
+    let longest = width.max(height).max(1);
+    if longest > fit {
+        f64::from(fit) / f64::from(longest)
+    } else {
+        f64::from((fit / longest).max(1))
+    }
+}
+
+fn resize(image: &RgbaImage, scale: f64) -> RgbaImage {
+    if scale == 1.0 {
+        return image.clone();
+    }
+    let (width, height) = image.dimensions();
+    let scaled = |side: u32| ((f64::from(side) * scale).round() as u32).max(1);
+    let filter = if scale > 1.0 {
+        FilterType::Nearest
+    } else {
+        FilterType::Triangle
+    };
+    imageops::resize(image, scaled(width), scaled(height), filter)
+}
+
+/// Writes `image` to the terminal at the cursor, then moves onto the next
+/// line.
+fn draw(out: &mut dyn Write, protocol: Protocol, image: &RgbaImage) -> BoxResult<()> {
+    match protocol {
+        Protocol::Sixel => sixel(out, image)?,
+        Protocol::Kitty => {
+            let png = BASE64.encode(png(image)?);
+            let chunks = png.as_bytes().chunks(4096).collect::<Vec<_>>();
+            for (index, chunk) in chunks.iter().enumerate() {
+                let more = u8::from(index + 1 < chunks.len());
+                match index {
+                    0 => write!(out, "\x1b_Ga=T,f=100,q=2,m={};", more)?,
+                    _ => write!(out, "\x1b_Gm={};", more)?,
+                }
+                out.write_all(chunk)?;
+                write!(out, "\x1b\\")?;
+            }
+        }
+        Protocol::ITerm => {
+            let png = png(image)?;
+            write!(
+                out,
+                "\x1b]1337;File=inline=1;size={};width={}px;height={}px:{}\x07",
+                png.len(),
+                image.width(),
+                image.height(),
+                BASE64.encode(&png)
+            )?;
+        }
+    }
+    writeln!(out)?;
+    out.flush()?;
+    Ok(())
+}
+
+fn png(image: &RgbaImage) -> BoxResult<Vec<u8>> {
+    let mut png = Vec::new();
+    image::png::PNGEncoder::new(&mut png).encode(
+        image,
+        image.width(),
+        image.height(),
+        image::ColorType::RGBA(8),
+    )?;
+    Ok(png)
+}
+
+/// Writes `image` as sixels, in the colours of a 6x6x6 cube, leaving
+/// pixels less than half opaque untouched.
+fn sixel(out: &mut dyn Write, image: &RgbaImage) -> io::Result<()> {
+    let (width, height) = image.dimensions();
+    let level = |channel: u8| (u32::from(channel) * 5 + 127) / 255;
+    let colour = |pixel: &Rgba<u8>| {
+        (pixel[3] >= 128).then(|| level(pixel[0]) * 36 + level(pixel[1]) * 6 + level(pixel[2]))
+    };
+    write!(out, "\x1bP0;1;0q\"1;1;{};{}", width, height)?;
+    for index in 0..216 {
+        let percent = |level: u32| level * 100 / 5;
+        let (r, g, b) = (index / 36, index / 6 % 6, index % 6);
+        write!(
+            out,
+            "#{};2;{};{};{}",
+            index,
+            percent(r),
+            percent(g),
+            percent(b)
+        )?;
+    }
+    for top in (0..height).step_by(6) {
+        // Each colour's column of six pixels, as bits from the top.
+        let mut band = BTreeMap::<u32, Vec<u8>>::new();
+        for y in top..(top + 6).min(height) {
+            for x in 0..width {
+                if let Some(colour) = colour(image.get_pixel(x, y)) {
+                    let columns = band
+                        .entry(colour)
+                        .or_insert_with(|| vec![0; width as usize]);
+                    columns[x as usize] |= 1 << (y - top);
+                }
+            }
+        }
+        for (index, (colour, columns)) in band.iter().enumerate() {
+            if index > 0 {
+                // Back to the start of the band for the next colour.
+                out.write_all(b"$")?;
+            }
+            write!(out, "#{}", colour)?;
+            let mut x = 0;
+            while x < columns.len() {
+                let run = columns[x..]
+                    .iter()
+                    .take_while(|bits| **bits == columns[x])
+                    .count();
+                let sixel = char::from(63 + columns[x]);
+                match run {
+                    1..=3 => (0..run).try_for_each(|_| write!(out, "{}", sixel))?,
+                    _ => write!(out, "!{}{}", run, sixel)?,
+                }
+                x += run;
+            }
+        }
+        out.write_all(b"-")?;
+    }
+    out.write_all(b"\x1b\\")
+}
@@ -0,0 +1,305 @@
+//! How long each frame is shown, for the held frames Blender animations
+//! often use. Durations are in ticks, one tick being one frame of the
+//! animation, and come from a `durations.json` in the frames folder or a
+//! `@N` suffix on the filename, such as `walk_0005@3.png`. An animation
+//! plays forwards unless its folder says otherwise, like `walk@pingpong`.
+//! A `--sequence` plays the stored frames in any order, repeating some,
+//! without storing them twice, and `--markers` turns Blender's timeline
+//! markers into events on the frames they mark.
+
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, io, path::Path};
+
+use crate::{
+    collect::Frame,
+    errors::{DurationsError, MarkersError, SequenceError},
+    pivot, template, Result,
+};
+
+/// Playback speed when none is given: Blender's default.
+pub const DEFAULT_FPS: u32 = 24;
+
+/// The sidecar file, kept in the frames folder and never used as a frame.
+pub const SIDECAR: &str = "durations.json";
+
+/// Frame durations from the sidecar, by path relative to the frames folder.
+pub type Durations = HashMap<String, u32>;
+
+/// Reads the sidecar in `frames_dir`, if there is one.
+pub fn load(frames_dir: &Path) -> Result<Option<Durations>> {
+    let path = frames_dir.join(SIDECAR);
+    let invalid = |message: String| DurationsError {
+        path: path.clone(),
+        message,
+    };
+    let bytes = match fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(invalid(err.to_string()).into()),
+    };
+    let durations = serde_json::from_slice::<Durations>(&bytes)
+        .map_err(|err| invalid(format!("should map frame paths to tick counts: {}", err)))?;
+    if let Some((frame, _)) = durations.iter().find(|(_, ticks)| **ticks == 0) {
+        return Err(invalid(format!("{} can't be held for 0 ticks", frame)).into());
+    }
+    Ok(Some(durations))
+}
+
+/// Sets every frame's duration from the sidecar, then from its filename,
+/// defaulting to a single tick.
+pub fn apply(frames: &mut [Frame], durations: Option<&Durations>) {
+    for frame in frames {
+        let relative = frame.relative.to_string_lossy().replace('\\', "/");
+        frame.duration = durations
+            .and_then(|durations| durations.get(&relative).copied())
+            .or_else(|| {
+                let stem = frame.relative.file_stem()?.to_str()?;
+                split_suffix(stem).1
+            })
+            .unwrap_or(1);
+    }
+}
+
+/// Splits a `@N` duration suffix off a file stem: `walk_0005@3` becomes
+/// `walk_0005` and 3. A `@pivot=X,Y` after it is dropped too.
+pub fn split_suffix(stem: &str) -> (&str, Option<u32>) {
+    let stem = pivot::split_suffix(stem).0;
+    match stem.rfind('@') {
+        Some(at) => match stem[at + 1..].parse::<u32>() {
+            Ok(ticks) if ticks > 0 => (&stem[..at], Some(ticks)),
+            _ => (stem, None),
+        },
+        None => (stem, None),
+    }
+}
+
+/// Which way an animation plays, so mirrored sequences needn't be rendered
+/// twice.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Forward,
+    Reverse,
+    /// Forwards, then backwards, and repeat.
+    PingPong,
+}
+
+impl Direction {
+    pub const NAMES: &'static [&'static str] = &["forward", "reverse", "pingpong"];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "forward" => Some(Direction::Forward),
+            "reverse" => Some(Direction::Reverse),
+            "pingpong" => Some(Direction::PingPong),
+            _ => None,
+        }
+    }
+
+    /// The name Aseprite uses.
+    pub fn name(self) -> &'static str {
+        Direction::NAMES[self as usize]
+    }
+}
+
+/// Splits a `@reverse` or `@pingpong` suffix off an animation folder's name:
+/// `walk@pingpong` becomes `walk` and [`Direction::PingPong`].
+pub fn split_direction(folder: &str) -> (&str, Option<Direction>) {
+    match folder.rfind('@') {
+        Some(at) => match Direction::parse(&folder[at + 1..]) {
+            Some(direction) => (&folder[..at], Some(direction)),
+            None => (folder, None),
+        },
+        None => (folder, None),
+    }
+}
+
+/// The order frames play in, as given to `--sequence`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Sequence {
+    pub spec: String,
+    /// Indices into the sheet's frames.
+    pub frames: Vec<usize>,
+}
+
+impl Sequence {
+    /// Fails if the sequence plays a frame past the last of `count`.
+    pub fn check(&self, count: usize) -> Result<()> {
+        match self.frames.iter().find(|index| **index >= count) {
+            Some(index) => Err(SequenceError {
+                spec: self.spec.clone(),
+                message: format!("there's no frame {}, the last is {}", index, count - 1),
+            }
+            .into()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The most frames a sequence may play, far more than any animation needs
+/// and few enough to expand without running out of memory.
+pub const MAX_SEQUENCE: usize = 1 << 16;
+
+/// Expands a sequence such as `0-9, 4x3, 9-0` into the indices of the frames
+/// to play, in order. Ranges may run backwards, and `x` repeats a frame or a
+/// range. Fails rather than play more than [`MAX_SEQUENCE`] frames.
+pub fn parse_sequence(spec: &str) -> Result<Sequence> {
+    let invalid = |message: String| SequenceError {
+        spec: spec.to_owned(),
+        message,
+    };
+    let index = |text: &str| {
+        text.trim()
+            .parse::<usize>()
+            .map_err(|_| invalid(format!("'{}' isn't a frame index", text.trim())))
+    };
+    let mut sequence = Vec::new();
+    for step in spec
+        .split(',')
+        .map(str::trim)
+        .filter(|step| !step.is_empty())
+    {
+        let (frames, times) = match step.split_once('x') {
+            Some((frames, times)) => (frames, index(times)?),
+            None => (step, 1),
+        };
+        let (from, to) = match frames.split_once('-') {
+            Some((from, to)) => (index(from)?, index(to)?),
+            None => (index(frames)?, index(frames)?),
+        };
+        // Counted before expanding, so a huge range or repeat fails at once.
+        let length = from.abs_diff(to).saturating_add(1);
+        let total = length.saturating_mul(times).saturating_add(sequence.len());
+        if length.max(total) > MAX_SEQUENCE {
+            return Err(invalid(format!("it would play more than {} frames", MAX_SEQUENCE)).into());
+        }
+        let frames = if from <= to {
+            (from..=to).collect::<Vec<_>>()
+        } else {
+            (to..=from).rev().collect()
+        };
+        for _ in 0..times {
+            sequence.extend(&frames);
+        }
+    }
+    if sequence.is_empty() {
+        return Err(invalid("it doesn't list any frames".to_owned()).into());
+    }
+    Ok(Sequence {
+        spec: spec.to_owned(),
+        frames: sequence,
+    })
+}
+
+/// A Blender timeline marker, such as a footstep or a hit, to be attached
+/// to the frame rendered at `frame`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Marker {
+    pub name: String,
+    /// The Blender frame number, matched against the number at the end of
+    /// each frame's filename.
+    pub frame: u64,
+    /// Only marks frames of this animation: their folder, or the filename
+    /// before the number. Markers without one mark every animation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub animation: Option<String>,
+}
+
+impl Marker {
+    /// Whether the marker falls on the frame numbered `number` of
+    /// `animation`.
+    pub fn marks(&self, animation: &str, number: Option<u64>) -> bool {
+        number == Some(self.frame)
+            && self
+                .animation
+                .as_deref()
+                .is_none_or(|only| only == animation)
+    }
+}
+
+/// The animation `frame` belongs to, by its folder or else the filename
+/// before the frame number, and that number.
+pub fn animation(frame: &Frame) -> (&str, Option<u64>) {
+    animation_at(&frame.relative)
+}
+
+/// Like [`animation`], for a frame at `relative` to the frames folder that
+/// hasn't been read yet.
+pub fn animation_at(relative: &Path) -> (&str, Option<u64>) {
+    let stem = relative
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or_default();
+    let (base, number) = template::split_number(split_suffix(stem).0);
+    let mut components = relative.components();
+    let folder = match (components.next(), components.next()) {
+        (Some(first), Some(_)) => first.as_os_str().to_str(),
+        _ => None,
+    };
+    let animation = folder.map_or(base, |folder| split_direction(folder).0);
+    (animation, number)
+}
+
+/// Playback speed of `animation`, given `fps` for every animation and
+/// overrides for some.
+pub fn fps(fps: u32, overrides: &[(String, u32)], animation: &str) -> u32 {
+    overrides
+        .iter()
+        .find(|(name, _)| name == animation)
+        .map_or(fps, |(_, fps)| *fps)
+}
+
+/// Names of the markers that fall on `frame`, in the order they were given.
+pub fn events(markers: &[Marker], frame: &Frame) -> Vec<String> {
+    if markers.is_empty() {
+        return Vec::new();
+    }
+    let (animation, number) = animation(frame);
+    markers
+        .iter()
+        .filter(|marker| marker.marks(animation, number))
+        .map(|marker| marker.name.clone())
+        .collect()
+}
+
+/// Reads a JSON list of markers like `[{"name": "step", "frame": 12}]`.
+pub fn load_markers(path: &Path) -> Result<Vec<Marker>> {
+    let invalid = |message: String| MarkersError {
+        path: path.to_path_buf(),
+        message,
+    };
+    let bytes = fs::read(path).map_err(|err| invalid(err.to_string()))?;
+    Ok(serde_json::from_slice(&bytes).map_err(|err| {
+        invalid(format!(
+            "should list markers with a name and a frame: {}",
+            err
+        ))
+    })?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_ranges_and_repeats() {
+        let sequence = parse_sequence("0-2, 4x3, 2-0x2").unwrap();
+        assert_eq!(sequence.frames, [0, 1, 2, 4, 4, 4, 2, 1, 0, 2, 1, 0]);
+    }
+
+    #[test]
+    fn refuses_to_expand_huge_sequences() {
+        for spec in [
+            "0-99999999999",
+            "3x4294967295",
+            "99999999999-0x0",
+            "0-65535, 0",
+        ] {
+            assert!(parse_sequence(spec).is_err(), "{}", spec);
+        }
+        assert_eq!(
+            parse_sequence("0-65535").unwrap().frames.len(),
+            MAX_SEQUENCE
+        );
+    }
+}
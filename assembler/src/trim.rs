@@ -0,0 +1,185 @@
+//! `--trim union`: cells shrunk to the smallest box holding every frame's
+//! visible pixels, so renders framed with plenty of empty space around the
+//! character pack tighter. Each frame is cut down to the same box, keeping
+//! the uniform grid that grid-based engines slice the sheet by, and the
+//! character stays in the same place from frame to frame. The metadata
+//! gives the box within the original canvas, so frames can be put back.
+
+use image::{GenericImageView, RgbaImage};
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::{collect::Frame, debug, pad::Anchor};
+
+/// How `--trim` cuts the frames down.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Trim {
+    /// To the box around every frame's visible pixels at once.
+    Union,
+}
+
+impl Trim {
+    pub const NAMES: &'static [&'static str] = &["union"];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "union" => Some(Trim::Union),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Trim::Union => "union",
+        }
+    }
+}
+
+/// Where the trimmed frames were on their original canvas.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Trimmed {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    /// The original canvas.
+    pub source: (u32, u32),
+}
+
+impl Trimmed {
+    /// The part of a frame on the original canvas that's kept.
+    pub fn crop(self, image: &RgbaImage) -> RgbaImage {
+        image
+            .view(self.x, self.y, self.width, self.height)
+            .to_image()
+    }
+}
+
+/// Cuts every frame down to the box around all their visible pixels,
+/// moving their pivots with them. Frames without a pivot get `pivot`'s on
+/// the original canvas, so it stays put as the box moves. Returns the box,
+/// or `None` if there's nothing to cut away. The frames must all be
+/// decoded, and the same size.
+pub fn union(frames: &mut [Frame], pivot: Option<Anchor>) -> Option<Trimmed> {
+    let source = frames.first().map(|frame| (frame.width, frame.height))?;
+    let bounds = frames
+        .iter()
+        .filter_map(|frame| frame.image.as_ref())
+        .filter_map(|image| {
+            let (width, height) = image.dimensions();
+            debug::opaque_bounds(image, 0, 0, width, height)
+        })
+        .reduce(|a, b| (a.0.min(b.0), a.1.min(b.1), a.2.max(b.2), a.3.max(b.3)));
+    let (left, top, right, bottom) = match bounds {
+        Some(bounds) => bounds,
+        None => {
+            warn!("Not trimming the frames, since they're all fully transparent");
+            return None;
+        }
+    };
+    let trimmed = Trimmed {
+        x: left,
+        y: top,
+        width: right + 1 - left,
+        height: bottom + 1 - top,
+        source,
+    };
+    if (trimmed.width, trimmed.height) == source {
+        return None;
+    }
+    info!(
+        "Trimming frames from {}x{}px to {}x{}px",
+        source.0, source.1, trimmed.width, trimmed.height
+    );
+    // Once any frame has a pivot, they all need one.
+    let pivot = pivot.or_else(|| {
+        frames
+            .iter()
+            .any(|frame| frame.pivot.is_some())
+            .then_some(Anchor::Center)
+    });
+    for frame in frames {
+        frame.image = frame.image.as_ref().map(|image| trimmed.crop(image));
+        frame.width = trimmed.width;
+        frame.height = trimmed.height;
+        let (x, y) = match (frame.pivot, pivot) {
+            (Some(pivot), _) => pivot,
+            (None, Some(anchor)) => {
+                let (x, y) = anchor.fraction();
+                (x * f64::from(source.0), y * f64::from(source.1))
+            }
+            (None, None) => continue,
+        };
+        frame.pivot = Some((x - f64::from(trimmed.x), y - f64::from(trimmed.y)));
+    }
+    Some(trimmed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+    use std::path::PathBuf;
+
+    /// An 8x8 frame, transparent but for `pixels`.
+    fn frame(pixels: &[(u32, u32)], pivot: Option<(f64, f64)>) -> Frame {
+        let mut image = RgbaImage::new(8, 8);
+        for (x, y) in pixels {
+            image.put_pixel(*x, *y, Rgba([255, 0, 0, 255]));
+        }
+        Frame {
+            path: PathBuf::new(),
+            sha256: String::new(),
+            relative: PathBuf::new(),
+            width: 8,
+            height: 8,
+            image: Some(image),
+            duration: 1,
+            pivot,
+        }
+    }
+
+    #[test]
+    fn cuts_every_frame_to_the_box_around_all_of_them() {
+        let mut frames = vec![frame(&[(2, 3)], None), frame(&[(5, 4)], None)];
+        let trimmed = union(&mut frames, None).unwrap();
+        assert_eq!(
+            trimmed,
+            Trimmed {
+                x: 2,
+                y: 3,
+                width: 4,
+                height: 2,
+                source: (8, 8),
+            }
+        );
+        for frame in &frames {
+            assert_eq!((frame.width, frame.height), (4, 2));
+            assert_eq!(frame.image.as_ref().unwrap().dimensions(), (4, 2));
+            assert!(frame.pivot.is_none());
+        }
+        assert_eq!(frames[0].image.as_ref().unwrap()[(0, 0)][3], 255);
+        assert_eq!(frames[1].image.as_ref().unwrap()[(3, 1)][3], 255);
+    }
+
+    #[test]
+    fn moves_pivots_with_the_box() {
+        let mut frames = vec![frame(&[(2, 3)], Some((3.0, 6.0))), frame(&[(5, 4)], None)];
+        union(&mut frames, None).unwrap();
+        assert_eq!(frames[0].pivot, Some((1.0, 3.0)));
+        // The centre of the original canvas, since another frame has a pivot.
+        assert_eq!(frames[1].pivot, Some((2.0, 1.0)));
+    }
+
+    #[test]
+    fn leaves_frames_alone_with_nothing_to_cut() {
+        let mut frames = vec![frame(&[(0, 0), (7, 7)], None)];
+        assert!(union(&mut frames, None).is_none());
+        assert_eq!(frames[0].width, 8);
+
+        let mut frames = vec![frame(&[], None), frame(&[], None)];
+        assert!(union(&mut frames, None).is_none());
+        assert!(union(&mut [], None).is_none());
+    }
+}
@@ -0,0 +1,90 @@
+//! `assembler update SHEET.json FRAME.png...`: redraws frames of a sheet
+//! from an earlier run in place, so fixing one bad render doesn't mean
+//! reassembling and reimporting the whole atlas. Only the image is
+//! rewritten; the metadata doesn't change.
+
+use image::GenericImage;
+use log::info;
+use spritesheet_assembler::AssemblerError;
+use std::path::{Path, PathBuf};
+
+use crate::{sheet::Sheet, BoxResult};
+
+/// Replaces the frames named in `frames` on the sheet described by the JSON
+/// at `metadata`. Each is `NAME=PATH`, or just a path for the frame whose
+/// name it ends with, so `renders/walk/0001.png` replaces `walk/0001`.
+pub fn run(metadata: &Path, frames: &[String]) -> BoxResult<()> {
+    let mut sheet = Sheet::open(metadata)?;
+    let mut replaced = Vec::with_capacity(frames.len());
+    for frame in frames {
+        let (name, path) = match frame.split_once('=') {
+            Some((name, path)) => (name.to_owned(), PathBuf::from(path)),
+            None => {
+                let path = PathBuf::from(frame);
+                (named_by(&sheet, &path), path)
+            }
+        };
+        let mut named = sheet.frames.iter().filter(|frame| frame.name == name);
+        let rect = match (named.next(), named.next()) {
+            (Some(frame), None) => frame.rect,
+            (None, _) => return Err(sheet.invalid(format!("has no frame named {}", name)).into()),
+            (Some(_), Some(_)) => {
+                return Err(sheet
+                    .invalid(format!(
+                        "has more than one frame named {}, so which to replace isn't clear",
+                        name
+                    ))
+                    .into())
+            }
+        };
+        let image = image::open(&path)
+            .map_err(|source| AssemblerError::Image {
+                path: Some(path.clone()),
+                source,
+            })?
+            .to_rgba();
+        if image.dimensions() != (rect.w, rect.h) {
+            return Err(sheet
+                .invalid(format!(
+                    "{} is {}x{}px, but {} is {}x{}px",
+                    name,
+                    rect.w,
+                    rect.h,
+                    path.display(),
+                    image.width(),
+                    image.height()
+                ))
+                .into());
+        }
+        replaced.push((name, rect, image));
+    }
+    // Check every frame before changing any.
+    for (name, rect, image) in &replaced {
+        sheet.image.copy_from(image, rect.x, rect.y);
+        info!("Replaced {}", name);
+    }
+    sheet.save_image()
+}
+
+/// The name of the frame on `sheet` that the file at `path` is, the
+/// longest that the path without its extension ends with, or else the
+/// file's stem.
+fn named_by(sheet: &Sheet, path: &Path) -> String {
+    let stem = path.with_extension("");
+    let stem = stem.to_string_lossy().replace('\\', "/");
+    sheet
+        .frames
+        .iter()
+        .map(|frame| &frame.name)
+        .filter(|name| {
+            stem.strip_suffix(name.as_str())
+                .is_some_and(|before| before.is_empty() || before.ends_with('/'))
+        })
+        .max_by_key(|name| name.len())
+        .cloned()
+        .unwrap_or_else(|| {
+            path.file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        })
+}
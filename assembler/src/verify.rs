@@ -0,0 +1,184 @@
+//! `assembler verify --metadata SHEET.json`: checks that every frame of a
+//! sheet still matches the render it came from, so a stale sheet that has
+//! drifted from the renders is caught before it ships.
+
+use image::RgbaImage;
+use log::{error, info, warn};
+use std::{
+    collections::HashMap,
+    error, fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::{sheet::Sheet, BoxResult};
+
+/// Some frames of the sheet differ from their sources.
+#[derive(Debug, Clone)]
+pub struct VerifyError {
+    pub sheet: PathBuf,
+    pub failed: usize,
+    pub checked: usize,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} of {} frames don't match their sources",
+            self.sheet.display(),
+            self.failed,
+            self.checked
+        )
+    }
+}
+
+impl error::Error for VerifyError {}
+
+/// Compares each frame of the sheet described by the JSON at `metadata`,
+/// or of the sheet at `image` if given, with the frame of the same name in
+/// `frames`. Channels may differ by up to `tolerance`.
+pub fn run(frames: &Path, metadata: &Path, image: Option<&Path>, tolerance: u8) -> BoxResult<()> {
+    let sheet = Sheet::open_with(metadata, image)?;
+    let sources = sources(frames);
+    let mut checked = 0;
+    let mut failed = 0;
+    for frame in &sheet.frames {
+        let path = match sources.get(&frame.name) {
+            Some(path) => path,
+            None => {
+                warn!(
+                    "No frame named {} in {}, not checking it",
+                    frame.name,
+                    frames.display()
+                );
+                continue;
+            }
+        };
+        checked += 1;
+        let source = match image::open(path) {
+            Ok(source) => source.to_rgba(),
+            Err(err) => {
+                error!("{}: can't read {}: {}", frame.name, path.display(), err);
+                failed += 1;
+                continue;
+            }
+        };
+        let rect = frame.rect;
+        if source.dimensions() != (rect.w, rect.h) {
+            error!(
+                "{} is {}x{}px, but {} is {}x{}px",
+                frame.name,
+                rect.w,
+                rect.h,
+                path.display(),
+                source.width(),
+                source.height()
+            );
+            failed += 1;
+            continue;
+        }
+        let (pixels, largest) = difference(&sheet.image, &source, (rect.x, rect.y), tolerance);
+        if pixels > 0 {
+            error!(
+                "{} differs from {}: {} pixels off by up to {}",
+                frame.name,
+                path.display(),
+                pixels,
+                largest
+            );
+            failed += 1;
+        }
+    }
+    if checked == 0 {
+        return Err(sheet
+            .invalid(format!(
+                "has no frames named after the files in {}",
+                frames.display()
+            ))
+            .into());
+    }
+    if failed > 0 {
+        return Err(VerifyError {
+            sheet: sheet.image_path,
+            failed,
+            checked,
+        }
+        .into());
+    }
+    info!(
+        "All {} checked frames of {} match their sources",
+        checked,
+        sheet.image_path.display()
+    );
+    Ok(())
+}
+
+/// The frames below `frames` by the names the assembler can give them: the
+/// file's stem, and its path below `frames` without the extension, both
+/// without `@` duration or direction suffixes.
+fn sources(frames: &Path) -> HashMap<String, PathBuf> {
+    let mut sources = HashMap::new();
+    let entries = walkdir::WalkDir::new(frames)
+        .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+        .into_iter()
+        .filter_map(|entry| {
+            entry
+                .map_err(|err| warn!("Skipping unreadable entry: {}", err))
+                .ok()
+        })
+        .filter(|entry| entry.file_type().is_file());
+    for entry in entries {
+        let relative = entry.path().strip_prefix(frames).unwrap_or(entry.path());
+        let mut parts = relative
+            .parent()
+            .into_iter()
+            .flat_map(Path::iter)
+            .map(|folder| unsuffixed(&folder.to_string_lossy()).to_owned())
+            .collect::<Vec<_>>();
+        let stem = relative
+            .file_stem()
+            .map(|stem| unsuffixed(&stem.to_string_lossy()).to_owned())
+            .unwrap_or_default();
+        parts.push(stem.clone());
+        sources
+            .entry(parts.join("/"))
+            .or_insert_with(|| entry.path().to_path_buf());
+        sources
+            .entry(stem)
+            .or_insert_with(|| entry.path().to_path_buf());
+    }
+    sources
+}
+
+/// `walk@pingpong` is the `walk` animation, and `walk_0005@3` the frame
+/// `walk_0005`.
+fn unsuffixed(name: &str) -> &str {
+    name.rfind('@').map_or(name, |at| &name[..at])
+}
+
+/// How many pixels of `source` differ from its cell of `sheet` at `(x, y)`
+/// by more than `tolerance` in any channel, and the largest difference.
+fn difference(
+    sheet: &RgbaImage,
+    source: &RgbaImage,
+    (x, y): (u32, u32),
+    tolerance: u8,
+) -> (u64, u8) {
+    let mut pixels = 0;
+    let mut largest = 0;
+    for (column, row, expected) in source.enumerate_pixels() {
+        let actual = sheet.get_pixel(x + column, y + row);
+        let off = expected
+            .0
+            .iter()
+            .zip(&actual.0)
+            .map(|(a, b)| a.abs_diff(*b))
+            .max()
+            .unwrap_or(0);
+        if off > tolerance {
+            pixels += 1;
+        }
+        largest = largest.max(off);
+    }
+    (pixels, largest)
+}
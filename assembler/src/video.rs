@@ -0,0 +1,176 @@
+//! `--preview-video`: an MP4 or WebM clip of the animation for review
+//! tickets, made by piping the frames to ffmpeg when it's installed.
+
+use log::{info, warn};
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use crate::{
+    errors::{IoContext, OptionError, Phase, VideoError},
+    output::{Overwrite, PendingFile},
+    preview::{self, Cell},
+    report, Result,
+};
+
+/// The ffmpeg to run, unless `SPRITESHEET_FFMPEG` names another.
+const FFMPEG: &str = "ffmpeg";
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Container {
+    Mp4,
+    Webm,
+}
+
+impl Container {
+    fn of(path: &str) -> Option<Self> {
+        let extension = Path::new(path).extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "mp4" => Some(Container::Mp4),
+            "webm" => Some(Container::Webm),
+            _ => None,
+        }
+    }
+
+    /// ffmpeg's arguments for the format and codec, picked to play in any
+    /// browser or chat app.
+    fn args(self) -> &'static [&'static str] {
+        match self {
+            Container::Mp4 => &[
+                "-c:v",
+                "libx264",
+                "-pix_fmt",
+                "yuv420p",
+                "-movflags",
+                "+faststart",
+                "-f",
+                "mp4",
+            ],
+            Container::Webm => &[
+                "-c:v",
+                "libvpx-vp9",
+                "-b:v",
+                "0",
+                "-crf",
+                "32",
+                "-pix_fmt",
+                "yuv420p",
+                "-f",
+                "webm",
+            ],
+        }
+    }
+}
+
+/// Fails unless `path` names an MP4 or WebM file, so a typo is caught
+/// before any work is done.
+pub fn check(path: &str) -> Result<()> {
+    match Container::of(path) {
+        Some(_) => Ok(()),
+        None => Err(OptionError {
+            name: "preview-video".to_owned(),
+            value: path.to_owned(),
+            expected: "a .mp4 or .webm filename".to_owned(),
+        }
+        .into()),
+    }
+}
+
+/// Encodes `frames` as a clip at `fps` frames per second, each frame
+/// repeated for as long as it shows and drawn over the preview's
+/// checkerboard, scaled by `scale`. Returns `None`, after a warning, if
+/// ffmpeg isn't installed.
+pub fn write<'a>(
+    frames: impl IntoIterator<Item = &'a Cell>,
+    fps: u32,
+    scale: f64,
+    path: &Path,
+    overwrite: Overwrite,
+) -> Result<Option<report::Output>> {
+    let mut frames = frames.into_iter().peekable();
+    let (width, height) = match frames.peek() {
+        Some(frame) => frame.image.dimensions(),
+        None => return Ok(None),
+    };
+    let container = Container::of(&path.to_string_lossy()).unwrap_or(Container::Mp4);
+    let ffmpeg = std::env::var("SPRITESHEET_FFMPEG").unwrap_or_else(|_| FFMPEG.to_owned());
+    // Scaled by whole pixels when enlarging, to keep pixel art sharp, and to
+    // even sizes, which yuv420p needs.
+    let filter = format!(
+        "scale=trunc(iw*{scale}/2)*2:trunc(ih*{scale}/2)*2:flags={}",
+        if scale >= 1.0 { "neighbor" } else { "area" },
+        scale = scale
+    );
+    let pending = PendingFile::create(path)?;
+    let child = Command::new(&ffmpeg)
+        .args(["-hide_banner", "-loglevel", "error", "-y"])
+        .args(["-f", "rawvideo", "-pix_fmt", "rgba"])
+        .args(["-s", &format!("{}x{}", width, height)])
+        .args(["-r", &fps.to_string(), "-i", "-", "-an", "-vf", &filter])
+        .args(container.args())
+        .arg(pending.path())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            warn!(
+                "Couldn't find {} to write {}; install ffmpeg or set SPRITESHEET_FFMPEG",
+                ffmpeg,
+                path.display()
+            );
+            return Ok(None);
+        }
+        Err(err) => return Err(err).context(Phase::Write, path),
+    };
+
+    let mut stdin = child.stdin.take().expect("ffmpeg's stdin is piped");
+    let mut shown = 0.0;
+    let mut written = 0u64;
+    let mut piped = Ok(());
+    for frame in frames {
+        // Whole video frames, rounding so the clip doesn't drift.
+        shown += frame.seconds();
+        let until = (shown * f64::from(fps)).round().max(written as f64 + 1.0) as u64;
+        let mut pixels = Vec::with_capacity(frame.image.len());
+        for (x, y, pixel) in frame.image.enumerate_pixels() {
+            pixels.extend_from_slice(&preview::over(pixel, preview::checker(x, y)).0);
+        }
+        while written < until && piped.is_ok() {
+            piped = stdin.write_all(&pixels);
+            written += 1;
+        }
+    }
+    drop(stdin);
+    let mut errors = String::new();
+    if let Some(mut stderr) = child.stderr.take() {
+        let _ = stderr.read_to_string(&mut errors);
+    }
+    let status = child.wait().context(Phase::Write, path)?;
+    if !status.success() || piped.is_err() {
+        let message = match errors.trim() {
+            "" => piped
+                .err()
+                .map_or(status.to_string(), |err| err.to_string()),
+            errors => errors.to_owned(),
+        };
+        return Err(VideoError {
+            path: path.to_path_buf(),
+            message,
+        }
+        .into());
+    }
+    pending.persist(path, overwrite)?;
+    let bytes = fs::read(path).context(Phase::Read, path)?;
+    info!("Wrote {}", path.display());
+    Ok(Some(report::Output {
+        path: path.to_path_buf(),
+        bytes: bytes.len() as u64,
+        sha256: report::sha256(&bytes),
+    }))
+}
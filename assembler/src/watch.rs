@@ -0,0 +1,86 @@
+use log::{error, info};
+use notify_debouncer_mini::{new_debouncer, notify::RecursiveMode, DebounceEventResult};
+use spritesheet_assembler::CancelHandle;
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::{Duration, SystemTime},
+};
+
+use crate::BoxResult;
+
+/// How long the frames have to stay untouched before a rebuild starts, so a
+/// render writing hundreds of files only triggers one assembly.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+/// How often to check for Ctrl+C while waiting for changes.
+const POLL: Duration = Duration::from_millis(200);
+
+/// Calls `rebuild` once, then again every time something under `frames`
+/// changes. The root (or the frames folder's parent, when it lives
+/// elsewhere) is what's actually watched, so the frames folder may be
+/// deleted and recreated between renders. Runs until `cancel` is used.
+pub fn watch(
+    root: &Path,
+    frames: &Path,
+    cancel: &CancelHandle,
+    mut rebuild: impl FnMut(),
+) -> BoxResult<()> {
+    // Events carry absolute paths, so compare against absolute ones, but not
+    // canonical ones, which Windows gives a `\\?\` prefix events don't have.
+    let absolute_root = std::path::absolute(root)?;
+    let frames = absolute_root.join(frames.strip_prefix(root).unwrap_or(frames));
+    let frames = frames.as_path();
+    let watched = if frames.starts_with(&absolute_root) {
+        absolute_root.as_path()
+    } else {
+        frames.parent().unwrap_or(frames)
+    };
+
+    let (tx, rx) = mpsc::channel::<DebounceEventResult>();
+    let mut debouncer = new_debouncer(DEBOUNCE, tx)?;
+    debouncer
+        .watcher()
+        .watch(watched, RecursiveMode::Recursive)?;
+
+    rebuild();
+    let mut last = snapshot(frames);
+    info!("Watching {} for changes", frames.display());
+    while !cancel.is_cancelled() {
+        let result = match rx.recv_timeout(POLL) {
+            Ok(result) => result,
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        };
+        match result {
+            Ok(events) => {
+                if !events.iter().any(|event| event.path.starts_with(frames)) {
+                    continue;
+                }
+                // Reading the frames during a rebuild raises events of its
+                // own on some platforms, so only act on real changes.
+                if snapshot(frames) != last {
+                    info!("Frames changed, rebuilding");
+                    rebuild();
+                    // Taken afterwards so the rebuild's own output, which may
+                    // sit among the frames, doesn't trigger another one.
+                    last = snapshot(frames);
+                }
+            }
+            Err(err) => error!("Watch error: {}", err),
+        }
+    }
+    info!("Stopped watching");
+    Ok(())
+}
+
+fn snapshot(frames: &Path) -> Vec<(PathBuf, u64, Option<SystemTime>)> {
+    walkdir::WalkDir::new(frames)
+        .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            Some((entry.into_path(), metadata.len(), metadata.modified().ok()))
+        })
+        .collect()
+}
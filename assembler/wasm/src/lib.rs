@@ -0,0 +1,104 @@
+//! A WebAssembly build of the assembler for web-based sprite tools. Frames
+//! go in as encoded images and the sheet comes back encoded, so nothing
+//! touches a filesystem.
+//!
+//! ```js
+//! import init, { Job } from "./spritesheet_assembler_wasm.js";
+//!
+//! await init();
+//! const job = new Job();
+//! job.setOption("metadata", "json");
+//! for (const file of files) {
+//!     job.addFrame(file.name, new Uint8Array(await file.arrayBuffer()));
+//! }
+//! for (const sheet of job.run()) {
+//!     download(sheet.name, sheet.png, sheet.metadata);
+//! }
+//! ```
+
+use spritesheet_assembler::{Assembler, EncodedFrame, EncodedSheet};
+use wasm_bindgen::prelude::*;
+
+/// An assembly being put together from frames in memory.
+#[wasm_bindgen]
+pub struct Job {
+    assembler: Assembler,
+    frames: Vec<EncodedFrame>,
+}
+
+#[wasm_bindgen]
+impl Job {
+    #[wasm_bindgen(constructor)]
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Job {
+        // Browsers can't spawn threads for rayon's default pool, so the
+        // parallel steps run on the calling thread instead. Later jobs find
+        // the pool already set up.
+        let _ = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .use_current_thread()
+            .build_global();
+        Job {
+            assembler: Assembler::new("").name("spritesheet"),
+            frames: Vec::new(),
+        }
+    }
+
+    /// Adds an encoded frame. `name` is used as its path for the
+    /// `frame-name` tokens, so `walk/0001.png` belongs to the `walk`
+    /// animation.
+    #[wasm_bindgen(js_name = addFrame)]
+    pub fn add_frame(&mut self, name: String, bytes: Vec<u8>) {
+        self.frames.push(EncodedFrame { name, bytes });
+    }
+
+    /// Sets an option by its command line name, such as `out`, `metadata`,
+    /// `frame-name`, `max-size` or `compression`.
+    #[wasm_bindgen(js_name = setOption)]
+    pub fn set_option(&mut self, name: &str, value: &str) -> Result<(), JsError> {
+        self.assembler = self
+            .assembler
+            .clone()
+            .option(name, value)
+            .map_err(|err| JsError::new(&err.to_string()))?;
+        Ok(())
+    }
+
+    /// Assembles the frames added so far into one or more pages.
+    pub fn run(&self) -> Result<Vec<Sheet>, JsError> {
+        let sheets = self
+            .assembler
+            .run_in_memory(&self.frames)
+            .map_err(|err| JsError::new(&err.to_string()))?;
+        Ok(sheets.into_iter().map(Sheet).collect())
+    }
+}
+
+/// One page of an assembled sheet.
+#[wasm_bindgen]
+pub struct Sheet(EncodedSheet);
+
+#[wasm_bindgen]
+impl Sheet {
+    /// The filename the `out` template gives this page.
+    #[wasm_bindgen(getter)]
+    pub fn name(&self) -> String {
+        self.0.name.clone()
+    }
+
+    /// The encoded PNG.
+    #[wasm_bindgen(getter)]
+    pub fn png(&self) -> Vec<u8> {
+        self.0.png.clone()
+    }
+
+    /// The page's metadata in the first format set with `metadata`, or
+    /// `undefined` if none was.
+    #[wasm_bindgen(getter)]
+    pub fn metadata(&self) -> Option<String> {
+        self.0
+            .metadata
+            .first()
+            .map(|(_, bytes)| String::from_utf8_lossy(bytes).into_owned())
+    }
+}
@@ -2,7 +2,6 @@ import os
 import sys
 import bpy
 import math
-import shutil
 import platform
 import subprocess
 import json
@@ -17,6 +16,25 @@ elif platform == "Linux":
 else:
     ASSEMBLER_FILENAME = "assembler_mac"
 
+# The first assembler version to report itself with --version, and to take
+# the options past --root and --out. Older binaries print no version.
+ASSEMBLER_OPTIONS_VERSION = (0, 1, 0)
+
+# Written next to the frames for the assembler while it runs
+MARKERS_FILENAME = "markers.json"
+
+# Exit codes returned by the assembler, see the README
+ASSEMBLER_ERRORS = {
+    2: "Invalid assembler options",
+    3: "No rendered frames were found",
+    4: "Rendered frames are not all the same size",
+    5: "A rendered frame could not be read",
+    6: "Could not read or write files in the output folder",
+    7: "The sprite sheet would be too large",
+    8: "The sprite sheet already exists",
+    9: "Assembly was cancelled",
+}
+
 class RenderSpriteSheet(bpy.types.Operator):
     """Operator used to render sprite sheets for an object"""
     bl_idname = "spritesheets.render"
@@ -32,6 +50,7 @@ class RenderSpriteSheet(bpy.types.Operator):
         progressProps.actionTotal = len(bpy.data.actions)
 
         animation_descs = []
+        markers = []
         frame_end = 0
 
         objectToRender = props.target
@@ -40,24 +59,26 @@ class RenderSpriteSheet(bpy.types.Operator):
             progressProps.actionIndex = index
             objectToRender.animation_data.action = action
 
-            count, _, _ = frame_count(action.frame_range)
+            count, frameMin, frameMax = frame_count(action.frame_range)
             frame_end += count
+            # Timeline markers within the action's frames become its events
+            action_markers = [marker for marker in scene.timeline_markers if frameMin <= marker.frame < frameMax]
+            markers += [{"name": marker.name, "frame": marker.frame, "animation": action.name} for marker in action_markers]
             animation_descs.append({
                 "name": action.name,
                 "end": frame_end,
+                "events": [{"name": marker.name, "frame": marker.frame - frameMin} for marker in action_markers],
             })
 
             self.processAction(action, scene, props,
                                progressProps, objectToRender)
 
-        assemblerPath = os.path.normpath(
-            os.path.join(
-                props.binPath,
-                ASSEMBLER_FILENAME,
-            )
-        )
-        print("Assembler path: ", assemblerPath)
-        subprocess.run([assemblerPath, "--root", bpy.path.abspath(props.outputPath), "--out", props.outputName + ".png"])
+        returncode = assemble(props, markers)
+        if returncode != 0:
+            progressProps.rendering = False
+            message = ASSEMBLER_ERRORS.get(returncode, "Assembling the sprite sheet failed")
+            self.report({'ERROR'}, "%s (exit code %d)" % (message, returncode))
+            return {'CANCELLED'}
 
         json_info = {
             "name": props.outputName,
@@ -73,7 +94,6 @@ class RenderSpriteSheet(bpy.types.Operator):
 
         progressProps.rendering = False
         progressProps.success = True
-        shutil.rmtree(bpy.path.abspath(os.path.join(props.outputPath, "temp")))
         return {'FINISHED'}
 
 
@@ -99,6 +119,69 @@ class RenderSpriteSheet(bpy.types.Operator):
                 bpy.ops.spritesheets.render_tile('EXEC_DEFAULT')
 
 
+def assemble(props, markers):
+    """Assembles the rendered frames in-process if the spritesheet_assembler module is installed next to the binaries, or else runs the assembler binary, returning its exit code"""
+    root = bpy.path.abspath(props.outputPath)
+    # The assembler turns timeline markers into events on the frames they mark
+    markersPath = os.path.join(root, MARKERS_FILENAME)
+    with open(markersPath, "w") as f:
+        json.dump(markers, f)
+    try:
+        return run_assembler(props, root)
+    finally:
+        os.remove(markersPath)
+
+
+def run_assembler(props, root):
+    """Runs the in-process module or the binary on the frames under root"""
+    binPath = bpy.path.abspath(props.binPath)
+    if binPath not in sys.path:
+        sys.path.append(binPath)
+    try:
+        import spritesheet_assembler
+    except ImportError:
+        spritesheet_assembler = None
+
+    if spritesheet_assembler is not None:
+        try:
+            spritesheet_assembler.assemble(root, output=props.outputName + ".png", overwrite="force", cleanup=True, markers=MARKERS_FILENAME, fps=props.fps)
+        except spritesheet_assembler.AssemblyError as e:
+            print("Assembler error: ", e)
+            return e.code
+        return 0
+
+    assemblerPath = os.path.normpath(
+        os.path.join(
+            props.binPath,
+            ASSEMBLER_FILENAME,
+        )
+    )
+    print("Assembler path: ", assemblerPath)
+    args = [assemblerPath, "--root", root, "--out", props.outputName + ".png"]
+    version = assembler_version(assemblerPath)
+    if version is not None and version >= ASSEMBLER_OPTIONS_VERSION:
+        args += ["--force", "--cleanup", "--markers", MARKERS_FILENAME, "--fps", str(props.fps)]
+    else:
+        print("Assembler predates --force, --cleanup, --markers and --fps, so they aren't passed")
+    result = subprocess.run(args)
+    return result.returncode
+
+
+def assembler_version(assemblerPath):
+    """The version the assembler binary reports, such as (0, 1, 0), or None if it reports none"""
+    try:
+        output = subprocess.run([assemblerPath, "--version"], capture_output=True, text=True).stdout
+    except OSError:
+        return None
+    words = output.split()
+    if len(words) < 2:
+        return None
+    try:
+        return tuple(int(part) for part in words[1].split("."))
+    except ValueError:
+        return None
+
+
 def frame_count(frame_range):
     frameMin = min(1, math.floor(frame_range[0]))
     frameMax = math.ceil(frame_range[1])
@@ -94,6 +94,14 @@ func _import(source_file, save_path, options, platform_variants, gen_files):
 
             animation_library.add_animation(anim_data["name"], animation)
 
+        # Blender timeline markers call animation_event(name) on the scene's root
+        var events = anim_data.get("events", [])
+        if events.size() > 0:
+            var event_track = animation.add_track(Animation.TYPE_METHOD)
+            animation.track_set_path(event_track, ".")
+            for event in events:
+                animation.track_insert_key(event_track, event["frame"] * time_offset, {"method": "animation_event", "args": [event["name"]]})
+
         animation.set_length(time)
         count += anim_data["end"]
     player.add_animation_library("bss", animation_library)